@@ -0,0 +1,55 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_norg::{parse, parse_tree, stage_1};
+
+const SECTION: &str = r#"* A heading with *bold* and /italic/ text
+  Some paragraph text which goes on for a while, with a {https://example.com}[link] and
+  `inline code` in it. Journal entries tend to be mostly prose like this one.
+
+  - An unordered list item
+  -- A nested item with an {* anchor}
+  ~ An ordered item
+  > A quote with an _underlined_ word
+
+  $ Definition
+  The definition of the term.
+
+  @code rust
+  fn main() {
+      println!("Hello, world!");
+  }
+  @end
+
+  #tag parameter
+  A paragraph with a carryover tag.
+___
+"#;
+
+fn document(sections: usize) -> String {
+    SECTION.repeat(sections)
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+
+    for sections in [10, 100] {
+        let input = document(sections);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("stage_1", sections), &input, |b, input| {
+            b.iter(|| stage_1(input))
+        });
+        group.bench_with_input(BenchmarkId::new("parse", sections), &input, |b, input| {
+            b.iter(|| parse(input).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("parse_tree", sections),
+            &input,
+            |b, input| b.iter(|| parse_tree(input).unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
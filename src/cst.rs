@@ -0,0 +1,400 @@
+//! A lossless concrete syntax tree (CST) built alongside the AST.
+//!
+//! Every byte of the input belongs to exactly one [`SyntaxToken`], including the whitespace,
+//! indentation and blank lines which the AST throws away. Tokens are grouped under
+//! [`SyntaxNode`]s which mirror the nodes of the [`NorgAST`], so that `parse_cst(input)
+//! .to_string() == input` holds for any document.
+
+use serde::Serialize;
+
+use crate::{
+    parse_recovering,
+    span::Span,
+    stage_1::{stage_1, NorgToken},
+    LinkTarget, NorgAST, ParagraphSegment,
+};
+
+/// The kind of a [`SyntaxNode`] or [`SyntaxToken`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum SyntaxKind {
+    // Nodes
+    Document,
+    Paragraph,
+    Heading,
+    NestableDetachedModifier,
+    RangeableDetachedModifier,
+    CarryoverTag,
+    VerbatimRangedTag,
+    RangedTag,
+    InfirmTag,
+    DelimitingModifier,
+    AttachedModifier,
+    Link,
+    LinkTarget,
+    AnchorDefinition,
+    Anchor,
+    InlineLinkTarget,
+    InlineVerbatim,
+    /// Content which could not be parsed.
+    Error,
+
+    // Tokens
+    Whitespace,
+    Newline,
+    Text,
+    Special,
+    Escape,
+    TagEnd,
+    Continuation,
+}
+
+impl SyntaxKind {
+    /// Returns `true` for tokens which carry no meaning for the AST, i.e. whitespace and
+    /// newlines.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Self::Whitespace | Self::Newline)
+    }
+}
+
+/// A leaf of the tree, holding a slice of the original input.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct SyntaxToken {
+    pub kind: SyntaxKind,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// An interior node of the tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub span: Span,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    /// Returns all tokens below this node in source order.
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        self.children
+            .iter()
+            .flat_map(|child| match child {
+                SyntaxElement::Node(node) => node.tokens(),
+                SyntaxElement::Token(token) => vec![token],
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.tokens()
+            .into_iter()
+            .try_for_each(|token| f.write_str(&token.text))
+    }
+}
+
+/// The shape of the tree, built from the spans of the AST before any tokens are attached.
+struct Outline {
+    kind: SyntaxKind,
+    span: Span,
+    children: Vec<Outline>,
+}
+
+impl Outline {
+    fn new(kind: SyntaxKind, span: Span, children: Vec<Outline>) -> Self {
+        Self {
+            kind,
+            span,
+            children,
+        }
+    }
+}
+
+fn outline_segments(segments: &[ParagraphSegment]) -> Vec<Outline> {
+    segments.iter().filter_map(outline_segment).collect()
+}
+
+fn outline_segment(segment: &ParagraphSegment) -> Option<Outline> {
+    let span = segment.span();
+
+    Some(match segment {
+        ParagraphSegment::AttachedModifier { content, .. }
+        | ParagraphSegment::FreeFormAttachedModifier { content, .. } => Outline::new(
+            SyntaxKind::AttachedModifier,
+            span,
+            outline_segments(content),
+        ),
+        ParagraphSegment::Link {
+            targets,
+            description,
+            ..
+        } => Outline::new(
+            SyntaxKind::Link,
+            span,
+            targets
+                .iter()
+                .map(outline_target)
+                .chain(outline_segments(description.as_deref().unwrap_or_default()))
+                .collect(),
+        ),
+        ParagraphSegment::AnchorDefinition {
+            content, target, ..
+        } => Outline::new(
+            SyntaxKind::AnchorDefinition,
+            span,
+            outline_segments(content)
+                .into_iter()
+                .chain(outline_segment(target))
+                .collect(),
+        ),
+        ParagraphSegment::Anchor {
+            content,
+            description,
+            ..
+        } => Outline::new(
+            SyntaxKind::Anchor,
+            span,
+            outline_segments(content)
+                .into_iter()
+                .chain(outline_segments(description.as_deref().unwrap_or_default()))
+                .collect(),
+        ),
+        ParagraphSegment::InlineLinkTarget { content, .. } => Outline::new(
+            SyntaxKind::InlineLinkTarget,
+            span,
+            outline_segments(content),
+        ),
+        ParagraphSegment::InlineVerbatim { .. } => {
+            Outline::new(SyntaxKind::InlineVerbatim, span, vec![])
+        }
+        _ => return None,
+    })
+}
+
+fn outline_target(target: &LinkTarget) -> Outline {
+    let children = match target {
+        LinkTarget::Heading { title, .. }
+        | LinkTarget::Footnote { title, .. }
+        | LinkTarget::Definition { title, .. }
+        | LinkTarget::Generic { title, .. }
+        | LinkTarget::Wiki { title, .. }
+        | LinkTarget::Extendable { title, .. } => outline_segments(title),
+        _ => vec![],
+    };
+
+    Outline::new(SyntaxKind::LinkTarget, target.span(), children)
+}
+
+fn outline_ast(node: &NorgAST) -> Outline {
+    let span = node.span();
+
+    match node {
+        NorgAST::Paragraph { content, .. } => {
+            Outline::new(SyntaxKind::Paragraph, span, outline_segments(content))
+        }
+        NorgAST::NestableDetachedModifier { text, content, .. } => Outline::new(
+            SyntaxKind::NestableDetachedModifier,
+            span,
+            std::iter::once(outline_ast(text))
+                .chain(content.iter().map(outline_ast))
+                .collect(),
+        ),
+        NorgAST::RangeableDetachedModifier { title, content, .. } => Outline::new(
+            SyntaxKind::RangeableDetachedModifier,
+            span,
+            outline_segments(title)
+                .into_iter()
+                .chain(content.iter().map(outline_ast))
+                .collect(),
+        ),
+        NorgAST::Heading { title, content, .. } => Outline::new(
+            SyntaxKind::Heading,
+            span,
+            outline_segments(title)
+                .into_iter()
+                .chain(content.iter().map(outline_ast))
+                .collect(),
+        ),
+        NorgAST::CarryoverTag { next_object, .. } => Outline::new(
+            SyntaxKind::CarryoverTag,
+            span,
+            vec![outline_ast(next_object)],
+        ),
+        NorgAST::VerbatimRangedTag { .. } => {
+            Outline::new(SyntaxKind::VerbatimRangedTag, span, vec![])
+        }
+        NorgAST::RangedTag { content, .. } => Outline::new(
+            SyntaxKind::RangedTag,
+            span,
+            content.iter().map(outline_ast).collect(),
+        ),
+        NorgAST::InfirmTag { .. } => Outline::new(SyntaxKind::InfirmTag, span, vec![]),
+        NorgAST::DelimitingModifier { .. } => {
+            Outline::new(SyntaxKind::DelimitingModifier, span, vec![])
+        }
+        NorgAST::Error { .. } => Outline::new(SyntaxKind::Error, span, vec![]),
+    }
+}
+
+/// Converts the lexer output into tokens, merging runs of regular characters into a single
+/// [`SyntaxKind::Text`] token.
+fn lex(input: &str, tokens: Vec<(NorgToken, Span)>) -> Vec<SyntaxToken> {
+    let mut result: Vec<SyntaxToken> = Vec::with_capacity(tokens.len());
+
+    for (token, span) in tokens {
+        let kind = match token {
+            NorgToken::Whitespace(_) => SyntaxKind::Whitespace,
+            NorgToken::SingleNewline | NorgToken::Newlines(_) => SyntaxKind::Newline,
+            NorgToken::Text(_) => SyntaxKind::Text,
+            NorgToken::Special(_) => SyntaxKind::Special,
+            NorgToken::Escape(_) => SyntaxKind::Escape,
+            NorgToken::End(_) => SyntaxKind::TagEnd,
+            NorgToken::Continuation(_) => SyntaxKind::Continuation,
+            NorgToken::Eof => continue,
+        };
+
+        match result.last_mut() {
+            Some(last) if kind == SyntaxKind::Text && last.kind == SyntaxKind::Text => {
+                last.span = last.span.union(span);
+                last.text = input[last.span.range()].to_string();
+            }
+            _ => result.push(SyntaxToken {
+                kind,
+                text: input[span.range()].to_string(),
+                span,
+            }),
+        }
+    }
+
+    result
+}
+
+/// Attaches the tokens to the outline. Tokens which are not covered by any child end up in the
+/// closest enclosing node, which is how trivia between blocks is preserved.
+fn fill<I>(outline: Outline, tokens: &mut std::iter::Peekable<I>) -> SyntaxNode
+where
+    I: Iterator<Item = SyntaxToken>,
+{
+    let mut children = Vec::new();
+
+    for child in outline.children {
+        while let Some(token) =
+            tokens.next_if(|token| token.span.end.offset <= child.span.start.offset)
+        {
+            children.push(SyntaxElement::Token(token));
+        }
+
+        children.push(SyntaxElement::Node(fill(child, tokens)));
+    }
+
+    while let Some(token) = tokens.next_if(|token| token.span.end.offset <= outline.span.end.offset)
+    {
+        children.push(SyntaxElement::Token(token));
+    }
+
+    SyntaxNode {
+        kind: outline.kind,
+        span: outline.span,
+        children,
+    }
+}
+
+/// Parses the input into a lossless concrete syntax tree.
+///
+/// This never fails: blocks which cannot be parsed have their tokens placed under a
+/// [`SyntaxKind::Error`] node, see [`crate::parse_recovering`].
+pub fn parse_cst(input: &str) -> SyntaxNode {
+    // Stage 1 accepts any character, so lexing cannot fail.
+    let tokens = stage_1(input);
+    let document_span = tokens
+        .iter()
+        .map(|(_, span)| *span)
+        .reduce(Span::union)
+        .unwrap_or_default();
+
+    let (tree, _) = parse_recovering(input);
+    let children = tree.iter().map(outline_ast).collect();
+
+    let mut tokens = lex(input, tokens).into_iter().peekable();
+    let mut document = fill(
+        Outline::new(SyntaxKind::Document, document_span, children),
+        &mut tokens,
+    );
+
+    // Anything left over (which should never happen) is kept, so that no input is lost.
+    document.children.extend(tokens.map(SyntaxElement::Token));
+
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::proptest;
+
+    use crate::cst::{parse_cst, SyntaxElement, SyntaxNode};
+
+    /// Renders the tree as an indented outline, to keep the snapshots readable.
+    fn outline(node: &SyntaxNode, depth: usize, lines: &mut Vec<String>) {
+        lines.push(format!("{}{:?}", "  ".repeat(depth), node.kind));
+        for child in &node.children {
+            match child {
+                SyntaxElement::Node(node) => outline(node, depth + 1, lines),
+                SyntaxElement::Token(token) => lines.push(format!(
+                    "{}{:?} {:?}",
+                    "  ".repeat(depth + 1),
+                    token.kind,
+                    token.text
+                )),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        [
+            "",
+            "\n\n\n",
+            "* Heading\n\tcontent  with   spaces\n\n\n** Sub\n",
+            "  - one\r\n  -- two\r\n",
+            "- - a list item\n",
+            "@code lua\n    print(\"hi\")\n  @end\n",
+            "|example\n  text\n|end\n",
+            "this *is* {* a link}[with description] and `verbatim`   \n",
+            "$$ Term\n   Long definition\n$$ text\n",
+            "no trailing newline",
+        ]
+        .into_iter()
+        .for_each(|input| assert_eq!(parse_cst(input).to_string(), input));
+    }
+
+    #[test]
+    fn trivia() {
+        let mut lines = Vec::new();
+        outline(
+            &parse_cst("* Heading\n\n  - item *bold*\n\n  para\n"),
+            0,
+            &mut lines,
+        );
+
+        assert_yaml_snapshot!(lines);
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_proptests(input in "(?s).*") {
+            assert_eq!(parse_cst(&input).to_string(), input);
+        }
+
+        #[test]
+        fn round_trip_markup_proptests(input in r"[*\-~>$^:@|=.#+<(){}\[\]`/_!%,\\ \t\r\na-z]*") {
+            assert_eq!(parse_cst(&input).to_string(), input);
+        }
+    }
+}
@@ -1,43 +1,218 @@
-use chumsky::error::Simple;
+use std::fmt;
 
-use crate::{stage_1::NorgToken, stage_2::NorgBlock, NorgASTFlat};
+use chumsky::error::{Simple, SimpleReason};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{span::Span, stage_1::NorgToken, stage_2::NorgBlock, NorgASTFlat};
 
 /// Represents errors that can occur during the parsing process across different stages.
 #[derive(Debug)]
 pub enum NorgParseError {
-    Stage1(Vec<Simple<char>>),
-    Stage2(Vec<Simple<NorgToken>>),
-    Stage3(Vec<Simple<NorgBlock>>),
-    Stage4(Vec<Simple<NorgASTFlat>>),
-    Meta(Simple<char>),
+    /// Errors from turning tokens into blocks. The tokens borrow from the input, so they are
+    /// kept as descriptions.
+    Stage2(Vec<Simple<String, Span>>),
+    /// Errors from turning blocks into nodes, with the blocks kept as descriptions.
+    Stage3(Vec<Simple<String, Span>>),
+    Stage4(Vec<Simple<NorgASTFlat, Span>>),
+    /// Errors from parsing the content of `@document.meta`, all of them rather than only the
+    /// first.
+    Meta(Vec<Simple<char, Span>>),
+}
+
+/// The errors found in a [`crate::query::Selector`], located in the selector string.
+#[derive(Debug)]
+pub struct SelectorError(pub(crate) Vec<Simple<char, Span>>);
+
+/// A single problem found in the input, located in the original source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+}
+
+impl NorgParseError {
+    /// Returns the individual errors of the failed stage, with their locations in the input.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::Meta(errors) => errors.iter().map(diagnostic).collect(),
+            Self::Stage2(errors) | Self::Stage3(errors) => errors.iter().map(diagnostic).collect(),
+            Self::Stage4(errors) => errors.iter().map(diagnostic).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.start.line + 1,
+            self.span.start.column + 1,
+            self.message
+        )
+    }
 }
 
-impl From<Vec<Simple<char>>> for NorgParseError {
-    fn from(error: Vec<Simple<char>>) -> Self {
-        NorgParseError::Stage1(error)
+impl fmt::Display for NorgParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.diagnostics().iter().join("\n"))
     }
 }
 
-impl From<Vec<Simple<NorgToken>>> for NorgParseError {
-    fn from(error: Vec<Simple<NorgToken>>) -> Self {
-        NorgParseError::Stage2(error)
+impl std::error::Error for NorgParseError {}
+
+impl SelectorError {
+    /// Returns the individual errors, with their locations in the selector.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.0.iter().map(diagnostic).collect()
+    }
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.diagnostics().iter().join("\n"))
     }
 }
 
-impl From<Vec<Simple<NorgBlock>>> for NorgParseError {
-    fn from(error: Vec<Simple<NorgBlock>>) -> Self {
-        NorgParseError::Stage3(error)
+impl std::error::Error for SelectorError {}
+
+impl<'a> From<Vec<Simple<NorgToken<'a>, Span>>> for NorgParseError {
+    fn from(error: Vec<Simple<NorgToken<'a>, Span>>) -> Self {
+        NorgParseError::Stage2(describe_errors(error))
     }
 }
 
-impl From<Vec<Simple<NorgASTFlat>>> for NorgParseError {
-    fn from(error: Vec<Simple<NorgASTFlat>>) -> Self {
+impl<'a> From<Vec<Simple<NorgBlock<'a>, Span>>> for NorgParseError {
+    fn from(error: Vec<Simple<NorgBlock<'a>, Span>>) -> Self {
+        NorgParseError::Stage3(describe_errors(error))
+    }
+}
+
+impl From<Vec<Simple<NorgASTFlat, Span>>> for NorgParseError {
+    fn from(error: Vec<Simple<NorgASTFlat, Span>>) -> Self {
         NorgParseError::Stage4(error)
     }
 }
 
-impl From<Simple<char>> for NorgParseError {
-    fn from(error: Simple<char>) -> Self {
-        NorgParseError::Meta(error)
+/// Human readable names for the items each stage consumes.
+trait Describe {
+    fn describe(&self) -> String;
+}
+
+impl Describe for String {
+    fn describe(&self) -> String {
+        self.clone()
+    }
+}
+
+impl Describe for char {
+    fn describe(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+impl Describe for NorgToken<'_> {
+    fn describe(&self) -> String {
+        match self {
+            Self::Whitespace(_) => "whitespace".into(),
+            Self::SingleNewline | Self::Newlines(_) => "newline".into(),
+            Self::Text(text) => format!("{:?}", text),
+            Self::Special(c) => c.describe(),
+            Self::Escape(c) => format!("escaped {:?}", c),
+            Self::End(c) => format!("'{}end'", c),
+            Self::Continuation(_) => "line continuation".into(),
+            Self::Eof => "end of input".into(),
+        }
+    }
+}
+
+impl Describe for NorgBlock<'_> {
+    fn describe(&self) -> String {
+        match self {
+            Self::ParagraphSegment(_) | Self::ParagraphSegmentEnd(_) => "paragraph".into(),
+            Self::Heading { .. } => "heading".into(),
+            Self::NestableDetachedModifier { modifier_type, .. } => {
+                format!("'{}' detached modifier", modifier_type)
+            }
+            Self::RangeableDetachedModifier { modifier_type, .. } => {
+                format!("'{}' rangeable detached modifier", modifier_type)
+            }
+            Self::RangeableDetachedModifierClose(c) => {
+                format!("closing '{}{}' modifier", c, c)
+            }
+            Self::RangedTag { tag_type, .. } => format!("'{}' ranged tag", tag_type),
+            Self::RangedTagEnd(c) => format!("'{}end'", c),
+            Self::VerbatimRangedTag { .. } => "verbatim ranged tag".into(),
+            Self::InfirmTag { .. } => "infirm tag".into(),
+            Self::CarryoverTag { tag_type, .. } => format!("'{}' carryover tag", tag_type),
+            Self::DelimitingModifier(c) => format!("'{}' delimiting modifier", c),
+            Self::Slide => "slide".into(),
+            Self::IndentSegment => "indent segment".into(),
+        }
+    }
+}
+
+impl Describe for NorgASTFlat {
+    fn describe(&self) -> String {
+        match self {
+            Self::Paragraph { .. } => "paragraph",
+            Self::NestableDetachedModifier { .. } => "nestable detached modifier",
+            Self::RangeableDetachedModifier { .. } => "rangeable detached modifier",
+            Self::Heading { .. } => "heading",
+            Self::CarryoverTag { .. } => "carryover tag",
+            Self::VerbatimRangedTag { .. } => "verbatim ranged tag",
+            Self::RangedTag { .. } => "ranged tag",
+            Self::InfirmTag { .. } => "infirm tag",
+            Self::DelimitingModifier { .. } => "delimiting modifier",
+            Self::Error { .. } => "invalid input",
+        }
+        .into()
+    }
+}
+
+fn describe_errors<T: Describe + std::hash::Hash + Eq>(
+    errors: Vec<Simple<T, Span>>,
+) -> Vec<Simple<String, Span>> {
+    errors
+        .into_iter()
+        .map(|error| error.map(|item| item.describe()))
+        .collect()
+}
+
+fn describe_item<T: Describe>(item: Option<&T>) -> String {
+    item.map_or_else(|| "end of input".into(), Describe::describe)
+}
+
+fn diagnostic<T: Describe + std::hash::Hash + Eq>(error: &Simple<T, Span>) -> Diagnostic {
+    let mut message = match error.reason() {
+        SimpleReason::Custom(message) => textwrap::dedent(message).trim().to_string(),
+        SimpleReason::Unclosed { delimiter, .. } => {
+            format!("unclosed delimiter {}", delimiter.describe())
+        }
+        SimpleReason::Unexpected => {
+            let expected = error
+                .expected()
+                .map(|item| describe_item(item.as_ref()))
+                .sorted()
+                .dedup()
+                .join(", ");
+            let found = describe_item(error.found());
+
+            if expected.is_empty() {
+                format!("unexpected {}", found)
+            } else {
+                format!("unexpected {}, expected {}", found, expected)
+            }
+        }
+    };
+
+    if let Some(label) = error.label() {
+        message = format!("{} (while parsing {})", message, label);
+    }
+
+    Diagnostic {
+        span: error.span(),
+        message,
     }
 }
@@ -0,0 +1,346 @@
+//! Helpers shared by the exporters and renderers.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    text::plain_text,
+    visit::{
+        walk_anchor_definition, walk_heading, walk_inline_link_target,
+        walk_rangeable_detached_modifier, Visitor,
+    },
+    NorgAST, ParagraphSegment, ParagraphSegmentToken, RangeableDetachedModifier, Span,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TargetKind {
+    Heading(u16),
+    Definition,
+    Footnote,
+    InlineLinkTarget,
+}
+
+/// Something links can point to.
+pub(crate) struct Target {
+    pub kind: TargetKind,
+    /// The title, normalised with [`normalize`].
+    pub name: String,
+    /// The title as plain text, without surrounding whitespace.
+    pub text: String,
+    pub id: String,
+}
+
+/// Gives a unique id to everything links can point to, in the order they appear in, and
+/// collects the content of footnotes and the links of anchor definitions.
+pub(crate) struct Targets {
+    targets: Vec<Target>,
+    ids: HashMap<Span, String>,
+    used: HashSet<String>,
+    /// Turns the title of a target into its id, before a number is added to make it unique.
+    identifier: fn(TargetKind, &str) -> String,
+    /// The content of footnotes, by their normalised title. The title of a footnote without
+    /// content is used as its only paragraph.
+    footnotes: HashMap<String, Vec<NorgAST>>,
+    /// The links of anchor definitions, by their normalised text.
+    anchors: HashMap<String, ParagraphSegment>,
+}
+
+impl Targets {
+    pub(crate) fn new(tree: &[NorgAST], identifier: fn(TargetKind, &str) -> String) -> Self {
+        let mut targets = Self {
+            targets: Vec::new(),
+            ids: HashMap::new(),
+            used: HashSet::new(),
+            identifier,
+            footnotes: HashMap::new(),
+            anchors: HashMap::new(),
+        };
+        tree.iter().for_each(|node| targets.visit_node(node));
+
+        targets
+    }
+
+    fn add(&mut self, kind: TargetKind, title: &[ParagraphSegment], span: Span) {
+        let text = plain_text(title);
+        let base = (self.identifier)(kind, &text);
+
+        let mut id = base.clone();
+        let mut suffix = 0;
+        while !self.used.insert(id.clone()) {
+            suffix += 1;
+            id = format!("{base}-{suffix}");
+        }
+
+        self.ids.insert(span, id.clone());
+        self.targets.push(Target {
+            kind,
+            name: normalize(&text),
+            text: text.trim().to_string(),
+            id,
+        });
+    }
+
+    /// The id of the target starting the node or segment with the given span, or an empty
+    /// string for anything else.
+    pub(crate) fn id(&self, span: &Span) -> &str {
+        self.ids.get(span).map_or("", String::as_str)
+    }
+
+    /// Finds the first target matching `title`, accepting any kind when `kind` is `None`. Links
+    /// to headings fall back to headings of any level.
+    pub(crate) fn find(
+        &self,
+        kind: Option<TargetKind>,
+        title: &[ParagraphSegment],
+    ) -> Option<&Target> {
+        match kind {
+            Some(TargetKind::Heading(_)) => self
+                .find_by(title, |found| Some(found) == kind)
+                .or_else(|| self.find_by(title, |found| matches!(found, TargetKind::Heading(_)))),
+            Some(kind) => self.find_by(title, |found| found == kind),
+            None => self.find_by(title, |_| true),
+        }
+    }
+
+    /// Finds the first target matching `title` whose kind is accepted by `accept`.
+    pub(crate) fn find_by(
+        &self,
+        title: &[ParagraphSegment],
+        accept: impl Fn(TargetKind) -> bool,
+    ) -> Option<&Target> {
+        let name = normalize(&plain_text(title));
+
+        self.targets
+            .iter()
+            .find(|target| target.name == name && accept(target.kind))
+    }
+
+    /// The number of the footnote with the given id, counting footnotes from 1 in the order
+    /// they appear in.
+    pub(crate) fn footnote_number(&self, id: &str) -> Option<usize> {
+        self.targets
+            .iter()
+            .filter(|target| target.kind == TargetKind::Footnote)
+            .position(|target| target.id == id)
+            .map(|i| i + 1)
+    }
+
+    /// The content of the first footnote with the given title.
+    pub(crate) fn footnote(&self, title: &[ParagraphSegment]) -> Option<&[NorgAST]> {
+        self.footnotes
+            .get(&normalize(&plain_text(title)))
+            .map(Vec::as_slice)
+    }
+
+    /// The link of the first anchor definition with the given text.
+    pub(crate) fn anchor(&self, text: &str) -> Option<&ParagraphSegment> {
+        self.anchors.get(&normalize(text))
+    }
+}
+
+impl Visitor for Targets {
+    fn visit_heading(&mut self, node: &NorgAST) {
+        if let NorgAST::Heading {
+            level, title, span, ..
+        } = node
+        {
+            self.add(TargetKind::Heading(*level), title, *span);
+        }
+
+        walk_heading(self, node)
+    }
+
+    fn visit_rangeable_detached_modifier(&mut self, node: &NorgAST) {
+        if let NorgAST::RangeableDetachedModifier {
+            modifier_type,
+            title,
+            content,
+            span,
+            ..
+        } = node
+        {
+            match modifier_type {
+                RangeableDetachedModifier::Definition => {
+                    self.add(TargetKind::Definition, title, *span)
+                }
+                RangeableDetachedModifier::Footnote => {
+                    self.add(TargetKind::Footnote, title, *span);
+
+                    let content = match content.is_empty() {
+                        true => vec![NorgAST::Paragraph {
+                            content: title.clone(),
+                            span: *span,
+                        }],
+                        false => content.clone(),
+                    };
+                    self.footnotes
+                        .entry(normalize(&plain_text(title)))
+                        .or_insert(content);
+                }
+                RangeableDetachedModifier::Table => {}
+            }
+        }
+
+        walk_rangeable_detached_modifier(self, node)
+    }
+
+    fn visit_inline_link_target(&mut self, segment: &ParagraphSegment) {
+        if let ParagraphSegment::InlineLinkTarget { content, span } = segment {
+            self.add(TargetKind::InlineLinkTarget, content, *span);
+        }
+
+        walk_inline_link_target(self, segment)
+    }
+
+    fn visit_anchor_definition(&mut self, segment: &ParagraphSegment) {
+        if let ParagraphSegment::AnchorDefinition {
+            content, target, ..
+        } = segment
+        {
+            self.anchors
+                .entry(normalize(&plain_text(content)))
+                .or_insert_with(|| target.as_ref().clone());
+        }
+
+        walk_anchor_definition(self, segment)
+    }
+}
+
+/// Splits the nodes of a scope into the groups they are exported in: runs of list items or
+/// quotes of the same kind and level, of definitions and of table cells, and single nodes for
+/// anything else.
+pub(crate) fn groups(nodes: &[NorgAST]) -> impl Iterator<Item = &[NorgAST]> {
+    let mut rest = nodes;
+
+    std::iter::from_fn(move || {
+        let first = rest.first()?;
+        let run = rest
+            .iter()
+            .take_while(|node| groups_with(first, node))
+            .count()
+            .max(1);
+        let (group, remaining) = rest.split_at(run);
+        rest = remaining;
+
+        Some(group)
+    })
+}
+
+/// Whether `node` goes into the same list, definition list or table as `first`.
+pub(crate) fn groups_with(first: &NorgAST, node: &NorgAST) -> bool {
+    match (first, node) {
+        (
+            NorgAST::NestableDetachedModifier {
+                modifier_type,
+                level,
+                ..
+            },
+            NorgAST::NestableDetachedModifier {
+                modifier_type: other_type,
+                level: other_level,
+                ..
+            },
+        ) => modifier_type == other_type && level == other_level,
+        (
+            NorgAST::RangeableDetachedModifier {
+                modifier_type:
+                    modifier_type @ (RangeableDetachedModifier::Definition
+                    | RangeableDetachedModifier::Table),
+                ..
+            },
+            NorgAST::RangeableDetachedModifier {
+                modifier_type: other_type,
+                ..
+            },
+        ) => modifier_type == other_type,
+        _ => false,
+    }
+}
+
+/// Leaves out the whitespace between the extensions and the title of a heading.
+pub(crate) fn trim_start(segments: &[ParagraphSegment]) -> &[ParagraphSegment] {
+    let start = segments
+        .iter()
+        .position(|segment| {
+            !matches!(
+                segment,
+                ParagraphSegment::Token {
+                    token: ParagraphSegmentToken::Whitespace,
+                    ..
+                }
+            )
+        })
+        .unwrap_or(segments.len());
+
+    &segments[start..]
+}
+
+/// The content of inline verbatim, math, variables and free-form attached modifiers, which is
+/// taken as it was written, or `None` for any other segment.
+pub(crate) fn verbatim(segment: &ParagraphSegment) -> Option<String> {
+    let content = match segment {
+        ParagraphSegment::InlineVerbatim { content, .. } => return Some(raw(content)),
+        ParagraphSegment::AttachedModifier {
+            modifier_type: '`' | '$' | '&',
+            content,
+            ..
+        }
+        | ParagraphSegment::FreeFormAttachedModifier { content, .. } => content,
+        _ => return None,
+    };
+
+    Some(raw(content.iter().filter_map(|segment| match segment {
+        ParagraphSegment::Token { token, .. } => Some(token),
+        _ => None,
+    })))
+}
+
+/// The text of tokens as written. The parser keeps verbatim content as a single text token, but
+/// trees built by hand can split it up.
+pub(crate) fn raw<'a>(tokens: impl IntoIterator<Item = &'a ParagraphSegmentToken>) -> String {
+    tokens.into_iter().map(ToString::to_string).collect()
+}
+
+/// Turns a title into an id: lowercase letters and digits, with everything else collapsed into
+/// single dashes.
+pub(crate) fn slug(text: &str) -> String {
+    let mut slug = String::new();
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    match slug.trim_end_matches('-') {
+        "" => "section".to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+/// Link targets are matched ignoring case and differences in whitespace.
+pub(crate) fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::proptest;
+
+    use crate::export::slug;
+
+    proptest! {
+        #[test]
+        fn slugs_are_plain(text in r"\PC*") {
+            let slug = slug(&text);
+
+            assert!(!slug.is_empty());
+            assert!(!slug.starts_with('-') && !slug.ends_with('-') && !slug.contains("--"));
+            assert!(slug.chars().all(|c| c == '-' || c.is_alphanumeric()));
+        }
+    }
+}
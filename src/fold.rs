@@ -0,0 +1,479 @@
+//! Rebuilding the tree returned by [`crate::parse_tree`].
+//!
+//! A [`Fold`] takes nodes by value and returns their replacements, with a method per kind of
+//! node. The default methods rebuild each node from its folded children through the free
+//! function of the same name, so an implementation only overrides the nodes it changes.
+
+use crate::{DetachedModifierExtension, LinkTarget, NorgAST, ParagraphSegment};
+
+/// Turns a tree into a new one, node by node.
+pub trait Fold {
+    /// Called for every node, dispatching to the method for its kind.
+    fn fold_node(&mut self, node: NorgAST) -> NorgAST {
+        fold_node(self, node)
+    }
+
+    fn fold_paragraph(&mut self, node: NorgAST) -> NorgAST {
+        fold_paragraph(self, node)
+    }
+
+    /// Called for list items and quotes.
+    fn fold_nestable_detached_modifier(&mut self, node: NorgAST) -> NorgAST {
+        fold_nestable_detached_modifier(self, node)
+    }
+
+    /// Called for definitions, footnotes and table cells.
+    fn fold_rangeable_detached_modifier(&mut self, node: NorgAST) -> NorgAST {
+        fold_rangeable_detached_modifier(self, node)
+    }
+
+    fn fold_heading(&mut self, node: NorgAST) -> NorgAST {
+        fold_heading(self, node)
+    }
+
+    fn fold_carryover_tag(&mut self, node: NorgAST) -> NorgAST {
+        fold_carryover_tag(self, node)
+    }
+
+    fn fold_verbatim_ranged_tag(&mut self, node: NorgAST) -> NorgAST {
+        node
+    }
+
+    fn fold_ranged_tag(&mut self, node: NorgAST) -> NorgAST {
+        fold_ranged_tag(self, node)
+    }
+
+    fn fold_infirm_tag(&mut self, node: NorgAST) -> NorgAST {
+        node
+    }
+
+    fn fold_delimiting_modifier(&mut self, node: NorgAST) -> NorgAST {
+        node
+    }
+
+    fn fold_error(&mut self, node: NorgAST) -> NorgAST {
+        node
+    }
+
+    /// Called for the extensions of headings and detached modifiers, e.g. their todo status.
+    fn fold_extension(
+        &mut self,
+        extension: DetachedModifierExtension,
+    ) -> DetachedModifierExtension {
+        extension
+    }
+
+    /// Called for every paragraph segment, dispatching to the method for its kind.
+    fn fold_segment(&mut self, segment: ParagraphSegment) -> ParagraphSegment {
+        fold_segment(self, segment)
+    }
+
+    fn fold_token(&mut self, segment: ParagraphSegment) -> ParagraphSegment {
+        segment
+    }
+
+    /// Called for both regular and free-form attached modifiers.
+    fn fold_attached_modifier(&mut self, segment: ParagraphSegment) -> ParagraphSegment {
+        fold_attached_modifier(self, segment)
+    }
+
+    fn fold_link(&mut self, segment: ParagraphSegment) -> ParagraphSegment {
+        fold_link(self, segment)
+    }
+
+    fn fold_link_target(&mut self, target: LinkTarget) -> LinkTarget {
+        fold_link_target(self, target)
+    }
+
+    fn fold_anchor_definition(&mut self, segment: ParagraphSegment) -> ParagraphSegment {
+        fold_anchor_definition(self, segment)
+    }
+
+    fn fold_anchor(&mut self, segment: ParagraphSegment) -> ParagraphSegment {
+        fold_anchor(self, segment)
+    }
+
+    fn fold_inline_link_target(&mut self, segment: ParagraphSegment) -> ParagraphSegment {
+        fold_inline_link_target(self, segment)
+    }
+
+    fn fold_inline_verbatim(&mut self, segment: ParagraphSegment) -> ParagraphSegment {
+        segment
+    }
+}
+
+/// Folds all of the given nodes in order.
+pub fn fold_nodes<F: Fold + ?Sized>(folder: &mut F, nodes: Vec<NorgAST>) -> Vec<NorgAST> {
+    nodes
+        .into_iter()
+        .map(|node| folder.fold_node(node))
+        .collect()
+}
+
+/// Folds all of the given segments in order.
+pub fn fold_segments<F: Fold + ?Sized>(
+    folder: &mut F,
+    segments: Vec<ParagraphSegment>,
+) -> Vec<ParagraphSegment> {
+    segments
+        .into_iter()
+        .map(|segment| folder.fold_segment(segment))
+        .collect()
+}
+
+fn fold_extensions<F: Fold + ?Sized>(
+    folder: &mut F,
+    extensions: Vec<DetachedModifierExtension>,
+) -> Vec<DetachedModifierExtension> {
+    extensions
+        .into_iter()
+        .map(|extension| folder.fold_extension(extension))
+        .collect()
+}
+
+pub fn fold_node<F: Fold + ?Sized>(folder: &mut F, node: NorgAST) -> NorgAST {
+    match node {
+        NorgAST::Paragraph { .. } => folder.fold_paragraph(node),
+        NorgAST::NestableDetachedModifier { .. } => folder.fold_nestable_detached_modifier(node),
+        NorgAST::RangeableDetachedModifier { .. } => folder.fold_rangeable_detached_modifier(node),
+        NorgAST::Heading { .. } => folder.fold_heading(node),
+        NorgAST::CarryoverTag { .. } => folder.fold_carryover_tag(node),
+        NorgAST::VerbatimRangedTag { .. } => folder.fold_verbatim_ranged_tag(node),
+        NorgAST::RangedTag { .. } => folder.fold_ranged_tag(node),
+        NorgAST::InfirmTag { .. } => folder.fold_infirm_tag(node),
+        NorgAST::DelimitingModifier { .. } => folder.fold_delimiting_modifier(node),
+        NorgAST::Error { .. } => folder.fold_error(node),
+    }
+}
+
+pub fn fold_paragraph<F: Fold + ?Sized>(folder: &mut F, node: NorgAST) -> NorgAST {
+    match node {
+        NorgAST::Paragraph { content, span } => NorgAST::Paragraph {
+            content: fold_segments(folder, content),
+            span,
+        },
+        node => node,
+    }
+}
+
+pub fn fold_nestable_detached_modifier<F: Fold + ?Sized>(folder: &mut F, node: NorgAST) -> NorgAST {
+    match node {
+        NorgAST::NestableDetachedModifier {
+            modifier_type,
+            level,
+            extensions,
+            segment,
+            text,
+            content,
+            span,
+        } => NorgAST::NestableDetachedModifier {
+            modifier_type,
+            level,
+            extensions: fold_extensions(folder, extensions),
+            segment,
+            text: Box::new(folder.fold_node(*text)),
+            content: fold_nodes(folder, content),
+            span,
+        },
+        node => node,
+    }
+}
+
+pub fn fold_rangeable_detached_modifier<F: Fold + ?Sized>(
+    folder: &mut F,
+    node: NorgAST,
+) -> NorgAST {
+    match node {
+        NorgAST::RangeableDetachedModifier {
+            modifier_type,
+            title,
+            extensions,
+            segment,
+            content,
+            span,
+        } => NorgAST::RangeableDetachedModifier {
+            modifier_type,
+            title: fold_segments(folder, title),
+            extensions: fold_extensions(folder, extensions),
+            segment,
+            content: fold_nodes(folder, content),
+            span,
+        },
+        node => node,
+    }
+}
+
+pub fn fold_heading<F: Fold + ?Sized>(folder: &mut F, node: NorgAST) -> NorgAST {
+    match node {
+        NorgAST::Heading {
+            level,
+            title,
+            extensions,
+            content,
+            span,
+        } => NorgAST::Heading {
+            level,
+            title: fold_segments(folder, title),
+            extensions: fold_extensions(folder, extensions),
+            content: fold_nodes(folder, content),
+            span,
+        },
+        node => node,
+    }
+}
+
+pub fn fold_carryover_tag<F: Fold + ?Sized>(folder: &mut F, node: NorgAST) -> NorgAST {
+    match node {
+        NorgAST::CarryoverTag {
+            tag_type,
+            name,
+            parameters,
+            next_object,
+            span,
+        } => NorgAST::CarryoverTag {
+            tag_type,
+            name,
+            parameters,
+            next_object: Box::new(folder.fold_node(*next_object)),
+            span,
+        },
+        node => node,
+    }
+}
+
+pub fn fold_ranged_tag<F: Fold + ?Sized>(folder: &mut F, node: NorgAST) -> NorgAST {
+    match node {
+        NorgAST::RangedTag {
+            name,
+            parameters,
+            content,
+            span,
+        } => NorgAST::RangedTag {
+            name,
+            parameters,
+            content: fold_nodes(folder, content),
+            span,
+        },
+        node => node,
+    }
+}
+
+pub fn fold_segment<F: Fold + ?Sized>(
+    folder: &mut F,
+    segment: ParagraphSegment,
+) -> ParagraphSegment {
+    match segment {
+        ParagraphSegment::Token { .. } => folder.fold_token(segment),
+        ParagraphSegment::AttachedModifier { .. }
+        | ParagraphSegment::FreeFormAttachedModifier { .. } => {
+            folder.fold_attached_modifier(segment)
+        }
+        ParagraphSegment::Link { .. } => folder.fold_link(segment),
+        ParagraphSegment::AnchorDefinition { .. } => folder.fold_anchor_definition(segment),
+        ParagraphSegment::Anchor { .. } => folder.fold_anchor(segment),
+        ParagraphSegment::InlineLinkTarget { .. } => folder.fold_inline_link_target(segment),
+        ParagraphSegment::InlineVerbatim { .. } => folder.fold_inline_verbatim(segment),
+        // Only used while parsing, these never end up in the tree.
+        ParagraphSegment::AttachedModifierOpener(_)
+        | ParagraphSegment::AttachedModifierOpenerFail(_)
+        | ParagraphSegment::AttachedModifierCloserCandidate(_)
+        | ParagraphSegment::AttachedModifierCloser(_)
+        | ParagraphSegment::AttachedModifierCandidate { .. } => segment,
+    }
+}
+
+pub fn fold_attached_modifier<F: Fold + ?Sized>(
+    folder: &mut F,
+    segment: ParagraphSegment,
+) -> ParagraphSegment {
+    match segment {
+        ParagraphSegment::AttachedModifier {
+            modifier_type,
+            content,
+            span,
+        } => ParagraphSegment::AttachedModifier {
+            modifier_type,
+            content: fold_segments(folder, content),
+            span,
+        },
+        ParagraphSegment::FreeFormAttachedModifier {
+            modifier_type,
+            content,
+            span,
+        } => ParagraphSegment::FreeFormAttachedModifier {
+            modifier_type,
+            content: fold_segments(folder, content),
+            span,
+        },
+        segment => segment,
+    }
+}
+
+pub fn fold_link<F: Fold + ?Sized>(folder: &mut F, segment: ParagraphSegment) -> ParagraphSegment {
+    match segment {
+        ParagraphSegment::Link {
+            filepath,
+            targets,
+            description,
+            span,
+        } => ParagraphSegment::Link {
+            filepath,
+            targets: targets
+                .into_iter()
+                .map(|target| folder.fold_link_target(target))
+                .collect(),
+            description: description.map(|description| fold_segments(folder, description)),
+            span,
+        },
+        segment => segment,
+    }
+}
+
+pub fn fold_link_target<F: Fold + ?Sized>(folder: &mut F, target: LinkTarget) -> LinkTarget {
+    match target {
+        LinkTarget::Heading { level, title, span } => LinkTarget::Heading {
+            level,
+            title: fold_segments(folder, title),
+            span,
+        },
+        LinkTarget::Footnote { title, span } => LinkTarget::Footnote {
+            title: fold_segments(folder, title),
+            span,
+        },
+        LinkTarget::Definition { title, span } => LinkTarget::Definition {
+            title: fold_segments(folder, title),
+            span,
+        },
+        LinkTarget::Generic { title, span } => LinkTarget::Generic {
+            title: fold_segments(folder, title),
+            span,
+        },
+        LinkTarget::Wiki { title, span } => LinkTarget::Wiki {
+            title: fold_segments(folder, title),
+            span,
+        },
+        LinkTarget::Extendable { title, span } => LinkTarget::Extendable {
+            title: fold_segments(folder, title),
+            span,
+        },
+        LinkTarget::LineNumber { .. }
+        | LinkTarget::Path { .. }
+        | LinkTarget::Url { .. }
+        | LinkTarget::Timestamp { .. } => target,
+    }
+}
+
+pub fn fold_anchor_definition<F: Fold + ?Sized>(
+    folder: &mut F,
+    segment: ParagraphSegment,
+) -> ParagraphSegment {
+    match segment {
+        ParagraphSegment::AnchorDefinition {
+            content,
+            target,
+            span,
+        } => ParagraphSegment::AnchorDefinition {
+            content: fold_segments(folder, content),
+            target: Box::new(folder.fold_segment(*target)),
+            span,
+        },
+        segment => segment,
+    }
+}
+
+pub fn fold_anchor<F: Fold + ?Sized>(
+    folder: &mut F,
+    segment: ParagraphSegment,
+) -> ParagraphSegment {
+    match segment {
+        ParagraphSegment::Anchor {
+            content,
+            description,
+            span,
+        } => ParagraphSegment::Anchor {
+            content: fold_segments(folder, content),
+            description: description.map(|description| fold_segments(folder, description)),
+            span,
+        },
+        segment => segment,
+    }
+}
+
+pub fn fold_inline_link_target<F: Fold + ?Sized>(
+    folder: &mut F,
+    segment: ParagraphSegment,
+) -> ParagraphSegment {
+    match segment {
+        ParagraphSegment::InlineLinkTarget { content, span } => {
+            ParagraphSegment::InlineLinkTarget {
+                content: fold_segments(folder, content),
+                span,
+            }
+        }
+        segment => segment,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+
+    use crate::{
+        fold::{fold_attached_modifier, fold_nodes, Fold},
+        parse_tree, DetachedModifierExtension, NorgAST, ParagraphSegment, TodoStatus,
+    };
+
+    /// Turns bold text into italics, marks every todo item as done and drops verbatim blocks.
+    struct Rewrite;
+
+    impl Fold for Rewrite {
+        fn fold_attached_modifier(&mut self, segment: ParagraphSegment) -> ParagraphSegment {
+            match fold_attached_modifier(self, segment) {
+                ParagraphSegment::AttachedModifier {
+                    modifier_type: '*',
+                    content,
+                    span,
+                } => ParagraphSegment::AttachedModifier {
+                    modifier_type: '/',
+                    content,
+                    span,
+                },
+                segment => segment,
+            }
+        }
+
+        fn fold_extension(
+            &mut self,
+            extension: DetachedModifierExtension,
+        ) -> DetachedModifierExtension {
+            match extension {
+                DetachedModifierExtension::Todo(_) => {
+                    DetachedModifierExtension::Todo(TodoStatus::Done)
+                }
+                extension => extension,
+            }
+        }
+
+        fn fold_verbatim_ranged_tag(&mut self, node: NorgAST) -> NorgAST {
+            NorgAST::Paragraph {
+                content: vec![],
+                span: node.span(),
+            }
+        }
+    }
+
+    #[test]
+    fn fold() {
+        let tree = parse_tree(
+            "* ( ) A *bold* heading
+  - (!) an item with {* a *bold* link}
+  @code
+  print()
+  @end
+",
+        )
+        .unwrap();
+
+        assert_yaml_snapshot!(fold_nodes(&mut Rewrite, tree));
+    }
+}
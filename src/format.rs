@@ -0,0 +1,1288 @@
+//! Turning a tree back into Norg text.
+//!
+//! The output is canonical: nested content is indented to line up with the text of its parent,
+//! detached modifier extensions are written as `(x|# A|< Feb 1)`, paragraphs are wrapped and
+//! blank lines separate blocks. Verbatim ranged tags keep their content as it is.
+//!
+//! Parsing the output gives back the same tree, apart from spans. Some of the input is lost
+//! while parsing and comes out in its canonical form instead: ranged tags are written with `|`,
+//! and definitions, footnotes and table cells only use the ranged `$$` form when they do not
+//! hold a single paragraph. The delimiting modifiers which close headings and indent segments
+//! are not part of the tree, so they are written wherever they are needed.
+
+use crate::{
+    stage_2::ParagraphSegmentToken, CarryoverTag, ContentSegment, DelimitingModifier,
+    DetachedModifierExtension, LinkTarget, NestableDetachedModifier, NorgAST, ParagraphSegment,
+    RangeableDetachedModifier, TodoStatus,
+};
+
+/// Options for [`format_with`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The width paragraphs are wrapped at, including their indentation. Words longer than
+    /// that are never split.
+    pub width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self { width: 80 }
+    }
+}
+
+/// Formats a tree with the default options.
+pub fn format(tree: &[NorgAST]) -> String {
+    format_with(tree, &FormatOptions::default())
+}
+
+/// Formats a tree as returned by [`crate::parse_tree`].
+pub fn format_with(tree: &[NorgAST], options: &FormatOptions) -> String {
+    let mut printer = Printer {
+        options,
+        output: String::new(),
+        previous: None,
+        headings: Vec::new(),
+        heading_level: 0,
+        unterminated: false,
+    };
+    printer.nodes(tree, 0, 0);
+    if printer.unterminated {
+        printer.output.pop();
+    }
+
+    printer.output
+}
+
+/// What the last line written belongs to, which decides whether a blank line has to follow.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Previous {
+    /// The first line of a node whose content comes next, e.g. a heading title.
+    Opener,
+    /// The text of a list item or quote.
+    Nestable,
+    Block,
+}
+
+struct Printer<'a> {
+    options: &'a FormatOptions,
+    output: String,
+    previous: Option<Previous>,
+    /// The levels of the headings whose content is being written, mirroring how `stage_4`
+    /// decides where headings end.
+    headings: Vec<u16>,
+    /// The level which `stage_4` compares against the open headings when it finds a `---`: the
+    /// level of the last heading, minus one for every `---` after it.
+    heading_level: i16,
+    /// Whether the last line is a paragraph which would be parsed as a tag if the line was
+    /// ended, so that the output has to stop right after it.
+    unterminated: bool,
+}
+
+impl Printer<'_> {
+    /// Writes a list of sibling nodes. `depth` is the number of open headings which contain
+    /// them.
+    fn nodes(&mut self, nodes: &[NorgAST], indent: usize, depth: usize) {
+        for node in nodes {
+            self.close_headings(node, indent, depth);
+            self.node(node, indent);
+        }
+    }
+
+    /// Writes nodes which are parsed on their own, like the content of a ranged tag, where no
+    /// heading from the outside can end.
+    fn scope(&mut self, nodes: &[NorgAST], indent: usize) {
+        let headings = std::mem::take(&mut self.headings);
+        let heading_level = self.heading_level;
+        self.nodes(nodes, indent, 0);
+        self.headings = headings;
+        self.heading_level = heading_level;
+    }
+
+    /// Ends the headings opened by the previous siblings of `node`, unless `node` is a heading
+    /// which ends them itself.
+    fn close_headings(&mut self, node: &NorgAST, indent: usize, depth: usize) {
+        let level = match tagged(node) {
+            NorgAST::Heading { level, .. } => Some(*level),
+            _ => None,
+        };
+
+        while self.headings.len() > depth {
+            if level.is_some_and(|level| self.headings[depth..].iter().all(|open| *open >= level)) {
+                break;
+            }
+
+            if depth == 0 {
+                self.block(indent, "===", Previous::Block);
+                self.headings.clear();
+            } else {
+                self.block(indent, "---", Previous::Block);
+                self.weak_delimiter();
+            }
+        }
+    }
+
+    fn weak_delimiter(&mut self) {
+        self.heading_level -= 1;
+
+        let level = self.heading_level;
+        self.headings.retain(|open| *open as i16 <= level);
+    }
+
+    /// Starts a new line for a block, after a blank line where one is needed.
+    fn start_block(&mut self, indent: usize, nestable: bool) {
+        let blank = match self.previous {
+            None | Some(Previous::Opener) => false,
+            Some(Previous::Nestable) => !nestable,
+            Some(Previous::Block) => true,
+        };
+
+        if blank {
+            self.output.push('\n');
+        }
+        self.output.push_str(&" ".repeat(indent));
+    }
+
+    /// Writes a block made up of a single line.
+    fn block(&mut self, indent: usize, line: &str, previous: Previous) {
+        self.start_block(indent, false);
+        self.line(line, previous);
+    }
+
+    /// Writes the line of a tag whose content comes next.
+    fn tag_line(&mut self, indent: usize, line: &str) {
+        self.block(indent, line, Previous::Opener);
+        // Keeps a `~` at the end from continuing the line with the content.
+        if line.ends_with('~') {
+            self.output.push('\n');
+        }
+    }
+
+    /// Ends the current line.
+    fn line(&mut self, line: &str, previous: Previous) {
+        self.output.push_str(line);
+        self.output.push('\n');
+        self.previous = Some(previous);
+        self.unterminated = false;
+    }
+
+    fn node(&mut self, node: &NorgAST, indent: usize) {
+        match node {
+            NorgAST::Paragraph { content, .. } => {
+                self.start_block(indent, false);
+                self.paragraph(content, indent, Previous::Block);
+            }
+            NorgAST::Heading {
+                level,
+                title,
+                extensions,
+                content,
+                ..
+            } => {
+                let level = *level;
+                self.headings.retain(|open| *open < level);
+                self.headings.push(level);
+                self.heading_level = level as i16;
+
+                self.start_block(indent, false);
+                let prefix = format!(
+                    "{} {}",
+                    "*".repeat(level as usize),
+                    extension_text(extensions)
+                );
+                self.title(&prefix, title, Previous::Opener);
+
+                self.nodes(content, indent + level as usize + 1, self.headings.len());
+            }
+            NorgAST::NestableDetachedModifier {
+                modifier_type,
+                level,
+                extensions,
+                segment,
+                text,
+                content,
+                ..
+            } => {
+                let marker = match modifier_type {
+                    NestableDetachedModifier::Quote => ">",
+                    NestableDetachedModifier::UnorderedList => "-",
+                    NestableDetachedModifier::OrderedList => "~",
+                };
+                let extensions = extension_text(extensions);
+                let prefix = if extensions.is_empty() {
+                    format!("{} ", marker.repeat(*level as usize))
+                } else {
+                    format!("{} {extensions} ", marker.repeat(*level as usize))
+                };
+                let content_indent = indent + *level as usize + 1;
+
+                self.start_block(indent, true);
+                self.output.push_str(&prefix);
+
+                let segments = match text.as_ref() {
+                    NorgAST::Paragraph { content, .. } => content.as_slice(),
+                    _ => &[],
+                };
+                let (chunks, unterminated) = Chunks::unambiguous(segments, |link_punctuation| {
+                    Chunks::nestable(segments, *segment, extensions.is_empty(), link_punctuation)
+                });
+                self.wrap(chunks, indent + prefix.chars().count(), content_indent);
+                self.line(
+                    "",
+                    match segment {
+                        Some(_) => Previous::Opener,
+                        None => Previous::Nestable,
+                    },
+                );
+                self.unterminated = unterminated;
+
+                self.segment_content(*segment, content, content_indent);
+            }
+            NorgAST::RangeableDetachedModifier {
+                modifier_type,
+                title,
+                extensions,
+                segment,
+                content,
+                ..
+            } => {
+                let marker = match modifier_type {
+                    RangeableDetachedModifier::Definition => "$",
+                    RangeableDetachedModifier::Footnote => "^",
+                    RangeableDetachedModifier::Table => ":",
+                };
+                let ranged =
+                    segment.is_none() && !matches!(content.as_slice(), [NorgAST::Paragraph { .. }]);
+                let marker = if ranged {
+                    marker.repeat(2)
+                } else {
+                    marker.to_string()
+                };
+                let content_indent = indent + marker.len() + 1;
+
+                self.start_block(indent, false);
+                let prefix = format!("{marker} {}", extension_text(extensions));
+                let title = [title.as_slice(), &segment_marker_segment(*segment)].concat();
+                self.title(&prefix, &title, Previous::Opener);
+
+                if segment.is_some() {
+                    self.segment_content(*segment, content, content_indent);
+                } else {
+                    self.scope(content, content_indent);
+                }
+
+                if ranged {
+                    self.output.push_str(&" ".repeat(indent));
+                    self.line(&marker, Previous::Block);
+                }
+            }
+            NorgAST::CarryoverTag {
+                tag_type,
+                name,
+                parameters,
+                next_object,
+                ..
+            } => {
+                let marker = match tag_type {
+                    CarryoverTag::Attribute => '+',
+                    CarryoverTag::Macro => '#',
+                };
+
+                self.tag_line(indent, &tag(marker, name, parameters));
+                self.node(next_object, indent);
+            }
+            NorgAST::VerbatimRangedTag {
+                name,
+                parameters,
+                content,
+                ..
+            } => {
+                // Leading blank lines only survive after trailing whitespace on the tag line.
+                let mut header = tag('@', name, parameters);
+                let content = match content.strip_prefix('\n') {
+                    Some(rest) if !parameters.is_empty() => {
+                        header.push(' ');
+                        rest
+                    }
+                    _ => content,
+                };
+                self.tag_line(indent, &header);
+                // The parser removes the indentation all lines with text share, unless a blank
+                // line after the first of them holds whitespace the lexer keeps as text, like a
+                // vertical tab. It has to differ from the indentation of that first line.
+                let mut breaker = textwrap::dedent(content) != content;
+                let first_line = content.lines().find(|line| !line.trim().is_empty());
+                let breaker_char = match first_line.is_some_and(|line| line.starts_with('\u{b}')) {
+                    true => '\u{c}',
+                    false => '\u{b}',
+                };
+                let mut seen_text = false;
+                for line in content.split_inclusive('\n') {
+                    if line.trim().is_empty() {
+                        if breaker && seen_text {
+                            self.output.push_str(&" ".repeat(indent));
+                            self.output.push(breaker_char);
+                            breaker = false;
+                        }
+                        self.output.push_str(line.trim_start_matches([' ', '\t']));
+                    } else {
+                        seen_text = true;
+                        self.output.push_str(&" ".repeat(indent));
+                        self.output.push_str(line);
+                    }
+                }
+                self.output.push_str(&" ".repeat(indent));
+                self.line("@end", Previous::Block);
+            }
+            NorgAST::RangedTag {
+                name,
+                parameters,
+                content,
+                ..
+            } => {
+                self.tag_line(indent, &tag('|', name, parameters));
+                self.scope(content, indent);
+                self.output.push_str(&" ".repeat(indent));
+                self.line("|end", Previous::Block);
+            }
+            NorgAST::InfirmTag {
+                name, parameters, ..
+            } => self.block(indent, &tag('.', name, parameters), Previous::Block),
+            NorgAST::DelimitingModifier { modifier_type, .. } => {
+                let line = match modifier_type {
+                    DelimitingModifier::Weak => {
+                        self.weak_delimiter();
+                        "---"
+                    }
+                    DelimitingModifier::Strong => {
+                        self.headings.clear();
+                        "==="
+                    }
+                    DelimitingModifier::HorizontalRule => "___",
+                };
+
+                self.block(indent, line, Previous::Block);
+            }
+            NorgAST::Error { content, .. } => {
+                self.start_block(0, false);
+                self.line(content.trim_end_matches('\n'), Previous::Block);
+            }
+        }
+    }
+
+    /// Writes the content of a list item, quote or rangeable detached modifier. With a slide, the
+    /// first node is the one the slide applies to; with an indent segment, all of the content is
+    /// written within the segment.
+    fn segment_content(
+        &mut self,
+        segment: Option<ContentSegment>,
+        content: &[NorgAST],
+        indent: usize,
+    ) {
+        match segment {
+            Some(ContentSegment::Slide) => {
+                if let Some((first, rest)) = content.split_first() {
+                    self.scope(std::slice::from_ref(first), indent);
+                    self.scope(rest, indent);
+                }
+            }
+            Some(ContentSegment::IndentSegment) => {
+                self.scope(content, indent);
+                self.block(indent, "---", Previous::Block);
+            }
+            None => self.scope(content, indent),
+        }
+    }
+
+    /// Writes a title after `prefix`, on a single line apart from line continuations.
+    fn title(&mut self, prefix: &str, title: &[ParagraphSegment], previous: Previous) {
+        let indent = self.output.len() - self.output.rfind('\n').map_or(0, |i| i + 1);
+
+        let (chunks, _) = Chunks::unambiguous(title, |link_punctuation| {
+            let mut chunks = Chunks {
+                link_punctuation,
+                ..Default::default()
+            };
+            chunks.segments(title, false);
+            (chunks, false)
+        });
+        self.output.push_str(prefix);
+        self.wrap(
+            chunks,
+            indent + prefix.chars().count(),
+            indent + prefix.chars().count(),
+        );
+        self.line("", previous);
+    }
+
+    fn paragraph(&mut self, content: &[ParagraphSegment], indent: usize, previous: Previous) {
+        let (chunks, unterminated) = Chunks::unambiguous(content, |link_punctuation| {
+            Chunks::paragraph(content, link_punctuation)
+        });
+        self.wrap(chunks, indent, indent);
+        self.line("", previous);
+        // Whitespace at the end of a paragraph comes from a line with only whitespace after it.
+        if matches!(
+            content.last(),
+            Some(ParagraphSegment::Token {
+                token: ParagraphSegmentToken::Whitespace,
+                ..
+            })
+        ) {
+            self.output.push_str(" \n");
+        }
+        self.unterminated = unterminated;
+    }
+
+    /// Lays out chunks starting at column `column`, with the lines after the first one indented
+    /// by `indent`.
+    fn wrap(&mut self, chunks: Chunks, mut column: usize, indent: usize) {
+        let chunks = chunks.chunks;
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            match chunk {
+                Chunk::Text(text) => {
+                    self.output.push_str(text);
+                    column += text.chars().count();
+                }
+                Chunk::Break => {
+                    let next: usize = chunks[i + 1..]
+                        .iter()
+                        .take_while(|chunk| matches!(chunk, Chunk::Text(_)))
+                        .map(|chunk| match chunk {
+                            Chunk::Text(text) => text.chars().count(),
+                            _ => 0,
+                        })
+                        .sum();
+
+                    if column > indent && column + 1 + next > self.options.width {
+                        self.newline(indent);
+                        column = indent;
+                    } else {
+                        self.output.push(' ');
+                        column += 1;
+                    }
+                }
+                Chunk::Newline => {
+                    self.newline(indent);
+                    column = indent;
+                }
+            }
+        }
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.output.push('\n');
+        self.output.push_str(&" ".repeat(indent));
+    }
+}
+
+/// Looks through carryover tags to the node they apply to.
+fn tagged(node: &NorgAST) -> &NorgAST {
+    match node {
+        NorgAST::CarryoverTag { next_object, .. } => tagged(next_object),
+        node => node,
+    }
+}
+
+fn tag(marker: char, name: &[String], parameters: &[String]) -> String {
+    std::iter::once(format!("{marker}{}", name.join(".")))
+        .chain(parameters.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The marker of an indent segment or slide, as segments to append to a title, e.g. ` :`.
+fn segment_marker_segment(segment: Option<ContentSegment>) -> Vec<ParagraphSegment> {
+    let colons = match segment {
+        Some(ContentSegment::Slide) => 1,
+        Some(ContentSegment::IndentSegment) => 2,
+        None => return vec![],
+    };
+
+    std::iter::once(ParagraphSegmentToken::Whitespace)
+        .chain(std::iter::repeat(ParagraphSegmentToken::Special(':')).take(colons))
+        .map(|token| ParagraphSegment::Token {
+            token,
+            span: Default::default(),
+        })
+        .collect()
+}
+
+/// A piece of inline content, as laid out by [`Printer::wrap`].
+#[derive(Debug)]
+enum Chunk {
+    Text(String),
+    /// Whitespace which may be turned into a line break.
+    Break,
+    /// A line break which has to be kept to get the same tokens back.
+    Newline,
+}
+
+#[derive(Default)]
+struct Chunks {
+    chunks: Vec<Chunk>,
+    /// Whether to put a link modifier between punctuation and the start of an attached modifier,
+    /// and between its end and punctuation. Whether the parser needs one depends on what comes
+    /// before, so all ways are tried.
+    link_punctuation: (bool, bool),
+    /// Whether an attached modifier is followed by punctuation.
+    ambiguous: bool,
+}
+
+impl Chunks {
+    /// Lays out the content of a paragraph, also returning whether it can only be written at the
+    /// very end of the input.
+    fn paragraph(content: &[ParagraphSegment], link_punctuation: (bool, bool)) -> (Self, bool) {
+        let mut chunks = Self {
+            link_punctuation,
+            ..Default::default()
+        };
+        chunks.segments(content, true);
+        // A `\\` at the very end of the input is the only one which does not escape anything.
+        let unterminated = chunks.protect_lines()
+            || matches!(
+                content.last(),
+                Some(ParagraphSegment::Token {
+                    token: ParagraphSegmentToken::Special('\\'),
+                    ..
+                })
+            );
+
+        (chunks, unterminated)
+    }
+
+    /// Lays out the text of a nestable detached modifier followed by the marker of its
+    /// segment, also returning whether it can only be written at the very end of the input.
+    fn nestable(
+        segments: &[ParagraphSegment],
+        segment: Option<ContentSegment>,
+        without_extensions: bool,
+        link_punctuation: (bool, bool),
+    ) -> (Self, bool) {
+        let mut chunks = Self {
+            link_punctuation,
+            ..Default::default()
+        };
+
+        // Text ending in ` :` would be taken for a slide, unless the colon is on a line of its
+        // own.
+        let (segments, colon) = match segments {
+            [rest @ .., whitespace, ParagraphSegment::Token {
+                token: ParagraphSegmentToken::Special(':'),
+                ..
+            }] if segment.is_none() && is_whitespace(whitespace) => (rest, true),
+            segments => (segments, false),
+        };
+
+        // Text starting like `( )` would be taken for extensions, unless the first line ends
+        // before the closing parenthesis.
+        let opening = matches!(
+            segments.first(),
+            Some(ParagraphSegment::Token {
+                token: ParagraphSegmentToken::Special('('),
+                ..
+            })
+        );
+        let split = segments.iter().position(is_whitespace).filter(|&i| {
+            opening && without_extensions && !segments.get(i + 1).is_some_and(is_whitespace)
+        });
+        let segments = match split {
+            Some(i) => {
+                chunks.segments(&segments[..i], true);
+                chunks.push(Chunk::Newline);
+                &segments[i + 1..]
+            }
+            None => segments,
+        };
+
+        chunks.segments(segments, true);
+        if colon {
+            chunks.push(Chunk::Newline);
+            chunks.text(":");
+        }
+        chunks.segments(&segment_marker_segment(segment), false);
+        let unterminated = chunks.protect_lines();
+
+        (chunks, unterminated)
+    }
+
+    /// Lays out `content` with the first placement of link modifiers next to punctuation which
+    /// gives it back, or with all of them if none does.
+    fn unambiguous(
+        content: &[ParagraphSegment],
+        lay_out: impl Fn((bool, bool)) -> (Self, bool),
+    ) -> (Self, bool) {
+        let (mut chunks, mut unterminated) = lay_out((false, false));
+
+        for link_punctuation in [(false, true), (true, false), (true, true)] {
+            if !chunks.ambiguous || chunks.parses_to(content, unterminated) {
+                break;
+            }
+            (chunks, unterminated) = lay_out(link_punctuation);
+        }
+
+        (chunks, unterminated)
+    }
+
+    /// Whether parsing the chunks on their own gives back a paragraph with this content.
+    fn parses_to(&self, content: &[ParagraphSegment], unterminated: bool) -> bool {
+        let mut text: String = self
+            .chunks
+            .iter()
+            .map(|chunk| match chunk {
+                Chunk::Text(text) => text.as_str(),
+                Chunk::Break => " ",
+                Chunk::Newline => "\n",
+            })
+            .collect();
+        if !unterminated {
+            text.push('\n');
+        }
+
+        let without_spans = |content: &[ParagraphSegment]| {
+            let mut content = content.to_vec();
+            content
+                .iter_mut()
+                .for_each(|segment| segment.spans_mut(&mut |span| *span = Default::default()));
+            content
+        };
+
+        match crate::parse_tree(&text).as_deref() {
+            Ok(
+                [NorgAST::Paragraph {
+                    content: parsed, ..
+                }],
+            ) => without_spans(parsed) == without_spans(content),
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, chunk: Chunk) {
+        match (self.chunks.last_mut(), chunk) {
+            (Some(Chunk::Text(text)), Chunk::Text(next)) => text.push_str(&next),
+            (_, chunk) => self.chunks.push(chunk),
+        }
+    }
+
+    fn text(&mut self, text: impl Into<String>) {
+        self.push(Chunk::Text(text.into()));
+    }
+
+    /// Writes a link modifier between an attached modifier and a segment next to it, which lets
+    /// the modifier start or end within a word. Next to punctuation or without a neighbour,
+    /// whether one is needed depends on the rest of the paragraph, so it is only written when
+    /// `punctuation` is set. At the start of a line, it keeps verbatim text like `$$` from being
+    /// taken for a detached modifier.
+    fn link_modifier(&mut self, neighbour: Option<&ParagraphSegment>, punctuation: bool) {
+        match neighbour {
+            Some(neighbour) if is_word(neighbour) => self.text(":"),
+            Some(neighbour) if !is_special(Some(neighbour)) => {}
+            _ => {
+                self.ambiguous = true;
+                if punctuation {
+                    self.text(":");
+                }
+            }
+        }
+    }
+
+    /// Adds the text of segments. Line breaks are only allowed between words when `wrap` is set.
+    fn segments(&mut self, segments: &[ParagraphSegment], wrap: bool) {
+        let mut skip = false;
+
+        for (i, segment) in segments.iter().enumerate() {
+            if std::mem::take(&mut skip) {
+                continue;
+            }
+
+            let previous = i.checked_sub(1).and_then(|i| segments.get(i));
+            let next = segments.get(i + 1);
+
+            match segment {
+                ParagraphSegment::Token { token, .. } => match token {
+                    ParagraphSegmentToken::Whitespace => match next {
+                        // Two whitespace tokens in a row come from whitespace at the end of a line.
+                        Some(ParagraphSegment::Token {
+                            token: ParagraphSegmentToken::Whitespace,
+                            ..
+                        }) => {
+                            self.text(" ");
+                            self.push(Chunk::Newline);
+                            skip = true;
+                        }
+                        // A tag end only stays text at the end of a line.
+                        _ if previous.is_some_and(is_tag_end) => self.push(Chunk::Newline),
+                        Some(next) if wrap && starts_word(next) && !is_special(previous) => {
+                            self.push(Chunk::Break)
+                        }
+                        _ => self.text(" "),
+                    },
+                    ParagraphSegmentToken::Text(text) => {
+                        // Text tokens are only ever split up by a line continuation, and `end`
+                        // right after anything but a line break would be read as a tag end.
+                        let split = match previous {
+                            Some(ParagraphSegment::Token {
+                                token: ParagraphSegmentToken::Text(_),
+                                ..
+                            }) => true,
+                            Some(ParagraphSegment::Token {
+                                token:
+                                    ParagraphSegmentToken::Whitespace
+                                    | ParagraphSegmentToken::Escape('\n'),
+                                ..
+                            })
+                            | None => false,
+                            Some(_) => text == "end",
+                        };
+                        if split {
+                            self.text("~");
+                            self.push(Chunk::Newline);
+                        }
+                        self.text(text);
+                    }
+                    token => self.text(token.to_string()),
+                },
+                ParagraphSegment::AttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                } => {
+                    self.link_modifier(previous, self.link_punctuation.0);
+                    self.text(*modifier_type);
+                    self.segments(content, false);
+                    self.text(*modifier_type);
+                    self.link_modifier(next, self.link_punctuation.1);
+                }
+                ParagraphSegment::FreeFormAttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                } => {
+                    self.link_modifier(previous, self.link_punctuation.0);
+                    self.text(format!("{modifier_type}|"));
+                    self.segments(content, false);
+                    self.text(format!("|{modifier_type}"));
+                    self.link_modifier(next, self.link_punctuation.1);
+                }
+                ParagraphSegment::Link {
+                    filepath,
+                    targets,
+                    description,
+                    ..
+                } => self.link(filepath.as_deref(), targets, description.as_deref()),
+                ParagraphSegment::AnchorDefinition {
+                    content, target, ..
+                } => {
+                    self.bracketed('[', content, ']');
+                    self.segments(std::slice::from_ref(target), false);
+                }
+                ParagraphSegment::Anchor {
+                    content,
+                    description,
+                    ..
+                } => {
+                    self.bracketed('[', content, ']');
+                    if let Some(description) = description {
+                        self.bracketed('[', description, ']');
+                    }
+                }
+                ParagraphSegment::InlineLinkTarget { content, .. } => {
+                    self.bracketed('<', content, '>')
+                }
+                ParagraphSegment::InlineVerbatim { content, .. } => {
+                    let content: Vec<_> = content
+                        .iter()
+                        .map(|token| ParagraphSegment::Token {
+                            token: token.clone(),
+                            span: Default::default(),
+                        })
+                        .collect();
+                    self.bracketed('`', &content, '`');
+                }
+                // Only used while parsing, these never end up in the tree.
+                ParagraphSegment::AttachedModifierOpener(_)
+                | ParagraphSegment::AttachedModifierOpenerFail(_)
+                | ParagraphSegment::AttachedModifierCloserCandidate(_)
+                | ParagraphSegment::AttachedModifierCloser(_)
+                | ParagraphSegment::AttachedModifierCandidate { .. } => {}
+            }
+        }
+    }
+
+    fn bracketed(&mut self, open: char, content: &[ParagraphSegment], close: char) {
+        self.text(open);
+        self.segments(content, false);
+        self.text(close);
+    }
+
+    fn link(
+        &mut self,
+        filepath: Option<&str>,
+        targets: &[LinkTarget],
+        description: Option<&[ParagraphSegment]>,
+    ) {
+        self.text("{");
+        if let Some(filepath) = filepath {
+            self.text(":");
+            self.raw(filepath);
+            self.text(":");
+        }
+
+        for (i, target) in targets.iter().enumerate() {
+            if i > 0 {
+                self.text(" : ");
+            }
+
+            let (modifier, title) = match target {
+                LinkTarget::Heading { level, title, .. } => ("*".repeat(*level as usize), title),
+                LinkTarget::Footnote { title, .. } => ("^".to_string(), title),
+                LinkTarget::Definition { title, .. } => ("$".to_string(), title),
+                LinkTarget::Generic { title, .. } => ("#".to_string(), title),
+                LinkTarget::Wiki { title, .. } => ("?".to_string(), title),
+                LinkTarget::Extendable { title, .. } => ("=".to_string(), title),
+                LinkTarget::LineNumber { line, .. } => {
+                    self.text(line.to_string());
+                    continue;
+                }
+                LinkTarget::Path { path, .. } => {
+                    self.text("/ ");
+                    self.raw(path);
+                    continue;
+                }
+                LinkTarget::Url { url, .. } => {
+                    self.raw(url);
+                    continue;
+                }
+                LinkTarget::Timestamp { timestamp, .. } => {
+                    self.text("@ ");
+                    self.raw(&timestamp.raw);
+                    continue;
+                }
+            };
+
+            self.text(format!("{modifier} "));
+            self.segments(title, false);
+        }
+
+        self.text("}");
+        if let Some(description) = description {
+            self.bracketed('[', description, ']');
+        }
+    }
+
+    /// Adds text which the parser keeps as written, like a url.
+    fn raw(&mut self, text: &str) {
+        // Two spaces in a row only come from whitespace at the end of a line.
+        for (i, part) in text.split("  ").enumerate() {
+            if i > 0 {
+                self.text(" ");
+                self.push(Chunk::Newline);
+            }
+            self.text(part);
+        }
+    }
+
+    /// Moves the text after what looks like the marker of a detached modifier at the start of a
+    /// line onto the next line, which is how the parser tells the two apart.
+    fn split_markers(&mut self) {
+        let mut i = 0;
+
+        while i < self.chunks.len() {
+            let line_start = i == 0 || matches!(self.chunks[i - 1], Chunk::Newline);
+            let Chunk::Text(text) = &self.chunks[i] else {
+                i += 1;
+                continue;
+            };
+
+            let marker: String = text
+                .chars()
+                .take_while(|c| "*-~>$^:%".contains(*c))
+                .collect();
+            // Only a run of a single character forms a detached modifier, e.g. `>^` never does.
+            let detached = marker.chars().all(|c| marker.starts_with(c));
+            let rest = text.strip_prefix(&format!("{marker} ")).unwrap_or_default();
+            if line_start && !marker.is_empty() && detached && !rest.is_empty() {
+                let rest = rest.to_string();
+                self.chunks.splice(
+                    i..i + 1,
+                    [Chunk::Text(marker), Chunk::Newline, Chunk::Text(rest)],
+                );
+            }
+            i += 1;
+        }
+    }
+
+    /// Keeps a paragraph which starts like a detached modifier or a tag from being parsed as
+    /// one. A last line like `.name parameter ` only stays a paragraph because of the
+    /// whitespace at its end, and a last line like `.name` is only a paragraph at the very end
+    /// of the input, which is returned.
+    fn protect_lines(&mut self) -> bool {
+        self.split_markers();
+
+        let tag = ['.', '#', '+', '@', '|', '='];
+        let is_tag = |chunk: Option<&Chunk>| match chunk {
+            Some(Chunk::Text(text)) => text.starts_with(tag),
+            _ => false,
+        };
+        let first_line_end = self
+            .chunks
+            .iter()
+            .position(|chunk| matches!(chunk, Chunk::Newline));
+        let last_line = self
+            .chunks
+            .iter()
+            .rposition(|chunk| matches!(chunk, Chunk::Newline))
+            .map_or(0, |newline| newline + 1);
+
+        // A line made of delimiter characters, like `-=`, is only text with whitespace at its
+        // end, which can only be written on the last line.
+        let is_delimiter = |chunk: Option<&Chunk>| {
+            matches!(chunk, Some(Chunk::Text(text))
+                if text.chars().count() > 1 && text.chars().all(|c| "-=_".contains(c)))
+        };
+
+        if is_tag(self.chunks.first())
+            || is_tag(self.chunks.get(last_line))
+            || self.chunks.iter().any(|chunk| is_delimiter(Some(chunk)))
+        {
+            for chunk in &mut self.chunks {
+                if matches!(chunk, Chunk::Break) {
+                    *chunk = Chunk::Text(" ".to_string());
+                }
+            }
+        }
+        if is_delimiter(self.chunks.get(last_line)) && self.chunks.len() == last_line + 1 {
+            self.text(" ");
+            // Starting like a macro tag as well, like `=-`, it is only text without a newline.
+            if is_tag(self.chunks.get(last_line)) {
+                return true;
+            }
+        }
+
+        // A last line like `::` closes a ranged detached modifier, and stays text only with
+        // whitespace at its end. Wrapping can move the last word to a line of its own.
+        let closes = self.chunks.last().is_some_and(|chunk| {
+            matches!(chunk, Chunk::Text(text)
+                if text.chars().count() > 1
+                    && text.chars().all(|c| "$^:".contains(c) && text.starts_with(c)))
+        }) && matches!(
+            self.chunks.len().checked_sub(2).map(|i| &self.chunks[i]),
+            None | Some(Chunk::Break | Chunk::Newline)
+        );
+        if closes {
+            self.text(" ");
+        }
+
+        let continued = first_line_end
+            .and_then(|end| self.chunks.get(end.checked_sub(1)?))
+            .is_some_and(|chunk| matches!(chunk, Chunk::Text(text) if text.ends_with('~')));
+        if is_tag(self.chunks.first()) && continued {
+            return true;
+        }
+
+        // Verbatim text keeps its line breaks, so the last line can also start inside of it.
+        let verbatim_line = self.chunks[last_line..]
+            .iter()
+            .rposition(|chunk| matches!(chunk, Chunk::Text(text) if text.contains('\n')));
+        if let Some(i) = verbatim_line.map(|i| last_line + i) {
+            let line = match &self.chunks[i] {
+                Chunk::Text(text) => text.rsplit('\n').next().unwrap_or_default(),
+                _ => "",
+            };
+            if line.trim_start().starts_with(tag) {
+                for chunk in &mut self.chunks[i..] {
+                    if matches!(chunk, Chunk::Break) {
+                        *chunk = Chunk::Text(" ".to_string());
+                    }
+                }
+                return true;
+            }
+        }
+
+        if is_tag(self.chunks.get(last_line)) {
+            if !self.chunks[last_line..]
+                .iter()
+                .any(|chunk| matches!(chunk, Chunk::Text(text) if text.contains(' ')))
+            {
+                return true;
+            }
+            self.text(" ");
+        }
+
+        false
+    }
+}
+
+/// Whether a segment is neither whitespace nor punctuation, so that an attached modifier next to
+/// it needs a link modifier.
+fn is_word(segment: &ParagraphSegment) -> bool {
+    !matches!(
+        segment,
+        ParagraphSegment::Token {
+            token: ParagraphSegmentToken::Whitespace | ParagraphSegmentToken::Special(_),
+            ..
+        }
+    )
+}
+
+/// Writes extensions in their canonical form, e.g. `(x|# A)`, or nothing if there are none.
+pub(crate) fn extension_text(extensions: &[DetachedModifierExtension]) -> String {
+    if extensions.is_empty() {
+        return String::new();
+    }
+
+    let extensions = extensions
+        .iter()
+        .map(|extension| {
+            let (marker, value) = match extension {
+                DetachedModifierExtension::Todo(status) => match status {
+                    TodoStatus::Undone => (" ", None),
+                    TodoStatus::Done => ("x", None),
+                    TodoStatus::NeedsClarification => ("?", None),
+                    TodoStatus::Paused => ("=", None),
+                    TodoStatus::Urgent => ("!", None),
+                    TodoStatus::Recurring(timestamp) => (
+                        "+",
+                        timestamp.as_ref().map(|timestamp| timestamp.raw.as_str()),
+                    ),
+                    TodoStatus::Pending => ("-", None),
+                    TodoStatus::Canceled => ("_", None),
+                },
+                DetachedModifierExtension::Priority(priority) => ("#", Some(priority.as_str())),
+                DetachedModifierExtension::Timestamp(timestamp) => {
+                    ("@", Some(timestamp.raw.as_str()))
+                }
+                DetachedModifierExtension::DueDate(timestamp) => {
+                    ("<", Some(timestamp.raw.as_str()))
+                }
+                DetachedModifierExtension::StartDate(timestamp) => {
+                    (">", Some(timestamp.raw.as_str()))
+                }
+            };
+
+            match value {
+                Some(value) if !value.is_empty() => format!("{marker} {value}"),
+                _ => marker.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+
+    format!("({extensions})")
+}
+
+fn is_whitespace(segment: &ParagraphSegment) -> bool {
+    matches!(
+        segment,
+        ParagraphSegment::Token {
+            token: ParagraphSegmentToken::Whitespace,
+            ..
+        }
+    )
+}
+
+fn is_special(segment: Option<&ParagraphSegment>) -> bool {
+    matches!(
+        segment,
+        Some(ParagraphSegment::Token {
+            token: ParagraphSegmentToken::Special(_),
+            ..
+        })
+    )
+}
+
+/// Whether a line may start with this segment without changing how it is parsed.
+fn starts_word(segment: &ParagraphSegment) -> bool {
+    match segment {
+        ParagraphSegment::Token {
+            token: ParagraphSegmentToken::Text(text),
+            ..
+        } => text.starts_with(char::is_alphanumeric),
+        ParagraphSegment::AttachedModifier { .. } | ParagraphSegment::Link { .. } => true,
+        _ => false,
+    }
+}
+
+/// A tag end like `@end` which did not close anything is kept as text.
+fn is_tag_end(segment: &ParagraphSegment) -> bool {
+    matches!(
+        segment,
+        ParagraphSegment::Token {
+            token: ParagraphSegmentToken::Text(text),
+            ..
+        } if text.len() == 4 && text.ends_with("end") && !text.starts_with(char::is_alphanumeric)
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::proptest;
+
+    use crate::{
+        format::{format_with, FormatOptions},
+        parse_tree, NorgAST,
+    };
+
+    fn without_spans(mut tree: Vec<NorgAST>) -> Vec<NorgAST> {
+        tree.iter_mut()
+            .for_each(|node| node.spans_mut(&mut |span| *span = Default::default()));
+        tree
+    }
+
+    /// Checks that parsing the formatted tree gives the same tree back, and that formatting it
+    /// again does not change anything.
+    pub(crate) fn assert_formats_back(tree: &[NorgAST]) {
+        assert_round_trip(tree, &FormatOptions::default());
+    }
+
+    fn assert_round_trip(tree: &[NorgAST], options: &FormatOptions) -> String {
+        let formatted = format_with(tree, options);
+        let reparsed = parse_tree(&formatted)
+            .unwrap_or_else(|error| panic!("failed to parse {formatted:?}: {error}"));
+
+        assert_eq!(
+            without_spans(reparsed.clone()),
+            without_spans(tree.to_vec()),
+            "formatted as {formatted:?}"
+        );
+        assert_eq!(format_with(&reparsed, options), formatted);
+
+        formatted
+    }
+
+    fn formatted(input: &str) -> String {
+        assert_round_trip(&parse_tree(input).unwrap(), &FormatOptions::default())
+    }
+
+    #[test]
+    fn formatting() {
+        assert_yaml_snapshot!(
+            "headings",
+            formatted(
+                "* Heading
+                 Some text that is long enough to be wrapped at the default width of eighty characters.
+                 ** Nested
+                 Text within the nested heading.
+                 ---
+                 Back in the first heading.
+                 ===
+                 Outside of any heading.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "lists",
+            formatted(
+                "- ( ) One
+                 -- (x|# A|< Feb 1) Two
+                 --- (?) Three
+                 ~ First
+                 ~ Second\n"
+            )
+        );
+        assert_yaml_snapshot!("quotes", formatted("> A quote\n>> Nested\n"));
+        assert_yaml_snapshot!(
+            "definitions",
+            formatted(
+                "$ Term
+                  Definition.
+                 $$ Long term
+                  First paragraph.
+
+                  Second paragraph.
+                 $$\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "footnotes",
+            formatted("^ Footnote\n  Text of the footnote.\n^^ Long\n  Content.\n^^\n")
+        );
+        assert_yaml_snapshot!("tables", formatted(": A1\n  Cell\n: B1\n  Other\n"));
+        assert_yaml_snapshot!("sparse_tables", formatted(": A1\n  a\n: C3\n  c\n"));
+        assert_yaml_snapshot!(
+            "tags",
+            formatted(
+                "#name parameter
+                 - Tagged item
+                 +name
+                 A paragraph.
+                 |example
+                 Inside a *ranged* tag.
+                 |end
+                 .toc Contents\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "verbatim",
+            formatted("@code lua\n  print(\"Hello\")\n\n@end\n")
+        );
+        assert_yaml_snapshot!(
+            "indent_segments",
+            formatted("- Item ::\n  Indent segment content.\n\n  -- Nested item.\n---\n")
+        );
+        assert_yaml_snapshot!(
+            "markup",
+            formatted(
+                "Text with *bold*, /italic/ and `verbatim`, and in*word*:s modifiers. Continued~
+                 line.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "links",
+            formatted(
+                "A link {:file:* Heading}[description] and an anchor
+                 [anchor]{https://example.com}.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "escapes",
+            formatted("Escaped \\*stars\\*, a backslash \\\\ and a pipe \\|.\n")
+        );
+        assert_yaml_snapshot!("escaped_pipes", formatted(": A1\n  a \\| b\n"));
+    }
+
+    #[test]
+    fn closing_markers() {
+        for input in [
+            ":: ",
+            "$$ ",
+            "^^ ",
+            "a\n:: \n",
+            "Some words before ::",
+            "#]# \n:$$\n@$",
+        ] {
+            for width in [10, 80] {
+                assert_round_trip(&parse_tree(input).unwrap(), &FormatOptions { width });
+            }
+        }
+    }
+
+    #[test]
+    fn wrapping() {
+        let input = "* A heading whose title is never wrapped
+                     A paragraph with quite a few words in it, to wrap at different widths.
+                     - A list item whose text is wrapped as well, lined up with its start.";
+        let examples: Vec<_> = [20, 40, 80]
+            .into_iter()
+            .map(|width| assert_round_trip(&parse_tree(input).unwrap(), &FormatOptions { width }))
+            .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_proptests(
+            input in r"((\*{1,3} |-{1,2} |> |~ )?([a-z]{1,8}|\*[a-z]{1,6}\*)( ([a-z]{1,8}|/[a-z]{1,6}/)){0,12}\n\n?){1,8}",
+            width in 40..100usize,
+        ) {
+            let formatted = assert_round_trip(&parse_tree(&input).unwrap(), &FormatOptions { width });
+
+            // Heading titles are the only lines which are never wrapped.
+            let heading = |line: &str| line.trim_start().trim_start_matches('*').starts_with(' ');
+            for line in formatted.lines().filter(|line| !heading(line)) {
+                assert!(line.chars().count() <= width, "{line:?} is wider than {width}");
+            }
+        }
+    }
+}
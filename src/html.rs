@@ -0,0 +1,694 @@
+//! Rendering a tree as HTML.
+//!
+//! The output is a fragment of semantic HTML, meant to be placed within the `<body>` of a page.
+//! Headings get ids made from their titles, so that links to them (and to definitions,
+//! footnotes and inline link targets) can be resolved to `#id` fragments. Links into other
+//! files point at the `.html` file with the same name. Links to URLs with a scheme other than
+//! `http`, `https` or `mailto` are left without an `href`. Footnotes are collected at the end of
+//! the output.
+//!
+//! Only available with the `html` feature.
+
+use crate::{
+    export::{groups, raw, slug, trim_start, verbatim, TargetKind, Targets},
+    table::NorgTable,
+    text::{plain_text, todo_name},
+    DelimitingModifier, DetachedModifierExtension, LinkTarget, NestableDetachedModifier, NorgAST,
+    ParagraphSegment, ParagraphSegmentToken, RangeableDetachedModifier, TodoStatus,
+};
+
+/// Renders a tree as returned by [`crate::parse_tree`].
+pub fn render(tree: &[NorgAST]) -> String {
+    let mut renderer = Renderer {
+        output: String::new(),
+        targets: Targets::new(tree, identifier),
+        footnotes: Vec::new(),
+    };
+    renderer.nodes(tree);
+    renderer.footnotes();
+
+    renderer.output
+}
+
+struct Renderer {
+    output: String,
+    targets: Targets,
+    /// The footnotes seen so far, written out at the end.
+    footnotes: Vec<NorgAST>,
+}
+
+impl Renderer {
+    /// Renders the nodes of a scope, grouping consecutive list items, quotes, definitions and
+    /// table cells.
+    fn nodes(&mut self, nodes: &[NorgAST]) {
+        for group in groups(nodes) {
+            match &group[0] {
+                NorgAST::NestableDetachedModifier { modifier_type, .. } => {
+                    self.list(modifier_type, group)
+                }
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Definition,
+                    ..
+                } => self.definitions(group),
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Table,
+                    ..
+                } => self.table(group),
+                node => self.node(node),
+            }
+        }
+    }
+
+    fn node(&mut self, node: &NorgAST) {
+        match node {
+            NorgAST::Paragraph { content, .. } => {
+                self.output.push_str("<p>");
+                self.segments(content);
+                self.output.push_str("</p>\n");
+            }
+            NorgAST::Heading {
+                level,
+                title,
+                extensions,
+                content,
+                span,
+                ..
+            } => {
+                let level = (*level).clamp(1, 6);
+                let id = self.targets.id(span).to_string();
+
+                self.output
+                    .push_str(&format!("<h{level} id=\"{}\">", escape(&id)));
+                self.checkbox(extensions);
+                self.segments(trim_start(title));
+                self.output.push_str(&format!("</h{level}>\n"));
+                self.nodes(content);
+            }
+            NorgAST::RangeableDetachedModifier {
+                modifier_type: RangeableDetachedModifier::Footnote,
+                ..
+            } => self.footnotes.push(node.clone()),
+            // Grouped by `nodes`.
+            NorgAST::NestableDetachedModifier { .. }
+            | NorgAST::RangeableDetachedModifier { .. } => self.nodes(std::slice::from_ref(node)),
+            NorgAST::CarryoverTag { next_object, .. } => {
+                self.nodes(std::slice::from_ref(next_object))
+            }
+            NorgAST::VerbatimRangedTag {
+                name,
+                parameters,
+                content,
+                ..
+            } => match name.join(".").as_str() {
+                "document.meta" => {}
+                "code" => {
+                    match parameters.first() {
+                        Some(language) => self.output.push_str(&format!(
+                            "<pre><code class=\"language-{}\">",
+                            escape(language)
+                        )),
+                        None => self.output.push_str("<pre><code>"),
+                    }
+                    self.output.push_str(&escape(content));
+                    self.output.push_str("</code></pre>\n");
+                }
+                "math" => self
+                    .output
+                    .push_str(&format!("<div class=\"math\">{}</div>\n", escape(content))),
+                _ => self
+                    .output
+                    .push_str(&format!("<pre>{}</pre>\n", escape(content))),
+            },
+            NorgAST::RangedTag { name, content, .. } => {
+                let name = name.join(".");
+                if name != "comment" {
+                    self.output
+                        .push_str(&format!("<div class=\"{}\">\n", escape(&name)));
+                    self.nodes(content);
+                    self.output.push_str("</div>\n");
+                }
+            }
+            NorgAST::DelimitingModifier {
+                modifier_type: DelimitingModifier::HorizontalRule,
+                ..
+            } => self.output.push_str("<hr>\n"),
+            NorgAST::Error { content, .. } => self
+                .output
+                .push_str(&format!("<pre class=\"error\">{}</pre>\n", escape(content))),
+            NorgAST::DelimitingModifier { .. } | NorgAST::InfirmTag { .. } => {}
+        }
+    }
+
+    fn list(&mut self, modifier_type: &NestableDetachedModifier, items: &[NorgAST]) {
+        let tag = match modifier_type {
+            NestableDetachedModifier::Quote => "blockquote",
+            NestableDetachedModifier::UnorderedList => "ul",
+            NestableDetachedModifier::OrderedList => "ol",
+        };
+        self.output.push_str(&format!("<{tag}>\n"));
+
+        for item in items {
+            let NorgAST::NestableDetachedModifier {
+                extensions,
+                text,
+                content,
+                ..
+            } = item
+            else {
+                continue;
+            };
+            let text = match text.as_ref() {
+                NorgAST::Paragraph { content, .. } => content.as_slice(),
+                _ => &[],
+            };
+
+            if *modifier_type == NestableDetachedModifier::Quote {
+                self.output.push_str("<p>");
+                self.checkbox(extensions);
+                self.segments(text);
+                self.output.push_str("</p>\n");
+                self.nodes(content);
+                continue;
+            }
+
+            match todo(extensions) {
+                Some(status) => self
+                    .output
+                    .push_str(&format!("<li class=\"todo todo-{}\">", todo_name(status))),
+                None => self.output.push_str("<li>"),
+            }
+            self.checkbox(extensions);
+            self.segments(text);
+            if !content.is_empty() {
+                self.output.push('\n');
+                self.nodes(content);
+            }
+            self.output.push_str("</li>\n");
+        }
+
+        self.output.push_str(&format!("</{tag}>\n"));
+    }
+
+    fn definitions(&mut self, definitions: &[NorgAST]) {
+        self.output.push_str("<dl>\n");
+
+        for definition in definitions {
+            if let NorgAST::RangeableDetachedModifier {
+                title,
+                content,
+                span,
+                ..
+            } = definition
+            {
+                let id = self.targets.id(span).to_string();
+
+                self.output
+                    .push_str(&format!("<dt id=\"{}\">", escape(&id)));
+                self.segments(title);
+                self.output.push_str("</dt>\n<dd>\n");
+                self.nodes(content);
+                self.output.push_str("</dd>\n");
+            }
+        }
+
+        self.output.push_str("</dl>\n");
+    }
+
+    /// Renders table cells as a grid. Cells without a valid address are left out, and the
+    /// content of cells written several times is joined.
+    fn table(&mut self, cells: &[NorgAST]) {
+        let Some(table) = NorgTable::from_nodes(cells) else {
+            return;
+        };
+
+        self.output.push_str("<table>\n");
+        for row in table.grid() {
+            self.output.push_str("<tr>");
+            for cell in row {
+                self.output.push_str("<td>");
+                match cell.map(|cell| cell.content.as_slice()) {
+                    Some([NorgAST::Paragraph { content, .. }]) => self.segments(content),
+                    Some(content) => {
+                        for node in content {
+                            self.nodes(std::slice::from_ref(node));
+                        }
+                    }
+                    None => {}
+                }
+                self.output.push_str("</td>");
+            }
+            self.output.push_str("</tr>\n");
+        }
+        self.output.push_str("</table>\n");
+    }
+
+    fn footnotes(&mut self) {
+        if self.footnotes.is_empty() {
+            return;
+        }
+
+        let mut footnotes = std::mem::take(&mut self.footnotes);
+        footnotes.sort_by_key(|footnote| {
+            self.targets
+                .footnote_number(self.targets.id(&footnote.span()))
+        });
+
+        self.output
+            .push_str("<section class=\"footnotes\">\n<ol>\n");
+        for footnote in &footnotes {
+            if let NorgAST::RangeableDetachedModifier {
+                title,
+                content,
+                span,
+                ..
+            } = footnote
+            {
+                let id = self.targets.id(span).to_string();
+
+                self.output
+                    .push_str(&format!("<li id=\"{}\">\n", escape(&id)));
+                if content.is_empty() {
+                    self.output.push_str("<p>");
+                    self.segments(title);
+                    self.output.push_str("</p>\n");
+                } else {
+                    self.nodes(content);
+                }
+                self.output.push_str("</li>\n");
+            }
+        }
+        self.output.push_str("</ol>\n</section>\n");
+    }
+
+    fn checkbox(&mut self, extensions: &[DetachedModifierExtension]) {
+        match todo(extensions) {
+            Some(TodoStatus::Done) => self
+                .output
+                .push_str("<input type=\"checkbox\" disabled checked> "),
+            Some(_) => self.output.push_str("<input type=\"checkbox\" disabled> "),
+            None => {}
+        }
+    }
+
+    fn segments(&mut self, segments: &[ParagraphSegment]) {
+        for segment in segments {
+            match segment {
+                ParagraphSegment::Token { token, .. } => self.token(token),
+                ParagraphSegment::AttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                }
+                | ParagraphSegment::FreeFormAttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                } => {
+                    let (open, close) = match modifier_type {
+                        '*' => ("<strong>", "</strong>"),
+                        '/' => ("<em>", "</em>"),
+                        '_' => ("<u>", "</u>"),
+                        '-' => ("<del>", "</del>"),
+                        '!' => ("<span class=\"spoiler\">", "</span>"),
+                        '^' => ("<sup>", "</sup>"),
+                        ',' => ("<sub>", "</sub>"),
+                        '`' => ("<code>", "</code>"),
+                        '$' => ("<span class=\"math\">", "</span>"),
+                        '&' => ("<var>", "</var>"),
+                        // The null modifier comments its content out.
+                        _ => continue,
+                    };
+
+                    self.output.push_str(open);
+                    match verbatim(segment) {
+                        Some(text) => self.output.push_str(&escape(&text)),
+                        None => self.segments(content),
+                    }
+                    self.output.push_str(close);
+                }
+                ParagraphSegment::InlineVerbatim { content, .. } => {
+                    self.output.push_str("<code>");
+                    self.output.push_str(&escape(&raw(content)));
+                    self.output.push_str("</code>");
+                }
+                ParagraphSegment::Link {
+                    filepath,
+                    targets,
+                    description,
+                    ..
+                } => self.link(filepath.as_deref(), targets, description.as_deref()),
+                ParagraphSegment::AnchorDefinition {
+                    content, target, ..
+                } => {
+                    let href = match target.as_ref() {
+                        ParagraphSegment::Link {
+                            filepath, targets, ..
+                        } => self.href(filepath.as_deref(), targets),
+                        _ => None,
+                    };
+                    self.anchor(href, content);
+                }
+                ParagraphSegment::Anchor {
+                    content,
+                    description,
+                    ..
+                } => {
+                    let href = match self.targets.anchor(&plain_text(content)) {
+                        Some(ParagraphSegment::Link {
+                            filepath, targets, ..
+                        }) => self.href(filepath.as_deref(), targets),
+                        _ => None,
+                    };
+                    self.anchor(href, description.as_deref().unwrap_or(content));
+                }
+                ParagraphSegment::InlineLinkTarget { content, span } => {
+                    let id = self.targets.id(span).to_string();
+
+                    self.output
+                        .push_str(&format!("<span id=\"{}\">", escape(&id)));
+                    self.segments(content);
+                    self.output.push_str("</span>");
+                }
+                // Only used while parsing, these never end up in the tree.
+                ParagraphSegment::AttachedModifierOpener(_)
+                | ParagraphSegment::AttachedModifierOpenerFail(_)
+                | ParagraphSegment::AttachedModifierCloserCandidate(_)
+                | ParagraphSegment::AttachedModifierCloser(_)
+                | ParagraphSegment::AttachedModifierCandidate { .. } => {}
+            }
+        }
+    }
+
+    fn token(&mut self, token: &ParagraphSegmentToken) {
+        match token {
+            ParagraphSegmentToken::Text(text) => self.output.push_str(&escape(text)),
+            ParagraphSegmentToken::Whitespace => self.output.push(' '),
+            ParagraphSegmentToken::Escape('\n') => self.output.push_str("<br>\n"),
+            ParagraphSegmentToken::Special(c) | ParagraphSegmentToken::Escape(c) => {
+                self.output.push_str(&escape(&c.to_string()))
+            }
+        }
+    }
+
+    fn link(
+        &mut self,
+        filepath: Option<&str>,
+        targets: &[LinkTarget],
+        description: Option<&[ParagraphSegment]>,
+    ) {
+        let href = self.href(filepath, targets);
+
+        // Footnote references without a description are shown as their number.
+        if let (None, None, Some(LinkTarget::Footnote { title, .. })) =
+            (filepath, description, targets.last())
+        {
+            if let Some(footnote) = self.targets.find(Some(TargetKind::Footnote), title) {
+                let id = &footnote.id;
+                let number = self.targets.footnote_number(id).unwrap_or_default();
+                self.output.push_str(&format!(
+                    "<sup class=\"footnote-ref\"><a href=\"#{}\">{number}</a></sup>",
+                    escape(id)
+                ));
+                return;
+            }
+        }
+
+        match href {
+            Some(href) => self
+                .output
+                .push_str(&format!("<a href=\"{}\">", escape(&href))),
+            None => self.output.push_str("<a>"),
+        }
+        match (description, targets.last()) {
+            (Some(description), _) => self.segments(description),
+            (
+                None,
+                Some(LinkTarget::Url { url: text, .. } | LinkTarget::Path { path: text, .. }),
+            ) => self.output.push_str(&escape(text)),
+            (None, Some(LinkTarget::LineNumber { line, .. })) => {
+                self.output.push_str(&line.to_string())
+            }
+            (None, Some(LinkTarget::Timestamp { timestamp, .. })) => {
+                self.output.push_str(&escape(&timestamp.raw))
+            }
+            (
+                None,
+                Some(
+                    LinkTarget::Heading { title, .. }
+                    | LinkTarget::Footnote { title, .. }
+                    | LinkTarget::Definition { title, .. }
+                    | LinkTarget::Generic { title, .. }
+                    | LinkTarget::Wiki { title, .. }
+                    | LinkTarget::Extendable { title, .. },
+                ),
+            ) => self.segments(title),
+            (None, None) => self.output.push_str(&escape(filepath.unwrap_or_default())),
+        }
+        self.output.push_str("</a>");
+    }
+
+    fn anchor(&mut self, href: Option<String>, content: &[ParagraphSegment]) {
+        match href {
+            Some(href) => {
+                self.output
+                    .push_str(&format!("<a href=\"{}\">", escape(&href)));
+                self.segments(content);
+                self.output.push_str("</a>");
+            }
+            None => self.segments(content),
+        }
+    }
+
+    /// Resolves the targets of a link. Only the last target is used, as it is the most
+    /// specific one. Targets in the same file which cannot be found still link to the id they
+    /// would have.
+    fn href(&self, filepath: Option<&str>, targets: &[LinkTarget]) -> Option<String> {
+        let file = filepath.map(|path| format!("{}.html", encode_path(path)));
+
+        let fragment = match targets.last() {
+            Some(LinkTarget::Url { url, .. }) => return safe_url(url),
+            Some(LinkTarget::Path { path, .. }) => return Some(encode_path(path)),
+            Some(LinkTarget::LineNumber { .. } | LinkTarget::Timestamp { .. }) | None => None,
+            Some(LinkTarget::Heading { level, title, .. }) => {
+                Some(self.fragment(file.is_none(), Some(TargetKind::Heading(*level)), title))
+            }
+            Some(LinkTarget::Footnote { title, .. }) => {
+                Some(self.fragment(file.is_none(), Some(TargetKind::Footnote), title))
+            }
+            Some(LinkTarget::Definition { title, .. }) => {
+                Some(self.fragment(file.is_none(), Some(TargetKind::Definition), title))
+            }
+            Some(
+                LinkTarget::Generic { title, .. }
+                | LinkTarget::Wiki { title, .. }
+                | LinkTarget::Extendable { title, .. },
+            ) => Some(self.fragment(file.is_none(), None, title)),
+        };
+
+        match (file, fragment) {
+            (Some(file), Some(fragment)) => Some(format!("{file}#{fragment}")),
+            (Some(file), None) => Some(file),
+            (None, Some(fragment)) => Some(format!("#{fragment}")),
+            (None, None) => None,
+        }
+    }
+
+    fn fragment(
+        &self,
+        local: bool,
+        kind: Option<TargetKind>,
+        title: &[ParagraphSegment],
+    ) -> String {
+        let found = local.then(|| self.targets.find(kind, title)).flatten();
+
+        match (found, kind) {
+            (Some(target), _) => target.id.clone(),
+            (None, Some(kind)) => identifier(kind, &plain_text(title)),
+            (None, None) => slug(&plain_text(title)),
+        }
+    }
+}
+
+/// Ids are made from titles with [`slug`], with `fn-` in front for footnotes.
+fn identifier(kind: TargetKind, text: &str) -> String {
+    match kind {
+        TargetKind::Footnote => format!("fn-{}", slug(text)),
+        _ => slug(text),
+    }
+}
+
+/// Returns `url` if it is relative or uses one of the `http`, `https` and `mailto` schemes, so
+/// that links like `javascript:` ones can't run anything.
+fn safe_url(url: &str) -> Option<String> {
+    // Browsers ignore these characters when reading the scheme.
+    let cleaned: String = url
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    let cleaned = cleaned.trim_start_matches(|c: char| c <= ' ');
+
+    let scheme = cleaned
+        .split_once(':')
+        .map(|(scheme, _)| scheme)
+        .filter(|scheme| {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        });
+
+    match scheme {
+        Some(scheme)
+            if !["http", "https", "mailto"]
+                .iter()
+                .any(|allowed| scheme.eq_ignore_ascii_case(allowed)) =>
+        {
+            None
+        }
+        _ => Some(url.to_string()),
+    }
+}
+
+/// Percent-encodes a file path, keeping its `/` separators.
+fn encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn todo(extensions: &[DetachedModifierExtension]) -> Option<&TodoStatus> {
+    extensions.iter().find_map(|extension| match extension {
+        DetachedModifierExtension::Todo(status) => Some(status),
+        _ => None,
+    })
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::proptest;
+
+    use crate::{
+        html::{escape, render},
+        parse_tree,
+    };
+
+    fn rendered(input: &str) -> String {
+        render(&parse_tree(input).unwrap())
+    }
+
+    #[test]
+    fn rendering() {
+        assert_yaml_snapshot!(
+            "headings",
+            rendered(
+                "* Tasks & 1 < 2
+                 ** (x) Done heading
+                 ---
+                 * Tasks & 1 < 2
+                   A duplicate title.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "lists",
+            rendered(
+                "- ( ) Write *the* renderer
+                 -- (x) Escape \"quotes\"
+                 - (!) Ship it
+                 ~ First
+                 ~ Second\n"
+            )
+        );
+        assert_yaml_snapshot!("quotes", rendered("> Quoted /text/\n>> Nested\n"));
+        assert_yaml_snapshot!(
+            "markup",
+            rendered(
+                "*Bold*, /italic/, _underline_, -deleted-, !spoiler!, ^super^, ,sub, and
+                 %comment%.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "links",
+            rendered(
+                "Links to {* Code}, {# Term}, {https://example.com?a=1&b=2}[example],
+                 {:other:** Heading}, {/ file.txt} and [anchor]{https://neorg.org}.
+                 Then [anchor] again and an <inline target>.
+                 * Code
+                 $ Term
+                 The definition.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "unsafe_links",
+            rendered(
+                "Unsafe {javascript:alert(1)}[links], { JavaScript:alert(1)} and {data:text/html,x}.
+                 Safe {mailto:me@example.com}[mail], {./relative/page}, {/ path/to file} and
+                 {:my notes:}[notes].\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "footnotes",
+            rendered("Some text.{^ Note}\n^ Note\nA footnote.\n^^ Long\nFirst.\n\nSecond.\n^^\n")
+        );
+        assert_yaml_snapshot!(
+            "definitions",
+            rendered("$ Term\nThe definition.\n$ Other term\nAnother one.\n")
+        );
+        assert_yaml_snapshot!(
+            "tables",
+            rendered(": A1\n  Name\n: B1\n  Age\n: A2\n  Bob\n")
+        );
+        assert_yaml_snapshot!("sparse_tables", rendered(": A1\n  a\n: C3\n  c\n"));
+        assert_yaml_snapshot!(
+            "code",
+            rendered("@code rust\nfn main() { println!(\"<hi>\"); }\n@end\n")
+        );
+        assert_yaml_snapshot!(
+            "verbatim",
+            rendered("`a\\b <c>`, $\\frac{1}{2}$, &x\\_y& and *|a\\b  c|*\n")
+        );
+        assert_yaml_snapshot!(
+            "escapes",
+            rendered("Escaped \\*stars\\*, a backslash \\\\ and a pipe \\|.\n")
+        );
+        assert_yaml_snapshot!("escaped_pipes", rendered(": A1\n  a \\| b\n: B1\n  c\n"));
+    }
+
+    proptest! {
+        #[test]
+        fn escaped_text_has_no_markup(text in r"\PC*") {
+            let escaped = escape(&text);
+
+            assert!(!escaped.contains(['<', '>', '"', '\'']));
+            let unescaped = escaped
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&#39;", "'")
+                .replace("&amp;", "&");
+            assert_eq!(unescaped, text);
+        }
+    }
+}
@@ -0,0 +1,394 @@
+//! Incremental reparsing of a document after it has been edited.
+//!
+//! A [`ParsedDocument`] keeps the top-level nodes of the previous parse. After an edit, only the
+//! nodes around it are lexed and parsed again. Parsing restarts and stops at the start of
+//! top-level nodes, which always line up with `stage_2` block boundaries. The nodes before and
+//! after that window are reused, with their spans moved to account for the edit.
+
+use std::ops::Range;
+
+use chumsky::{error::Simple, Parser as _};
+
+use crate::{
+    error::{Diagnostic, NorgParseError, SelectorError},
+    query::{self, Match},
+    span::{position_at, source_end, spanned_stream, Position, Span},
+    stage_1::{stage_1_between, NorgToken},
+    stage_2::{stage_2, NorgBlock},
+    stage_3::stage_3_recovering,
+    stage_4::stage_4,
+    NorgAST, NorgASTFlat,
+};
+
+/// A parsed document which can be updated after an edit without parsing it from scratch.
+///
+/// The result is always identical to [`crate::parse_recovering`] on the edited input.
+#[derive(Debug, Clone)]
+pub struct ParsedDocument {
+    input: String,
+    nodes: Vec<Node>,
+    tree: Vec<NorgAST>,
+}
+
+/// A top-level node, together with what is needed to decide whether it can be reused.
+#[derive(Debug, Clone)]
+struct Node {
+    flat: NorgASTFlat,
+    diagnostics: Vec<Diagnostic>,
+    /// Whether parsing this node may have looked arbitrarily far ahead, for example to find the
+    /// end of a ranged tag which is never closed. Such nodes are parsed again after every edit
+    /// which follows them.
+    open_ended: bool,
+}
+
+impl ParsedDocument {
+    /// Parses the whole input.
+    pub fn new(input: impl Into<String>) -> Self {
+        let input = input.into();
+        let nodes = parse_nodes(&input, Position::default(), source_end(&input));
+
+        Self::from_nodes(input, nodes)
+    }
+
+    fn from_nodes(input: String, nodes: Vec<Node>) -> Self {
+        let tree = stage_4(nodes.iter().map(|node| node.flat.clone()).collect());
+
+        Self { input, nodes, tree }
+    }
+
+    /// Returns the current input.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Returns the parsed tree of the current input.
+    pub fn tree(&self) -> &[NorgAST] {
+        &self.tree
+    }
+
+    /// Returns every node of the current tree matched by a [`crate::query`] selector, e.g.
+    /// `heading[level=2] > list[todo=undone]`.
+    pub fn select(&self, selector: &str) -> Result<Vec<Match<'_>>, SelectorError> {
+        query::select(&self.tree, selector)
+    }
+
+    /// Returns every problem found in the current input.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.nodes
+            .iter()
+            .flat_map(|node| node.diagnostics.iter().cloned())
+            .collect()
+    }
+
+    /// Consumes the document, returning the tree and the diagnostics.
+    pub fn into_parts(self) -> (Vec<NorgAST>, Vec<Diagnostic>) {
+        let diagnostics = self.diagnostics();
+
+        (self.tree, diagnostics)
+    }
+
+    /// Replaces the text in `range` (byte offsets into the current input) with `replacement`
+    /// and updates the tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or does not lie on character boundaries, just like
+    /// [`String::replace_range`].
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) {
+        let old_start = position_at(&self.input, range.start);
+        let old_end = position_at(&self.input, range.end);
+
+        let mut input = std::mem::take(&mut self.input);
+        input.replace_range(range.clone(), replacement);
+
+        let new_end = position_at(&input, range.start + replacement.len());
+        let shift = Shift { old_end, new_end };
+
+        let nodes = std::mem::take(&mut self.nodes);
+        let starts: Vec<_> = nodes.iter().map(|node| node.flat.span().start).collect();
+
+        // The node right before the edit may change as well (e.g. when a newline is inserted at
+        // its end), and the one before that looks one block ahead into it. Anything that may have
+        // looked even further ahead has to be parsed again too.
+        let touched = starts.partition_point(|start| start.offset < old_start.offset);
+        let first = nodes
+            .iter()
+            .position(|node| node.open_ended)
+            .unwrap_or(usize::MAX)
+            .min(touched.saturating_sub(2));
+        let window_start = match first {
+            0 => Position::default(),
+            first => starts[first],
+        };
+
+        // The first node that starts after the edit is always parsed again, so that there is a
+        // node to compare against the previous result.
+        let after = starts.partition_point(|start| start.offset <= old_end.offset);
+        let mut last = (after + 1).min(nodes.len());
+
+        let window = loop {
+            let window_end = match starts.get(last) {
+                Some(start) => shift.position(*start),
+                None => source_end(&input),
+            };
+
+            let window = parse_nodes(&input, window_start, window_end);
+
+            if last == nodes.len() || converged(&window, &nodes[last - 1], &shift) {
+                break window;
+            }
+
+            // Grow the window exponentially, so that an edit which changes the rest of the
+            // document does not get parsed over and over again.
+            last = (last + (last - first).max(1)).min(nodes.len());
+        };
+
+        // When the window converged, its last node is the same as the old one, which already
+        // comes with the correct diagnostics.
+        let reused = if last == nodes.len() { last } else { last - 1 };
+        let window_len = window.len() - (last - reused);
+
+        let mut nodes = nodes;
+        let suffix = nodes.split_off(reused).into_iter().map(|mut node| {
+            shift.node(&mut node);
+            node
+        });
+        nodes.truncate(first);
+        nodes.extend(window.into_iter().take(window_len));
+        nodes.extend(suffix);
+
+        *self = Self::from_nodes(input, nodes);
+    }
+}
+
+/// Checks whether the nodes parsed in the window agree with what a full parse would produce:
+/// none of them may have looked past the end of the window, and the last one has to match the
+/// node of the previous parse, after which everything is the same as before.
+fn converged(window: &[Node], previous: &Node, shift: &Shift) -> bool {
+    let Some((last, rest)) = window.split_last() else {
+        return false;
+    };
+
+    let mut previous = previous.clone();
+    shift.node(&mut previous);
+
+    rest.iter().all(|node| !node.open_ended) && last.flat == previous.flat
+}
+
+/// Runs all stages apart from the last one over the part of the input between `start` and `end`.
+///
+/// Blocks which cannot be parsed become error nodes. Should `stage_2` fail, the line it failed on
+/// becomes an error node instead, and the input before and after that line is parsed on its own.
+fn parse_nodes(input: &str, start: Position, end: Position) -> Vec<Node> {
+    let tokens = stage_1_between(input, start, end);
+    let blocks = match stage_2().parse(spanned_stream(tokens)) {
+        Ok(blocks) => blocks,
+        Err(errors) => return recover_line(input, start, end, errors),
+    };
+
+    stage_3_recovering(input, blocks.clone())
+        .into_iter()
+        .map(|(flat, errors)| {
+            let span = flat.span();
+            let open_ended = matches!(flat, NorgASTFlat::Error { .. })
+                || blocks
+                    .iter()
+                    .filter(|(_, block_span)| {
+                        span.start <= block_span.start && block_span.end <= span.end
+                    })
+                    .any(|(block, _)| may_be_verbatim(block));
+
+            Node {
+                flat,
+                diagnostics: NorgParseError::from(errors).diagnostics(),
+                open_ended,
+            }
+        })
+        .collect()
+}
+
+/// Turns the line on which `stage_2` failed into an error node, parsing what comes before and
+/// after it separately.
+fn recover_line(
+    input: &str,
+    start: Position,
+    end: Position,
+    errors: Vec<Simple<NorgToken, Span>>,
+) -> Vec<Node> {
+    let at = errors
+        .iter()
+        .map(|error| error.span().start)
+        .min()
+        .unwrap_or(start)
+        .clamp(start, end);
+    let line_start = match input[start.offset..at.offset].rfind('\n') {
+        Some(i) => position_at(input, start.offset + i + 1),
+        None => start,
+    };
+    let line_end = match input[at.offset..end.offset].find('\n') {
+        Some(i) => position_at(input, at.offset + i + 1),
+        None => end,
+    };
+    // An error on an empty last line leaves nothing to skip, so the whole part is given up on.
+    let (line_start, line_end) = match line_start == line_end {
+        true => (start, end),
+        false => (line_start, line_end),
+    };
+
+    let span = Span::new(line_start, line_end);
+    let mut nodes = match line_start == start {
+        true => vec![],
+        false => parse_nodes(input, start, line_start),
+    };
+    nodes.push(Node {
+        flat: NorgASTFlat::Error {
+            content: input[span.range()].to_string(),
+            span,
+        },
+        diagnostics: NorgParseError::from(errors).diagnostics(),
+        open_ended: true,
+    });
+    if line_end != end {
+        nodes.extend(parse_nodes(input, line_end, end));
+    }
+
+    nodes
+}
+
+/// Paragraphs starting with `@` are what is left of a verbatim ranged tag without an `@end`,
+/// which `stage_2` searches for until the end of the input.
+fn may_be_verbatim(block: &NorgBlock) -> bool {
+    match block {
+        NorgBlock::ParagraphSegment(tokens) | NorgBlock::ParagraphSegmentEnd(tokens) => {
+            matches!(tokens.first(), Some((NorgToken::Special('@'), _)))
+        }
+        _ => false,
+    }
+}
+
+/// Moves locations after an edit to where they are in the edited input.
+struct Shift {
+    old_end: Position,
+    new_end: Position,
+}
+
+impl Shift {
+    fn position(&self, position: Position) -> Position {
+        let Self { old_end, new_end } = self;
+
+        if position.line == old_end.line {
+            Position {
+                offset: position.offset - old_end.offset + new_end.offset,
+                line: new_end.line,
+                column: position.column - old_end.column + new_end.column,
+            }
+        } else {
+            Position {
+                offset: position.offset - old_end.offset + new_end.offset,
+                line: position.line - old_end.line + new_end.line,
+                column: position.column,
+            }
+        }
+    }
+
+    fn span(&self, span: &mut Span) {
+        *span = Span::new(self.position(span.start), self.position(span.end));
+    }
+
+    fn node(&self, node: &mut Node) {
+        node.flat.spans_mut(&mut |span| self.span(span));
+        node.diagnostics
+            .iter_mut()
+            .for_each(|diagnostic| self.span(&mut diagnostic.span));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::{collection::vec, prop_assert_eq, proptest};
+
+    use chumsky::error::Simple;
+
+    use crate::incremental::{recover_line, ParsedDocument};
+    use crate::span::{position_at, source_end, Position, Span};
+    use crate::NorgASTFlat;
+
+    const DOCUMENT_REGEX: &str = r"([*\-~>$^:@|=.#+(){}\[\] \na-z]|@end|@code|\|end|\$\$)*";
+
+    fn assert_same_as_full_parse(document: &ParsedDocument) {
+        let full = ParsedDocument::new(document.input());
+
+        assert_eq!(document.tree(), full.tree());
+        assert_eq!(document.diagnostics(), full.diagnostics());
+    }
+
+    #[test]
+    fn edits() {
+        let mut document = ParsedDocument::new("* Heading\n  Some text.\n\n- a\n- b\n\nThe end.\n");
+
+        let edits = [
+            (2..9, "Title"),
+            (20..20, "ly"),
+            (28..28, "~ c\n"),
+            (0..0, "@code\n"),
+            (43..43, "@end\n"),
+            (0..6, ""),
+        ];
+
+        let examples: Vec<_> = edits
+            .into_iter()
+            .map(|(range, replacement)| {
+                document.edit(range, replacement);
+                assert_same_as_full_parse(&document);
+
+                document.input().to_string()
+            })
+            .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn stage_2_errors() {
+        let input = "* Heading\n\nbroken line\n\n- item\n";
+        let error = Simple::custom(
+            Span::new(position_at(input, 13), position_at(input, 14)),
+            "x",
+        );
+
+        let kinds: Vec<_> =
+            recover_line(input, Position::default(), source_end(input), vec![error])
+                .iter()
+                .map(|node| match &node.flat {
+                    NorgASTFlat::Error { content, .. } => format!("error {content:?}"),
+                    flat => format!("{:?}", &input[flat.span().range()]),
+                })
+                .collect();
+
+        assert_eq!(
+            kinds,
+            ["\"* Heading\"", "error \"broken line\\n\"", "\"- item\""]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn edits_proptests(
+            input in DOCUMENT_REGEX,
+            edits in vec((0..1000usize, 0..20usize, DOCUMENT_REGEX), 1..4),
+        ) {
+            let mut document = ParsedDocument::new(input);
+
+            for (start, len, replacement) in edits {
+                let start = start % (document.input().len() + 1);
+                let end = (start + len).min(document.input().len());
+                document.edit(start..end, &replacement);
+
+                let full = ParsedDocument::new(document.input());
+                prop_assert_eq!(document.tree(), full.tree());
+                prop_assert_eq!(document.diagnostics(), full.diagnostics());
+            }
+        }
+    }
+}
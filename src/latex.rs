@@ -0,0 +1,800 @@
+//! Exporting a tree as a LaTeX document.
+//!
+//! Headings become `\section` down to `\subparagraph`, with deeper headings written at that
+//! level. Lists become `itemize` and `enumerate`, quotes `quote`, definitions `description` and
+//! tables made of cells a `tabular`. Footnote references become `\footnote`s holding the
+//! content of the footnote, which is left out where it is defined. Inline math and `@math`
+//! tags are passed through untouched, the latter in `\[ … \]` unless it starts an environment
+//! of its own. Code blocks and other verbatim ranged tags become `verbatim` environments.
+//!
+//! Links to headings, definitions and inline link targets use `\hyperref`, links into other
+//! files point at the `.pdf` file with the same name. Done and undone list items get a checkbox
+//! as their label, and other extensions are kept in their Norg form in `\texttt`.
+//!
+//! The preamble is set up from `@document.meta`. `title`, `authors` and `date` become the
+//! title, and the `latex` object can set the document `class`, its `options`, extra
+//! `packages` and the options of the `geometry` package:
+//!
+//! ```norg
+//! @document.meta
+//! title: Notes
+//! latex: {
+//!   class: report
+//!   options: [
+//!     a4paper
+//!     11pt
+//!   ]
+//!   packages: siunitx
+//!   geometry: margin=2cm
+//! }
+//! @end
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::{
+    export::{groups, normalize, raw, slug, trim_start, verbatim, TargetKind, Targets},
+    format::extension_text,
+    metadata::{parse_metadata, NorgMeta},
+    table::NorgTable,
+    text::plain_text,
+    DelimitingModifier, DetachedModifierExtension, LinkTarget, NestableDetachedModifier, NorgAST,
+    ParagraphSegment, ParagraphSegmentToken, RangeableDetachedModifier, Span, TodoStatus,
+};
+
+/// The packages the output relies on. Extra packages are loaded after these, and `hyperref`
+/// last.
+const PACKAGES: [&str; 4] = [
+    "[T1]{fontenc}",
+    "{amsmath}",
+    "{amssymb}",
+    "[normalem]{ulem}",
+];
+
+const SECTIONS: [&str; 5] = [
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+
+/// Exports a tree as returned by [`crate::parse_tree`].
+pub fn export(tree: &[NorgAST]) -> String {
+    let mut exporter = Exporter {
+        targets: Targets::new(tree, |kind, text| match kind {
+            TargetKind::Footnote => format!("fn-{}", slug(text)),
+            _ => slug(text),
+        }),
+        notes: Vec::new(),
+    };
+
+    let meta = tree.iter().find_map(|node| match node {
+        NorgAST::VerbatimRangedTag { name, content, .. } if name == &["document", "meta"] => {
+            match parse_metadata(content) {
+                Ok(NorgMeta::Object(fields)) => Some(fields),
+                _ => None,
+            }
+        }
+        _ => None,
+    });
+    let preamble = Preamble::new(meta.unwrap_or_default());
+
+    let mut output = preamble.to_string();
+    output += "\n\\begin{document}\n\n";
+    if preamble.title.is_some() {
+        output += "\\maketitle\n\n";
+    }
+    for block in exporter.blocks(tree) {
+        output += &block;
+        output += "\n\n";
+    }
+    output += "\\end{document}\n";
+
+    output
+}
+
+/// The options taken from `@document.meta`.
+#[derive(Debug, Default)]
+struct Preamble {
+    class: Option<String>,
+    options: Vec<String>,
+    packages: Vec<String>,
+    geometry: Vec<String>,
+    title: Option<String>,
+    authors: Vec<String>,
+    date: Option<String>,
+}
+
+impl Preamble {
+    fn new(mut meta: BTreeMap<String, NorgMeta>) -> Self {
+        let mut latex = match meta.remove("latex") {
+            Some(NorgMeta::Object(latex)) => latex,
+            _ => BTreeMap::new(),
+        };
+        let take = |meta: &mut BTreeMap<String, NorgMeta>, key: &str| {
+            meta.remove(key).map(strings).unwrap_or_default()
+        };
+
+        Self {
+            class: take(&mut latex, "class").pop(),
+            options: take(&mut latex, "options"),
+            packages: take(&mut latex, "packages"),
+            geometry: take(&mut latex, "geometry"),
+            title: take(&mut meta, "title").pop(),
+            authors: take(&mut meta, "authors"),
+            date: take(&mut meta, "date").pop(),
+        }
+    }
+}
+
+impl std::fmt::Display for Preamble {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let class = self.class.as_deref().unwrap_or("article");
+        match self.options.as_slice() {
+            [] => writeln!(f, "\\documentclass{{{class}}}")?,
+            options => writeln!(f, "\\documentclass[{}]{{{class}}}", options.join(","))?,
+        }
+
+        for package in PACKAGES {
+            writeln!(f, "\\usepackage{package}")?;
+        }
+        if !self.geometry.is_empty() {
+            writeln!(f, "\\usepackage[{}]{{geometry}}", self.geometry.join(","))?;
+        }
+        for package in &self.packages {
+            writeln!(f, "\\usepackage{{{package}}}")?;
+        }
+        writeln!(f, "\\usepackage{{hyperref}}")?;
+
+        if let Some(title) = &self.title {
+            writeln!(f, "\\title{{{}}}", escape(title))?;
+        }
+        if !self.authors.is_empty() {
+            let authors: Vec<_> = self.authors.iter().map(|author| escape(author)).collect();
+            writeln!(f, "\\author{{{}}}", authors.join(" \\and "))?;
+        }
+        if let Some(date) = &self.date {
+            writeln!(f, "\\date{{{}}}", escape(date))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A metadata value as a list of strings, so that single values and arrays can be used alike.
+fn strings(meta: NorgMeta) -> Vec<String> {
+    match meta {
+        NorgMeta::Str(text) | NorgMeta::EmptyKey(text) => vec![text],
+        NorgMeta::Num(number) => vec![number.to_string()],
+        NorgMeta::Bool(value) => vec![value.to_string()],
+        NorgMeta::Array(items) => items.into_iter().flat_map(strings).collect(),
+        NorgMeta::Object(_) | NorgMeta::Nil | NorgMeta::Invalid => Vec::new(),
+    }
+}
+
+struct Exporter {
+    targets: Targets,
+    /// The footnotes whose content is being exported, so that a footnote referencing itself
+    /// does not expand forever.
+    notes: Vec<String>,
+}
+
+impl Exporter {
+    fn label(&self, span: &Span) -> &str {
+        self.targets.id(span)
+    }
+
+    /// Exports the nodes of a scope as separate blocks, grouping consecutive list items, quotes,
+    /// definitions and table cells.
+    fn blocks(&mut self, nodes: &[NorgAST]) -> Vec<String> {
+        let mut blocks = Vec::new();
+
+        for group in groups(nodes) {
+            let block = match &group[0] {
+                NorgAST::NestableDetachedModifier { modifier_type, .. } => {
+                    Some(self.list(modifier_type, group))
+                }
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Definition,
+                    ..
+                } => Some(self.definitions(group)),
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Table,
+                    ..
+                } => self.table(group),
+                node => self.node(node),
+            };
+            blocks.extend(block.filter(|block| !block.is_empty()));
+        }
+
+        blocks
+    }
+
+    fn node(&mut self, node: &NorgAST) -> Option<String> {
+        match node {
+            NorgAST::Paragraph { content, .. } => Some(self.inline(content)),
+            NorgAST::Heading {
+                level,
+                title,
+                extensions,
+                content,
+                span,
+            } => {
+                let command = SECTIONS[(*level).clamp(1, 5) as usize - 1];
+                let heading = format!(
+                    "\\{command}{{{}{}}}\\label{{{}}}",
+                    code_extensions(extensions),
+                    self.inline(trim_start(title)),
+                    self.label(span)
+                );
+
+                Some(
+                    std::iter::once(heading)
+                        .chain(self.blocks(content))
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                )
+            }
+            // Footnotes are exported where they are referenced.
+            NorgAST::RangeableDetachedModifier {
+                modifier_type: RangeableDetachedModifier::Footnote,
+                ..
+            } => None,
+            // Grouped by `blocks`.
+            NorgAST::NestableDetachedModifier { .. }
+            | NorgAST::RangeableDetachedModifier { .. } => {
+                Some(self.blocks(std::slice::from_ref(node)).join("\n\n"))
+            }
+            NorgAST::CarryoverTag { next_object, .. } => {
+                Some(self.blocks(std::slice::from_ref(next_object)).join("\n\n"))
+            }
+            NorgAST::VerbatimRangedTag { name, content, .. } => match name.join(".").as_str() {
+                // Written as the preamble.
+                "document.meta" => None,
+                "math" => {
+                    let content = content.trim();
+                    match content.starts_with("\\begin{") {
+                        true => Some(content.to_string()),
+                        false => Some(format!("\\[\n{content}\n\\]")),
+                    }
+                }
+                _ => Some(format!(
+                    "\\begin{{verbatim}}\n{}\\end{{verbatim}}",
+                    with_newline(content)
+                )),
+            },
+            NorgAST::RangedTag { name, content, .. } => {
+                (name != &["comment"]).then(|| self.blocks(content).join("\n\n"))
+            }
+            NorgAST::DelimitingModifier {
+                modifier_type: DelimitingModifier::HorizontalRule,
+                ..
+            } => Some("\\noindent\\rule{\\linewidth}{0.4pt}".to_string()),
+            NorgAST::DelimitingModifier { .. } | NorgAST::InfirmTag { .. } => None,
+            NorgAST::Error { content, .. } => Some(escape(content.trim())),
+        }
+    }
+
+    fn list(&mut self, modifier_type: &NestableDetachedModifier, items: &[NorgAST]) -> String {
+        let environment = match modifier_type {
+            NestableDetachedModifier::Quote => "quote",
+            NestableDetachedModifier::UnorderedList => "itemize",
+            NestableDetachedModifier::OrderedList => "enumerate",
+        };
+        let mut blocks = Vec::new();
+
+        for item in items {
+            let NorgAST::NestableDetachedModifier {
+                extensions,
+                text,
+                content,
+                ..
+            } = item
+            else {
+                continue;
+            };
+            let text = match text.as_ref() {
+                NorgAST::Paragraph { content, .. } => self.inline(content),
+                _ => String::new(),
+            };
+
+            // Checkboxes replace the bullet, numbered items keep their number.
+            let item = match modifier_type {
+                NestableDetachedModifier::Quote => code_extensions(extensions) + &text,
+                NestableDetachedModifier::UnorderedList => match checkbox(extensions) {
+                    Some(checkbox) => format!("\\item[{checkbox}] {}{text}", rest(extensions)),
+                    None => format!("\\item {}{text}", code_extensions(extensions)),
+                },
+                NestableDetachedModifier::OrderedList => {
+                    let checkbox = checkbox(extensions).map(|checkbox| checkbox + " ");
+                    format!(
+                        "\\item {}{}{text}",
+                        checkbox.unwrap_or_default(),
+                        rest(extensions)
+                    )
+                }
+            };
+
+            blocks.push(
+                std::iter::once(item)
+                    .chain(self.blocks(content))
+                    .collect::<Vec<_>>()
+                    .join("\n\n"),
+            );
+        }
+
+        let separator = match modifier_type {
+            NestableDetachedModifier::Quote => "\n\n",
+            _ => "\n",
+        };
+        format!(
+            "\\begin{{{environment}}}\n{}\n\\end{{{environment}}}",
+            blocks.join(separator)
+        )
+    }
+
+    fn definitions(&mut self, definitions: &[NorgAST]) -> String {
+        let mut blocks = Vec::new();
+
+        for definition in definitions {
+            if let NorgAST::RangeableDetachedModifier {
+                title,
+                content,
+                span,
+                ..
+            } = definition
+            {
+                let term = format!(
+                    "\\item[{{{}}}]\\phantomsection\\label{{{}}}",
+                    self.inline(title).trim(),
+                    self.label(span)
+                );
+
+                match self.blocks(content).join("\n\n") {
+                    content if content.is_empty() => blocks.push(term),
+                    content => blocks.push(format!("{term}\n{content}")),
+                }
+            }
+        }
+
+        format!(
+            "\\begin{{description}}\n{}\n\\end{{description}}",
+            blocks.join("\n")
+        )
+    }
+
+    /// Exports table cells as a `tabular` with ruled cells. Cells without a valid address are
+    /// left out, and the content of cells written several times is joined.
+    fn table(&mut self, cells: &[NorgAST]) -> Option<String> {
+        let table = NorgTable::from_nodes(cells)?;
+        let grid = table.grid();
+        let columns = grid.first().map_or(0, Vec::len);
+
+        let mut lines = vec![
+            format!("\\begin{{tabular}}{{|{}}}", "l|".repeat(columns)),
+            "\\hline".to_string(),
+        ];
+        for row in grid {
+            let cells: Vec<_> = row
+                .into_iter()
+                .map(|cell| match cell {
+                    Some(cell) => self.blocks(&cell.content).join(" ").replace('\n', " "),
+                    None => String::new(),
+                })
+                .collect();
+            lines.push(format!("{} \\\\", cells.join(" & ")));
+            lines.push("\\hline".to_string());
+        }
+        lines.push("\\end{tabular}".to_string());
+
+        Some(lines.join("\n"))
+    }
+
+    fn inline(&mut self, segments: &[ParagraphSegment]) -> String {
+        let mut output = String::new();
+
+        for segment in segments {
+            match segment {
+                ParagraphSegment::Token { token, .. } => output += &token_text(token),
+                ParagraphSegment::AttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                }
+                | ParagraphSegment::FreeFormAttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                } => {
+                    let command = match modifier_type {
+                        '*' => "\\textbf",
+                        '/' => "\\emph",
+                        '_' => "\\uline",
+                        '-' => "\\sout",
+                        '^' => "\\textsuperscript",
+                        ',' => "\\textsubscript",
+                        '`' => {
+                            let text = verbatim(segment).unwrap_or_default();
+                            output += &format!("\\texttt{{{}}}", escape(&text));
+                            continue;
+                        }
+                        '$' => {
+                            output += &format!("${}$", verbatim(segment).unwrap_or_default());
+                            continue;
+                        }
+                        // The null modifier comments its content out.
+                        '%' => continue,
+                        _ => "",
+                    };
+
+                    output += &format!("{command}{{{}}}", self.inline(content));
+                }
+                ParagraphSegment::InlineVerbatim { content, .. } => {
+                    output += &format!("\\texttt{{{}}}", escape(&raw(content)));
+                }
+                ParagraphSegment::Link {
+                    filepath,
+                    targets,
+                    description,
+                    ..
+                } => output += &self.link(filepath.as_deref(), targets, description.as_deref()),
+                ParagraphSegment::AnchorDefinition {
+                    content, target, ..
+                } => {
+                    let text = self.inline(content);
+                    output += &self.anchor(text, target);
+                }
+                ParagraphSegment::Anchor {
+                    content,
+                    description,
+                    ..
+                } => {
+                    let text = self.inline(description.as_deref().unwrap_or(content));
+                    output += &match self.targets.anchor(&plain_text(content)).cloned() {
+                        Some(target) => self.anchor(text, &target),
+                        None => text,
+                    };
+                }
+                ParagraphSegment::InlineLinkTarget { content, span } => {
+                    output += &format!("\\phantomsection\\label{{{}}}", self.label(span));
+                    output += &self.inline(content);
+                }
+                // Only used while parsing, these never end up in the tree.
+                ParagraphSegment::AttachedModifierOpener(_)
+                | ParagraphSegment::AttachedModifierOpenerFail(_)
+                | ParagraphSegment::AttachedModifierCloserCandidate(_)
+                | ParagraphSegment::AttachedModifierCloser(_)
+                | ParagraphSegment::AttachedModifierCandidate { .. } => {}
+            }
+        }
+
+        output
+    }
+
+    fn link(
+        &mut self,
+        filepath: Option<&str>,
+        targets: &[LinkTarget],
+        description: Option<&[ParagraphSegment]>,
+    ) -> String {
+        let text = description.map(|description| self.inline(description));
+
+        // Footnote references become footnotes, after the description if there is one.
+        if let (None, Some(LinkTarget::Footnote { title, .. })) = (filepath, targets.last()) {
+            let name = normalize(&plain_text(title));
+
+            if let Some(content) = self.targets.footnote(title).map(<[_]>::to_vec) {
+                if !self.notes.contains(&name) {
+                    self.notes.push(name);
+                    let footnote = self.blocks(&content).join("\n\n");
+                    self.notes.pop();
+
+                    return format!("{}\\footnote{{{footnote}}}", text.unwrap_or_default());
+                }
+            }
+        }
+
+        if let (None, None, Some(LinkTarget::Url { url, .. })) = (filepath, &text, targets.last()) {
+            return format!("\\url{{{}}}", escape_url(url));
+        }
+
+        let text = match (text, targets.last()) {
+            (Some(text), _) => text,
+            (
+                None,
+                Some(LinkTarget::Url { url: text, .. } | LinkTarget::Path { path: text, .. }),
+            ) => escape(text),
+            (None, Some(LinkTarget::LineNumber { line, .. })) => line.to_string(),
+            (None, Some(LinkTarget::Timestamp { timestamp, .. })) => escape(&timestamp.raw),
+            (
+                None,
+                Some(
+                    LinkTarget::Heading { title, .. }
+                    | LinkTarget::Footnote { title, .. }
+                    | LinkTarget::Definition { title, .. }
+                    | LinkTarget::Generic { title, .. }
+                    | LinkTarget::Wiki { title, .. }
+                    | LinkTarget::Extendable { title, .. },
+                ),
+            ) => self.inline(title),
+            (None, None) => escape(filepath.unwrap_or_default()),
+        };
+
+        self.reference(text, filepath, targets)
+    }
+
+    /// Links the text of an anchor to the target of its definition.
+    fn anchor(&mut self, text: String, target: &ParagraphSegment) -> String {
+        match target {
+            ParagraphSegment::Link {
+                filepath, targets, ..
+            } => self.reference(text, filepath.as_deref(), targets),
+            _ => text,
+        }
+    }
+
+    /// Links text to the targets of a link. Only the last target is used, as it is the most
+    /// specific one. Text linking to targets in this file which cannot be found is left as it
+    /// is.
+    fn reference(&self, text: String, filepath: Option<&str>, targets: &[LinkTarget]) -> String {
+        let href = match (filepath, targets.last()) {
+            (None, Some(LinkTarget::Url { url, .. } | LinkTarget::Path { path: url, .. })) => {
+                Some(url.clone())
+            }
+            (Some(filepath), _) => Some(format!("{filepath}.pdf")),
+            (None, _) => None,
+        };
+        if let Some(href) = href {
+            return format!("\\href{{{}}}{{{text}}}", escape_url(&href));
+        }
+
+        let target = match targets.last() {
+            Some(LinkTarget::Heading { level, title, .. }) => {
+                self.targets.find(Some(TargetKind::Heading(*level)), title)
+            }
+            Some(LinkTarget::Definition { title, .. }) => {
+                self.targets.find(Some(TargetKind::Definition), title)
+            }
+            Some(
+                LinkTarget::Generic { title, .. }
+                | LinkTarget::Wiki { title, .. }
+                | LinkTarget::Extendable { title, .. },
+            ) => self.targets.find(None, title),
+            _ => None,
+        };
+        // Footnotes are exported where they are referenced, without a label.
+        let label = target
+            .filter(|target| target.kind != TargetKind::Footnote)
+            .map(|target| &target.id);
+
+        match label {
+            Some(label) => format!("\\hyperref[{label}]{{{text}}}"),
+            None => text,
+        }
+    }
+}
+
+/// The checkbox of a done or undone item.
+fn checkbox(extensions: &[DetachedModifierExtension]) -> Option<String> {
+    extensions.iter().find_map(|extension| match extension {
+        DetachedModifierExtension::Todo(TodoStatus::Done) => Some("$\\boxtimes$".to_string()),
+        DetachedModifierExtension::Todo(TodoStatus::Undone) => Some("$\\square$".to_string()),
+        _ => None,
+    })
+}
+
+/// The extensions a checkbox cannot show, see [`code_extensions`].
+fn rest(extensions: &[DetachedModifierExtension]) -> String {
+    let rest: Vec<_> = extensions
+        .iter()
+        .filter(|extension| {
+            !matches!(
+                extension,
+                DetachedModifierExtension::Todo(TodoStatus::Done | TodoStatus::Undone)
+            )
+        })
+        .cloned()
+        .collect();
+
+    code_extensions(&rest)
+}
+
+/// Extensions in their Norg form in a typewriter font, followed by a space.
+fn code_extensions(extensions: &[DetachedModifierExtension]) -> String {
+    match extensions {
+        [] => String::new(),
+        extensions => format!("\\texttt{{{}}} ", escape(&extension_text(extensions))),
+    }
+}
+
+fn token_text(token: &ParagraphSegmentToken) -> String {
+    match token {
+        ParagraphSegmentToken::Text(text) => escape(text),
+        ParagraphSegmentToken::Whitespace => " ".to_string(),
+        ParagraphSegmentToken::Escape('\n') => "\\\\\n".to_string(),
+        ParagraphSegmentToken::Special(c) | ParagraphSegmentToken::Escape(c) => {
+            escape(&c.to_string())
+        }
+    }
+}
+
+fn with_newline(content: &str) -> String {
+    match content {
+        "" => String::new(),
+        content if content.ends_with('\n') => content.to_string(),
+        content => format!("{content}\n"),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '<' => escaped.push_str("\\textless{}"),
+            '>' => escaped.push_str("\\textgreater{}"),
+            '|' => escaped.push_str("\\textbar{}"),
+            '{' | '}' | '$' | '&' | '%' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Escapes the characters `\url` and `\href` cannot take as they are.
+fn escape_url(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+
+    for c in url.chars() {
+        if matches!(c, '\\' | '{' | '}' | '%' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::proptest;
+
+    use crate::{
+        latex::{escape, export},
+        parse_tree,
+    };
+
+    /// Exports `input`, leaving out the preamble.
+    fn body(input: &str) -> String {
+        let output = export(&parse_tree(input).unwrap());
+        let (_, body) = output.split_once("\\begin{document}\n\n").unwrap();
+
+        body.trim_end_matches("\\end{document}\n").to_string()
+    }
+
+    #[test]
+    fn exporting() {
+        assert_yaml_snapshot!(
+            "preamble",
+            export(
+                &parse_tree(
+                    "@document.meta
+                     title: Notes & Formulas
+                     authors: [
+                       Jane Doe
+                       John Doe
+                     ]
+                     latex: {
+                       class: report
+                       options: [
+                         a4paper
+                         11pt
+                       ]
+                       packages: [
+                         siunitx
+                         microtype
+                       ]
+                       geometry: margin=2cm
+                     }
+                     @end
+                     Some text.\n"
+                )
+                .unwrap()
+            )
+        );
+        assert_yaml_snapshot!(
+            "headings",
+            body("* (x) Tasks\n** Second level\n****** Deep heading\n")
+        );
+        assert_yaml_snapshot!(
+            "lists",
+            body(
+                "- ( ) Write *the* /exporter/
+                 -- (x) Escape 100% of \\{braces\\} and #hashes
+                 - (!|# A) Ship it
+                 ~ First
+                 ~ (x) Second\n"
+            )
+        );
+        assert_yaml_snapshot!("quotes", body("> Quoted -text-\n>> Nested\n"));
+        assert_yaml_snapshot!(
+            "markup",
+            body("*Bold*, /italic/, _underline_, `inline`, ^super^, ,sub, and %comment%.\n")
+        );
+        assert_yaml_snapshot!(
+            "links",
+            body(
+                "Links to {* Code}, {$ Term}, {https://example.com/a_b#c}[example],
+                 {https://neorg.org}, {:other:** Heading}[elsewhere] and
+                 [anchor]{https://neorg.org}. Then [anchor] again and an <inline target>.
+                 * Code
+                 $ Term
+                 The definition.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "footnotes",
+            body("A note.{^ Note}\n^ Note\nA footnote with $x_1$.\n")
+        );
+        assert_yaml_snapshot!(
+            "definitions",
+            body("$ Term\nThe definition.\n$ Other term\nAnother one.\n")
+        );
+        assert_yaml_snapshot!("tables", body(": A1\n  Name\n: B1\n  Age\n: A2\n  Bob\n"));
+        assert_yaml_snapshot!("sparse_tables", body(": A1\n  a\n: C3\n  c\n"));
+        assert_yaml_snapshot!("code", body("@code rust\nfn main() {}\n@end\n"));
+        assert_yaml_snapshot!(
+            "math",
+            body(
+                "Euler's identity $e^{i\\pi} + 1 = 0$.
+                 @math
+                 \\int_0^1 x^2 \\, dx = \\frac{1}{3}
+                 @end
+                 @math
+                 \\begin{align}
+                   a &= b
+                 \\end{align}
+                 @end\n"
+            )
+        );
+        assert_yaml_snapshot!("verbatim", body("`a\\b`, $\\frac{1}{2}$ and &x\\_y&\n"));
+        assert_yaml_snapshot!(
+            "escapes",
+            body("Escaped \\*stars\\*, a backslash \\\\ and a pipe \\|.\n")
+        );
+        assert_yaml_snapshot!("escaped_pipes", body(": A1\n  a \\| b\n: B1\n  c\n"));
+    }
+
+    proptest! {
+        #[test]
+        fn escaped_text_has_no_markup(text in r"\PC*") {
+            let escaped = escape(&text);
+            let mut chars = escaped.chars().peekable();
+
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        let next = chars.next().unwrap();
+                        if next.is_ascii_alphabetic() {
+                            while chars.next_if(char::is_ascii_alphabetic).is_some() {}
+                            assert_eq!(chars.next(), Some('{'));
+                            assert_eq!(chars.next(), Some('}'));
+                        } else {
+                            assert!("{}$&%#_".contains(next));
+                        }
+                    }
+                    c => assert!(!"\\{}$&%#_~^".contains(c)),
+                }
+            }
+        }
+    }
+}
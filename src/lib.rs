@@ -1,6 +1,12 @@
+// Every chumsky 0.9 parser closure returns `Simple<_>` as its error, which is large by design.
+#![allow(clippy::result_large_err)]
+
 use chumsky::Parser as _;
-use error::NorgParseError;
 
+pub use crate::error::{Diagnostic, NorgParseError, SelectorError};
+use crate::incremental::ParsedDocument;
+use crate::span::spanned_stream;
+pub use crate::span::{Position, Span};
 pub use crate::stage_1::stage_1;
 pub use crate::stage_2::stage_2;
 use crate::stage_4::stage_4;
@@ -8,13 +14,33 @@ use crate::stage_4::stage_4;
 pub use crate::stage_2::ParagraphSegmentToken;
 pub use crate::stage_3::*;
 pub use crate::stage_4::NorgAST;
+pub use crate::table::NorgTable;
+pub use crate::timestamp::NorgTimestamp;
 
+pub mod cst;
 mod error;
+mod export;
+pub mod fold;
+pub mod format;
+#[cfg(feature = "html")]
+pub mod html;
+pub mod incremental;
+pub mod latex;
+pub mod markdown;
 pub mod metadata;
+pub mod org;
+pub mod pandoc;
+pub mod query;
+mod span;
 mod stage_1;
 mod stage_2;
 mod stage_3;
 mod stage_4;
+pub mod table;
+pub mod terminal;
+mod text;
+pub mod timestamp;
+pub mod visit;
 
 /// Parses the given input string through multiple stages to produce a flattened abstract syntax tree (AST).
 ///
@@ -26,23 +52,35 @@ mod stage_4;
 ///
 /// * `Ok(Vec<NorgASTFlat>)` if parsing is successful.
 /// * `Err(NorgParseError)` if any stage of parsing fails.
+///
+/// Every node carries a [`Span`] pointing back into `input`.
 pub fn parse(input: &str) -> Result<Vec<NorgASTFlat>, NorgParseError> {
-    Ok(stage_3().parse(stage_2().parse(stage_1().parse(input)?)?)?)
+    let tokens = stage_1(input);
+    let blocks = stage_2().parse(spanned_stream(tokens))?;
+
+    Ok(stage_3(input).parse(spanned_stream(blocks))?)
 }
 
 pub fn parse_tree(input: &str) -> Result<Vec<NorgAST>, NorgParseError> {
-    Ok(stage_4(
-        stage_3().parse(stage_2().parse(stage_1().parse(input)?)?)?,
-    ))
+    Ok(stage_4(parse(input)?))
+}
+
+/// Parses the given input string into a tree, recovering from errors instead of giving up.
+///
+/// Blocks which cannot be parsed are kept as [`NorgAST::Error`] nodes holding the original text,
+/// and parsing resumes at the next block which can be parsed. Why parsing failed is reported
+/// as a [`Diagnostic`] for every error node.
+pub fn parse_recovering(input: &str) -> (Vec<NorgAST>, Vec<Diagnostic>) {
+    ParsedDocument::new(input).into_parts()
 }
 
 #[cfg(test)]
 mod tests {
     use insta::assert_yaml_snapshot;
     use itertools::Itertools;
-    use proptest::{prop_oneof, proptest};
+    use proptest::{prop_assume, prop_oneof, proptest, sample::select};
 
-    use crate::{parse, parse_tree};
+    use crate::{format::tests::assert_formats_back, parse, parse_recovering, parse_tree};
 
     const TAG_NAME_REGEX: &str = r"[\w_\-\.\d]+";
     const TAG_PARAMETER_REGEX: &str = r"[^\s]+";
@@ -50,9 +88,9 @@ mod tests {
 
     const PARAGRAPH_REGEX: &str = r"[^[:punct:]\s][^\n\r]*";
 
-    #[test]
-    fn headings() {
-        let examples: Vec<_> = [
+    /// The inputs of the snapshot tests which parse successfully, also used by `formats_back`.
+    mod examples {
+        pub(super) const HEADINGS: &[&str] = &[
             "* Heading",
             "********* Heading",
             "
@@ -78,19 +116,9 @@ mod tests {
                more sneaky content inside.
             * Back to regular heading
             ",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
+        ];
 
-        assert_yaml_snapshot!(examples);
-    }
-
-    #[test]
-    fn headings_tree() {
-        let headings_tree_examples: Vec<_> = [
+        pub(super) const HEADINGS_TREE: &[&str] = &[
             "
             * Heading
             ** Another heading
@@ -101,18 +129,9 @@ mod tests {
             content
             * Back to regular heading
             ",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse_tree(&str))
-        .try_collect()
-        .unwrap();
-        assert_yaml_snapshot!(headings_tree_examples);
-    }
+        ];
 
-    #[test]
-    fn delimiting_mods_tree() {
-        let examples: Vec<_> = [
+        pub(super) const DELIMITING_MODS_TREE: &[&str] = &[
             "* One
                content
                ---
@@ -140,18 +159,9 @@ mod tests {
                one
                ---
              none",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse_tree(&str))
-        .try_collect()
-        .unwrap();
-        assert_yaml_snapshot!(examples);
-    }
+        ];
 
-    #[test]
-    fn lists_tree() {
-        let examples: Vec<_> = [
+        pub(super) const LISTS_TREE: &[&str] = &[
             "- base",
             "- one
              -- two",
@@ -163,18 +173,58 @@ mod tests {
              - one",
             "-- two
              - one",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse_tree(&str))
-        .try_collect()
-        .unwrap();
-        assert_yaml_snapshot!(examples);
-    }
+        ];
+
+        pub(super) const SLIDES_AND_INDENT_SEGMENTS: &[&str] = &[
+            "- item :
+             @code lua
+             print(1)
+             @end
+             - next",
+            "> quote ::
+               First paragraph.
+
+               Second paragraph.
+               -- nested
+             ---
+             After the segment.",
+            "* Heading
+             - one ::
+               content
+             ---
+             Still in the heading.",
+            "$ Term :
+             @code
+             x
+             @end",
+            ": A1 ::
+               First cell.
+
+               Still the first cell.
+             : B1
+               Second cell.",
+            "- not a slide:",
+        ];
+
+        pub(super) const NESTED_CONTENT_TREE: &[&str] = &[
+            "|details
+             * Heading
+               content
+             - one
+             -- two
+             |end",
+            "$$ Term
+             - one
+             -- two
+             $$",
+            "^^ Footnote
+             |example
+             ** Heading
+             |end
+             ^^",
+        ];
 
-    #[test]
-    fn lists() {
-        let examples: Vec<_> = [
+        pub(super) const LISTS: &[&str] = &[
             "- Test list",
             "---- Test list",
             "
@@ -188,19 +238,9 @@ mod tests {
             "---not list",
             // "- - a list item",
             "--> not a list",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
+        ];
 
-    #[test]
-    fn modifier_extensions() {
-        let examples: Vec<_> = [
+        pub(super) const MODIFIER_EXTENSIONS: &[&str] = &[
             "- ( ) undone",
             "* (x) done",
             "- (=) hold",
@@ -211,33 +251,9 @@ mod tests {
             "~ (+ Friday) recurring with date",
             "** ( |# Low|< Feb 1) undone, low, & before Feb",
             "** (# Two Words|x| |!|+|_|+ 5th|=|-|< Feb 1|> 2025|@ Jan 1 2025) All of them",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    #[test]
-    fn lists_regressions() {
-        [
-            "- - a list item",
-            "---- - a list item",
-            "---- > a list item",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .for_each(|str| {
-            parse(&str).unwrap_err();
-        });
-    }
+        ];
 
-    #[test]
-    fn ordered_lists() {
-        let examples: Vec<_> = [
+        pub(super) const ORDERED_LISTS: &[&str] = &[
             "~ Test list",
             "~~~~ Test list",
             "
@@ -250,33 +266,9 @@ mod tests {
             ",
             "~~~not list",
             "~~> not a list",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    #[test]
-    fn ordered_lists_regressions() {
-        [
-            "~ ~ a list item",
-            "~~~~ - a list item",
-            "~~~~ > a list item",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .for_each(|str| {
-            parse(&str).unwrap_err();
-        });
-    }
+        ];
 
-    #[test]
-    fn quotes() {
-        let examples: Vec<_> = [
+        pub(super) const QUOTES: &[&str] = &[
             "> Test quote",
             ">>>> Test quote",
             "
@@ -290,176 +282,42 @@ mod tests {
             ">>>not quote",
             // "> > a quote item",
             ">>- not a quote",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    #[test]
-    fn quotes_regressions() {
-        [
-            "> > a list item",
-            ">>>> - a list item",
-            ">>>> ~ a list item",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .for_each(|str| {
-            parse(&str).unwrap_err();
-        });
-    }
+        ];
 
-    #[test]
-    fn definitions() {
-        let examples: Vec<_> = [
+        pub(super) const DEFINITIONS: &[&str] = &[
             "$ Term
                Definition",
             "$$ Term
                 Long definition
              $$",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    #[test]
-    fn definitions_regressions() {
-        [
-            "$ Term Definition",
-            "$$ Term
-                Long definition $$",
-            "$$ Term
-                Long definition
-             $$text",
-            "$$ Term
-                Long definition
-             $$ text",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .for_each(|str| {
-            parse(&str).unwrap_err();
-        });
-    }
+        ];
 
-    #[test]
-    fn footnotes() {
-        let examples: Vec<_> = [
+        pub(super) const FOOTNOTES: &[&str] = &[
             "^ Title
                Content",
             "^^ Title
                 Long content
              ^^",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    #[test]
-    fn footnotes_regressions() {
-        [
-            "^ Term Definition",
-            "^^ Term
-                Long definition ^^",
-            "^^ Term
-                Long definition
-             ^^text",
-            "^^ Term
-                Long definition
-             ^^ text",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .for_each(|str| {
-            parse(&str).unwrap_err();
-        });
-    }
+        ];
 
-    #[test]
-    fn tables() {
-        let examples: Vec<_> = [
+        pub(super) const TABLES: &[&str] = &[
             ": A1
                Cell content",
             ":: A1
                 Long cell content.
              ::",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    #[test]
-    fn tables_regressions() {
-        [
-            ": Term Definition",
-            ":: Term
-                Long definition ::",
-            ":: Term
-                Long definition
-             ::text",
-            ":: Term
-                Long definition
-             :: text",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .for_each(|str| {
-            parse(&str).unwrap_err();
-        });
-    }
+        ];
 
-    #[test]
-    fn infirm_tags() {
-        let examples: Vec<_> = [
+        pub(super) const INFIRM_TAGS: &[&str] = &[
             ".tag",
             ".tag-name_with-complexchars",
             ".tag-name_ parameter",
             ".tag-name_ one\\ large\\ parameter",
             ".tag-name_ one\\ large\\ parameter &^@! third parameter",
             ".tag.name.image https://github.com/super-special/repo.git?text=hello&other_text=bye",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    proptest! {
-        #[test]
-        fn infirm_tags_proptests(tag_name in TAG_NAME_REGEX, parameter in TAG_PARAMETER_REGEX, multi_parameter in TAG_MULTI_PARAMETER_REGEX) {
-            let tag = format!(".{} {} {}\n", tag_name, parameter, multi_parameter);
+        ];
 
-            // TODO: Ensure that the number of parameters parsed is correct?
-            parse(&tag).unwrap();
-        }
-    }
-
-    #[test]
-    fn carryover_tags() {
-        let examples: Vec<_> = [
+        pub(super) const CARRYOVER_TAGS: &[&str] = &[
             "+tag
              paragraph",
             "+tag-name_with-complexchars
@@ -484,19 +342,9 @@ mod tests {
              paragraph",
             "#tag.name.image https://github.com/super-special/repo.git?text=hello&other_text=bye
              paragraph",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
+        ];
 
-    #[test]
-    fn carryover_tags_tree() {
-        let examples: Vec<_> = [
+        pub(super) const CARRYOVER_TAGS_TREE: &[&str] = &[
             "
             #id 123
             * tree
@@ -528,27 +376,9 @@ mod tests {
             #comment
             comment with id
             ",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse_tree(&str))
-        .try_collect()
-        .unwrap();
-        assert_yaml_snapshot!(examples);
-    }
-
-    proptest! {
-        #[test]
-        fn carryover_tags_proptests(tag_name in TAG_NAME_REGEX, parameter in TAG_PARAMETER_REGEX, multi_parameter in TAG_MULTI_PARAMETER_REGEX) {
-            let content = format!("#{} {} {}\nhello world!", tag_name, parameter, multi_parameter);
-
-            parse(&content).unwrap();
-        }
-    }
+        ];
 
-    #[test]
-    fn ranged_verbatim_tags() {
-        let examples: Vec<_> = [
+        pub(super) const RANGED_VERBATIM_TAGS: &[&str] = &[
             r#"@code
                print("Hello world!")
                @end"#,
@@ -563,34 +393,9 @@ mod tests {
 
                hello()
                @end"#,
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
+        ];
 
-    proptest! {
-        #[test]
-        // NOTE: `.*` may at some point generate an `@end` purely by chance. There is a basic
-        // check against this, but this should probably be done as a filter in proptest.
-        fn ranged_verbatim_tags_proptests(tag_name in TAG_NAME_REGEX, parameter in TAG_PARAMETER_REGEX, multi_parameter in TAG_MULTI_PARAMETER_REGEX, content in ".*") {
-            if content.contains("@end") {
-                return Ok(());
-            }
-
-            let content = format!("@{} {} {}\n{}\n@end", tag_name, parameter, multi_parameter, content);
-
-            parse(&content).unwrap();
-        }
-    }
-
-    #[test]
-    fn ranged_tags() {
-        let examples: Vec<_> = [
+        pub(super) const RANGED_TAGS: &[&str] = &[
             r#"|example
                Hello world!
                |end"#,
@@ -627,34 +432,9 @@ mod tests {
                * Hello world!
                =end
                =end"#,
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    proptest! {
-        #[test]
-        // NOTE: `.*` may at some point generate an `@end` purely by chance. There is a basic
-        // check against this, but this should probably be done as a filter in proptest.
-        fn ranged_tags_proptests(tag_type in prop_oneof!["@", "|"], tag_name in TAG_NAME_REGEX, parameter in TAG_PARAMETER_REGEX, multi_parameter in TAG_MULTI_PARAMETER_REGEX, content in PARAGRAPH_REGEX) {
-            if content.contains(format!("{}end", tag_type).as_str()) {
-                return Ok(());
-            }
+        ];
 
-            let content = format!("{tag_type}{tag_name} {parameter} {multi_parameter}\n{content}\n{tag_type}end");
-
-            parse(&content).unwrap();
-        }
-    }
-
-    #[test]
-    fn paragraphs() {
-        let examples: Vec<_> = [
+        pub(super) const PARAGRAPHS: &[&str] = &[
             "hello, world!",
             "*hello, world!*",
             "*hello,
@@ -667,26 +447,34 @@ mod tests {
 
              another paragraph
              here.",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    proptest! {
-        #[test]
-        fn paragraphs_proptests(paragraph_content in PARAGRAPH_REGEX) {
-            parse(&paragraph_content).unwrap();
-        }
-    }
-
-    #[test]
-    fn modifiers() {
-        let examples: Vec<_> = [
+        ];
+
+        pub(super) const LINE_CONTINUATIONS: &[&str] = &[
+            "join~
+             ed words",
+            "keeps the ~
+             space",
+            "* A long~
+               heading",
+            "- item ~
+             - not a new item",
+            "not joined~
+
+             here",
+            "~
+             start of a line",
+        ];
+
+        pub(super) const INTERSECTING_MODIFIERS: &[&str] = &[
+            "$ Term : The definition.",
+            "^ Note : The footnote.",
+            ": A1 : Cell content",
+            "$ {* a : b} : Colons in links are skipped.",
+            "$ Term :
+             Not an intersecting modifier.",
+        ];
+
+        pub(super) const MODIFIERS: &[&str] = &[
             "this *is* a test",
             "hello, *world*!",
             "*hello, world!*",
@@ -697,19 +485,44 @@ mod tests {
             "this *is/ a test",
             "this *is*/ a test",
             "this */is/*/ a test",
-        ]
-        .into_iter()
-        .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
-
-        assert_yaml_snapshot!(examples);
-    }
-
-    #[test]
-    fn links() {
-        let examples: Vec<_> = [
+        ];
+
+        pub(super) const ATTACHED_MODIFIERS: &[&str] = &[
+            "!spoiler! ^super^ ,sub, %comment%",
+            "*bold, with a comma*",
+            "$a *b* c$",
+            "&variable& and $x^2$",
+            "costs $5 and $10",
+            "a$b$ c",
+            "*$x$*",
+        ];
+
+        pub(super) const FREE_FORM_MODIFIERS: &[&str] = &[
+            "*| leading spaces |*",
+            "$| \\frac{a}{b} |$",
+            "text `|*not bold*|` text",
+            "a /| b |/ c",
+            "*| unclosed",
+        ];
+
+        pub(super) const LINK_MODIFIERS: &[&str] = &[
+            "bold:*ness*:",
+            "W:*h*:y",
+            "Intra:/word/:s",
+            "x:$a + b$:y",
+            "*bold*: text",
+            "a :*b*: c",
+            "(*in parentheses*:)",
+        ];
+
+        pub(super) const SCOPED_LINKS: &[&str] = &[
+            "{* Heading : ** Sub : $ Term}",
+            "{:file:* Heading : # Generic}[description]",
+            "{* Heading : not a scope}",
+            "{https://example.com : no scope}",
+        ];
+
+        pub(super) const LINKS: &[&str] = &[
             // expected to fail
             // r#"{:path:/ file}"#,
             // r#"{:path:@ timestamp}"#,
@@ -772,30 +585,646 @@ mod tests {
             // - Scoping:
             r#"{* Heading Name : *** Level 3 heading}"#,
             r#"{* heading1 : ** heading2 : ^ Footnote}"#,
+        ];
+
+        pub(super) const INLINE_VERBATIM: &[&str] = &[
+            "some text `inline verbatim`",
+            "`verbatim at start`",
+            "{/ some_link.txt}[with `inline verbatim` in anchor]",
+            "`*markup* /inside/ /-verbatim-/`",
+        ];
+
+        pub(super) const VERBATIM_BACKSLASHES: &[&str] = &[
+            r"$\frac{1}{2}$",
+            r"`a\nb \` c`",
+            r"&C:\Users&",
+            r"*|a\b  c|*",
+        ];
+    }
+
+    #[test]
+    fn formats_back() {
+        [
+            examples::HEADINGS,
+            examples::HEADINGS_TREE,
+            examples::DELIMITING_MODS_TREE,
+            examples::LISTS_TREE,
+            examples::SLIDES_AND_INDENT_SEGMENTS,
+            examples::NESTED_CONTENT_TREE,
+            examples::LISTS,
+            examples::MODIFIER_EXTENSIONS,
+            examples::ORDERED_LISTS,
+            examples::QUOTES,
+            examples::DEFINITIONS,
+            examples::FOOTNOTES,
+            examples::TABLES,
+            examples::INFIRM_TAGS,
+            examples::CARRYOVER_TAGS,
+            examples::CARRYOVER_TAGS_TREE,
+            examples::RANGED_VERBATIM_TAGS,
+            examples::RANGED_TAGS,
+            examples::PARAGRAPHS,
+            examples::LINE_CONTINUATIONS,
+            examples::INTERSECTING_MODIFIERS,
+            examples::MODIFIERS,
+            examples::ATTACHED_MODIFIERS,
+            examples::FREE_FORM_MODIFIERS,
+            examples::LINK_MODIFIERS,
+            examples::SCOPED_LINKS,
+            examples::LINKS,
+            examples::INLINE_VERBATIM,
+            examples::VERBATIM_BACKSLASHES,
+        ]
+        .concat()
+        .into_iter()
+        .map(|example| example.to_string() + "\n")
+        .for_each(|input| assert_formats_back(&parse_tree(&input).unwrap()));
+    }
+
+    proptest! {
+        #[test]
+        fn formats_back_proptests(tag_type in select(vec!["@", "|"]), tag_name in TAG_NAME_REGEX, parameter in TAG_PARAMETER_REGEX, multi_parameter in TAG_MULTI_PARAMETER_REGEX, verbatim in ".*", paragraph in PARAGRAPH_REGEX) {
+            prop_assume!(!verbatim.contains("@end") && !paragraph.contains(&format!("{tag_type}end")));
+            // A trailing backslash or `~` takes in the newline, so the end of the tag is never found.
+            prop_assume!(!paragraph.ends_with(['\\', '~']));
+
+            [
+                format!(".{tag_name} {parameter} {multi_parameter}\n"),
+                format!("#{tag_name} {parameter} {multi_parameter}\nhello world!"),
+                format!("@{tag_name} {parameter} {multi_parameter}\n{verbatim}\n@end"),
+                format!("{tag_type}{tag_name} {parameter} {multi_parameter}\n{paragraph}\n{tag_type}end"),
+                paragraph.clone(),
+            ]
+            .into_iter()
+            .for_each(|input| assert_formats_back(&parse_tree(&input).unwrap()));
+        }
+    }
+
+    #[test]
+    fn headings() {
+        let examples: Vec<_> = examples::HEADINGS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn headings_tree() {
+        let headings_tree_examples: Vec<_> = examples::HEADINGS_TREE
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse_tree(&str))
+            .try_collect()
+            .unwrap();
+        assert_yaml_snapshot!(headings_tree_examples);
+    }
+
+    #[test]
+    fn delimiting_mods_tree() {
+        let examples: Vec<_> = examples::DELIMITING_MODS_TREE
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse_tree(&str))
+            .try_collect()
+            .unwrap();
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn lists_tree() {
+        let examples: Vec<_> = examples::LISTS_TREE
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse_tree(&str))
+            .try_collect()
+            .unwrap();
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn slides_and_indent_segments() {
+        let examples: Vec<_> = examples::SLIDES_AND_INDENT_SEGMENTS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse_tree(&str))
+            .try_collect()
+            .unwrap();
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn nested_content_tree() {
+        let examples: Vec<_> = examples::NESTED_CONTENT_TREE
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse_tree(&str))
+            .try_collect()
+            .unwrap();
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn lists() {
+        let examples: Vec<_> = examples::LISTS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn modifier_extensions() {
+        let examples: Vec<_> = examples::MODIFIER_EXTENSIONS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn lists_regressions() {
+        [
+            "- - a list item",
+            "---- - a list item",
+            "---- > a list item",
+        ]
+        .into_iter()
+        .map(|example| example.to_string() + "\n")
+        .for_each(|str| {
+            parse(&str).unwrap_err();
+        });
+    }
+
+    #[test]
+    fn ordered_lists() {
+        let examples: Vec<_> = examples::ORDERED_LISTS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn ordered_lists_regressions() {
+        [
+            "~ ~ a list item",
+            "~~~~ - a list item",
+            "~~~~ > a list item",
+        ]
+        .into_iter()
+        .map(|example| example.to_string() + "\n")
+        .for_each(|str| {
+            parse(&str).unwrap_err();
+        });
+    }
+
+    #[test]
+    fn quotes() {
+        let examples: Vec<_> = examples::QUOTES
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn quotes_regressions() {
+        [
+            "> > a list item",
+            ">>>> - a list item",
+            ">>>> ~ a list item",
+        ]
+        .into_iter()
+        .map(|example| example.to_string() + "\n")
+        .for_each(|str| {
+            parse(&str).unwrap_err();
+        });
+    }
+
+    #[test]
+    fn definitions() {
+        let examples: Vec<_> = examples::DEFINITIONS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn definitions_regressions() {
+        [
+            "$ Term Definition",
+            "$$ Term
+                Long definition $$",
+            "$$ Term
+                Long definition
+             $$text",
+            "$$ Term
+                Long definition
+             $$ text",
+        ]
+        .into_iter()
+        .map(|example| example.to_string() + "\n")
+        .for_each(|str| {
+            parse(&str).unwrap_err();
+        });
+    }
+
+    #[test]
+    fn footnotes() {
+        let examples: Vec<_> = examples::FOOTNOTES
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn footnotes_regressions() {
+        [
+            "^ Term Definition",
+            "^^ Term
+                Long definition ^^",
+            "^^ Term
+                Long definition
+             ^^text",
+            "^^ Term
+                Long definition
+             ^^ text",
+        ]
+        .into_iter()
+        .map(|example| example.to_string() + "\n")
+        .for_each(|str| {
+            parse(&str).unwrap_err();
+        });
+    }
+
+    #[test]
+    fn tables() {
+        let examples: Vec<_> = examples::TABLES
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn tables_regressions() {
+        [
+            ": Term Definition",
+            ":: Term
+                Long definition ::",
+            ":: Term
+                Long definition
+             ::text",
+            ":: Term
+                Long definition
+             :: text",
         ]
         .into_iter()
         .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
+        .for_each(|str| {
+            parse(&str).unwrap_err();
+        });
+    }
+
+    #[test]
+    fn infirm_tags() {
+        let examples: Vec<_> = examples::INFIRM_TAGS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn infirm_tags_proptests(tag_name in TAG_NAME_REGEX, parameter in TAG_PARAMETER_REGEX, multi_parameter in TAG_MULTI_PARAMETER_REGEX) {
+            let tag = format!(".{} {} {}\n", tag_name, parameter, multi_parameter);
+
+            // TODO: Ensure that the number of parameters parsed is correct?
+            parse(&tag).unwrap();
+        }
+    }
+
+    #[test]
+    fn carryover_tags() {
+        let examples: Vec<_> = examples::CARRYOVER_TAGS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn carryover_tags_tree() {
+        let examples: Vec<_> = examples::CARRYOVER_TAGS_TREE
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse_tree(&str))
+            .try_collect()
+            .unwrap();
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn carryover_tags_proptests(tag_name in TAG_NAME_REGEX, parameter in TAG_PARAMETER_REGEX, multi_parameter in TAG_MULTI_PARAMETER_REGEX) {
+            let content = format!("#{} {} {}\nhello world!", tag_name, parameter, multi_parameter);
+
+            parse(&content).unwrap();
+        }
+    }
+
+    #[test]
+    fn ranged_verbatim_tags() {
+        let examples: Vec<_> = examples::RANGED_VERBATIM_TAGS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        // NOTE: `.*` may at some point generate an `@end` purely by chance. There is a basic
+        // check against this, but this should probably be done as a filter in proptest.
+        fn ranged_verbatim_tags_proptests(tag_name in TAG_NAME_REGEX, parameter in TAG_PARAMETER_REGEX, multi_parameter in TAG_MULTI_PARAMETER_REGEX, content in ".*") {
+            if content.contains("@end") {
+                return Ok(());
+            }
+
+            let content = format!("@{} {} {}\n{}\n@end", tag_name, parameter, multi_parameter, content);
+
+            parse(&content).unwrap();
+        }
+    }
+
+    #[test]
+    fn ranged_tags() {
+        let examples: Vec<_> = examples::RANGED_TAGS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        // NOTE: `.*` may at some point generate an `@end` purely by chance. There is a basic
+        // check against this, but this should probably be done as a filter in proptest.
+        fn ranged_tags_proptests(tag_type in prop_oneof!["@", "|"], tag_name in TAG_NAME_REGEX, parameter in TAG_PARAMETER_REGEX, multi_parameter in TAG_MULTI_PARAMETER_REGEX, content in PARAGRAPH_REGEX) {
+            if content.contains(format!("{}end", tag_type).as_str()) {
+                return Ok(());
+            }
+
+            let content = format!("{tag_type}{tag_name} {parameter} {multi_parameter}\n{content}\n{tag_type}end");
+
+            parse(&content).unwrap();
+        }
+    }
+
+    #[test]
+    fn paragraphs() {
+        let examples: Vec<_> = examples::PARAGRAPHS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn line_continuations() {
+        let examples: Vec<_> = examples::LINE_CONTINUATIONS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn intersecting_modifiers() {
+        let examples: Vec<_> = examples::INTERSECTING_MODIFIERS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn paragraphs_proptests(paragraph_content in PARAGRAPH_REGEX) {
+            parse(&paragraph_content).unwrap();
+        }
+    }
+
+    #[test]
+    fn modifiers() {
+        let examples: Vec<_> = examples::MODIFIERS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn attached_modifiers() {
+        let examples: Vec<_> = examples::ATTACHED_MODIFIERS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn free_form_modifiers() {
+        let examples: Vec<_> = examples::FREE_FORM_MODIFIERS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn link_modifiers() {
+        let examples: Vec<_> = examples::LINK_MODIFIERS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn scoped_links() {
+        let examples: Vec<_> = examples::SCOPED_LINKS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn links() {
+        let examples: Vec<_> = examples::LINKS
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
 
         assert_yaml_snapshot!(examples);
     }
 
     #[test]
     fn inline_verbatim() {
+        let examples: Vec<_> = examples::INLINE_VERBATIM
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn verbatim_backslashes() {
+        let examples: Vec<_> = examples::VERBATIM_BACKSLASHES
+            .iter()
+            .map(|example| example.to_string() + "\n")
+            .map(|str| parse(&str))
+            .try_collect()
+            .unwrap();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn spans() {
+        let input =
+            "* Überschrift *fett*\n  {* Ziel}[Beschreibung]\n- ( ) item\n  @code\n  x\n  @end\n";
+
+        let nodes = parse(input).unwrap();
+        let segments = match &nodes[1] {
+            crate::NorgASTFlat::Paragraph { content, .. } => content,
+            _ => unreachable!(),
+        };
+        let title = match &nodes[0] {
+            crate::NorgASTFlat::Heading { title, .. } => title,
+            _ => unreachable!(),
+        };
+
+        let examples: Vec<_> = nodes
+            .iter()
+            .map(|node| node.span())
+            .chain(title.iter().map(|segment| segment.span()))
+            .chain(segments.iter().map(|segment| segment.span()))
+            .map(|span| (&input[span.range()], span.start.line, span.start.column))
+            .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn errors() {
         let examples: Vec<_> = [
-            "some text `inline verbatim`",
-            "`verbatim at start`",
-            "{/ some_link.txt}[with `inline verbatim` in anchor]",
-            "`*markup* /inside/ /-verbatim-/`",
+            "- - a list item",
+            "$ Term Definition",
+            "$$ Term\n  Long definition\n$$ text",
+            "---- > a list item",
         ]
         .into_iter()
         .map(|example| example.to_string() + "\n")
-        .map(|str| parse(&str))
-        .try_collect()
-        .unwrap();
+        .map(|str| parse(&str).unwrap_err().to_string())
+        .collect();
 
         assert_yaml_snapshot!(examples);
     }
+
+    #[test]
+    fn recovering() {
+        let examples: Vec<_> = [
+            "* Notes\n\n- - a list item\n\nStill *here*.",
+            "$$ Term\n  Long definition\n$$ text\n",
+            "Before\n\n---- > a\n---- > b\n\nAfter",
+            "",
+        ]
+        .into_iter()
+        .map(parse_recovering)
+        .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn recovering_proptests(input in r"[*\-~>$^:@|=.#+(){}\[\] \na-z]*") {
+            let (tree, diagnostics) = parse_recovering(&input);
+
+            if diagnostics.is_empty() && !tree.is_empty() {
+                assert_eq!(parse_tree(&input).unwrap(), tree);
+            }
+        }
+    }
 }
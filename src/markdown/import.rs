@@ -0,0 +1,1436 @@
+//! Converting Markdown into a Norg tree.
+//!
+//! The Markdown is first split into blocks line by line, the way CommonMark does it, and then
+//! turned into the same flat nodes the Norg parser produces, which are nested by
+//! [`stage_4`]. The literal text of the Markdown is escaped where it would otherwise form Norg
+//! markup, so formatting the tree gives Norg text which parses back into the same tree.
+//!
+//! Items of lists and quotes which hold more than their text and nested lists keep the rest of
+//! their content in an indent segment. Quotes start a new item at every paragraph. Reference
+//! link definitions become a paragraph of anchor definitions, and the links using them become
+//! anchors. Items which do not start with a paragraph are left out, keeping only their
+//! content, as Norg items always have text. Headings without a title and raw HTML are kept as
+//! text.
+
+use std::collections::HashMap;
+
+use textwrap::dedent;
+
+use crate::{
+    format::format,
+    span::{position_at, Span},
+    stage_1::is_special,
+    stage_4::stage_4,
+    ContentSegment, DelimitingModifier, DetachedModifierExtension, LinkTarget,
+    NestableDetachedModifier, NorgAST, NorgASTFlat, ParagraphSegment, ParagraphSegmentToken,
+    RangeableDetachedModifier, TodoStatus,
+};
+
+/// Converts Markdown into a tree like the one returned by [`crate::parse_tree`]. Nodes point
+/// at the Markdown lines they come from, and paragraph segments share the span of their block.
+pub fn import(input: &str) -> Vec<NorgAST> {
+    let lines: Vec<_> = input.lines().collect();
+    let mut parser = BlockParser {
+        input,
+        references: HashMap::new(),
+    };
+
+    let (front_matter, rest) = front_matter(&lines);
+    let blocks: Vec<_> = front_matter
+        .map(|content| {
+            // The span includes both delimiters.
+            let span = parser.span(&lines[..content.len() + 2]);
+            (Block::FrontMatter(content), span)
+        })
+        .into_iter()
+        .chain(parser.blocks(rest))
+        .collect();
+
+    let mut flat = Vec::new();
+    let converter = Converter {
+        references: parser.references,
+    };
+    converter.blocks(&blocks, 1, &mut flat);
+
+    stage_4(flat)
+}
+
+/// Converts Markdown into Norg text, see [`import`].
+pub fn to_norg(input: &str) -> String {
+    format(&import(input))
+}
+
+#[derive(Debug)]
+enum Block<'a> {
+    FrontMatter(Vec<&'a str>),
+    Heading {
+        level: u16,
+        text: &'a str,
+    },
+    Paragraph(Vec<&'a str>),
+    Code {
+        info: &'a str,
+        content: Vec<&'a str>,
+    },
+    Quote(Vec<(Block<'a>, Span)>),
+    List {
+        ordered: bool,
+        items: Vec<Item<'a>>,
+    },
+    Rule,
+    Table(Vec<&'a str>),
+    Footnote {
+        label: &'a str,
+        blocks: Vec<(Block<'a>, Span)>,
+    },
+    /// Consecutive reference link definitions, each with its label and destination.
+    References(Vec<(&'a str, &'a str)>),
+}
+
+#[derive(Debug)]
+struct Item<'a> {
+    task: Option<bool>,
+    blocks: Vec<(Block<'a>, Span)>,
+    span: Span,
+}
+
+/// Splits lines into blocks. All of the lines are slices of `input`, with the markers of
+/// their containers removed, which is how their location is found.
+struct BlockParser<'a> {
+    input: &'a str,
+    /// The destinations of reference links, by their normalised label.
+    references: HashMap<String, String>,
+}
+
+impl<'a> BlockParser<'a> {
+    fn offset(&self, text: &str) -> usize {
+        text.as_ptr() as usize - self.input.as_ptr() as usize
+    }
+
+    fn span(&self, lines: &[&str]) -> Span {
+        match (lines.first(), lines.last()) {
+            (Some(first), Some(last)) => Span::new(
+                position_at(self.input, self.offset(first)),
+                position_at(self.input, self.offset(last) + last.len()),
+            ),
+            _ => Span::default(),
+        }
+    }
+
+    fn blocks(&mut self, lines: &[&'a str]) -> Vec<(Block<'a>, Span)> {
+        let mut blocks = Vec::new();
+        let mut i = 0;
+
+        while i < lines.len() {
+            let line = lines[i];
+            if line.trim().is_empty() {
+                i += 1;
+                continue;
+            }
+
+            let start = i;
+            let block = if let Some((fence, indent, info)) = opening_fence(line) {
+                i += 1;
+                let mut content = Vec::new();
+                while i < lines.len() && !closes_fence(lines[i], fence) {
+                    content.push(strip_indent(lines[i], indent));
+                    i += 1;
+                }
+                // The closing fence, if there is one.
+                i = (i + 1).min(lines.len());
+
+                Block::Code { info, content }
+            } else if indentation(line) >= 4 {
+                let mut content = Vec::new();
+                while i < lines.len() && (lines[i].trim().is_empty() || indentation(lines[i]) >= 4)
+                {
+                    content.push(strip_indent(lines[i], 4));
+                    i += 1;
+                }
+                while content.last().is_some_and(|line| line.trim().is_empty()) {
+                    content.pop();
+                    i -= 1;
+                }
+
+                Block::Code { info: "", content }
+            } else if let Some((level, text)) = atx_heading(line) {
+                i += 1;
+                if text.is_empty() {
+                    Block::Paragraph(vec![line.trim()])
+                } else {
+                    Block::Heading { level, text }
+                }
+            } else if is_rule(line) {
+                i += 1;
+                Block::Rule
+            } else if quote_line(line).is_some() {
+                let mut content = Vec::new();
+                while i < lines.len() {
+                    match quote_line(lines[i]) {
+                        Some(rest) => content.push(rest),
+                        None if is_lazy(&content, lines[i]) => content.push(lines[i].trim_start()),
+                        None => break,
+                    }
+                    i += 1;
+                }
+
+                Block::Quote(self.blocks(&content))
+            } else if let Some(marker) = list_marker(line) {
+                let mut items = Vec::new();
+                while let Some(item) = list_marker(lines.get(i).copied().unwrap_or_default())
+                    .filter(|item| {
+                        item.ordered == marker.ordered && item.delimiter == marker.delimiter
+                    })
+                {
+                    let (item, end) = self.item(lines, i, item);
+                    items.push(item);
+                    i = end;
+
+                    // Blank lines between items do not end the list.
+                    let mut next = i;
+                    while next < lines.len() && lines[next].trim().is_empty() {
+                        next += 1;
+                    }
+                    if next < lines.len() && list_marker(lines[next]).is_some() {
+                        i = next;
+                    }
+                }
+
+                Block::List {
+                    ordered: marker.ordered,
+                    items,
+                }
+            } else if let Some((label, rest)) = footnote_definition(line) {
+                let mut content = vec![rest];
+                i += 1;
+                while i < lines.len() {
+                    let line = lines[i];
+                    if indentation(line) >= 4 {
+                        content.push(strip_indent(line, 4));
+                    } else if line.trim().is_empty() {
+                        content.push(&line[line.len()..]);
+                    } else if is_lazy(&content, line) {
+                        content.push(line.trim_start());
+                    } else {
+                        break;
+                    }
+                    i += 1;
+                }
+                while content.last().is_some_and(|line| line.trim().is_empty()) {
+                    content.pop();
+                }
+
+                Block::Footnote {
+                    label,
+                    blocks: self.blocks(&content),
+                }
+            } else if reference_definition(line).is_some() {
+                let mut definitions = Vec::new();
+                while let Some((label, destination)) =
+                    lines.get(i).and_then(|line| reference_definition(line))
+                {
+                    self.references
+                        .entry(normalize(label))
+                        .or_insert_with(|| unescape(destination));
+                    definitions.push((label, destination));
+                    i += 1;
+                }
+
+                Block::References(definitions)
+            } else if line.contains('|')
+                && lines.get(i + 1).is_some_and(|line| is_delimiter_row(line))
+            {
+                let mut rows = Vec::new();
+                while i < lines.len() && lines[i].contains('|') {
+                    rows.push(lines[i].trim());
+                    i += 1;
+                }
+
+                Block::Table(rows)
+            } else {
+                let mut content = vec![line];
+                i += 1;
+                let mut heading = None;
+                while i < lines.len() && !lines[i].trim().is_empty() {
+                    let line = lines[i];
+                    if let Some(level) = setext_underline(line) {
+                        heading = Some(level);
+                        i += 1;
+                        break;
+                    }
+                    if starts_block(line) {
+                        break;
+                    }
+                    content.push(line);
+                    i += 1;
+                }
+
+                match heading {
+                    Some(level) => {
+                        let first = content[0].trim();
+                        let last = content[content.len() - 1].trim();
+                        let text = &self.input[self.offset(first)..self.offset(last) + last.len()];
+                        Block::Heading { level, text }
+                    }
+                    None => Block::Paragraph(content),
+                }
+            };
+
+            blocks.push((block, self.span(&lines[start..i.max(start + 1)])));
+        }
+
+        blocks
+    }
+
+    /// Parses the list item starting at `lines[i]`, returning it together with the index of
+    /// the line after it.
+    fn item(&mut self, lines: &[&'a str], i: usize, marker: ListMarker<'a>) -> (Item<'a>, usize) {
+        let (task, first) = match marker.rest.get(..3) {
+            Some("[ ]") if is_item_text(&marker.rest[3..]) => {
+                (Some(false), marker.rest[3..].trim_start())
+            }
+            Some("[x]" | "[X]") if is_item_text(&marker.rest[3..]) => {
+                (Some(true), marker.rest[3..].trim_start())
+            }
+            _ => (None, marker.rest),
+        };
+
+        let mut content = vec![first];
+        let mut j = i + 1;
+        while j < lines.len() {
+            let line = lines[j];
+            if line.trim().is_empty() {
+                content.push(&line[line.len()..]);
+            } else if indentation(line) >= marker.width {
+                content.push(strip_indent(line, marker.width));
+            } else if is_lazy(&content, line) {
+                content.push(line.trim_start());
+            } else {
+                break;
+            }
+            j += 1;
+        }
+        while content.len() > 1 && content.last().is_some_and(|line| line.trim().is_empty()) {
+            content.pop();
+            j -= 1;
+        }
+
+        let item = Item {
+            task,
+            blocks: self.blocks(&content),
+            span: self.span(&lines[i..j]),
+        };
+
+        (item, j)
+    }
+}
+
+/// Turns blocks into flat Norg nodes.
+struct Converter {
+    references: HashMap<String, String>,
+}
+
+impl Converter {
+    /// Converts the blocks of a scope, using `level` for the lists and quotes directly within
+    /// it.
+    fn blocks(&self, blocks: &[(Block, Span)], level: u16, flat: &mut Vec<NorgASTFlat>) {
+        for (block, span) in blocks {
+            let span = *span;
+
+            match block {
+                Block::FrontMatter(lines) => flat.push(NorgASTFlat::VerbatimRangedTag {
+                    name: vec!["document".to_string(), "meta".to_string()],
+                    parameters: vec![],
+                    content: verbatim(lines),
+                    span,
+                }),
+                Block::Heading { level, text } => flat.push(NorgASTFlat::Heading {
+                    level: *level,
+                    title: protect_start(self.inline(text, span)),
+                    extensions: vec![],
+                    span,
+                }),
+                Block::Paragraph(lines) => flat.push(self.paragraph(lines, span)),
+                Block::Code { info, content } => flat.push(NorgASTFlat::VerbatimRangedTag {
+                    name: vec!["code".to_string()],
+                    parameters: info.split_whitespace().take(1).map(String::from).collect(),
+                    content: verbatim(content),
+                    span,
+                }),
+                Block::Quote(blocks) => {
+                    // Every paragraph starts a new quote, holding the blocks up to the next one.
+                    let mut rest = blocks.as_slice();
+                    while !rest.is_empty() {
+                        let end = rest
+                            .iter()
+                            .skip(1)
+                            .position(|(block, _)| matches!(block, Block::Paragraph(_)))
+                            .map_or(rest.len(), |i| i + 1);
+                        let span = rest[..end]
+                            .iter()
+                            .map(|(_, span)| *span)
+                            .reduce(Span::union)
+                            .unwrap_or(span);
+
+                        self.item(
+                            NestableDetachedModifier::Quote,
+                            level,
+                            vec![],
+                            &rest[..end],
+                            span,
+                            flat,
+                        );
+                        rest = &rest[end..];
+                    }
+                }
+                Block::List { ordered, items } => {
+                    let modifier_type = if *ordered {
+                        NestableDetachedModifier::OrderedList
+                    } else {
+                        NestableDetachedModifier::UnorderedList
+                    };
+
+                    for item in items {
+                        let extensions = match item.task {
+                            Some(true) => vec![DetachedModifierExtension::Todo(TodoStatus::Done)],
+                            Some(false) => {
+                                vec![DetachedModifierExtension::Todo(TodoStatus::Undone)]
+                            }
+                            None => vec![],
+                        };
+                        self.item(
+                            modifier_type.clone(),
+                            level,
+                            extensions,
+                            &item.blocks,
+                            item.span,
+                            flat,
+                        );
+                    }
+                }
+                Block::Rule => flat.push(NorgASTFlat::DelimitingModifier {
+                    modifier_type: DelimitingModifier::HorizontalRule,
+                    span,
+                }),
+                Block::Table(rows) => flat.push(NorgASTFlat::VerbatimRangedTag {
+                    name: vec!["table".to_string()],
+                    parameters: vec![],
+                    content: verbatim(rows),
+                    span,
+                }),
+                Block::Footnote { label, blocks } => {
+                    let mut content = Vec::new();
+                    self.blocks(blocks, 1, &mut content);
+
+                    flat.push(NorgASTFlat::RangeableDetachedModifier {
+                        modifier_type: RangeableDetachedModifier::Footnote,
+                        title: protect_start(literal(label, span)),
+                        extensions: vec![],
+                        segment: None,
+                        content,
+                        span,
+                    });
+                }
+                Block::References(definitions) => {
+                    let mut content = Vec::new();
+                    for (label, destination) in definitions {
+                        if !content.is_empty() {
+                            content.push(token(ParagraphSegmentToken::Whitespace, span));
+                        }
+                        content.push(ParagraphSegment::AnchorDefinition {
+                            content: self.inline(label, span),
+                            target: Box::new(link(&unescape(destination), None, span)),
+                            span,
+                        });
+                    }
+
+                    flat.push(NorgASTFlat::Paragraph { content, span });
+                }
+            }
+        }
+    }
+
+    /// Adds a list item or quote. Its first paragraph becomes its text, and nested lists and
+    /// quotes follow it one level deeper. Anything else goes into an indent segment.
+    fn item(
+        &self,
+        modifier_type: NestableDetachedModifier,
+        level: u16,
+        extensions: Vec<DetachedModifierExtension>,
+        blocks: &[(Block, Span)],
+        span: Span,
+        flat: &mut Vec<NorgASTFlat>,
+    ) {
+        let [(Block::Paragraph(lines), paragraph_span), rest @ ..] = blocks else {
+            // Norg items always have text, so the content of items without it takes their place.
+            self.blocks(blocks, level, flat);
+            return;
+        };
+        let text = match self.paragraph(lines, *paragraph_span) {
+            NorgASTFlat::Paragraph { content, span } => NorgASTFlat::Paragraph {
+                content: protect_colons(content),
+                span,
+            },
+            text => text,
+        };
+        let segment = rest
+            .iter()
+            .any(|(block, _)| !matches!(block, Block::List { .. } | Block::Quote(_)))
+            .then_some(ContentSegment::IndentSegment);
+
+        flat.push(NorgASTFlat::NestableDetachedModifier {
+            modifier_type,
+            level,
+            extensions,
+            segment,
+            content: Box::new(text),
+            span,
+        });
+        self.blocks(rest, level + 1, flat);
+
+        if segment.is_some() {
+            flat.push(NorgASTFlat::DelimitingModifier {
+                modifier_type: DelimitingModifier::Weak,
+                span: Span::empty(span.end),
+            });
+        }
+    }
+
+    fn paragraph(&self, lines: &[&str], span: Span) -> NorgASTFlat {
+        // Lines ending in two spaces or a backslash are hard line breaks, which are written as
+        // an escaped newline.
+        let mut text = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.trim_start();
+            if i + 1 == lines.len() {
+                text += line.trim_end();
+            } else if line.ends_with("  ") {
+                text += line.trim_end();
+                text += "\\\n";
+            } else {
+                text += line;
+                text += "\n";
+            }
+        }
+
+        NorgASTFlat::Paragraph {
+            content: protect_start(self.inline(&text, span)),
+            span,
+        }
+    }
+
+    fn inline(&self, text: &str, span: Span) -> Vec<ParagraphSegment> {
+        let mut parser = InlineParser {
+            converter: self,
+            chars: text.chars().collect(),
+            span,
+            segments: Vec::new(),
+            literal: Vec::new(),
+        };
+        parser.parse(0, parser.chars.len());
+
+        parser.finish()
+    }
+}
+
+/// Parses the inline Markdown of a single block.
+struct InlineParser<'a> {
+    converter: &'a Converter,
+    chars: Vec<char>,
+    span: Span,
+    segments: Vec<ParagraphSegment>,
+    /// Text which has not been turned into tokens yet, and whether each character was escaped.
+    literal: Vec<(char, bool)>,
+}
+
+impl InlineParser<'_> {
+    fn parse(&mut self, start: usize, end: usize) {
+        let mut i = start;
+
+        while i < end {
+            let c = self.chars[i];
+            let next = self.chars.get(i + 1).copied().filter(|_| i + 1 < end);
+
+            let parsed = match c {
+                '\\' if next == Some('\n') => {
+                    self.flush();
+                    self.segments
+                        .push(token(ParagraphSegmentToken::Escape('\n'), self.span));
+                    Some(i + 2)
+                }
+                '\\' if next.is_some_and(|c| c.is_ascii_punctuation()) => {
+                    self.literal.push((next.unwrap_or_default(), true));
+                    Some(i + 2)
+                }
+                '`' => self.code_span(i, end),
+                '<' => self.autolink(i, end),
+                '!' if next == Some('[') => self.link(i + 1, end),
+                '[' => self.link(i, end),
+                '*' | '_' | '~' => self.emphasis(i, end),
+                _ => None,
+            };
+
+            match parsed {
+                Some(next) => i = next,
+                None => {
+                    // A run of backticks or emphasis characters which does not open anything
+                    // stays text as a whole.
+                    let run = match c {
+                        '`' | '*' | '_' | '~' => self.run(i, end),
+                        _ => 1,
+                    };
+                    self.literal
+                        .extend((i..i + run).map(|i| (self.chars[i], false)));
+                    i += run;
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<ParagraphSegment> {
+        self.flush();
+        self.segments
+    }
+
+    /// Parses the given characters on their own.
+    fn nested(&self, start: usize, end: usize) -> Vec<ParagraphSegment> {
+        let mut parser = InlineParser {
+            converter: self.converter,
+            chars: self.chars.clone(),
+            span: self.span,
+            segments: Vec::new(),
+            literal: Vec::new(),
+        };
+        parser.parse(start, end);
+
+        parser.finish()
+    }
+
+    fn text(&self, start: usize, end: usize) -> String {
+        self.chars[start..end].iter().collect()
+    }
+
+    /// The length of the run of the character at `i`.
+    fn run(&self, i: usize, end: usize) -> usize {
+        self.chars[i..end]
+            .iter()
+            .take_while(|&&c| c == self.chars[i])
+            .count()
+    }
+
+    fn flush(&mut self) {
+        if !self.literal.is_empty() {
+            let literal = std::mem::take(&mut self.literal);
+            let previous = self.segments.last().map(|_| '_');
+            self.segments
+                .extend(tokens(previous, &literal).map(|t| token(t, self.span)));
+        }
+    }
+
+    fn code_span(&mut self, i: usize, end: usize) -> Option<usize> {
+        let run = self.run(i, end);
+        let mut j = i + run;
+
+        while j < end {
+            if self.chars[j] != '`' {
+                j += 1;
+                continue;
+            }
+
+            let closing = self.run(j, end);
+            if closing == run {
+                let content = self.text(i + run, j).replace('\n', " ");
+                let content = content.trim();
+                if content.is_empty() {
+                    return None;
+                }
+
+                self.flush();
+                self.segments.push(ParagraphSegment::InlineVerbatim {
+                    content: vec![ParagraphSegmentToken::Text(verbatim_text(content))],
+                    span: self.span,
+                });
+                return Some(j + closing);
+            }
+            j += closing;
+        }
+
+        None
+    }
+
+    fn autolink(&mut self, i: usize, end: usize) -> Option<usize> {
+        let close = (i + 1..end).find(|&j| matches!(self.chars[j], '>' | '<' | ' ' | '\n'))?;
+        let url = self.text(i + 1, close);
+        let url = match url.split_once(':') {
+            Some((scheme, _)) if is_scheme(scheme) => url,
+            None if url.contains('@') => format!("mailto:{url}"),
+            _ => return None,
+        };
+        if self.chars[close] != '>' || !is_linkable(&url) {
+            return None;
+        }
+
+        self.flush();
+        self.segments.push(link(&url, None, self.span));
+
+        Some(close + 1)
+    }
+
+    /// Finds the `]` closing the bracket at `i`.
+    fn closing_bracket(&self, i: usize, end: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut j = i;
+
+        while j < end {
+            match self.chars[j] {
+                '\\' => j += 1,
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(j);
+                    }
+                }
+                _ => {}
+            }
+            j += 1;
+        }
+
+        None
+    }
+
+    fn link(&mut self, i: usize, end: usize) -> Option<usize> {
+        let close = self.closing_bracket(i, end)?;
+        let text = self.text(i + 1, close);
+
+        // Footnote references, `[^label]`.
+        if let Some(label) = text.strip_prefix('^') {
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                return None;
+            }
+
+            self.flush();
+            self.segments.push(ParagraphSegment::Link {
+                filepath: None,
+                targets: vec![LinkTarget::Footnote {
+                    title: literal(label, self.span),
+                    span: self.span,
+                }],
+                description: None,
+                span: self.span,
+            });
+            return Some(close + 1);
+        }
+
+        let description = (close > i + 1).then(|| self.nested(i + 1, close));
+
+        // Inline links, `[text](destination "title")`.
+        if self.chars.get(close + 1) == Some(&'(') && close + 1 < end {
+            let (destination, after) = self.destination(close + 2, end)?;
+
+            self.flush();
+            self.segments
+                .push(link(&destination, description, self.span));
+            return Some(after);
+        }
+
+        // Reference links, `[text][label]`, `[label][]` and `[label]`.
+        let (label, after) = match self.chars.get(close + 1) {
+            Some('[') if close + 1 < end => {
+                let label_close = self.closing_bracket(close + 1, end)?;
+                match self.text(close + 2, label_close) {
+                    label if label.trim().is_empty() => ((i + 1, close), label_close + 1),
+                    _ => ((close + 2, label_close), label_close + 1),
+                }
+            }
+            _ => ((i + 1, close), close + 1),
+        };
+        let name = self.text(label.0, label.1);
+        if !self.converter.references.contains_key(&normalize(&name)) {
+            return None;
+        }
+
+        let content = self.nested(label.0, label.1);
+        let description = description.filter(|_| label.0 != i + 1);
+        self.flush();
+        self.segments.push(ParagraphSegment::Anchor {
+            content,
+            description,
+            span: self.span,
+        });
+
+        Some(after)
+    }
+
+    /// Parses the destination and optional title of an inline link, starting after the `(`.
+    fn destination(&self, start: usize, end: usize) -> Option<(String, usize)> {
+        let mut j = start;
+        let skip_whitespace = |j: &mut usize| {
+            while *j < end && self.chars[*j].is_whitespace() {
+                *j += 1;
+            }
+        };
+        skip_whitespace(&mut j);
+
+        let destination = if self.chars.get(j) == Some(&'<') {
+            let close = (j + 1..end).find(|&k| self.chars[k] == '>')?;
+            let destination = self.text(j + 1, close);
+            j = close + 1;
+            destination
+        } else {
+            let from = j;
+            let mut depth = 0;
+            while j < end && !self.chars[j].is_whitespace() {
+                match self.chars[j] {
+                    '(' => depth += 1,
+                    ')' if depth == 0 => break,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                j += 1;
+            }
+            self.text(from, j)
+        };
+        let destination = unescape(&destination);
+        if !is_linkable(&destination) {
+            return None;
+        }
+
+        skip_whitespace(&mut j);
+        if let Some(&quote @ ('"' | '\'')) = self.chars.get(j) {
+            j = (j + 1..end).find(|&k| self.chars[k] == quote)? + 1;
+            skip_whitespace(&mut j);
+        }
+
+        (j < end && self.chars[j] == ')').then_some((destination, j + 1))
+    }
+
+    /// Parses emphasis and strikethrough, following the flanking rules of CommonMark in a
+    /// simplified form.
+    fn emphasis(&mut self, i: usize, end: usize) -> Option<usize> {
+        let c = self.chars[i];
+        let run = self.run(i, end);
+        match (c, run) {
+            ('~', 1 | 2) | ('*' | '_', 1..=3) => {}
+            _ => return None,
+        }
+
+        let before = i.checked_sub(1).map(|i| self.chars[i]);
+        let after = self.chars.get(i + run).copied().filter(|_| i + run < end);
+        if !opens(c, before, after) {
+            return None;
+        }
+
+        let mut j = i + run;
+        while j < end {
+            match self.chars[j] {
+                '\\' => j += 2,
+                '`' => j += self.run(j, end),
+                other if other == c => {
+                    let closing = self.run(j, end);
+                    let before = Some(self.chars[j - 1]);
+                    let after = self
+                        .chars
+                        .get(j + closing)
+                        .copied()
+                        .filter(|_| j + closing < end);
+                    if closing == run && j > i + run && closes(c, before, after) {
+                        let content = unnest(self.nested(i + run, j));
+                        let segment = match (c, run) {
+                            ('~', _) => modifier('-', content, self.span),
+                            (_, 1) => modifier('/', content, self.span),
+                            (_, 2) => modifier('*', content, self.span),
+                            _ => modifier('*', vec![modifier('/', content, self.span)], self.span),
+                        };
+
+                        self.flush();
+                        self.segments.push(segment);
+                        return Some(j + closing);
+                    }
+                    j += closing;
+                }
+                _ => j += 1,
+            }
+        }
+
+        None
+    }
+}
+
+/// Norg does not see an attached modifier which follows the first token of another one and a
+/// single whitespace or punctuation, like the `/` in `*a /b/*`, so its opener is escaped. So is
+/// punctuation at either end, which could be read as part of the markers, like in `*|a|*`.
+fn unnest(mut content: Vec<ParagraphSegment>) -> Vec<ParagraphSegment> {
+    for i in [0, content.len().saturating_sub(1)] {
+        if let Some(ParagraphSegment::Token { token, .. }) = content.get_mut(i) {
+            if let ParagraphSegmentToken::Special(c) = *token {
+                *token = ParagraphSegmentToken::Escape(c);
+            }
+        }
+    }
+
+    if let [ParagraphSegment::Token { .. }, ParagraphSegment::Token {
+        token: ParagraphSegmentToken::Whitespace | ParagraphSegmentToken::Special(_),
+        ..
+    }, ParagraphSegment::AttachedModifier { .. }, ..] = content.as_slice()
+    {
+        if let ParagraphSegment::AttachedModifier {
+            modifier_type,
+            content: inner,
+            span,
+        } = content.remove(2)
+        {
+            let literal =
+                std::iter::once(token(ParagraphSegmentToken::Escape(modifier_type), span))
+                    .chain(inner)
+                    .chain([token(ParagraphSegmentToken::Special(modifier_type), span)]);
+            content.splice(2..2, literal);
+        }
+    }
+
+    content
+}
+
+fn opens(c: char, before: Option<char>, after: Option<char>) -> bool {
+    let Some(after) = after else {
+        return false;
+    };
+    let left_flanking = !after.is_whitespace()
+        && (!is_punctuation(after)
+            || before.map_or(true, |b| b.is_whitespace() || is_punctuation(b)));
+
+    left_flanking && (c != '_' || !before.is_some_and(char::is_alphanumeric))
+}
+
+fn closes(c: char, before: Option<char>, after: Option<char>) -> bool {
+    let Some(before) = before else {
+        return false;
+    };
+    let right_flanking = !before.is_whitespace()
+        && (!is_punctuation(before)
+            || after.map_or(true, |a| a.is_whitespace() || is_punctuation(a)));
+
+    right_flanking && (c != '_' || !after.is_some_and(char::is_alphanumeric))
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+
+    (2..=32).contains(&scheme.len())
+        && chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+}
+
+/// Whether a destination can be written as the target of a Norg link.
+fn is_linkable(destination: &str) -> bool {
+    !destination.is_empty() && !destination.contains(['{', '}', '\\'])
+}
+
+/// Removes backslash escapes from a link destination.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next) if c == '\\' && next.is_ascii_punctuation() => {}
+            _ => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+}
+
+fn modifier(modifier_type: char, content: Vec<ParagraphSegment>, span: Span) -> ParagraphSegment {
+    ParagraphSegment::AttachedModifier {
+        modifier_type,
+        content,
+        span,
+    }
+}
+
+fn token(token: ParagraphSegmentToken, span: Span) -> ParagraphSegment {
+    ParagraphSegment::Token { token, span }
+}
+
+/// Turns a link destination into a link. Links to other Markdown files become links to the
+/// Norg file with the same name, and fragments become links to any title.
+fn link(
+    destination: &str,
+    description: Option<Vec<ParagraphSegment>>,
+    span: Span,
+) -> ParagraphSegment {
+    let (path, fragment) = match destination.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (destination, None),
+    };
+    let title = |fragment: &str| LinkTarget::Generic {
+        title: literal(&fragment.replace('-', " "), span),
+        span,
+    };
+
+    let (filepath, targets) = if destination.contains("://") || destination.starts_with("mailto:") {
+        (
+            None,
+            vec![LinkTarget::Url {
+                url: destination.to_string(),
+                span,
+            }],
+        )
+    } else if let Some(file) = path.strip_suffix(".md") {
+        (
+            Some(file.to_string()),
+            fragment.map(title).into_iter().collect(),
+        )
+    } else if path.is_empty() {
+        (None, fragment.map(title).into_iter().collect())
+    } else {
+        (
+            None,
+            vec![LinkTarget::Path {
+                path: destination.to_string(),
+                span,
+            }],
+        )
+    };
+
+    ParagraphSegment::Link {
+        filepath,
+        targets,
+        description,
+        span,
+    }
+}
+
+/// Characters which are always escaped, as they start links, anchors, inline link targets and
+/// inline verbatim or join lines.
+const ALWAYS_ESCAPED: &str = "\\{}[]<>~`";
+
+/// Characters which are escaped when they could open an attached modifier.
+const MODIFIERS: &str = "*/_-!^,%$&";
+
+/// Turns literal text into tokens, escaping the characters which would otherwise be read as
+/// Norg markup. `previous` is the character before the text, if any.
+fn tokens(
+    previous: Option<char>,
+    literal: &[(char, bool)],
+) -> impl Iterator<Item = ParagraphSegmentToken> {
+    let mut tokens: Vec<ParagraphSegmentToken> = Vec::new();
+
+    for (i, &(c, escaped)) in literal.iter().enumerate() {
+        let before = match i {
+            0 => previous,
+            i => Some(literal[i - 1].0),
+        };
+        let after = literal.get(i + 1).map(|(c, _)| *c);
+
+        if c.is_whitespace() {
+            if !matches!(tokens.last(), Some(ParagraphSegmentToken::Whitespace)) {
+                tokens.push(ParagraphSegmentToken::Whitespace);
+            }
+        } else if is_special(c) {
+            let opens = before.map_or(true, |b| b.is_whitespace() || is_special(b))
+                && after.map_or(true, |a| !a.is_whitespace());
+
+            tokens.push(
+                if escaped || ALWAYS_ESCAPED.contains(c) || (MODIFIERS.contains(c) && opens) {
+                    ParagraphSegmentToken::Escape(c)
+                } else {
+                    ParagraphSegmentToken::Special(c)
+                },
+            );
+        } else if let Some(ParagraphSegmentToken::Text(text)) = tokens.last_mut() {
+            text.push(c);
+        } else {
+            tokens.push(ParagraphSegmentToken::Text(c.to_string()));
+        }
+    }
+
+    tokens.into_iter()
+}
+
+/// Literal text as paragraph segments.
+fn literal(text: &str, span: Span) -> Vec<ParagraphSegment> {
+    let literal: Vec<_> = text.trim().chars().map(|c| (c, false)).collect();
+
+    tokens(None, &literal).map(|t| token(t, span)).collect()
+}
+
+/// The content of inline verbatim as Norg source text. Backticks are escaped, and so is a run of
+/// backslashes which would otherwise escape a backtick or the closing modifier.
+fn verbatim_text(content: &str) -> String {
+    let mut text = String::with_capacity(content.len());
+    let mut backslashes = 0;
+
+    for c in content.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+
+        let escape = if c == '`' { 2 } else { 1 };
+        text.push_str(&"\\".repeat(backslashes * escape));
+        backslashes = 0;
+
+        if c == '`' {
+            text.push('\\');
+        }
+        text.push(c);
+    }
+    text.push_str(&"\\".repeat(backslashes * 2));
+
+    text
+}
+
+/// Escapes colons after whitespace in the text of an item, which would start a segment if they
+/// ended up at the end of a line.
+fn protect_colons(mut segments: Vec<ParagraphSegment>) -> Vec<ParagraphSegment> {
+    for i in 1..segments.len() {
+        if let [ParagraphSegment::Token {
+            token: ParagraphSegmentToken::Whitespace,
+            ..
+        }, ParagraphSegment::Token {
+            token: token @ ParagraphSegmentToken::Special(':'),
+            ..
+        }] = &mut segments[i - 1..=i]
+        {
+            *token = ParagraphSegmentToken::Escape(':');
+        }
+    }
+
+    segments
+}
+
+/// Keeps a block from starting with punctuation, which could make it a different kind of block.
+fn protect_start(mut segments: Vec<ParagraphSegment>) -> Vec<ParagraphSegment> {
+    while let Some(ParagraphSegment::Token {
+        token: ParagraphSegmentToken::Whitespace,
+        ..
+    }) = segments.first()
+    {
+        segments.remove(0);
+    }
+    while let Some(ParagraphSegment::Token {
+        token: ParagraphSegmentToken::Whitespace,
+        ..
+    }) = segments.last()
+    {
+        segments.pop();
+    }
+
+    if let Some(ParagraphSegment::Token { token, .. }) = segments.first_mut() {
+        if let ParagraphSegmentToken::Special(c) = token {
+            *token = ParagraphSegmentToken::Escape(*c);
+        }
+    }
+
+    segments
+}
+
+/// The content of a verbatim ranged tag, without common indentation like the Norg parser.
+fn verbatim(lines: &[&str]) -> String {
+    dedent(
+        &lines
+            .iter()
+            .map(|line| format!("{line}\n"))
+            .collect::<String>(),
+    )
+}
+
+/// Link labels are matched ignoring case and differences in whitespace.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Splits off YAML front matter, delimited by `---` lines at the very start.
+fn front_matter<'a, 'b>(lines: &'b [&'a str]) -> (Option<Vec<&'a str>>, &'b [&'a str]) {
+    if lines.first().map(|line| line.trim_end()) != Some("---") {
+        return (None, lines);
+    }
+
+    match lines
+        .iter()
+        .skip(1)
+        .position(|line| matches!(line.trim_end(), "---" | "..."))
+    {
+        Some(end) => (Some(lines[1..=end].to_vec()), &lines[end + 2..]),
+        None => (None, lines),
+    }
+}
+
+fn indentation(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Removes up to `width` columns of indentation.
+fn strip_indent(line: &str, width: usize) -> &str {
+    let mut removed = 0;
+
+    for (i, c) in line.char_indices() {
+        if removed >= width || !(c == ' ' || c == '\t') {
+            return &line[i..];
+        }
+        removed += if c == '\t' { 4 } else { 1 };
+    }
+
+    &line[line.len()..]
+}
+
+/// Strips the up to three spaces of indentation a block may start with.
+fn block_start(line: &str) -> Option<&str> {
+    (indentation(line) < 4).then(|| line.trim_start())
+}
+
+fn opening_fence(line: &str) -> Option<((char, usize), usize, &str)> {
+    let rest = block_start(line)?;
+    let c = rest.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let length = rest.chars().take_while(|&other| other == c).count();
+    let info = rest[length..].trim();
+
+    (length >= 3 && !(c == '`' && info.contains('`'))).then_some((
+        (c, length),
+        indentation(line),
+        info,
+    ))
+}
+
+fn closes_fence(line: &str, (c, length): (char, usize)) -> bool {
+    block_start(line).is_some_and(|rest| {
+        let run = rest.chars().take_while(|&other| other == c).count();
+        run >= length && rest[run..].trim().is_empty()
+    })
+}
+
+fn atx_heading(line: &str) -> Option<(u16, &str)> {
+    let rest = block_start(line)?;
+    let level = rest.chars().take_while(|&c| c == '#').count();
+    let text = &rest[level..];
+
+    if !(1..=6).contains(&level) || !(text.is_empty() || text.starts_with([' ', '\t'])) {
+        return None;
+    }
+
+    let text = text.trim();
+    let without_closing = text.trim_end_matches('#');
+    let text = match without_closing {
+        "" => "",
+        rest if rest.ends_with([' ', '\t']) => rest.trim_end(),
+        _ => text,
+    };
+
+    Some((level as u16, text))
+}
+
+fn is_rule(line: &str) -> bool {
+    let Some(rest) = block_start(line) else {
+        return false;
+    };
+    let mut chars = rest.chars().filter(|c| !c.is_whitespace());
+
+    match chars.next() {
+        Some(c @ ('*' | '-' | '_')) => {
+            let count = chars.clone().count() + 1;
+            chars.all(|other| other == c) && count >= 3
+        }
+        _ => false,
+    }
+}
+
+fn setext_underline(line: &str) -> Option<u16> {
+    let rest = block_start(line)?.trim_end();
+
+    match rest.chars().next()? {
+        '=' if rest.chars().all(|c| c == '=') => Some(1),
+        '-' if rest.chars().all(|c| c == '-') => Some(2),
+        _ => None,
+    }
+}
+
+fn quote_line(line: &str) -> Option<&str> {
+    let rest = block_start(line)?.strip_prefix('>')?;
+
+    Some(rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ListMarker<'a> {
+    ordered: bool,
+    delimiter: char,
+    /// The indentation of the content of the item.
+    width: usize,
+    /// The content on the line of the marker.
+    rest: &'a str,
+}
+
+fn list_marker(line: &str) -> Option<ListMarker<'_>> {
+    let indent = indentation(line);
+    let rest = block_start(line)?;
+
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    let (ordered, marker) = match rest[digits..].chars().next()? {
+        '-' | '*' | '+' if digits == 0 => (false, 1),
+        '.' | ')' if (1..=9).contains(&digits) => (true, digits + 1),
+        _ => return None,
+    };
+    let delimiter = rest[..marker].chars().last()?;
+
+    let after = &rest[marker..];
+    if !(after.is_empty() || after.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let spaces = indentation(after);
+    let width = match spaces {
+        1..=4 if !after.trim().is_empty() => indent + marker + spaces,
+        _ => indent + marker + 1,
+    };
+
+    Some(ListMarker {
+        ordered,
+        delimiter,
+        width,
+        rest: after.trim_start(),
+    })
+}
+
+/// Whether the rest of the line after a task list checkbox makes it a task.
+fn is_item_text(rest: &str) -> bool {
+    rest.is_empty() || rest.starts_with([' ', '\t'])
+}
+
+fn footnote_definition(line: &str) -> Option<(&str, &str)> {
+    let rest = block_start(line)?.strip_prefix("[^")?;
+    let (label, rest) = rest.split_once("]:")?;
+
+    (!label.is_empty() && !label.contains(char::is_whitespace)).then(|| (label, rest.trim()))
+}
+
+fn reference_definition(line: &str) -> Option<(&str, &str)> {
+    let rest = block_start(line)?.strip_prefix('[')?;
+    let (label, rest) = rest.split_once("]:")?;
+    if label.trim().is_empty() || label.starts_with('^') || label.contains(['[', ']']) {
+        return None;
+    }
+
+    let rest = rest.trim();
+    let destination = match rest.strip_prefix('<') {
+        Some(rest) => rest.split_once('>')?.0,
+        None => rest.split_whitespace().next()?,
+    };
+
+    is_linkable(&unescape(destination)).then_some((label, destination))
+}
+
+fn is_delimiter_row(line: &str) -> bool {
+    let line = line.trim();
+
+    line.contains('-')
+        && line
+            .chars()
+            .all(|c| matches!(c, '|' | ':' | '-' | ' ' | '\t'))
+}
+
+/// Whether the line starts a block which interrupts a paragraph.
+fn starts_block(line: &str) -> bool {
+    opening_fence(line).is_some()
+        || atx_heading(line).is_some()
+        || is_rule(line)
+        || quote_line(line).is_some()
+        || list_marker(line).is_some_and(|marker| !marker.rest.is_empty())
+}
+
+/// Whether `line` continues the paragraph at the end of `content` even though it is missing
+/// the indentation or the markers of its container.
+fn is_lazy(content: &[&str], line: &str) -> bool {
+    content.last().is_some_and(|last| !last.trim().is_empty())
+        && !line.trim().is_empty()
+        && !starts_block(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::proptest;
+
+    use crate::{
+        format::tests::assert_formats_back,
+        markdown::{import, to_norg},
+    };
+
+    #[test]
+    fn importing() {
+        let examples: Vec<_> = [
+            "---
+title: Notes
+tags: [a, b]
+---
+
+# Notes
+
+Some *emphasis*, **strong** and ~~struck~~ text with `code`,
+a [link](https://neorg.org \"Neorg\") and a [file](other.md#some-heading).
+
+Setext heading
+--------------
+
+- [ ] Open task
+- [x] Done task
+  1. Nested
+  2. Ordered
+
+> A quote
+> > Nested",
+            "```rust
+fn main() {
+    println!(\"{}\", 1 < 2);
+}
+```
+
+    indented code
+
+A [reference][neorg] and [neorg], with a footnote.[^1]
+
+[neorg]: https://github.com/nvim-neorg/neorg
+
+[^1]: The footnote.
+
+| a | b |
+|---|---|
+| 1 | 2 |
+
+***",
+            "- An item
+
+  with a second paragraph
+- Literal *stars, /slashes/ and {braces}
+- \\# Not a heading, -5 and a_b_c",
+        ]
+        .into_iter()
+        .map(|input| {
+            let tree = import(input);
+            assert_formats_back(&tree);
+            to_norg(input)
+        })
+        .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn imported_trees_format_back(
+            input in r"((#{1,3} |[-*] (\[[ x]\] )?|1\. |> |    )?[a-z]{1,5}([a-z]{1,5}|[*_~`\[\]()<>#!\\:/^,%$&|{}.-]|  ?|\n){0,12}\n\n?){1,6}"
+        ) {
+            assert_formats_back(&import(&input));
+        }
+    }
+}
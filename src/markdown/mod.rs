@@ -0,0 +1,776 @@
+//! Exporting a tree as GitHub-flavoured Markdown, and importing Markdown, see [`import`].
+//!
+//! Headings, lists, quotes, code blocks, emphasis, links and footnotes map directly onto their
+//! Markdown counterparts. Todo items become task list items, and tables made of cells become
+//! pipe tables. Anchors become reference links, defined at the end of the output.
+//!
+//! Everything Markdown cannot express falls back to something close to it:
+//!
+//! - Extensions other than a done or undone status, like priorities, timestamps or other todo
+//!   statuses, are kept in their Norg form as inline code, e.g. `` `(# A)` ``. Only list items
+//!   have checkboxes, so the todo status of headings and quotes is kept the same way.
+//! - Headings deeper than `######` are written at that level.
+//! - Definitions become a paragraph holding the term in bold, followed by the definition.
+//! - Ranged tags, including `=` macros, keep their content, preceded by an HTML comment with
+//!   their name and parameters. Macro carryover tags and infirm tags become such a comment as
+//!   well. `|comment` tags are left out.
+//! - Underline, superscript and subscript use inline HTML. Spoilers and inline variables are
+//!   written as plain text and the null modifier is left out.
+//! - `@document.meta` becomes YAML front matter when it is the first node, and is left out
+//!   anywhere else. Other verbatim ranged tags than `@code` and `@math` become fenced code
+//!   blocks without a language.
+
+mod import;
+
+pub use import::{import, to_norg};
+
+use crate::{
+    export::{groups, normalize, trim_start, verbatim, TargetKind, Targets},
+    format::extension_text,
+    table::NorgTable,
+    text::plain_text,
+    CarryoverTag, DelimitingModifier, DetachedModifierExtension, LinkTarget,
+    NestableDetachedModifier, NorgAST, ParagraphSegment, ParagraphSegmentToken,
+    RangeableDetachedModifier, TodoStatus,
+};
+
+/// Exports a tree as returned by [`crate::parse_tree`].
+pub fn export(tree: &[NorgAST]) -> String {
+    let mut exporter = Exporter {
+        targets: Targets::new(tree, |_, text| github_slug(text)),
+        footnotes: Vec::new(),
+        references: Vec::new(),
+    };
+
+    let front_matter = match tree.first() {
+        Some(NorgAST::VerbatimRangedTag { name, content, .. }) if name == &["document", "meta"] => {
+            Some(format!("---\n{}---", with_newline(content)))
+        }
+        _ => None,
+    };
+    let mut blocks: Vec<_> = front_matter
+        .into_iter()
+        .chain(exporter.blocks(tree))
+        .collect();
+
+    let mut footnotes = std::mem::take(&mut exporter.footnotes);
+    footnotes.sort_by_key(|(number, _)| *number);
+    blocks.extend(
+        footnotes
+            .into_iter()
+            .map(|(number, content)| format!("[^{number}]: {}", indent(&content, 4))),
+    );
+
+    if !exporter.references.is_empty() {
+        let references: Vec<_> = exporter
+            .references
+            .iter()
+            .map(|(label, href)| format!("[{label}]: {}", destination(href)))
+            .collect();
+        blocks.push(references.join("\n"));
+    }
+
+    match blocks.join("\n\n") {
+        output if output.is_empty() => output,
+        output => output + "\n",
+    }
+}
+
+struct Exporter {
+    targets: Targets,
+    /// The footnotes seen so far together with their number, written out at the end.
+    footnotes: Vec<(usize, String)>,
+    /// The reference link definitions used so far, written out at the end.
+    references: Vec<(String, String)>,
+}
+
+impl Exporter {
+    /// Exports the nodes of a scope as separate blocks, grouping consecutive list items, quotes,
+    /// definitions and table cells.
+    fn blocks(&mut self, nodes: &[NorgAST]) -> Vec<String> {
+        self.groups(nodes)
+            .into_iter()
+            .map(|(_, block)| block)
+            .collect()
+    }
+
+    /// Like [`Self::blocks`], also returning the first node of every block.
+    fn groups<'a>(&mut self, nodes: &'a [NorgAST]) -> Vec<(&'a NorgAST, String)> {
+        let mut blocks = Vec::new();
+
+        for group in groups(nodes) {
+            let first = &group[0];
+            let block = match first {
+                NorgAST::NestableDetachedModifier { modifier_type, .. } => {
+                    self.list(modifier_type, group)
+                }
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Definition,
+                    ..
+                } => self.definitions(group),
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Table,
+                    ..
+                } => self.table(group),
+                node => self.node(node),
+            };
+            blocks.extend(
+                block
+                    .filter(|block| !block.is_empty())
+                    .map(|block| (first, block)),
+            );
+        }
+
+        blocks
+    }
+
+    fn node(&mut self, node: &NorgAST) -> Option<String> {
+        match node {
+            NorgAST::Paragraph { content, .. } => Some(protect_start(self.inline(content))),
+            NorgAST::Heading {
+                level,
+                title,
+                extensions,
+                content,
+                ..
+            } => {
+                let mut heading = "#".repeat((*level).clamp(1, 6) as usize) + " ";
+                heading += &code_extensions(extensions);
+                heading += &self.inline(trim_start(title));
+
+                Some(
+                    std::iter::once(heading)
+                        .chain(self.blocks(content))
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                )
+            }
+            NorgAST::RangeableDetachedModifier {
+                modifier_type: RangeableDetachedModifier::Footnote,
+                title,
+                content,
+                span,
+                ..
+            } => {
+                let content = match self.blocks(content) {
+                    blocks if blocks.is_empty() => self.inline(title),
+                    blocks => blocks.join("\n\n"),
+                };
+                let number = self
+                    .targets
+                    .footnote_number(self.targets.id(span))
+                    .unwrap_or_default();
+                self.footnotes.push((number, content));
+
+                None
+            }
+            // Grouped by `blocks`.
+            NorgAST::NestableDetachedModifier { .. }
+            | NorgAST::RangeableDetachedModifier { .. } => {
+                Some(self.blocks(std::slice::from_ref(node)).join("\n\n"))
+            }
+            NorgAST::CarryoverTag {
+                tag_type,
+                name,
+                parameters,
+                next_object,
+                ..
+            } => {
+                let next = self.blocks(std::slice::from_ref(next_object));
+                let comment = match tag_type {
+                    CarryoverTag::Macro => Some(comment("#", name, parameters)),
+                    CarryoverTag::Attribute => None,
+                };
+
+                Some(
+                    comment
+                        .into_iter()
+                        .chain(next)
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                )
+            }
+            NorgAST::VerbatimRangedTag {
+                name,
+                parameters,
+                content,
+                ..
+            } => match name.join(".").as_str() {
+                // Written as front matter when it comes first.
+                "document.meta" => None,
+                "code" => Some(fenced(
+                    content,
+                    parameters.first().map_or("", String::as_str),
+                )),
+                "math" => Some(format!("$$\n{}$$", with_newline(content))),
+                _ => Some(fenced(content, "")),
+            },
+            NorgAST::RangedTag {
+                name,
+                parameters,
+                content,
+                ..
+            } => (name != &["comment"]).then(|| {
+                std::iter::once(comment("", name, parameters))
+                    .chain(self.blocks(content))
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            }),
+            NorgAST::InfirmTag {
+                name, parameters, ..
+            } => Some(comment(".", name, parameters)),
+            NorgAST::DelimitingModifier {
+                modifier_type: DelimitingModifier::HorizontalRule,
+                ..
+            } => Some("---".to_string()),
+            NorgAST::DelimitingModifier { .. } => None,
+            NorgAST::Error { content, .. } => Some(protect_start(escape(content.trim()))),
+        }
+    }
+
+    fn list(
+        &mut self,
+        modifier_type: &NestableDetachedModifier,
+        items: &[NorgAST],
+    ) -> Option<String> {
+        let mut lines = Vec::new();
+
+        for (i, item) in items.iter().enumerate() {
+            let NorgAST::NestableDetachedModifier {
+                extensions,
+                text,
+                content,
+                ..
+            } = item
+            else {
+                continue;
+            };
+            let text = match text.as_ref() {
+                NorgAST::Paragraph { content, .. } => self.inline(content),
+                _ => String::new(),
+            };
+
+            let (marker, extensions) = match modifier_type {
+                NestableDetachedModifier::Quote => ("> ".to_string(), code_extensions(extensions)),
+                NestableDetachedModifier::UnorderedList => ("- ".to_string(), task(extensions)),
+                NestableDetachedModifier::OrderedList => (format!("{}. ", i + 1), task(extensions)),
+            };
+            let mut item = extensions + &protect_start(text);
+
+            // Nested lists stay tight, anything else needs a blank line to not be taken as a
+            // continuation of the text.
+            for (nested, block) in self.groups(content) {
+                match nested {
+                    NorgAST::NestableDetachedModifier { .. } => item += "\n",
+                    _ => item += "\n\n",
+                }
+                item += &block;
+            }
+
+            lines.push(match modifier_type {
+                NestableDetachedModifier::Quote => quote(&item),
+                _ => marker.clone() + &indent(&item, marker.len()),
+            });
+        }
+
+        Some(lines.join("\n"))
+    }
+
+    /// Exports every definition as a paragraph holding the term in bold, followed by the
+    /// definition.
+    fn definitions(&mut self, definitions: &[NorgAST]) -> Option<String> {
+        let mut blocks = Vec::new();
+
+        for definition in definitions {
+            if let NorgAST::RangeableDetachedModifier { title, content, .. } = definition {
+                blocks.push(format!("**{}**", self.inline(title).trim()));
+                blocks.extend(self.blocks(content));
+            }
+        }
+
+        Some(blocks.join("\n\n"))
+    }
+
+    /// Exports table cells as a pipe table, using the first row as the header. Cells without a
+    /// valid address are left out.
+    fn table(&mut self, cells: &[NorgAST]) -> Option<String> {
+        let table = NorgTable::from_nodes(cells)?;
+        let grid = table.grid();
+        let columns = grid.first().map_or(0, Vec::len);
+
+        let mut lines: Vec<_> = grid
+            .into_iter()
+            .map(|row| {
+                let cells: Vec<_> = row
+                    .into_iter()
+                    .map(|cell| match cell {
+                        Some(cell) => self.blocks(&cell.content).join("<br>").replace('\n', " "),
+                        None => String::new(),
+                    })
+                    .collect();
+                format!("| {} |", cells.join(" | "))
+            })
+            .collect();
+        lines.insert(1, format!("|{}", " --- |".repeat(columns)));
+
+        Some(lines.join("\n"))
+    }
+
+    fn inline(&mut self, segments: &[ParagraphSegment]) -> String {
+        let mut output = String::new();
+
+        for segment in segments {
+            match segment {
+                ParagraphSegment::Token { token, .. } => output += &token_text(token),
+                ParagraphSegment::AttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                }
+                | ParagraphSegment::FreeFormAttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                } => {
+                    let (open, close) = match modifier_type {
+                        '*' => ("**", "**"),
+                        '/' => ("*", "*"),
+                        '-' => ("~~", "~~"),
+                        '_' => ("<u>", "</u>"),
+                        '^' => ("<sup>", "</sup>"),
+                        ',' => ("<sub>", "</sub>"),
+                        '`' => {
+                            output += &code_span(&single_line(verbatim(segment)));
+                            continue;
+                        }
+                        '$' => {
+                            output += &format!("${}$", single_line(verbatim(segment)));
+                            continue;
+                        }
+                        // The null modifier comments its content out.
+                        '%' => continue,
+                        _ => ("", ""),
+                    };
+
+                    output += open;
+                    output += &self.inline(content);
+                    output += close;
+                }
+                ParagraphSegment::InlineVerbatim { .. } => {
+                    output += &code_span(&single_line(verbatim(segment)))
+                }
+                ParagraphSegment::Link {
+                    filepath,
+                    targets,
+                    description,
+                    ..
+                } => output += &self.link(filepath.as_deref(), targets, description.as_deref()),
+                ParagraphSegment::AnchorDefinition {
+                    content, target, ..
+                } => {
+                    let text = self.inline(content);
+                    output += &self.reference(text, &plain_text(content), target);
+                }
+                ParagraphSegment::Anchor {
+                    content,
+                    description,
+                    ..
+                } => {
+                    let text = self.inline(description.as_deref().unwrap_or(content));
+                    let name = plain_text(content);
+                    output += &match self.targets.anchor(&name).cloned() {
+                        Some(target) => self.reference(text, &name, &target),
+                        None => text,
+                    };
+                }
+                ParagraphSegment::InlineLinkTarget { content, .. } => {
+                    output += &self.inline(content)
+                }
+                // Only used while parsing, these never end up in the tree.
+                ParagraphSegment::AttachedModifierOpener(_)
+                | ParagraphSegment::AttachedModifierOpenerFail(_)
+                | ParagraphSegment::AttachedModifierCloserCandidate(_)
+                | ParagraphSegment::AttachedModifierCloser(_)
+                | ParagraphSegment::AttachedModifierCandidate { .. } => {}
+            }
+        }
+
+        output
+    }
+
+    fn link(
+        &mut self,
+        filepath: Option<&str>,
+        targets: &[LinkTarget],
+        description: Option<&[ParagraphSegment]>,
+    ) -> String {
+        let text = description.map(|description| self.inline(description));
+
+        if let (None, Some(LinkTarget::Footnote { title, .. })) = (filepath, targets.last()) {
+            let number = self
+                .targets
+                .find(Some(TargetKind::Footnote), title)
+                .and_then(|footnote| self.targets.footnote_number(&footnote.id));
+            return match number {
+                Some(number) => format!("{}[^{number}]", text.unwrap_or_default()),
+                None => text.unwrap_or_else(|| self.inline(title)),
+            };
+        }
+
+        let text = match (text, targets.last()) {
+            (Some(text), _) => text,
+            (None, Some(LinkTarget::Url { url, .. }))
+                if description.is_none() && url.contains("://") =>
+            {
+                return format!("<{url}>");
+            }
+            (
+                None,
+                Some(LinkTarget::Url { url: text, .. } | LinkTarget::Path { path: text, .. }),
+            ) => escape(text),
+            (None, Some(LinkTarget::LineNumber { line, .. })) => line.to_string(),
+            (None, Some(LinkTarget::Timestamp { timestamp, .. })) => escape(&timestamp.raw),
+            (
+                None,
+                Some(
+                    LinkTarget::Heading { title, .. }
+                    | LinkTarget::Footnote { title, .. }
+                    | LinkTarget::Definition { title, .. }
+                    | LinkTarget::Generic { title, .. }
+                    | LinkTarget::Wiki { title, .. }
+                    | LinkTarget::Extendable { title, .. },
+                ),
+            ) => self.inline(title),
+            (None, None) => escape(filepath.unwrap_or_default()),
+        };
+
+        match href(filepath, targets) {
+            Some(href) => format!("[{text}]({})", destination(&href)),
+            None => text,
+        }
+    }
+
+    /// Writes a reference link, defining the reference on first use.
+    fn reference(&mut self, text: String, name: &str, target: &ParagraphSegment) -> String {
+        let ParagraphSegment::Link {
+            filepath, targets, ..
+        } = target
+        else {
+            return text;
+        };
+        let Some(href) = href(filepath.as_deref(), targets) else {
+            return text;
+        };
+
+        let label = escape(&normalize(name));
+        if !self
+            .references
+            .iter()
+            .any(|(existing, _)| existing == &label)
+        {
+            self.references.push((label.clone(), href));
+        }
+
+        if normalize(&text) == label {
+            format!("[{text}]")
+        } else {
+            format!("[{text}][{label}]")
+        }
+    }
+}
+
+/// Resolves the targets of a link. Only the last target is used, as it is the most specific
+/// one. Links into other files point at the `.md` file with the same name.
+fn href(filepath: Option<&str>, targets: &[LinkTarget]) -> Option<String> {
+    let file = filepath.map(|path| format!("{path}.md"));
+
+    let fragment = match targets.last() {
+        Some(LinkTarget::Url { url, .. }) => return Some(url.clone()),
+        Some(LinkTarget::Path { path, .. }) => return Some(path.clone()),
+        Some(LinkTarget::LineNumber { .. } | LinkTarget::Timestamp { .. }) | None => None,
+        Some(
+            LinkTarget::Heading { title, .. }
+            | LinkTarget::Footnote { title, .. }
+            | LinkTarget::Definition { title, .. }
+            | LinkTarget::Generic { title, .. }
+            | LinkTarget::Wiki { title, .. }
+            | LinkTarget::Extendable { title, .. },
+        ) => Some(github_slug(&plain_text(title))),
+    };
+
+    match (file, fragment) {
+        (Some(file), Some(fragment)) => Some(format!("{file}#{fragment}")),
+        (Some(file), None) => Some(file),
+        (None, Some(fragment)) => Some(format!("#{fragment}")),
+        (None, None) => None,
+    }
+}
+
+/// The checkbox of a task list item, followed by the extensions it cannot show.
+fn task(extensions: &[DetachedModifierExtension]) -> String {
+    let checkbox = extensions.iter().find_map(|extension| match extension {
+        DetachedModifierExtension::Todo(TodoStatus::Done) => Some("[x] "),
+        DetachedModifierExtension::Todo(_) => Some("[ ] "),
+        _ => None,
+    });
+    let rest: Vec<_> = extensions
+        .iter()
+        .filter(|extension| {
+            !matches!(
+                extension,
+                DetachedModifierExtension::Todo(TodoStatus::Done | TodoStatus::Undone)
+            )
+        })
+        .cloned()
+        .collect();
+
+    checkbox.unwrap_or_default().to_string() + &code_extensions(&rest)
+}
+
+/// Extensions in their Norg form as inline code, followed by a space.
+fn code_extensions(extensions: &[DetachedModifierExtension]) -> String {
+    match extensions {
+        [] => String::new(),
+        extensions => code_span(&extension_text(extensions)) + " ",
+    }
+}
+
+/// Writes a comment holding the name and parameters of a tag, after the given prefix.
+fn comment(prefix: &str, name: &[String], parameters: &[String]) -> String {
+    let tag = std::iter::once(format!("{prefix}{}", name.join(".")))
+        .chain(parameters.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("--", "- -");
+
+    format!("<!-- {tag} -->")
+}
+
+fn fenced(content: &str, language: &str) -> String {
+    let fence = "`".repeat(longest_run(content, '`').max(2) + 1);
+
+    format!("{fence}{language}\n{}{fence}", with_newline(content))
+}
+
+fn code_span(content: &str) -> String {
+    let fence = "`".repeat(longest_run(content, '`') + 1);
+    let padding = if content.starts_with('`') || content.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+
+    format!("{fence}{padding}{content}{padding}{fence}")
+}
+
+fn longest_run(text: &str, c: char) -> usize {
+    text.split(|other| other != c)
+        .map(str::len)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Verbatim content on a single line, as the next line could otherwise start a block.
+fn single_line(text: Option<String>) -> String {
+    text.unwrap_or_default().replace('\n', " ")
+}
+
+fn token_text(token: &ParagraphSegmentToken) -> String {
+    match token {
+        ParagraphSegmentToken::Text(text) => escape(text),
+        ParagraphSegmentToken::Whitespace => " ".to_string(),
+        ParagraphSegmentToken::Escape('\n') => "\\\n".to_string(),
+        ParagraphSegmentToken::Special(c) | ParagraphSegmentToken::Escape(c) => {
+            escape(&c.to_string())
+        }
+    }
+}
+
+/// Escapes the start of a block which would otherwise be taken for a heading, list item, quote
+/// or thematic break.
+fn protect_start(text: String) -> String {
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+
+    match text[digits..].chars().next() {
+        Some('.' | ')') if digits > 0 => format!("{}\\{}", &text[..digits], &text[digits..]),
+        Some('#' | '>' | '-' | '+' | '=') if digits == 0 => format!("\\{text}"),
+        _ => text,
+    }
+}
+
+fn indent(text: &str, width: usize) -> String {
+    let padding = " ".repeat(width);
+
+    text.split('\n')
+        .enumerate()
+        .map(|(i, line)| match line {
+            _ if i == 0 || line.is_empty() => line.to_string(),
+            line => format!("{padding}{line}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn quote(text: &str) -> String {
+    text.split('\n')
+        .map(|line| match line {
+            "" => ">".to_string(),
+            line => format!("> {line}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn with_newline(content: &str) -> String {
+    match content {
+        "" => String::new(),
+        content if content.ends_with('\n') => content.to_string(),
+        content => format!("{content}\n"),
+    }
+}
+
+/// A link destination, in angle brackets when it would otherwise end early.
+fn destination(href: &str) -> String {
+    if href.contains([' ', '(', ')', '<', '>']) {
+        format!("<{}>", href.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        href.to_string()
+    }
+}
+
+/// Turns a title into the id GitHub gives to a heading with that title.
+fn github_slug(text: &str) -> String {
+    text.trim()
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if "\\`*_[]<>~|$&".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::proptest;
+
+    use crate::{
+        markdown::{code_span, escape, export},
+        parse_tree,
+    };
+
+    fn exported(input: &str) -> String {
+        export(&parse_tree(input).unwrap())
+    }
+
+    #[test]
+    fn exporting() {
+        assert_yaml_snapshot!(
+            "metadata",
+            exported("@document.meta\ntitle: Tasks\n@end\nSome text.\n")
+        );
+        assert_yaml_snapshot!(
+            "headings",
+            exported(
+                "* Tasks
+                 ** (x) Done
+                    A paragraph.
+                 ---
+                 Back in the first heading.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "lists",
+            exported(
+                "- ( ) Write *the* /exporter/
+                 -- (x) Escape | pipes | and 1 < 2
+                 - (!|# A) Ship it
+                 ~ First
+                 ~ Second\n"
+            )
+        );
+        assert_yaml_snapshot!("quotes", exported("> Quoted -text-\n>> Nested\n"));
+        assert_yaml_snapshot!(
+            "markup",
+            exported(
+                "*Bold*, /italic/, _underline_, -deleted-, `code`, ^super^ and
+                 %a comment%.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "links",
+            exported(
+                "Links to {* Tasks}, {https://example.com}, {:other:** Some heading}[elsewhere] and
+                 [anchor]{https://neorg.org}. Then [anchor] again.
+                 * Tasks\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "footnotes",
+            exported("Some text.{^ Note}\n^ Note\nA footnote.\n")
+        );
+        assert_yaml_snapshot!("definitions", exported("$ Term\nThe definition.\n"));
+        assert_yaml_snapshot!(
+            "tables",
+            exported(": A1\n  Name\n: B1\n  Age\n: A2\n  Bob\n")
+        );
+        assert_yaml_snapshot!("sparse_tables", exported(": A1\n  a\n: C3\n  c\n"));
+        assert_yaml_snapshot!(
+            "tags",
+            exported("=toc\nMacro content.\n=end\n#name param\nA paragraph.\n___\n")
+        );
+        assert_yaml_snapshot!("code", exported("@code rust\nfn main() {}\n@end\n"));
+        assert_yaml_snapshot!(
+            "verbatim",
+            exported("`a\\b`, $\\frac{1}{2}$ and *|a\\b  c|*\n")
+        );
+        assert_yaml_snapshot!(
+            "escapes",
+            exported(
+                "Escaped \\*stars\\*, a backslash \\\\ and a pipe \\|.
+
+                 10. Not a list\n"
+            )
+        );
+        assert_yaml_snapshot!("escaped_pipes", exported(": A1\n  a \\| b\n: B1\n  c\n"));
+    }
+
+    proptest! {
+        #[test]
+        fn escaped_text_has_no_markup(text in r"\PC*") {
+            let escaped = escape(&text);
+            let mut chars = escaped.chars();
+
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => assert!(chars.next().is_some()),
+                    c => assert!(!"`*_[]<>~|$&".contains(c)),
+                }
+            }
+        }
+
+        #[test]
+        fn code_spans_contain_their_content(content in r"[a-z`]*[a-z]") {
+            let span = code_span(&content);
+            let fence = span.chars().take_while(|&c| c == '`').count();
+
+            assert_eq!(span[fence..span.len() - fence].trim(), content.trim());
+            assert!(!content.contains(&"`".repeat(fence)));
+        }
+    }
+}
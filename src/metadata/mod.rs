@@ -1,16 +1,50 @@
 use chumsky::Parser;
 pub use stage_1::NorgMeta;
 
-use crate::error::NorgParseError;
+use crate::{
+    error::NorgParseError,
+    span::{source_chars, spanned_stream, Span},
+};
 
 pub mod stage_1;
 
-/// Parses the given input string to produce an AST for the metadata
+/// Parses the given input string to produce an AST for the metadata.
+///
+/// Error locations are relative to `input`.
 pub fn parse_metadata(input: &str) -> Result<NorgMeta, NorgParseError> {
-    // don't ask me why this fixes it. I don't even care
-    let processed = input.replace("\n]", "\n ]");
-    let processed = format!("{{\n{}\n}}\n", processed.trim());
-    Ok(stage_1::meta_parser().parse(processed)?)
+    let chars: Vec<_> = source_chars(input).collect();
+
+    let start = chars
+        .iter()
+        .position(|(c, _)| !c.is_whitespace())
+        .unwrap_or(chars.len());
+    let end = chars
+        .iter()
+        .rposition(|(c, _)| !c.is_whitespace())
+        .map_or(start, |i| i + 1);
+    let body = &chars[start..end];
+
+    let first = body
+        .first()
+        .map_or_else(Span::default, |(_, span)| Span::empty(span.start));
+    let last = body.last().map_or(first, |(_, span)| Span::empty(span.end));
+
+    // The body is wrapped in braces so that it parses as a single object. The characters we
+    // insert have empty spans, which keeps the error locations pointing into `input`.
+    let mut processed = vec![('{', first), ('\n', first)];
+    for (i, (c, span)) in body.iter().enumerate() {
+        processed.push((*c, *span));
+
+        // don't ask me why this fixes it. I don't even care
+        if *c == '\n' && matches!(body.get(i + 1), Some((']', _))) {
+            processed.push((' ', Span::empty(span.end)));
+        }
+    }
+    processed.extend([('\n', last), ('}', last), ('\n', last)]);
+
+    stage_1::meta_parser()
+        .parse(spanned_stream(processed))
+        .map_err(NorgParseError::Meta)
 }
 
 #[cfg(test)]
@@ -134,4 +168,14 @@ mod tests {
 
         assert_yaml_snapshot!(examples);
     }
+
+    #[test]
+    fn errors() {
+        let examples: Vec<_> = ["key: [\n  a\n", "a: b\nkeys: {\n  in: x\n]\n"]
+            .into_iter()
+            .map(|str| parse_metadata(str).unwrap_err().diagnostics())
+            .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
 }
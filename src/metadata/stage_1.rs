@@ -3,6 +3,8 @@ use serde::Serialize;
 use std::collections::BTreeMap;
 use text::TextParser;
 
+use crate::span::Span;
+
 #[derive(Clone, Debug, Serialize)]
 pub enum NorgMeta {
     Invalid,
@@ -17,7 +19,7 @@ pub enum NorgMeta {
 
 const SPECIAL: &str = "{}[]:\n";
 
-pub fn meta_parser() -> impl Parser<char, NorgMeta, Error = Simple<char>> {
+pub fn meta_parser() -> impl Parser<char, NorgMeta, Error = Simple<char, Span>> {
     recursive(|value| {
         let frac = just('.').chain(text::digits(10));
 
@@ -0,0 +1,1296 @@
+//! Exporting a tree as an Org document.
+//!
+//! Headings, lists, quotes, emphasis, links, footnotes and tables map directly onto their Org
+//! counterparts, and definitions become description lists. The extensions of headings turn into
+//! what Org uses for planning:
+//!
+//! - Todo statuses become keywords: undone, urgent and recurring items are `TODO`, pending
+//!   ones `NEXT`, items which need clarification `WAITING`, paused ones `HOLD`, done ones
+//!   `DONE` and canceled ones `CANCELLED`. An `#+TODO:` line declares the keywords Org does
+//!   not know by itself when they are used. Urgent items get the priority `[#A]`, unless they
+//!   have one of their own.
+//! - Priorities made of a single letter or a number become cookies like `[#A]`, and so do
+//!   `high`, `medium` and `low`, as `[#A]`, `[#B]` and `[#C]`.
+//! - Due and start dates become `DEADLINE:` and `SCHEDULED:` on the line after the heading.
+//!   Recurring items are scheduled as well, with a repeater like `+1y` for the largest part of
+//!   the date which is left out, unless they have a start date. Other timestamps become active
+//!   timestamps on the line after that.
+//!
+//! Org timestamps always have a full date. Parts which are left out are filled in with the first
+//! matching date on or after [`ExportOptions::today`], e.g. `(< Friday)` is due on the next
+//! Friday. Timestamps which do not give a date, like invalid ones, are kept in their Norg form as
+//! inline code, e.g. `~(< 30 Feb)~`, and so are priorities Org has no cookie for.
+//!
+//! List items use checkboxes for their status, followed by the keyword where a checkbox cannot
+//! tell it apart, and keep their priority and dates on the line of the item. As Org only reads
+//! planning right below a headline, the due and start dates of list items and quotes are written
+//! as plain text, e.g. `deadline <2025-01-31 Fri>` and `scheduled <2025-01-27 Mon>`.
+//!
+//! Lines of text which Org would take for something else, like `* Not a heading`, start with an
+//! entity instead of the character or space which makes them special, e.g. `\ast{} Not a
+//! heading`. Escaped emphasis markers become entities as well, so `\*not bold\*` is written as
+//! `\ast{}not bold\ast{}`.
+//!
+//! `@code` becomes a `#+begin_src` block, `@math` a LaTeX fragment and other verbatim ranged
+//! tags example blocks. Other ranged tags become special blocks of the same name, so that
+//! `|comment` is left out by Org as well. `@document.meta` becomes keywords at the top of the
+//! document: `title`, `description`, `authors`, `created` and `categories` are written as
+//! `#+TITLE`, `#+DESCRIPTION`, `#+AUTHOR`, `#+DATE` and `#+FILETAGS`, and every other field as
+//! a keyword of its own name in upper case.
+//!
+//! Headings which are linked to get a `CUSTOM_ID` to link to, and definitions a `<<target>>` in
+//! front of their term. Inline link targets become `<<targets>>` as well.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    export::{groups, normalize, raw, slug, trim_start, verbatim, TargetKind, Targets},
+    format::extension_text,
+    metadata::{parse_metadata, NorgMeta},
+    table::NorgTable,
+    text::plain_text,
+    timestamp::NorgDateTime,
+    visit::{walk_link, Visitor},
+    CarryoverTag, DelimitingModifier, DetachedModifierExtension, LinkTarget,
+    NestableDetachedModifier, NorgAST, NorgTimestamp, ParagraphSegment, ParagraphSegmentToken,
+    RangeableDetachedModifier, TodoStatus,
+};
+
+/// Declares the keywords used for todo statuses, the ones after `|` being closed.
+const TODO_KEYWORDS: &str = "#+TODO: TODO NEXT WAITING HOLD | DONE CANCELLED";
+
+/// The metadata fields with a keyword of their own in Org, written in this order.
+const KEYWORDS: [(&str, &str); 5] = [
+    ("title", "TITLE"),
+    ("description", "DESCRIPTION"),
+    ("authors", "AUTHOR"),
+    ("created", "DATE"),
+    ("categories", "FILETAGS"),
+];
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Options for [`export_with`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// The year, month and day timestamps without a full date are resolved from: `Friday` is
+    /// the first Friday on or after it. Defaults to the current date in UTC.
+    pub today: (u32, u8, u8),
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        let days = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() / 86400);
+
+        Self {
+            today: (0..days).fold((1970, 1, 1), |date, _| next_day(date)),
+        }
+    }
+}
+
+/// Exports a tree with the default options.
+pub fn export(tree: &[NorgAST]) -> String {
+    export_with(tree, &ExportOptions::default())
+}
+
+/// Exports a tree as returned by [`crate::parse_tree`].
+pub fn export_with(tree: &[NorgAST], options: &ExportOptions) -> String {
+    let mut exporter = Exporter {
+        targets: Targets::new(tree, |_, text| slug(text)),
+        today: options.today,
+        linked: HashSet::new(),
+        footnotes: Vec::new(),
+        custom_keywords: false,
+    };
+    tree.iter().for_each(|node| exporter.visit_node(node));
+
+    let meta = tree.iter().find_map(|node| match node {
+        NorgAST::VerbatimRangedTag { name, content, .. } if name == &["document", "meta"] => {
+            match parse_metadata(content) {
+                Ok(NorgMeta::Object(fields)) => Some(fields),
+                _ => None,
+            }
+        }
+        _ => None,
+    });
+
+    let mut blocks = exporter.blocks(tree);
+    blocks.append(&mut exporter.footnotes);
+
+    let mut header = keywords(meta.unwrap_or_default());
+    if exporter.custom_keywords {
+        header.push(TODO_KEYWORDS.to_string());
+    }
+    if !header.is_empty() {
+        blocks.insert(0, header.join("\n"));
+    }
+
+    match blocks.join("\n\n") {
+        output if output.is_empty() => output,
+        output => output + "\n",
+    }
+}
+
+/// The `#+KEY: value` lines for the fields of `@document.meta`.
+fn keywords(mut meta: BTreeMap<String, NorgMeta>) -> Vec<String> {
+    let mut fields: Vec<_> = KEYWORDS
+        .iter()
+        .filter_map(|(key, keyword)| Some((keyword.to_string(), meta.remove(*key)?)))
+        .collect();
+    fields.extend(
+        meta.into_iter()
+            .map(|(key, value)| (key.to_uppercase(), value)),
+    );
+
+    fields
+        .into_iter()
+        .filter_map(|(keyword, value)| {
+            let values = strings(value);
+            let value = match keyword.as_str() {
+                _ if values.is_empty() => return None,
+                "AUTHOR" => values.join(", "),
+                "FILETAGS" => format!(":{}:", values.join(":").replace(char::is_whitespace, "_")),
+                _ => values.join(" "),
+            };
+
+            Some(format!("#+{keyword}: {value}"))
+        })
+        .collect()
+}
+
+/// A metadata value as a list of strings, so that single values and arrays can be used alike.
+fn strings(meta: NorgMeta) -> Vec<String> {
+    match meta {
+        NorgMeta::Str(text) | NorgMeta::EmptyKey(text) => vec![text],
+        NorgMeta::Num(number) => vec![number.to_string()],
+        NorgMeta::Bool(value) => vec![value.to_string()],
+        NorgMeta::Array(items) => items.into_iter().flat_map(strings).collect(),
+        NorgMeta::Object(_) | NorgMeta::Nil | NorgMeta::Invalid => Vec::new(),
+    }
+}
+
+struct Exporter {
+    targets: Targets,
+    today: (u32, u8, u8),
+    /// The normalised titles links in this file point to, whose headings need an id.
+    linked: HashSet<String>,
+    /// The footnote definitions seen so far, written out at the end.
+    footnotes: Vec<String>,
+    /// Whether a keyword was used which has to be declared with [`TODO_KEYWORDS`].
+    custom_keywords: bool,
+}
+
+impl Visitor for Exporter {
+    fn visit_link(&mut self, segment: &ParagraphSegment) {
+        if let ParagraphSegment::Link {
+            filepath: None,
+            targets,
+            ..
+        } = segment
+        {
+            if let Some(
+                LinkTarget::Heading { title, .. }
+                | LinkTarget::Definition { title, .. }
+                | LinkTarget::Generic { title, .. }
+                | LinkTarget::Wiki { title, .. }
+                | LinkTarget::Extendable { title, .. },
+            ) = targets.last()
+            {
+                self.linked.insert(normalize(&plain_text(title)));
+            }
+        }
+
+        walk_link(self, segment)
+    }
+}
+
+impl Exporter {
+    /// Exports the nodes of a scope as separate blocks, grouping consecutive list items, quotes,
+    /// definitions and table cells.
+    fn blocks(&mut self, nodes: &[NorgAST]) -> Vec<String> {
+        self.groups(nodes)
+            .into_iter()
+            .map(|(_, block)| block)
+            .collect()
+    }
+
+    /// Like [`Self::blocks`], also returning the first node of every block.
+    fn groups<'a>(&mut self, nodes: &'a [NorgAST]) -> Vec<(&'a NorgAST, String)> {
+        let mut blocks = Vec::new();
+
+        for group in groups(nodes) {
+            let first = &group[0];
+            let block = match first {
+                NorgAST::NestableDetachedModifier {
+                    modifier_type: NestableDetachedModifier::Quote,
+                    ..
+                } => Some(self.quote(group)),
+                NorgAST::NestableDetachedModifier { modifier_type, .. } => {
+                    Some(self.list(modifier_type, group))
+                }
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Definition,
+                    ..
+                } => Some(self.definitions(group)),
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Table,
+                    ..
+                } => self.table(group),
+                node => self.node(node),
+            };
+            blocks.extend(
+                block
+                    .filter(|block| !block.is_empty())
+                    .map(|block| (first, block)),
+            );
+        }
+
+        blocks
+    }
+
+    fn node(&mut self, node: &NorgAST) -> Option<String> {
+        match node {
+            NorgAST::Paragraph { content, .. } => Some(protect_lines(&self.inline(content))),
+            NorgAST::Heading {
+                level,
+                title,
+                extensions,
+                content,
+                span,
+            } => {
+                let todo = Todo::new(extensions, self.today);
+                let mut heading = "*".repeat((*level).max(1) as usize) + " ";
+                if let Some(keyword) = todo.keyword {
+                    self.custom_keywords |= !matches!(keyword, "TODO" | "DONE");
+                    heading += keyword;
+                    heading += " ";
+                }
+                heading += &todo.priority();
+                heading += &self.inline(trim_start(title));
+
+                let mut lines = vec![heading.trim_end().to_string()];
+                if !todo.planning.is_empty() {
+                    let planning: Vec<_> = todo
+                        .planning
+                        .iter()
+                        .map(|(keyword, timestamp)| format!("{keyword}: {timestamp}"))
+                        .collect();
+                    lines.push(planning.join(" "));
+                }
+                if self.linked.contains(&normalize(&plain_text(title))) {
+                    let id = self.targets.id(span);
+                    lines.push(format!(":PROPERTIES:\n:CUSTOM_ID: {id}\n:END:"));
+                }
+                if !todo.notes.is_empty() {
+                    lines.push(todo.notes.join(" "));
+                }
+
+                Some(
+                    std::iter::once(lines.join("\n"))
+                        .chain(self.blocks(content))
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                )
+            }
+            NorgAST::RangeableDetachedModifier {
+                modifier_type: RangeableDetachedModifier::Footnote,
+                title,
+                content,
+                span,
+                ..
+            } => {
+                let label = self.targets.id(span).to_string();
+                let content = match self.blocks(content) {
+                    blocks if blocks.is_empty() => self.inline(title).trim().to_string(),
+                    blocks => blocks.join("\n\n"),
+                };
+                self.footnotes.push(format!("[fn:{label}] {content}"));
+
+                None
+            }
+            // Grouped by `groups`.
+            NorgAST::NestableDetachedModifier { .. }
+            | NorgAST::RangeableDetachedModifier { .. } => {
+                Some(self.blocks(std::slice::from_ref(node)).join("\n\n"))
+            }
+            NorgAST::CarryoverTag {
+                tag_type,
+                name,
+                parameters,
+                next_object,
+                ..
+            } => {
+                let next = self.blocks(std::slice::from_ref(next_object));
+                let comment = match tag_type {
+                    CarryoverTag::Macro => Some(comment("#", name, parameters)),
+                    CarryoverTag::Attribute => None,
+                };
+
+                Some(
+                    comment
+                        .into_iter()
+                        .chain(next)
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                )
+            }
+            NorgAST::VerbatimRangedTag {
+                name,
+                parameters,
+                content,
+                ..
+            } => match name.join(".").as_str() {
+                // Written as keywords.
+                "document.meta" => None,
+                "code" => Some(block("src", &parameters.join(" "), &escape_block(content))),
+                "math" => {
+                    let content = content.trim();
+                    match content.starts_with("\\begin{") {
+                        true => Some(content.to_string()),
+                        false => Some(format!("\\[\n{content}\n\\]")),
+                    }
+                }
+                _ => Some(block("example", "", &escape_block(content))),
+            },
+            NorgAST::RangedTag {
+                name,
+                parameters,
+                content,
+                ..
+            } => Some(block(
+                &name.join("."),
+                &parameters.join(" "),
+                &self.blocks(content).join("\n\n"),
+            )),
+            NorgAST::InfirmTag {
+                name, parameters, ..
+            } => Some(comment(".", name, parameters)),
+            NorgAST::DelimitingModifier {
+                modifier_type: DelimitingModifier::HorizontalRule,
+                ..
+            } => Some("-----".to_string()),
+            NorgAST::DelimitingModifier { .. } => None,
+            NorgAST::Error { content, .. } => Some(protect_lines(content.trim())),
+        }
+    }
+
+    fn list(&mut self, modifier_type: &NestableDetachedModifier, items: &[NorgAST]) -> String {
+        let mut lines = Vec::new();
+
+        for (i, item) in items.iter().enumerate() {
+            let NorgAST::NestableDetachedModifier {
+                extensions,
+                text,
+                content,
+                ..
+            } = item
+            else {
+                continue;
+            };
+            let todo = Todo::new(extensions, self.today);
+
+            let mut item = match modifier_type {
+                NestableDetachedModifier::OrderedList => format!("{}. ", i + 1),
+                _ => "- ".to_string(),
+            };
+            let marker = item.len();
+            item += match todo.status {
+                Some(TodoStatus::Done | TodoStatus::Canceled) => "[X] ",
+                Some(TodoStatus::Pending) => "[-] ",
+                Some(_) => "[ ] ",
+                None => "",
+            };
+            // The checkbox tells all but these apart.
+            if let Some(keyword @ ("WAITING" | "HOLD" | "CANCELLED")) = todo.keyword {
+                self.custom_keywords = true;
+                item += keyword;
+                item += " ";
+            }
+            item += &todo.priority();
+            item += &self.paragraph(text);
+            for note in todo.plain_notes() {
+                item += " ";
+                item += &note;
+            }
+
+            let mut item = item.trim_end().to_string();
+            item += &nested(self.groups(content));
+            lines.push(indent(&item, marker));
+        }
+
+        lines.join("\n")
+    }
+
+    fn quote(&mut self, quotes: &[NorgAST]) -> String {
+        let mut blocks = Vec::new();
+
+        for quote in quotes {
+            let NorgAST::NestableDetachedModifier {
+                extensions,
+                text,
+                content,
+                ..
+            } = quote
+            else {
+                continue;
+            };
+            let todo = Todo::new(extensions, self.today);
+
+            let mut line = String::new();
+            if let Some(keyword) = todo.keyword {
+                self.custom_keywords |= !matches!(keyword, "TODO" | "DONE");
+                line += keyword;
+                line += " ";
+            }
+            line += &todo.priority();
+            line += &self.paragraph(text);
+            for note in todo.plain_notes() {
+                line += " ";
+                line += &note;
+            }
+
+            blocks.extend(Some(line.trim_end().to_string()).filter(|line| !line.is_empty()));
+            blocks.extend(self.blocks(content));
+        }
+
+        block("quote", "", &blocks.join("\n\n"))
+    }
+
+    fn definitions(&mut self, definitions: &[NorgAST]) -> String {
+        let mut items = Vec::new();
+
+        for definition in definitions {
+            if let NorgAST::RangeableDetachedModifier {
+                title,
+                content,
+                span,
+                ..
+            } = definition
+            {
+                // Linked definitions get a target in front of their term to link to.
+                let target = match self.linked.contains(&normalize(&plain_text(title))) {
+                    true => format!("<<{}>>", self.targets.id(span)),
+                    false => String::new(),
+                };
+                let mut item = format!("- {target}{} ::", self.inline(title).trim());
+                let mut groups = self.groups(content).into_iter();
+
+                // A paragraph can start on the line of the term, anything else goes below it.
+                if let Some((first, block)) = groups.next() {
+                    match first {
+                        NorgAST::Paragraph { .. } => item += &format!(" {block}"),
+                        _ => item += &format!("\n{block}"),
+                    }
+                }
+                item += &nested(groups.collect());
+
+                items.push(indent(&item, 2));
+            }
+        }
+
+        items.join("\n")
+    }
+
+    /// Exports table cells as an Org table, with a rule after the first row. Cells without a
+    /// valid address are left out.
+    fn table(&mut self, cells: &[NorgAST]) -> Option<String> {
+        let table = NorgTable::from_nodes(cells)?;
+        let grid = table.grid();
+        let columns = grid.first().map_or(0, Vec::len);
+
+        let mut lines: Vec<_> = grid
+            .into_iter()
+            .map(|row| {
+                let cells: Vec<_> = row
+                    .into_iter()
+                    .map(|cell| match cell {
+                        Some(cell) => self
+                            .blocks(&cell.content)
+                            .join(" ")
+                            .replace('\n', " ")
+                            .replace('|', "\\vert{}"),
+                        None => String::new(),
+                    })
+                    .collect();
+                format!("| {} |", cells.join(" | "))
+            })
+            .collect();
+        if lines.len() > 1 {
+            lines.insert(1, format!("|{}|", vec!["---"; columns].join("+")));
+        }
+
+        Some(lines.join("\n"))
+    }
+
+    /// The text of a list item or quote.
+    fn paragraph(&mut self, text: &NorgAST) -> String {
+        match text {
+            NorgAST::Paragraph { content, .. } => protect_lines(&self.inline(content)),
+            _ => String::new(),
+        }
+    }
+
+    fn inline(&mut self, segments: &[ParagraphSegment]) -> String {
+        let mut output = String::new();
+
+        for segment in segments {
+            match segment {
+                ParagraphSegment::Token { token, .. } => output += &token_text(token),
+                ParagraphSegment::AttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                }
+                | ParagraphSegment::FreeFormAttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                } => {
+                    let (open, close) = match modifier_type {
+                        '*' => ("*", "*"),
+                        '/' => ("/", "/"),
+                        '_' => ("_", "_"),
+                        '-' => ("+", "+"),
+                        '^' => ("^{", "}"),
+                        ',' => ("_{", "}"),
+                        '`' => {
+                            output += &code(&verbatim(segment).unwrap_or_default());
+                            continue;
+                        }
+                        '$' => {
+                            output += &format!("\\({}\\)", verbatim(segment).unwrap_or_default());
+                            continue;
+                        }
+                        // The null modifier comments its content out.
+                        '%' => continue,
+                        _ => ("", ""),
+                    };
+
+                    output += open;
+                    output += &self.inline(content);
+                    output += close;
+                }
+                ParagraphSegment::InlineVerbatim { content, .. } => output += &code(&raw(content)),
+                ParagraphSegment::Link {
+                    filepath,
+                    targets,
+                    description,
+                    ..
+                } => output += &self.link(filepath.as_deref(), targets, description.as_deref()),
+                ParagraphSegment::AnchorDefinition {
+                    content, target, ..
+                } => {
+                    let text = self.inline(content);
+                    output += &self.anchor(text, target);
+                }
+                ParagraphSegment::Anchor {
+                    content,
+                    description,
+                    ..
+                } => {
+                    let text = self.inline(description.as_deref().unwrap_or(content));
+                    output += &match self.targets.anchor(&plain_text(content)).cloned() {
+                        Some(target) => self.anchor(text, &target),
+                        None => text,
+                    };
+                }
+                ParagraphSegment::InlineLinkTarget { content, .. } => {
+                    output += &format!("<<{}>>", plain_text(content).trim())
+                }
+                // Only used while parsing, these never end up in the tree.
+                ParagraphSegment::AttachedModifierOpener(_)
+                | ParagraphSegment::AttachedModifierOpenerFail(_)
+                | ParagraphSegment::AttachedModifierCloserCandidate(_)
+                | ParagraphSegment::AttachedModifierCloser(_)
+                | ParagraphSegment::AttachedModifierCandidate { .. } => {}
+            }
+        }
+
+        output
+    }
+
+    fn link(
+        &mut self,
+        filepath: Option<&str>,
+        targets: &[LinkTarget],
+        description: Option<&[ParagraphSegment]>,
+    ) -> String {
+        let text = description.map(|description| self.inline(description));
+
+        // Footnote references become footnote references, after the description if there is
+        // one.
+        if let (None, Some(LinkTarget::Footnote { title, .. })) = (filepath, targets.last()) {
+            if let Some(footnote) = self.targets.find(Some(TargetKind::Footnote), title) {
+                return format!("{}[fn:{}]", text.unwrap_or_default(), footnote.id);
+            }
+        }
+
+        // Org shows links to URLs and files without a description as they are.
+        match (filepath, &text, targets.last()) {
+            (None, None, Some(LinkTarget::Url { url, .. })) => {
+                return format!("[[{}]]", link_path(url));
+            }
+            (None, None, Some(LinkTarget::Path { path, .. })) => {
+                return format!("[[file:{}]]", link_path(path));
+            }
+            _ => {}
+        }
+
+        let text = match (text, targets.last()) {
+            (Some(text), _) => text,
+            (
+                None,
+                Some(LinkTarget::Url { url: text, .. } | LinkTarget::Path { path: text, .. }),
+            ) => text.clone(),
+            (None, Some(LinkTarget::LineNumber { line, .. })) => line.to_string(),
+            (None, Some(LinkTarget::Timestamp { timestamp, .. })) => timestamp.raw.clone(),
+            (
+                None,
+                Some(
+                    LinkTarget::Heading { title, .. }
+                    | LinkTarget::Footnote { title, .. }
+                    | LinkTarget::Definition { title, .. }
+                    | LinkTarget::Generic { title, .. }
+                    | LinkTarget::Wiki { title, .. }
+                    | LinkTarget::Extendable { title, .. },
+                ),
+            ) => self.inline(title),
+            (None, None) => filepath.unwrap_or_default().to_string(),
+        };
+
+        self.reference(text, filepath, targets)
+    }
+
+    /// Links the text of an anchor to the target of its definition.
+    fn anchor(&mut self, text: String, target: &ParagraphSegment) -> String {
+        match target {
+            ParagraphSegment::Link {
+                filepath, targets, ..
+            } => self.reference(text, filepath.as_deref(), targets),
+            _ => text,
+        }
+    }
+
+    /// Links text to the targets of a link. Only the last target is used, as it is the most
+    /// specific one. Links into other files point at the `.org` file with the same name, and
+    /// text linking to targets in this file which cannot be found is left as it is.
+    fn reference(&self, text: String, filepath: Option<&str>, targets: &[LinkTarget]) -> String {
+        let path = match (filepath, targets.last()) {
+            (Some(filepath), target) => {
+                let search = match target {
+                    Some(LinkTarget::Heading { title, .. }) => {
+                        format!("::*{}", plain_text(title).trim())
+                    }
+                    Some(
+                        LinkTarget::Definition { title, .. }
+                        | LinkTarget::Generic { title, .. }
+                        | LinkTarget::Wiki { title, .. }
+                        | LinkTarget::Extendable { title, .. },
+                    ) => format!("::{}", plain_text(title).trim()),
+                    Some(LinkTarget::LineNumber { line, .. }) => format!("::{line}"),
+                    _ => String::new(),
+                };
+                Some(format!("file:{filepath}.org{search}"))
+            }
+            (None, Some(LinkTarget::Url { url, .. })) => Some(url.clone()),
+            (None, Some(LinkTarget::Path { path, .. })) => Some(format!("file:{path}")),
+            (None, Some(LinkTarget::Heading { level, title, .. })) => self
+                .targets
+                .find(Some(TargetKind::Heading(*level)), title)
+                .map(|heading| format!("#{}", heading.id)),
+            (None, Some(LinkTarget::Definition { title, .. })) => self
+                .targets
+                .find(Some(TargetKind::Definition), title)
+                .map(|definition| definition.id.clone()),
+            (
+                None,
+                Some(
+                    LinkTarget::Generic { title, .. }
+                    | LinkTarget::Wiki { title, .. }
+                    | LinkTarget::Extendable { title, .. },
+                ),
+            ) => self
+                .targets
+                .find_by(title, |kind| kind != TargetKind::Footnote)
+                .map(|target| match target.kind {
+                    TargetKind::Heading(_) => format!("#{}", target.id),
+                    TargetKind::Definition => target.id.clone(),
+                    _ => target.text.clone(),
+                }),
+            (None, _) => None,
+        };
+
+        match path {
+            Some(path) => format!("[[{}][{text}]]", link_path(&path)),
+            None => text,
+        }
+    }
+}
+
+/// What the extensions of a heading, list item or quote turn into.
+struct Todo {
+    status: Option<TodoStatus>,
+    keyword: Option<&'static str>,
+    priority: Option<String>,
+    /// The `DEADLINE` and `SCHEDULED` timestamps, together with their keyword.
+    planning: Vec<(&'static str, String)>,
+    /// Active timestamps, followed by the extensions Org has no counterpart for.
+    notes: Vec<String>,
+}
+
+impl Todo {
+    fn new(extensions: &[DetachedModifierExtension], today: (u32, u8, u8)) -> Self {
+        let mut todo = Self {
+            status: None,
+            keyword: None,
+            priority: None,
+            planning: Vec::new(),
+            notes: Vec::new(),
+        };
+        let mut recurring = None;
+        let mut rest = Vec::new();
+
+        for extension in extensions {
+            let written = match extension {
+                DetachedModifierExtension::Todo(status) => {
+                    todo.keyword = Some(keyword(status));
+                    todo.status = Some(status.clone());
+                    match status {
+                        TodoStatus::Recurring(Some(timestamp)) => {
+                            recurring = timestamp_text(timestamp, today, true);
+                            recurring.as_ref().map(|_| ())
+                        }
+                        _ => Some(()),
+                    }
+                }
+                DetachedModifierExtension::Priority(priority) => {
+                    let priority = match priority.to_lowercase().as_str() {
+                        "high" => "A".to_string(),
+                        "medium" => "B".to_string(),
+                        "low" => "C".to_string(),
+                        _ => priority.to_uppercase(),
+                    };
+                    let valid = (priority.len() == 1 && priority.chars().all(char::is_alphabetic))
+                        || priority.parse::<u8>().is_ok();
+                    valid.then(|| todo.priority = Some(priority))
+                }
+                DetachedModifierExtension::DueDate(timestamp) => {
+                    timestamp_text(timestamp, today, false)
+                        .map(|timestamp| todo.planning.push(("DEADLINE", timestamp)))
+                }
+                DetachedModifierExtension::StartDate(timestamp) => {
+                    timestamp_text(timestamp, today, false)
+                        .map(|timestamp| todo.planning.push(("SCHEDULED", timestamp)))
+                }
+                DetachedModifierExtension::Timestamp(timestamp) => {
+                    timestamp_text(timestamp, today, false)
+                        .map(|timestamp| todo.notes.push(timestamp))
+                }
+            };
+
+            if written.is_none() {
+                rest.push(extension.clone());
+            }
+        }
+
+        // A recurring item is scheduled, unless it has a start date of its own.
+        if let Some(timestamp) = recurring {
+            match todo
+                .planning
+                .iter()
+                .any(|(keyword, _)| *keyword == "SCHEDULED")
+            {
+                true => todo.notes.push(timestamp),
+                false => todo.planning.push(("SCHEDULED", timestamp)),
+            }
+        }
+        if todo.status == Some(TodoStatus::Urgent) {
+            todo.priority.get_or_insert_with(|| "A".to_string());
+        }
+        if !rest.is_empty() {
+            todo.notes.push(code(&extension_text(&rest)));
+        }
+
+        todo
+    }
+
+    /// The planning and notes of a list item or quote. Org only reads planning right below a
+    /// headline, so the keywords are written in lower case, as plain text.
+    fn plain_notes(&self) -> Vec<String> {
+        self.planning
+            .iter()
+            .map(|(keyword, timestamp)| format!("{} {timestamp}", keyword.to_lowercase()))
+            .chain(self.notes.iter().cloned())
+            .collect()
+    }
+
+    /// The priority cookie, followed by a space.
+    fn priority(&self) -> String {
+        self.priority
+            .as_ref()
+            .map(|priority| format!("[#{priority}] "))
+            .unwrap_or_default()
+    }
+}
+
+fn keyword(status: &TodoStatus) -> &'static str {
+    match status {
+        TodoStatus::Undone | TodoStatus::Urgent | TodoStatus::Recurring(_) => "TODO",
+        TodoStatus::Pending => "NEXT",
+        TodoStatus::NeedsClarification => "WAITING",
+        TodoStatus::Paused => "HOLD",
+        TodoStatus::Done => "DONE",
+        TodoStatus::Canceled => "CANCELLED",
+    }
+}
+
+/// An active Org timestamp like `<2025-01-31 Fri 10:30>`, if the timestamp has a date. Parts
+/// of the date which are left out are taken from the first matching date on or after `today`,
+/// and the end of a range takes them from the start. The timestamp of a recurring item repeats
+/// by the largest part which is left out, e.g. yearly for `4th May`.
+fn timestamp_text(
+    timestamp: &NorgTimestamp,
+    today: (u32, u8, u8),
+    recurring: bool,
+) -> Option<String> {
+    let start = timestamp.start.as_ref()?;
+    let start_date = resolve(start, today)?;
+    let repeater = match (start.year, start.month, start.day, start.weekday) {
+        _ if !recurring => "",
+        (Some(_), ..) => "",
+        (None, Some(_), ..) => " +1y",
+        (None, None, Some(_), _) => " +1m",
+        (None, None, None, Some(_)) => " +1w",
+        (None, None, None, None) => " +1d",
+    };
+
+    let Some(end) = &timestamp.end else {
+        return Some(format!("<{}{repeater}>", date_time(start_date, start)));
+    };
+    let end_date = (
+        end.year.unwrap_or(start_date.0),
+        end.month.map_or(start_date.1, |month| month as u8 + 1),
+        end.day.unwrap_or(start_date.2),
+    );
+
+    match (&start.time, &end.time) {
+        (Some(_), Some(time)) if end_date == start_date => Some(format!(
+            "<{}-{:02}:{:02}{repeater}>",
+            date_time(start_date, start),
+            time.hour,
+            time.minute
+        )),
+        _ => Some(format!(
+            "<{}>--<{}>",
+            date_time(start_date, start),
+            date_time(end_date, end)
+        )),
+    }
+}
+
+/// The year, month and day of the first date on or after `today` which has the parts of
+/// `date_time` that are written down, searching from the start of its year if it has one. The
+/// weekday is only matched when there is no day.
+fn resolve(date_time: &NorgDateTime, today: (u32, u8, u8)) -> Option<(u32, u8, u8)> {
+    let mut date = match date_time.year {
+        Some(year) => (year, 1, 1),
+        None => today,
+    };
+
+    // The days of the week and of the year repeat every 400 years.
+    for _ in 0..400 * 366 {
+        let (year, month, day) = date;
+        if date_time.year.is_some_and(|other| other != year) {
+            return None;
+        }
+
+        let matches = date_time
+            .month
+            .map_or(true, |other| other as u8 + 1 == month)
+            && date_time.day.map_or(true, |other| other == day)
+            && (date_time.day.is_some()
+                || date_time.weekday.map_or(true, |other| {
+                    (other as usize + 1) % 7 == weekday(year, month, day)
+                }));
+        if matches {
+            return Some(date);
+        }
+        date = next_day(date);
+    }
+
+    None
+}
+
+fn next_day((year, month, day): (u32, u8, u8)) -> (u32, u8, u8) {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+
+    match (day < days, month < 12) {
+        (true, _) => (year, month, day + 1),
+        (false, true) => (year, month + 1, 1),
+        (false, false) => (year + 1, 1, 1),
+    }
+}
+
+fn date_time((year, month, day): (u32, u8, u8), date_time: &NorgDateTime) -> String {
+    let mut text = format!(
+        "{year:04}-{month:02}-{day:02} {}",
+        WEEKDAYS[weekday(year, month, day)]
+    );
+    if let Some(time) = &date_time.time {
+        text += &format!(" {:02}:{:02}", time.hour, time.minute);
+    }
+
+    text
+}
+
+/// The day of the week of a date, starting with Sunday as 0.
+fn weekday(year: u32, month: u8, day: u8) -> usize {
+    const OFFSETS: [u32; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    // The days of the week repeat every 400 years, which keeps the sums small.
+    let year = year % 400 + if month < 3 { 399 } else { 400 };
+
+    ((year + year / 4 - year / 100 + year / 400 + OFFSETS[month as usize - 1] + day as u32) % 7)
+        as usize
+}
+
+/// The blocks nested in a list item, to append to its first line. Nested lists stay tight,
+/// anything else is separated by a blank line.
+fn nested(groups: Vec<(&NorgAST, String)>) -> String {
+    let mut output = String::new();
+
+    for (nested, block) in groups {
+        match nested {
+            NorgAST::NestableDetachedModifier {
+                modifier_type:
+                    NestableDetachedModifier::UnorderedList | NestableDetachedModifier::OrderedList,
+                ..
+            } => output += "\n",
+            _ => output += "\n\n",
+        }
+        output += &block;
+    }
+
+    output
+}
+
+/// Writes a comment holding the name and parameters of a tag, after the given prefix.
+fn comment(prefix: &str, name: &[String], parameters: &[String]) -> String {
+    let tag = std::iter::once(format!("{prefix}{}", name.join(".")))
+        .chain(parameters.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!("# {tag}")
+}
+
+fn block(name: &str, parameters: &str, content: &str) -> String {
+    let begin = format!("#+begin_{name} {parameters}");
+
+    format!(
+        "{}\n{}#+end_{name}",
+        begin.trim_end(),
+        with_newline(content)
+    )
+}
+
+/// Escapes the lines of a source or example block which Org would take for a heading or a
+/// keyword, by putting a comma in front of them.
+fn escape_block(content: &str) -> String {
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+            let rest = line[indent..].trim_start_matches(',');
+
+            match rest.starts_with('*') || rest.starts_with("#+") {
+                true => format!("{},{}", &line[..indent], &line[indent..]),
+                false => line.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Inline code, using `=` when the code contains a `~`. Line breaks become spaces, as the
+/// next line could otherwise be taken for a heading or list item.
+fn code(content: &str) -> String {
+    let content = content.replace('\n', " ");
+
+    match content.contains('~') {
+        true => format!("={content}="),
+        false => format!("~{content}~"),
+    }
+}
+
+/// Escapes the brackets of a link and the backslashes which would escape them.
+fn link_path(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    let mut chars = path.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' | ']' => escaped.push('\\'),
+            '\\' if matches!(chars.peek(), None | Some('[' | ']' | '\\')) => escaped.push('\\'),
+            _ => {}
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// The text of a token. Escaped emphasis markers are written as entities, as Org has no escape
+/// character for them.
+fn token_text(token: &ParagraphSegmentToken) -> String {
+    match token {
+        ParagraphSegmentToken::Text(text) => text.to_string(),
+        ParagraphSegmentToken::Whitespace => " ".to_string(),
+        ParagraphSegmentToken::Escape('\n') => "\\\\\n".to_string(),
+        ParagraphSegmentToken::Escape('*') => "\\ast{}".to_string(),
+        ParagraphSegmentToken::Escape('/') => "\\slash{}".to_string(),
+        ParagraphSegmentToken::Escape('_') => "\\under{}".to_string(),
+        ParagraphSegmentToken::Escape('+') => "\\plus{}".to_string(),
+        ParagraphSegmentToken::Escape('=') => "\\equal{}".to_string(),
+        ParagraphSegmentToken::Escape('~') => "\\tilde{}".to_string(),
+        ParagraphSegmentToken::Special(c) | ParagraphSegmentToken::Escape(c) => c.to_string(),
+    }
+}
+
+/// Escapes the start of lines which Org would otherwise take for a heading, list item, keyword,
+/// comment, table, fixed width line, rule or footnote.
+fn protect_lines(text: &str) -> String {
+    text.split('\n')
+        .map(protect_line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Org's only escape character is an invisible zero width space, so entities are used instead:
+/// `\ast{}` for the first star of a heading, `\vert{}` for the start of a table and `\nbsp{}`
+/// for the space after a bullet, `#` or `:`. Lines which would be a keyword, rule or footnote
+/// start with `\nbsp{}`.
+fn protect_line(line: &str) -> String {
+    let ends_marker = |rest: &str| rest.is_empty() || rest.starts_with(' ');
+    let stars = line.trim_start_matches('*');
+    let digits = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    let bullet = line
+        .strip_prefix(['-', '+', '#', ':'])
+        .or_else(|| {
+            (digits.len() < line.len())
+                .then_some(digits)?
+                .strip_prefix(['.', ')'])
+        })
+        .filter(|rest| ends_marker(rest));
+
+    if stars.len() < line.len() && ends_marker(stars) {
+        format!("\\ast{{}}{}", &line[1..])
+    } else if let Some(rest) = bullet {
+        let marker = &line[..line.len() - rest.len()];
+        format!(
+            "{marker}\\nbsp{{}}{}",
+            rest.strip_prefix(' ').unwrap_or(rest)
+        )
+    } else if let Some(rest) = line.strip_prefix('|') {
+        format!("\\vert{{}}{rest}")
+    } else if line.starts_with("#+")
+        || line.starts_with("[fn:")
+        || (line.len() >= 5 && line.chars().all(|c| c == '-'))
+    {
+        format!("\\nbsp{{}}{line}")
+    } else {
+        line.to_string()
+    }
+}
+
+fn indent(text: &str, width: usize) -> String {
+    let padding = " ".repeat(width);
+
+    text.split('\n')
+        .enumerate()
+        .map(|(i, line)| match line {
+            _ if i == 0 || line.is_empty() => line.to_string(),
+            line => format!("{padding}{line}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn with_newline(content: &str) -> String {
+    match content {
+        "" => String::new(),
+        content if content.ends_with('\n') => content.to_string(),
+        content => format!("{content}\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::proptest;
+
+    use crate::{
+        org::{escape_block, export_with, weekday, ExportOptions},
+        parse_tree,
+    };
+
+    /// Exports `input` on Monday, 27 January 2025.
+    fn exported(input: &str) -> String {
+        let options = ExportOptions {
+            today: (2025, 1, 27),
+        };
+
+        export_with(&parse_tree(input).unwrap(), &options)
+    }
+
+    #[test]
+    fn exporting() {
+        assert_yaml_snapshot!(
+            "metadata",
+            exported(
+                "@document.meta
+                 title: Tasks
+                 description: What is left to do
+                 authors: [
+                   Jane Doe
+                   John Doe
+                 ]
+                 categories: [
+                   work
+                   side projects
+                 ]
+                 version: 0.1
+                 @end
+                 Some text.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "headings",
+            exported(
+                "* (x|# A) Done
+                 * (!|< 31 Jan 2025) Urgent
+                 ** (=|> Mon 3 Feb 2025 9:00|< Friday) Paused
+                 ** (?|@ 5 Feb 2025 10:00 - 12:30) Unclear
+                 *** (+ 4th may|# high) Yearly\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "lists",
+            exported(
+                "- ( ) Write *the* /exporter/
+                 -- (x) Strike -this- out
+                 -- (-) In progress
+                 - (_|# B) Cancel it
+                 - ( |< 31 Jan 2025|> 27 Jan 2025) Plan it
+                 ~ First
+                 ~ (=) Second\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "quotes",
+            exported("> (?) Quoted _text_\n> (< 1 Feb 2025) Due quote\n>> Nested\n")
+        );
+        assert_yaml_snapshot!(
+            "markup",
+            exported(
+                "*Bold*, /italic/, _underline_, -deleted-, ^super^, ,sub, and
+                 %a comment%.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "links",
+            exported(
+                "Links to {* Code}, {# inline target}, {$ Term}, {https://example.com}[example],
+                 {https://neorg.org}, {/ notes.txt}, {:other:** Heading}[elsewhere] and
+                 [anchor]{https://neorg.org}. Then [anchor] again and an <inline target>.
+                 * Code
+                 $ Term
+                 The definition.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "footnotes",
+            exported("A note.{^ Note}\n^ Note\nA footnote with `code` and $x_1$.\n")
+        );
+        assert_yaml_snapshot!(
+            "definitions",
+            exported("$ Term\nThe definition.\n$ Other term\nAnother one.\n")
+        );
+        assert_yaml_snapshot!(
+            "tables",
+            exported(": A1\n  Name\n: B1\n  Age\n: A2\n  Bob\n")
+        );
+        assert_yaml_snapshot!("sparse_tables", exported(": A1\n  a\n: C3\n  c\n"));
+        assert_yaml_snapshot!(
+            "tags",
+            exported("|example\nRanged content.\n|end\n#name param\nA paragraph.\n___\n")
+        );
+        assert_yaml_snapshot!(
+            "code",
+            exported(
+                "@code rust
+                 * not a heading
+                 #+not a keyword
+                 @end
+                 @math
+                 e^{i\\pi} + 1 = 0
+                 @end\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "verbatim",
+            exported("`a\\b`, $\\frac{1}{2}$, `x~y` and `a ~ b`\n")
+        );
+        assert_yaml_snapshot!(
+            "escapes",
+            exported(
+                "Escaped \\*stars\\*, a backslash \\\\ and a pipe \\|.
+
+                 1. Not a list
+
+                 \\* Not a heading\n"
+            )
+        );
+        assert_yaml_snapshot!("escaped_pipes", exported(": A1\n  a \\| b\n: B1\n  c\n"));
+    }
+
+    #[test]
+    fn planning() {
+        let examples: Vec<_> = [
+            "* (< Friday) Next Friday",
+            "* (> 4th May 10:00|# medium) Without a year",
+            "* (+ Monday) Weekly",
+            "* (+ 15th|> 3 Feb 2025) Monthly, starting in February",
+            "* (< 30 Feb|# someday) Invalid",
+        ]
+        .into_iter()
+        .map(|example| exported(&(example.to_string() + "\n")))
+        .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn escaped_blocks_have_no_headings_or_keywords(content in r"[ \t,*#+a\n]*") {
+            for line in escape_block(&content).lines() {
+                let line = line.trim_start();
+                assert!(!line.starts_with('*') && !line.starts_with("#+"));
+            }
+        }
+
+        #[test]
+        fn weekdays_follow_each_other(year in 1u32..10000, month in 1u8..=12, day in 1u8..28) {
+            assert_eq!(weekday(year, month, day + 1), (weekday(year, month, day) + 1) % 7);
+        }
+    }
+}
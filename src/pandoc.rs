@@ -0,0 +1,919 @@
+//! Converting a tree into Pandoc's document model, which Pandoc reads as JSON with
+//! `pandoc -f json`, e.g. to produce DOCX, ODT, PDF or EPUB.
+//!
+//! The `@document.meta` tag becomes the metadata of the document, with strings as inlines, so
+//! that Pandoc's templates can use fields like `title` and `authors`. Headings get ids the way
+//! Pandoc makes them, and links to headings, definitions and inline link targets are resolved
+//! to `#id` fragments. Links into other files keep the path as written. Footnote references
+//! become notes holding the content of the footnote, which is left out where it is defined.
+//!
+//! Norg constructs Pandoc has no counterpart for are kept as attributes:
+//!
+//! - The extensions of headings go into the attributes of the header, and those of list items
+//!   and quotes into a `Div` around their content, as `todo`, `priority`, `timestamp`, `due`
+//!   and `start`. The todo status is named like in [`crate::query`], and the recurrence of
+//!   recurring tasks is kept as `recurrence`. List items and quotes with a todo status also
+//!   start with a `☐` or `☒`, like Pandoc's task lists.
+//! - Carryover tags become attributes of the node they apply to, in the same places or in a
+//!   `Div` around other nodes. `+name` tags become a `name` attribute holding their
+//!   parameters, and `#name` tags a `name` class, with their parameters in a `name` attribute.
+//! - Ranged tags become a `Div` with their name as class, and verbatim ranged tags other than
+//!   `@code` and `@math` a code block with their name as class. Spoilers and variables become
+//!   a `Span` with the `spoiler` or `variable` class.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    export::{groups_with, normalize, raw, trim_start, verbatim, TargetKind, Targets},
+    metadata::{parse_metadata, NorgMeta},
+    table::NorgTable,
+    text::{plain_text, todo_name},
+    CarryoverTag, DelimitingModifier, DetachedModifierExtension, LinkTarget,
+    NestableDetachedModifier, NorgAST, ParagraphSegment, ParagraphSegmentToken,
+    RangeableDetachedModifier, TodoStatus,
+};
+
+/// The version of Pandoc's document model the output follows.
+pub const API_VERSION: [u32; 3] = [1, 23, 1];
+
+/// Converts a tree as returned by [`crate::parse_tree`].
+pub fn convert(tree: &[NorgAST]) -> Pandoc {
+    let mut writer = Writer {
+        targets: Targets::new(tree, |kind, text| match kind {
+            TargetKind::Footnote => format!("fn-{}", identifier(text)),
+            _ => identifier(text),
+        }),
+        notes: Vec::new(),
+    };
+
+    Pandoc {
+        api_version: API_VERSION,
+        meta: meta(tree),
+        blocks: writer.blocks(tree),
+    }
+}
+
+/// Converts a tree into the JSON Pandoc reads, see [`convert`].
+pub fn to_json(tree: &[NorgAST]) -> String {
+    serde_json::to_string(&convert(tree)).expect("documents always serialize")
+}
+
+/// A Pandoc document. Only the parts of Pandoc's model the conversion produces are included.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Pandoc {
+    #[serde(rename = "pandoc-api-version")]
+    pub api_version: [u32; 3],
+    pub meta: BTreeMap<String, MetaValue>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "t", content = "c")]
+pub enum MetaValue {
+    MetaMap(BTreeMap<String, MetaValue>),
+    MetaList(Vec<MetaValue>),
+    MetaBool(bool),
+    MetaString(String),
+    MetaInlines(Vec<Inline>),
+}
+
+/// The id, classes and key-value pairs of an element.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Attr(pub String, pub Vec<String>, pub Vec<(String, String)>);
+
+impl Attr {
+    fn class(class: &str) -> Self {
+        Self(String::new(), vec![class.to_string()], Vec::new())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty() && self.1.is_empty() && self.2.is_empty()
+    }
+}
+
+// Tables are large, but boxing them would change how they serialize.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "t", content = "c")]
+pub enum Block {
+    /// Text without a paragraph around it, as in list items and table cells.
+    Plain(Vec<Inline>),
+    Para(Vec<Inline>),
+    CodeBlock(Attr, String),
+    BlockQuote(Vec<Block>),
+    OrderedList(ListAttributes, Vec<Vec<Block>>),
+    BulletList(Vec<Vec<Block>>),
+    /// Terms, each with its definitions.
+    DefinitionList(Vec<(Vec<Inline>, Vec<Vec<Block>>)>),
+    Header(u16, Attr, Vec<Inline>),
+    HorizontalRule,
+    Table(
+        Attr,
+        Caption,
+        Vec<ColSpec>,
+        TableHead,
+        Vec<TableBody>,
+        TableFoot,
+    ),
+    Div(Attr, Vec<Block>),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "t", content = "c")]
+pub enum Inline {
+    Str(String),
+    Emph(Vec<Inline>),
+    Underline(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Strikeout(Vec<Inline>),
+    Superscript(Vec<Inline>),
+    Subscript(Vec<Inline>),
+    Code(Attr, String),
+    Space,
+    LineBreak,
+    Math(MathType, String),
+    /// The text of the link and its url and title.
+    Link(Attr, Vec<Inline>, (String, String)),
+    Note(Vec<Block>),
+    Span(Attr, Vec<Inline>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(tag = "t")]
+pub enum MathType {
+    DisplayMath,
+    InlineMath,
+}
+
+/// The number of the first item and the numbering style.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ListAttributes(pub u32, pub ListNumberStyle, pub ListNumberDelim);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(tag = "t")]
+pub enum ListNumberStyle {
+    Decimal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(tag = "t")]
+pub enum ListNumberDelim {
+    Period,
+}
+
+/// The short caption, if any, and the caption.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Caption(pub Option<Vec<Inline>>, pub Vec<Block>);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ColSpec(pub Alignment, pub ColWidth);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(tag = "t")]
+pub enum Alignment {
+    AlignDefault,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(tag = "t")]
+pub enum ColWidth {
+    ColWidthDefault,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TableHead(pub Attr, pub Vec<Row>);
+
+/// The number of row header columns, the head rows and the rows of a table body.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TableBody(pub Attr, pub u32, pub Vec<Row>, pub Vec<Row>);
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TableFoot(pub Attr, pub Vec<Row>);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Row(pub Attr, pub Vec<Cell>);
+
+/// The alignment, row span, column span and content of a cell.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Cell(pub Attr, pub Alignment, pub u32, pub u32, pub Vec<Block>);
+
+struct Writer {
+    targets: Targets,
+    /// The footnotes whose content is being converted, so that a footnote referencing itself
+    /// does not expand forever.
+    notes: Vec<String>,
+}
+
+impl Writer {
+    /// Converts the nodes of a scope, grouping consecutive list items, quotes, definitions and
+    /// table cells.
+    fn blocks(&mut self, nodes: &[NorgAST]) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut rest = nodes;
+
+        while let Some(first) = rest.first() {
+            let run = rest
+                .iter()
+                .take_while(|node| groups_with(tagged(first).0, tagged(node).0))
+                .count()
+                .max(1);
+            let (group, remaining) = rest.split_at(run);
+            rest = remaining;
+
+            match tagged(first).0 {
+                NorgAST::NestableDetachedModifier { modifier_type, .. } => {
+                    blocks.push(self.list(modifier_type, group))
+                }
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Definition,
+                    ..
+                } => blocks.push(self.definitions(group)),
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Table,
+                    ..
+                } => blocks.extend(self.table(group)),
+                _ => self.node(first, &mut blocks),
+            }
+        }
+
+        blocks
+    }
+
+    fn node(&mut self, node: &NorgAST, blocks: &mut Vec<Block>) {
+        let (node, mut attr) = tagged(node);
+        let converted = match node {
+            NorgAST::Paragraph { content, .. } => vec![Block::Para(self.inlines(content))],
+            NorgAST::Heading {
+                level,
+                title,
+                extensions,
+                content,
+                span,
+            } => {
+                attr.0 = self.targets.id(span).to_string();
+                attr.2.extend(extension_attributes(extensions));
+
+                blocks.push(Block::Header(*level, attr, self.inlines(trim_start(title))));
+                blocks.extend(self.blocks(content));
+                return;
+            }
+            // Footnotes are converted where they are referenced.
+            NorgAST::RangeableDetachedModifier {
+                modifier_type: RangeableDetachedModifier::Footnote,
+                ..
+            } => return,
+            // Grouped by `blocks`.
+            NorgAST::NestableDetachedModifier { .. }
+            | NorgAST::RangeableDetachedModifier { .. }
+            | NorgAST::CarryoverTag { .. } => self.blocks(std::slice::from_ref(node)),
+            NorgAST::VerbatimRangedTag {
+                name,
+                parameters,
+                content,
+                ..
+            } => match name.join(".").as_str() {
+                "document.meta" => return,
+                "code" => {
+                    let classes = parameters.first().cloned().into_iter().collect();
+                    vec![Block::CodeBlock(
+                        Attr(String::new(), classes, Vec::new()),
+                        content.trim_end_matches('\n').to_string(),
+                    )]
+                }
+                "math" => vec![Block::Para(vec![Inline::Math(
+                    MathType::DisplayMath,
+                    content.trim().to_string(),
+                )])],
+                name => vec![Block::CodeBlock(
+                    Attr::class(name),
+                    content.trim_end_matches('\n').to_string(),
+                )],
+            },
+            NorgAST::RangedTag { name, content, .. } => match name.join(".").as_str() {
+                "comment" => return,
+                name => vec![Block::Div(Attr::class(name), self.blocks(content))],
+            },
+            NorgAST::DelimitingModifier {
+                modifier_type: DelimitingModifier::HorizontalRule,
+                ..
+            } => vec![Block::HorizontalRule],
+            NorgAST::Error { content, .. } => vec![Block::CodeBlock(
+                Attr::class("error"),
+                content.trim_end_matches('\n').to_string(),
+            )],
+            NorgAST::DelimitingModifier { .. } | NorgAST::InfirmTag { .. } => return,
+        };
+
+        match attr.is_empty() {
+            true => blocks.extend(converted),
+            false => blocks.push(Block::Div(attr, converted)),
+        }
+    }
+
+    fn list(&mut self, modifier_type: &NestableDetachedModifier, items: &[NorgAST]) -> Block {
+        let mut converted = Vec::new();
+
+        for item in items {
+            let (
+                NorgAST::NestableDetachedModifier {
+                    extensions,
+                    text,
+                    content,
+                    ..
+                },
+                mut attr,
+            ) = tagged(item)
+            else {
+                continue;
+            };
+            attr.2.extend(extension_attributes(extensions));
+
+            let mut inlines = checkbox(extensions);
+            if let NorgAST::Paragraph { content, .. } = text.as_ref() {
+                inlines.extend(self.inlines(content));
+            }
+
+            let mut blocks = match modifier_type {
+                NestableDetachedModifier::Quote => vec![Block::Para(inlines)],
+                _ => vec![Block::Plain(inlines)],
+            };
+            blocks.extend(self.blocks(content));
+
+            converted.push(match attr.is_empty() {
+                true => blocks,
+                false => vec![Block::Div(attr, blocks)],
+            });
+        }
+
+        match modifier_type {
+            NestableDetachedModifier::Quote => Block::BlockQuote(converted.concat()),
+            NestableDetachedModifier::UnorderedList => Block::BulletList(converted),
+            NestableDetachedModifier::OrderedList => Block::OrderedList(
+                ListAttributes(1, ListNumberStyle::Decimal, ListNumberDelim::Period),
+                converted,
+            ),
+        }
+    }
+
+    /// Converts definitions into a definition list. The id of a definition and its carryover
+    /// tags go on a `Span` around the term.
+    fn definitions(&mut self, definitions: &[NorgAST]) -> Block {
+        let mut converted = Vec::new();
+
+        for definition in definitions {
+            if let (
+                NorgAST::RangeableDetachedModifier {
+                    title,
+                    content,
+                    span,
+                    ..
+                },
+                mut attr,
+            ) = tagged(definition)
+            {
+                attr.0 = self.targets.id(span).to_string();
+
+                let term = vec![Inline::Span(attr, self.inlines(title))];
+                converted.push((term, vec![self.blocks(content)]));
+            }
+        }
+
+        Block::DefinitionList(converted)
+    }
+
+    /// Converts table cells into a table without a head. Cells without a valid address are
+    /// left out, and the content of cells written several times is joined.
+    fn table(&mut self, cells: &[NorgAST]) -> Option<Block> {
+        let table = NorgTable::from_nodes(cells.iter().map(|cell| tagged(cell).0))?;
+        let grid = table.grid();
+        let columns = grid.first().map_or(0, Vec::len);
+
+        let rows = grid
+            .into_iter()
+            .map(|row| {
+                let cells = row
+                    .into_iter()
+                    .map(|cell| {
+                        let blocks = match cell.map(|cell| cell.content.as_slice()) {
+                            Some([NorgAST::Paragraph { content, .. }]) => {
+                                vec![Block::Plain(self.inlines(content))]
+                            }
+                            Some(content) => content
+                                .iter()
+                                .flat_map(|node| self.blocks(std::slice::from_ref(node)))
+                                .collect(),
+                            None => Vec::new(),
+                        };
+                        Cell(Attr::default(), Alignment::AlignDefault, 1, 1, blocks)
+                    })
+                    .collect();
+                Row(Attr::default(), cells)
+            })
+            .collect();
+
+        Some(Block::Table(
+            Attr::default(),
+            Caption::default(),
+            vec![ColSpec(Alignment::AlignDefault, ColWidth::ColWidthDefault); columns],
+            TableHead::default(),
+            vec![TableBody(Attr::default(), 0, Vec::new(), rows)],
+            TableFoot::default(),
+        ))
+    }
+
+    fn inlines(&mut self, segments: &[ParagraphSegment]) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        segments
+            .iter()
+            .for_each(|segment| self.segment(segment, &mut inlines));
+
+        if inlines.last() == Some(&Inline::Space) {
+            inlines.pop();
+        }
+        inlines
+    }
+
+    fn segment(&mut self, segment: &ParagraphSegment, inlines: &mut Vec<Inline>) {
+        let inline = match segment {
+            ParagraphSegment::Token { token, .. } => return push_token(inlines, token),
+            ParagraphSegment::AttachedModifier {
+                modifier_type,
+                content,
+                ..
+            }
+            | ParagraphSegment::FreeFormAttachedModifier {
+                modifier_type,
+                content,
+                ..
+            } => match modifier_type {
+                '*' => Inline::Strong(self.inlines(content)),
+                '/' => Inline::Emph(self.inlines(content)),
+                '_' => Inline::Underline(self.inlines(content)),
+                '-' => Inline::Strikeout(self.inlines(content)),
+                '!' => Inline::Span(Attr::class("spoiler"), self.inlines(content)),
+                '^' => Inline::Superscript(self.inlines(content)),
+                ',' => Inline::Subscript(self.inlines(content)),
+                '`' => Inline::Code(Attr::default(), verbatim(segment).unwrap_or_default()),
+                '$' => Inline::Math(MathType::InlineMath, verbatim(segment).unwrap_or_default()),
+                '&' => Inline::Span(
+                    Attr::class("variable"),
+                    words(&verbatim(segment).unwrap_or_default()),
+                ),
+                // The null modifier comments its content out.
+                _ => return,
+            },
+            ParagraphSegment::InlineVerbatim { content, .. } => {
+                Inline::Code(Attr::default(), raw(content))
+            }
+            ParagraphSegment::Link {
+                filepath,
+                targets,
+                description,
+                ..
+            } => {
+                return self.link(
+                    filepath.as_deref(),
+                    targets,
+                    description.as_deref(),
+                    inlines,
+                )
+            }
+            ParagraphSegment::AnchorDefinition {
+                content, target, ..
+            } => {
+                let href = match target.as_ref() {
+                    ParagraphSegment::Link {
+                        filepath, targets, ..
+                    } => self.href(filepath.as_deref(), targets),
+                    _ => None,
+                };
+                return self.anchor(href, content, inlines);
+            }
+            ParagraphSegment::Anchor {
+                content,
+                description,
+                ..
+            } => {
+                let href = match self.targets.anchor(&plain_text(content)) {
+                    Some(ParagraphSegment::Link {
+                        filepath, targets, ..
+                    }) => self.href(filepath.as_deref(), targets),
+                    _ => None,
+                };
+                return self.anchor(href, description.as_deref().unwrap_or(content), inlines);
+            }
+            ParagraphSegment::InlineLinkTarget { content, span } => {
+                let id = self.targets.id(span).to_string();
+                Inline::Span(Attr(id, Vec::new(), Vec::new()), self.inlines(content))
+            }
+            // Only used while parsing, these never end up in the tree.
+            ParagraphSegment::AttachedModifierOpener(_)
+            | ParagraphSegment::AttachedModifierOpenerFail(_)
+            | ParagraphSegment::AttachedModifierCloserCandidate(_)
+            | ParagraphSegment::AttachedModifierCloser(_)
+            | ParagraphSegment::AttachedModifierCandidate { .. } => return,
+        };
+
+        inlines.push(inline);
+    }
+
+    fn link(
+        &mut self,
+        filepath: Option<&str>,
+        targets: &[LinkTarget],
+        description: Option<&[ParagraphSegment]>,
+        inlines: &mut Vec<Inline>,
+    ) {
+        // Footnote references become notes, after the description if there is one.
+        if let (None, Some(LinkTarget::Footnote { title, .. })) = (filepath, targets.last()) {
+            let name = normalize(&plain_text(title));
+
+            if let Some(content) = self.targets.footnote(title).map(<[_]>::to_vec) {
+                if !self.notes.contains(&name) {
+                    if let Some(description) = description {
+                        inlines.extend(self.inlines(description));
+                    }
+
+                    self.notes.push(name);
+                    inlines.push(Inline::Note(self.blocks(&content)));
+                    self.notes.pop();
+                    return;
+                }
+            }
+        }
+
+        let text = match (description, targets.last()) {
+            (Some(description), _) => self.inlines(description),
+            (
+                None,
+                Some(LinkTarget::Url { url: text, .. } | LinkTarget::Path { path: text, .. }),
+            ) => words(text),
+            (None, Some(LinkTarget::LineNumber { line, .. })) => words(&line.to_string()),
+            (None, Some(LinkTarget::Timestamp { timestamp, .. })) => words(&timestamp.raw),
+            (
+                None,
+                Some(
+                    LinkTarget::Heading { title, .. }
+                    | LinkTarget::Footnote { title, .. }
+                    | LinkTarget::Definition { title, .. }
+                    | LinkTarget::Generic { title, .. }
+                    | LinkTarget::Wiki { title, .. }
+                    | LinkTarget::Extendable { title, .. },
+                ),
+            ) => self.inlines(title),
+            (None, None) => words(filepath.unwrap_or_default()),
+        };
+
+        push_link(inlines, self.href(filepath, targets), text);
+    }
+
+    fn anchor(
+        &mut self,
+        href: Option<String>,
+        content: &[ParagraphSegment],
+        inlines: &mut Vec<Inline>,
+    ) {
+        let content = self.inlines(content);
+        push_link(inlines, href, content);
+    }
+
+    /// Resolves the targets of a link. Only the last target is used, as it is the most
+    /// specific one. Targets in the same file which cannot be found still link to the id they
+    /// would have.
+    fn href(&self, filepath: Option<&str>, targets: &[LinkTarget]) -> Option<String> {
+        let fragment = |kind: Option<TargetKind>, title: &[ParagraphSegment]| {
+            let found = filepath
+                .is_none()
+                .then(|| self.targets.find(kind, title))
+                .flatten();
+
+            match found {
+                Some(target) => target.id.clone(),
+                None => identifier(&plain_text(title)),
+            }
+        };
+
+        let fragment = match targets.last() {
+            Some(LinkTarget::Url { url, .. }) => return Some(url.clone()),
+            Some(LinkTarget::Path { path, .. }) => return Some(path.clone()),
+            Some(LinkTarget::LineNumber { .. } | LinkTarget::Timestamp { .. }) | None => None,
+            Some(LinkTarget::Heading { level, title, .. }) => {
+                Some(fragment(Some(TargetKind::Heading(*level)), title))
+            }
+            Some(LinkTarget::Definition { title, .. }) => {
+                Some(fragment(Some(TargetKind::Definition), title))
+            }
+            Some(
+                LinkTarget::Footnote { title, .. }
+                | LinkTarget::Generic { title, .. }
+                | LinkTarget::Wiki { title, .. }
+                | LinkTarget::Extendable { title, .. },
+            ) => Some(fragment(None, title)),
+        };
+
+        match (filepath, fragment) {
+            (Some(file), Some(fragment)) => Some(format!("{file}#{fragment}")),
+            (Some(file), None) => Some(file.to_string()),
+            (None, Some(fragment)) => Some(format!("#{fragment}")),
+            (None, None) => None,
+        }
+    }
+}
+
+/// The fields of the first `@document.meta` tag at the top level. Invalid metadata is left
+/// out.
+fn meta(tree: &[NorgAST]) -> BTreeMap<String, MetaValue> {
+    let content = tree.iter().find_map(|node| match tagged(node).0 {
+        NorgAST::VerbatimRangedTag { name, content, .. } if name.join(".") == "document.meta" => {
+            Some(content)
+        }
+        _ => None,
+    });
+
+    match content.map(|content| parse_metadata(content)) {
+        Some(Ok(NorgMeta::Object(fields))) => meta_fields(fields),
+        _ => BTreeMap::new(),
+    }
+}
+
+fn meta_fields(fields: BTreeMap<String, NorgMeta>) -> BTreeMap<String, MetaValue> {
+    fields
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, meta_value(value)?)))
+        .collect()
+}
+
+/// Strings become inlines, as Pandoc treats them as Markdown when reading YAML metadata.
+fn meta_value(meta: NorgMeta) -> Option<MetaValue> {
+    Some(match meta {
+        NorgMeta::Invalid | NorgMeta::Nil => return None,
+        NorgMeta::Bool(value) => MetaValue::MetaBool(value),
+        NorgMeta::Str(text) | NorgMeta::EmptyKey(text) => MetaValue::MetaInlines(words(&text)),
+        NorgMeta::Num(number) => MetaValue::MetaString(number.to_string()),
+        NorgMeta::Array(items) => {
+            MetaValue::MetaList(items.into_iter().filter_map(meta_value).collect())
+        }
+        NorgMeta::Object(fields) => MetaValue::MetaMap(meta_fields(fields)),
+    })
+}
+
+/// Looks through carryover tags to the node they apply to, turning them into attributes.
+fn tagged(node: &NorgAST) -> (&NorgAST, Attr) {
+    let mut attr = Attr::default();
+    let mut node = node;
+
+    while let NorgAST::CarryoverTag {
+        tag_type,
+        name,
+        parameters,
+        next_object,
+        ..
+    } = node
+    {
+        let name = name.join(".");
+        let value = parameters.join(" ");
+
+        match tag_type {
+            CarryoverTag::Attribute => attr.2.push((name, value)),
+            CarryoverTag::Macro => {
+                if !value.is_empty() {
+                    attr.2.push((name.clone(), value));
+                }
+                attr.1.push(name);
+            }
+        }
+        node = next_object;
+    }
+
+    (node, attr)
+}
+
+fn extension_attributes(extensions: &[DetachedModifierExtension]) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+
+    for extension in extensions {
+        let (key, value) = match extension {
+            DetachedModifierExtension::Todo(status) => {
+                if let TodoStatus::Recurring(Some(timestamp)) = status {
+                    attributes.push(("recurrence".to_string(), timestamp.raw.clone()));
+                }
+                ("todo", todo_name(status).to_string())
+            }
+            DetachedModifierExtension::Priority(priority) => ("priority", priority.clone()),
+            DetachedModifierExtension::Timestamp(timestamp) => ("timestamp", timestamp.raw.clone()),
+            DetachedModifierExtension::DueDate(timestamp) => ("due", timestamp.raw.clone()),
+            DetachedModifierExtension::StartDate(timestamp) => ("start", timestamp.raw.clone()),
+        };
+        attributes.push((key.to_string(), value));
+    }
+
+    attributes
+}
+
+fn checkbox(extensions: &[DetachedModifierExtension]) -> Vec<Inline> {
+    let status = extensions.iter().find_map(|extension| match extension {
+        DetachedModifierExtension::Todo(status) => Some(status),
+        _ => None,
+    });
+
+    match status {
+        Some(TodoStatus::Done) => vec![Inline::Str("☒".to_string()), Inline::Space],
+        Some(_) => vec![Inline::Str("☐".to_string()), Inline::Space],
+        None => Vec::new(),
+    }
+}
+
+/// Adds a token to the inlines, joining text with the `Str` before it. Runs of whitespace
+/// become a single `Space`, and leading whitespace is left out.
+fn push_token(inlines: &mut Vec<Inline>, token: &ParagraphSegmentToken) {
+    let text = match token {
+        ParagraphSegmentToken::Whitespace => {
+            if !matches!(inlines.last(), None | Some(Inline::Space)) {
+                inlines.push(Inline::Space);
+            }
+            return;
+        }
+        ParagraphSegmentToken::Escape('\n') => return inlines.push(Inline::LineBreak),
+        ParagraphSegmentToken::Text(text) => text.clone(),
+        ParagraphSegmentToken::Special(c) | ParagraphSegmentToken::Escape(c) => c.to_string(),
+    };
+
+    match inlines.last_mut() {
+        Some(Inline::Str(last)) => last.push_str(&text),
+        _ => inlines.push(Inline::Str(text)),
+    }
+}
+
+/// Adds a link to `href`, or just its content without one.
+fn push_link(inlines: &mut Vec<Inline>, href: Option<String>, content: Vec<Inline>) {
+    match href {
+        Some(href) => inlines.push(Inline::Link(
+            Attr::default(),
+            content,
+            (href, String::new()),
+        )),
+        None => inlines.extend(content),
+    }
+}
+
+/// Plain text as words separated by spaces.
+fn words(text: &str) -> Vec<Inline> {
+    let mut inlines = Vec::new();
+
+    for word in text.split_whitespace() {
+        if !inlines.is_empty() {
+            inlines.push(Inline::Space);
+        }
+        inlines.push(Inline::Str(word.to_string()));
+    }
+
+    inlines
+}
+
+/// Turns a title into an id like Pandoc's `auto_identifiers`: lowercase, with spaces turned
+/// into dashes, punctuation other than `_`, `-` and `.` removed, and everything before the
+/// first letter left out.
+fn identifier(text: &str) -> String {
+    let id: String = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .skip_while(|c| !c.is_alphabetic())
+        .collect();
+
+    match id.is_empty() {
+        true => "section".to_string(),
+        false => id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::{prop_assert_eq, proptest};
+
+    use crate::{
+        pandoc::{convert, identifier, to_json, Block, Inline},
+        parse_tree,
+    };
+
+    fn blocks(input: &str) -> Vec<Block> {
+        convert(&parse_tree(input).unwrap()).blocks
+    }
+
+    #[test]
+    fn converting() {
+        assert_yaml_snapshot!(
+            "metadata",
+            convert(
+                &parse_tree(
+                    "@document.meta
+                     title: Notes
+                     authors: [
+                       Jane Doe
+                       John Doe
+                     ]
+                     version: 2
+                     draft: true
+                     @end
+                     Some text.\n"
+                )
+                .unwrap()
+            )
+        );
+        assert_yaml_snapshot!(
+            "headings",
+            blocks("* (x) Done heading\n---\n* Done heading\n  A duplicate title.\n")
+        );
+        assert_yaml_snapshot!(
+            "lists",
+            blocks(
+                "- ( ) Write *the* writer
+                 -- (x) Use /Pandoc/
+                 - (!|# A|< 2024-01-01) Ship it
+                 ~ First
+                 ~ Second\n"
+            )
+        );
+        assert_yaml_snapshot!("quotes", blocks("> Quoted text\n>> Nested\n"));
+        assert_yaml_snapshot!(
+            "markup",
+            blocks(
+                "*Bold*, /italic/, _underline_, -deleted-, ^super^, ,sub,, !spoiler! and
+                 %comment%.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "links",
+            blocks(
+                "Links to {* Code}, {$ Term}, {https://example.com}[example], {:other:** Heading}
+                 and [anchor]{https://neorg.org}. Then [anchor] again and an <inline target>.
+                 * Code
+                 $ Term
+                 The definition.\n"
+            )
+        );
+        assert_yaml_snapshot!(
+            "footnotes",
+            blocks("A note.{^ Note}\n^ Note\nA footnote with $x^2$.\n")
+        );
+        assert_yaml_snapshot!(
+            "definitions",
+            blocks("$ Term\nThe definition.\n+color red\n$ Other term\nAnother one.\n")
+        );
+        assert_yaml_snapshot!("tables", blocks(": A1\n  Name\n: B1\n  Age\n: A2\n  Bob\n"));
+        assert_yaml_snapshot!("sparse_tables", blocks(": A1\n  a\n: C3\n  c\n"));
+        assert_yaml_snapshot!(
+            "tags",
+            blocks("#tag important\n- Tagged item\n|example\nSome text.\n|end\n")
+        );
+        assert_yaml_snapshot!(
+            "code",
+            blocks("@code rust\nfn main() {}\n@end\n@math\ne^{i\\pi} + 1 = 0\n@end\n")
+        );
+        assert_yaml_snapshot!("verbatim", blocks("`a\\b`, $\\frac{1}{2}$ and &x\\_y&\n"));
+        assert_yaml_snapshot!(
+            "escapes",
+            blocks("Escaped \\*stars\\*, a backslash \\\\ and a pipe \\|.\n")
+        );
+        assert_yaml_snapshot!("escaped_pipes", blocks(": A1\n  a \\| b\n: B1\n  c\n"));
+    }
+
+    #[test]
+    fn json() {
+        let tree = parse_tree("* Hi\n*there*\n").unwrap();
+
+        assert_eq!(
+            to_json(&tree),
+            r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[{"t":"Header","c":[1,["hi",[],[]],[{"t":"Str","c":"Hi"}]]},{"t":"Para","c":[{"t":"Strong","c":[{"t":"Str","c":"there"}]}]}]}"#
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn words_become_strs_and_spaces(words in proptest::collection::vec("[a-z]{1,8}", 1..10)) {
+            let tree = parse_tree(&(words.join(" ") + "\n")).unwrap();
+            let inlines: Vec<_> = words
+                .iter()
+                .map(|word| Inline::Str(word.clone()))
+                .flat_map(|word| [Inline::Space, word])
+                .skip(1)
+                .collect();
+
+            prop_assert_eq!(convert(&tree).blocks, vec![Block::Para(inlines)]);
+        }
+
+        #[test]
+        fn identifiers_are_plain(text in r"\PC*") {
+            let id = identifier(&text);
+
+            assert!(id.chars().next().is_some_and(char::is_alphabetic));
+            assert!(id.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.')));
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! CSS-like selectors for finding nodes in the tree returned by [`crate::parse_tree`].
+//!
+//! A selector is a list of compound selectors joined by combinators, e.g.
+//! `heading[level=2] > list[todo=undone]`:
+//!
+//! - A compound selector is a node kind (or `*` for any node) followed by any number of
+//!   `[attribute]` or `[attribute=value]` filters. The kind may be left out when there is at
+//!   least one filter. Values containing whitespace or `]` can be quoted, as in
+//!   `[title="Some title"]`.
+//! - Whitespace between two compound selectors matches any descendant, `>` only direct
+//!   children.
+//!
+//! The node kinds are `paragraph`, `heading`, `list` (both `unordered-list` and
+//! `ordered-list`), `quote`, `definition`, `footnote`, `cell`, `tag` (any of `carryover-tag`,
+//! `verbatim`, `ranged-tag` and `infirm-tag`), `delimiter` and `error`.
+//!
+//! The attributes are:
+//!
+//! - `level`: the level of a heading, list item or quote.
+//! - `todo`: the todo status, one of `undone`, `done`, `needs-clarification`, `paused`, `urgent`,
+//!   `recurring`, `pending` and `canceled`. Without a value it matches any status.
+//! - `priority`: the priority. Without a value it matches any priority.
+//! - `title`: the text of a heading, list item, quote, definition, footnote or cell title,
+//!   ignoring markup and surrounding whitespace.
+//! - `name`: the name of a tag, with its parts joined by `.`, e.g. `document.meta`.
+//! - `param`: one of the parameters of a tag.
+//!
+//! The children of a list item or quote are its text paragraph followed by its content, and a
+//! carryover tag has the node it applies to as its only child.
+
+use std::str::FromStr;
+
+use chumsky::prelude::*;
+
+use crate::{
+    error::SelectorError,
+    span::{source_chars, spanned_stream, Span},
+    text::{plain_text, todo_name, PlainText, TODO_STATUSES},
+    visit::Visitor,
+    DetachedModifierExtension, NestableDetachedModifier, NorgAST, RangeableDetachedModifier,
+};
+
+/// A parsed selector, which can be matched against trees any number of times.
+#[derive(Clone, Hash, Debug, PartialEq, Eq)]
+pub struct Selector {
+    /// The compound selectors from left to right, each together with the combinator joining it
+    /// to the previous one. The combinator of the first one is not used.
+    parts: Vec<(Combinator, Compound)>,
+}
+
+#[derive(Clone, Copy, Hash, Debug, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq)]
+struct Compound {
+    kind: Option<NodeKind>,
+    attributes: Vec<Attribute>,
+}
+
+#[derive(Clone, Copy, Hash, Debug, PartialEq, Eq)]
+enum NodeKind {
+    Paragraph,
+    Heading,
+    List,
+    UnorderedList,
+    OrderedList,
+    Quote,
+    Definition,
+    Footnote,
+    Cell,
+    Tag,
+    CarryoverTag,
+    Verbatim,
+    RangedTag,
+    InfirmTag,
+    Delimiter,
+    Error,
+}
+
+const NODE_KINDS: [(&str, NodeKind); 16] = [
+    ("paragraph", NodeKind::Paragraph),
+    ("heading", NodeKind::Heading),
+    ("list", NodeKind::List),
+    ("unordered-list", NodeKind::UnorderedList),
+    ("ordered-list", NodeKind::OrderedList),
+    ("quote", NodeKind::Quote),
+    ("definition", NodeKind::Definition),
+    ("footnote", NodeKind::Footnote),
+    ("cell", NodeKind::Cell),
+    ("tag", NodeKind::Tag),
+    ("carryover-tag", NodeKind::CarryoverTag),
+    ("verbatim", NodeKind::Verbatim),
+    ("ranged-tag", NodeKind::RangedTag),
+    ("infirm-tag", NodeKind::InfirmTag),
+    ("delimiter", NodeKind::Delimiter),
+    ("error", NodeKind::Error),
+];
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq)]
+enum Attribute {
+    Level(u16),
+    /// The name of a todo status, or any status.
+    Todo(Option<String>),
+    Priority(Option<String>),
+    Title(String),
+    Name(String),
+    Parameter(String),
+}
+
+/// A node found by [`Selector::select`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match<'a> {
+    pub node: &'a NorgAST,
+    /// The nodes containing [`Match::node`], starting at the top level.
+    pub ancestors: Vec<&'a NorgAST>,
+}
+
+impl Selector {
+    /// Parses a selector. Error locations are relative to `selector`.
+    pub fn parse(selector: &str) -> Result<Self, SelectorError> {
+        selector_parser()
+            .parse(spanned_stream(source_chars(selector).collect()))
+            .map_err(SelectorError)
+    }
+
+    /// Returns every node of the tree matched by the selector, in document order.
+    pub fn select<'a>(&self, tree: &'a [NorgAST]) -> Vec<Match<'a>> {
+        let mut matches = Vec::new();
+        for node in tree {
+            self.collect(node, &mut Vec::new(), &mut matches);
+        }
+
+        matches
+    }
+
+    fn collect<'a>(
+        &self,
+        node: &'a NorgAST,
+        ancestors: &mut Vec<&'a NorgAST>,
+        matches: &mut Vec<Match<'a>>,
+    ) {
+        if self.matches(self.parts.len() - 1, node, ancestors) {
+            matches.push(Match {
+                node,
+                ancestors: ancestors.clone(),
+            });
+        }
+
+        ancestors.push(node);
+        for child in children(node) {
+            self.collect(child, ancestors, matches);
+        }
+        ancestors.pop();
+    }
+
+    /// Checks whether the parts up to and including `part` match `node`, whose ancestors are
+    /// `ancestors`.
+    fn matches(&self, part: usize, node: &NorgAST, ancestors: &[&NorgAST]) -> bool {
+        let (combinator, compound) = &self.parts[part];
+
+        if !compound.matches(node) {
+            return false;
+        }
+        if part == 0 {
+            return true;
+        }
+
+        match combinator {
+            Combinator::Child => ancestors
+                .split_last()
+                .is_some_and(|(parent, rest)| self.matches(part - 1, parent, rest)),
+            Combinator::Descendant => (0..ancestors.len())
+                .rev()
+                .any(|i| self.matches(part - 1, ancestors[i], &ancestors[..i])),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = SelectorError;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        Self::parse(selector)
+    }
+}
+
+/// Parses `selector` and returns every node of the tree it matches, in document order.
+pub fn select<'a>(tree: &'a [NorgAST], selector: &str) -> Result<Vec<Match<'a>>, SelectorError> {
+    Ok(Selector::parse(selector)?.select(tree))
+}
+
+fn children(node: &NorgAST) -> Vec<&NorgAST> {
+    match node {
+        NorgAST::NestableDetachedModifier { text, content, .. } => {
+            std::iter::once(text.as_ref()).chain(content).collect()
+        }
+        NorgAST::Heading { content, .. }
+        | NorgAST::RangeableDetachedModifier { content, .. }
+        | NorgAST::RangedTag { content, .. } => content.iter().collect(),
+        NorgAST::CarryoverTag { next_object, .. } => vec![next_object],
+        _ => vec![],
+    }
+}
+
+impl Compound {
+    fn matches(&self, node: &NorgAST) -> bool {
+        self.kind.map_or(true, |kind| kind.matches(node))
+            && self
+                .attributes
+                .iter()
+                .all(|attribute| attribute.matches(node))
+    }
+}
+
+impl NodeKind {
+    fn matches(self, node: &NorgAST) -> bool {
+        use NestableDetachedModifier as Nestable;
+        use RangeableDetachedModifier as Rangeable;
+
+        match node {
+            NorgAST::Paragraph { .. } => self == Self::Paragraph,
+            NorgAST::Heading { .. } => self == Self::Heading,
+            NorgAST::NestableDetachedModifier { modifier_type, .. } => matches!(
+                (self, modifier_type),
+                (Self::List, Nestable::UnorderedList | Nestable::OrderedList)
+                    | (Self::UnorderedList, Nestable::UnorderedList)
+                    | (Self::OrderedList, Nestable::OrderedList)
+                    | (Self::Quote, Nestable::Quote)
+            ),
+            NorgAST::RangeableDetachedModifier { modifier_type, .. } => matches!(
+                (self, modifier_type),
+                (Self::Definition, Rangeable::Definition)
+                    | (Self::Footnote, Rangeable::Footnote)
+                    | (Self::Cell, Rangeable::Table)
+            ),
+            NorgAST::CarryoverTag { .. } => matches!(self, Self::Tag | Self::CarryoverTag),
+            NorgAST::VerbatimRangedTag { .. } => matches!(self, Self::Tag | Self::Verbatim),
+            NorgAST::RangedTag { .. } => matches!(self, Self::Tag | Self::RangedTag),
+            NorgAST::InfirmTag { .. } => matches!(self, Self::Tag | Self::InfirmTag),
+            NorgAST::DelimitingModifier { .. } => self == Self::Delimiter,
+            NorgAST::Error { .. } => self == Self::Error,
+        }
+    }
+}
+
+impl Attribute {
+    fn matches(&self, node: &NorgAST) -> bool {
+        match self {
+            Self::Level(expected) => match node {
+                NorgAST::Heading { level, .. }
+                | NorgAST::NestableDetachedModifier { level, .. } => level == expected,
+                _ => false,
+            },
+            Self::Todo(expected) => extensions(node).iter().any(|extension| match extension {
+                DetachedModifierExtension::Todo(status) => expected
+                    .as_deref()
+                    .map_or(true, |expected| todo_name(status) == expected),
+                _ => false,
+            }),
+            Self::Priority(expected) => extensions(node).iter().any(|extension| match extension {
+                DetachedModifierExtension::Priority(priority) => expected
+                    .as_ref()
+                    .map_or(true, |expected| priority == expected),
+                _ => false,
+            }),
+            Self::Title(expected) => title(node).is_some_and(|title| &title == expected),
+            Self::Name(expected) => tag(node).is_some_and(|(name, _)| &name.join(".") == expected),
+            Self::Parameter(expected) => {
+                tag(node).is_some_and(|(_, parameters)| parameters.contains(expected))
+            }
+        }
+    }
+}
+
+fn extensions(node: &NorgAST) -> &[DetachedModifierExtension] {
+    match node {
+        NorgAST::Heading { extensions, .. }
+        | NorgAST::NestableDetachedModifier { extensions, .. }
+        | NorgAST::RangeableDetachedModifier { extensions, .. } => extensions,
+        _ => &[],
+    }
+}
+
+fn tag(node: &NorgAST) -> Option<(&[String], &[String])> {
+    match node {
+        NorgAST::CarryoverTag {
+            name, parameters, ..
+        }
+        | NorgAST::VerbatimRangedTag {
+            name, parameters, ..
+        }
+        | NorgAST::RangedTag {
+            name, parameters, ..
+        }
+        | NorgAST::InfirmTag {
+            name, parameters, ..
+        } => Some((name, parameters)),
+        _ => None,
+    }
+}
+
+/// The plain text of a title, used by the `title` attribute.
+fn title(node: &NorgAST) -> Option<String> {
+    let text = match node {
+        NorgAST::Heading { title, .. } | NorgAST::RangeableDetachedModifier { title, .. } => {
+            plain_text(title)
+        }
+        NorgAST::NestableDetachedModifier {
+            text: paragraph, ..
+        } => {
+            let mut text = PlainText::default();
+            text.visit_node(paragraph);
+            text.0
+        }
+        _ => return None,
+    };
+
+    Some(text.trim().to_string())
+}
+
+fn selector_parser() -> impl Parser<char, Selector, Error = Simple<char, Span>> {
+    let identifier = filter(|c: &char| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .repeated()
+        .at_least(1)
+        .collect::<String>();
+
+    let kind = just('*').to(None).or(identifier.map(Some));
+
+    let quoted = none_of("\"")
+        .repeated()
+        .delimited_by(just('"'), just('"'))
+        .collect::<String>();
+    let value = quoted.or(none_of("]\"").repeated().at_least(1).collect::<String>());
+
+    let attribute = identifier
+        .then(just('=').ignore_then(value).or_not())
+        .delimited_by(just('['), just(']'))
+        .try_map(|(name, value), span| {
+            let required = |value: Option<String>| {
+                value.ok_or_else(|| {
+                    Simple::custom(span, format!("the `{name}` attribute needs a value"))
+                })
+            };
+
+            match name.as_str() {
+                "level" => required(value.clone())?
+                    .trim()
+                    .parse()
+                    .map(Attribute::Level)
+                    .map_err(|_| Simple::custom(span, "levels have to be numbers")),
+                "todo" => match value {
+                    Some(status) if !TODO_STATUSES.contains(&status.as_str()) => Err(
+                        Simple::custom(span, format!("unknown todo status `{status}`")),
+                    ),
+                    status => Ok(Attribute::Todo(status)),
+                },
+                "priority" => Ok(Attribute::Priority(value)),
+                "title" => required(value).map(Attribute::Title),
+                "name" => required(value).map(Attribute::Name),
+                "param" => required(value).map(Attribute::Parameter),
+                _ => Err(Simple::custom(span, format!("unknown attribute `{name}`"))),
+            }
+        });
+
+    let compound =
+        kind.or_not()
+            .then(attribute.repeated())
+            .try_map(|(kind, attributes), span: Span| {
+                let kind = match kind {
+                    Some(Some(name)) => Some(
+                        NODE_KINDS
+                            .iter()
+                            .find(|(kind, _)| *kind == name)
+                            .map(|(_, kind)| *kind)
+                            .ok_or_else(|| {
+                                Simple::custom(span, format!("unknown node kind `{name}`"))
+                            })?,
+                    ),
+                    None if attributes.is_empty() => {
+                        return Err(Simple::custom(span, "expected a selector"))
+                    }
+                    Some(None) | None => None,
+                };
+
+                Ok(Compound { kind, attributes })
+            });
+
+    let combinator = just('>')
+        .padded()
+        .to(Combinator::Child)
+        .or(text::whitespace().at_least(1).to(Combinator::Descendant));
+
+    compound
+        .clone()
+        .map(|compound| (Combinator::Descendant, compound))
+        .chain(combinator.then(compound).repeated())
+        .padded()
+        .then_ignore(end())
+        .map(|parts| Selector { parts })
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use itertools::Itertools;
+
+    use crate::{
+        parse_tree,
+        query::{select, Match},
+    };
+
+    /// Describes a match by the location of the node and of its ancestors.
+    fn describe(found: &Match) -> String {
+        let location =
+            |span: crate::Span| format!("{}:{}", span.start.line + 1, span.start.column + 1);
+
+        found
+            .ancestors
+            .iter()
+            .chain([&found.node])
+            .map(|node| location(node.span()))
+            .join(" > ")
+    }
+
+    #[test]
+    fn selectors() {
+        let input = "* Tasks
+                     - ( ) Write the parser
+                     -- (x) Lexer
+                     -- ( ) Tokens
+                     - (x|# A) Write the docs
+                     ** Later
+                        ~ ( ) Benchmarks
+            * Notes
+              > A quote
+              #name value
+              $ Term
+                Definition of the term.
+              @code lua
+              print()
+              @end
+              |example
+              - ( ) Inside a tag
+              |end
+            ";
+        let tree = parse_tree(input).unwrap();
+
+        let examples: Vec<_> = [
+            "list[todo=undone]",
+            "heading[title=Tasks] > list[todo]",
+            "heading[level=1] list[level=2]",
+            "heading[level=1] > heading > ordered-list",
+            "[priority=A]",
+            "[todo=done] > paragraph",
+            "quote",
+            "heading[title=\"Notes\"] > *",
+            "tag[name=code][param=lua]",
+            "carryover-tag > definition",
+            "ranged-tag list",
+        ]
+        .into_iter()
+        .map(|selector| {
+            let found =
+                select(&tree, selector).map(|found| found.iter().map(describe).collect_vec());
+            (selector, found.map_err(|error| error.to_string()))
+        })
+        .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn selector_errors() {
+        let examples: Vec<_> = [
+            "",
+            "section",
+            "heading[depth=2]",
+            "heading[level=two]",
+            "list[todo=finished]",
+            "tag[name]",
+            "heading >",
+            "heading[level=1",
+        ]
+        .into_iter()
+        .map(|selector| (selector, select(&[], selector).unwrap_err().to_string()))
+        .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+}
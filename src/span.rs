@@ -0,0 +1,124 @@
+//! Source locations for tokens, blocks and AST nodes.
+//!
+//! Every stage of the parser operates on a stream of `(item, Span)` pairs, so chumsky itself
+//! takes care of merging the spans of the individual tokens into the span of a larger node.
+
+use chumsky::Stream;
+use serde::Serialize;
+
+/// A single location in the original input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Position {
+    /// Byte offset into the input.
+    pub offset: usize,
+    /// Zero-based line number.
+    pub line: usize,
+    /// Zero-based column, counted in characters from the start of the line.
+    pub column: usize,
+}
+
+/// A half-open range in the original input, `start` inclusive and `end` exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+
+    /// Creates an empty span located at the given position.
+    pub fn empty(at: Position) -> Self {
+        Self { start: at, end: at }
+    }
+
+    /// Returns the byte range of this span, suitable for slicing the original input.
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start.offset..self.end.offset
+    }
+
+    /// Returns the smallest span which covers both `self` and `other`.
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+impl chumsky::Span for Span {
+    type Context = ();
+    type Offset = Position;
+
+    fn new((): Self::Context, range: std::ops::Range<Self::Offset>) -> Self {
+        Self {
+            start: range.start,
+            end: range.end,
+        }
+    }
+
+    fn context(&self) -> Self::Context {}
+
+    fn start(&self) -> Self::Offset {
+        self.start
+    }
+
+    fn end(&self) -> Self::Offset {
+        self.end
+    }
+}
+
+/// Iterates over the characters of the input, each tagged with its location.
+pub(crate) fn source_chars(input: &str) -> impl Iterator<Item = (char, Span)> + '_ {
+    let mut position = Position::default();
+
+    input.chars().map(move |c| {
+        let start = position;
+
+        position.offset += c.len_utf8();
+        if c == '\n' {
+            position.line += 1;
+            position.column = 0;
+        } else {
+            position.column += 1;
+        }
+
+        (c, Span::new(start, position))
+    })
+}
+
+/// Turns the output of a previous stage into a stream for the next one.
+pub(crate) fn spanned_stream<T>(
+    items: Vec<(T, Span)>,
+) -> Stream<'static, T, Span, std::vec::IntoIter<(T, Span)>> {
+    let eoi = items
+        .last()
+        .map(|(_, span)| Span::empty(span.end))
+        .unwrap_or_default();
+
+    Stream::from_iter(eoi, items.into_iter())
+}
+
+/// Computes the position right after the last character of the input.
+pub(crate) fn source_end(input: &str) -> Position {
+    position_at(input, input.len())
+}
+
+/// Computes the position of the given byte offset in the input.
+pub(crate) fn position_at(input: &str, offset: usize) -> Position {
+    let before = &input[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+    Position {
+        offset,
+        line: before.matches('\n').count(),
+        column: before[line_start..].chars().count(),
+    }
+}
+
+/// Pairs a parsed item with its span, for use with [`chumsky::Parser::map_with_span`].
+pub(crate) fn with_span<T>(item: T, span: Span) -> (T, Span) {
+    (item, span)
+}
@@ -2,81 +2,238 @@
 
 use std::fmt::Write as _;
 
-use chumsky::prelude::*;
-use chumsky::{
-    text::{keyword, Character},
-    Parser,
-};
 use serde::Serialize;
 use unicode_categories::UnicodeCategories;
 
+use crate::span::{source_end, Position, Span};
+
 /// Describes an individual part of the document.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
-pub enum NorgToken {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum NorgToken<'a> {
     Whitespace(u16),
     SingleNewline,
     Newlines(u16),
-    Regular(char),
+    /// A run of characters without any special meaning.
+    Text(&'a str),
     Special(char),
     Escape(char),
     End(char),
+    /// A trailing `~`, together with the newline and indentation after it, which joins two lines
+    /// without any whitespace in between.
+    Continuation(&'a str),
     Eof,
 }
 
-impl std::fmt::Display for NorgToken {
+impl std::fmt::Display for NorgToken<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::End(c) => write!(f, "{}end", c),
             Self::Eof => f.write_char('\0'),
             Self::Escape(c) => write!(f, "\\{}", c),
             Self::Newlines(count) => f.write_str(&"\n".repeat(*count as usize)),
-            Self::Regular(c) | Self::Special(c) => f.write_char(*c),
+            Self::Text(text) => f.write_str(text),
+            Self::Special(c) => f.write_char(*c),
             Self::SingleNewline => f.write_char('\n'),
             Self::Whitespace(count) => f.write_str(&" ".repeat(*count as usize)),
+            Self::Continuation(text) => f.write_str(text),
         }
     }
 }
 
-impl From<NorgToken> for String {
-    fn from(value: NorgToken) -> Self {
+impl From<NorgToken<'_>> for String {
+    fn from(value: NorgToken<'_>) -> Self {
         value.to_string()
     }
 }
 
 /// A list of characters which are considered "special", i.e. for parsing of attached modifiers.
-const SPECIAL_CHARS: &str = "*-~/_!%^,\"'`$:@|=.#+<>()[]{}\\";
+const SPECIAL_CHARS: &str = "*-~/_!%^,\"'`$&:@|=.#+<>()[]{}\\";
+
+pub(crate) fn is_special(c: char) -> bool {
+    SPECIAL_CHARS.contains(c)
+}
+
+fn is_newline(c: char) -> bool {
+    c == '\n' || c == '\r' || c.is_separator_line() || c.is_separator_paragraph()
+}
+
+fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t' || c.is_separator_space()
+}
+
+/// Breaks up the input into tokens, each paired with its location in the input. Runs of regular
+/// characters become a single [`NorgToken::Text`] which borrows from the input.
+///
+/// The last token is always [`NorgToken::Eof`]. Lexing cannot fail.
+pub fn stage_1(input: &str) -> Vec<(NorgToken<'_>, Span)> {
+    stage_1_between(input, Position::default(), source_end(input))
+}
+
+/// Lexes the part of the input between `start` and `end`. The locations are still relative to
+/// the whole input.
+pub(crate) fn stage_1_between(
+    input: &str,
+    start: Position,
+    end: Position,
+) -> Vec<(NorgToken<'_>, Span)> {
+    let mut lexer = Lexer {
+        input: &input[..end.offset],
+        position: start,
+    };
+    let mut tokens = Vec::new();
+
+    while let Some(c) = lexer.peek() {
+        let start = lexer.position;
+
+        let token = if let Some(token) = lexer.tag_end(c) {
+            token
+        } else if lexer.at_continuation(c) {
+            lexer.bump();
+            lexer.bump();
+            lexer.eat_while(is_whitespace);
+            NorgToken::Continuation(&lexer.input[start.offset..lexer.position.offset])
+        } else if c == '\\' && lexer.peek_nth(1).is_some() {
+            lexer.bump();
+            NorgToken::Escape(lexer.bump())
+        } else if is_special(c) {
+            NorgToken::Special(lexer.bump())
+        } else if is_newline(c) {
+            match lexer.eat_while(is_newline) {
+                1 => NorgToken::SingleNewline,
+                count => NorgToken::Newlines(count as u16),
+            }
+        } else if is_whitespace(c) {
+            NorgToken::Whitespace(lexer.eat_while(is_whitespace) as u16)
+        } else {
+            lexer.eat_while(|c| !is_special(c) && !is_newline(c) && !is_whitespace(c));
+            NorgToken::Text(&lexer.input[start.offset..lexer.position.offset])
+        };
+
+        tokens.push((token, Span::new(start, lexer.position)));
+    }
+
+    tokens.push((NorgToken::Eof, Span::empty(lexer.position)));
+    tokens
+}
+
+/// A cursor over the input which keeps track of the current location.
+struct Lexer<'a> {
+    input: &'a str,
+    position: Position,
+}
+
+impl Lexer<'_> {
+    fn rest(&self) -> &str {
+        &self.input[self.position.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
 
-/// Parses a `.norg` document and breaks it up into tokens.
-pub fn stage_1() -> impl Parser<char, Vec<NorgToken>, Error = chumsky::error::Simple<char>> {
-    let ws = filter(|c: &char| c.is_inline_whitespace() || c.is_separator_space())
-        .repeated()
-        .at_least(1)
-        .map(|content| NorgToken::Whitespace(content.len() as u16));
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    fn bump(&mut self) -> char {
+        let c = self.peek().expect("bump past the end of the input");
+
+        self.position.offset += c.len_utf8();
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 0;
+        } else {
+            self.position.column += 1;
+        }
+
+        c
+    }
+
+    /// Consumes characters while they match, returning how many were consumed.
+    fn eat_while(&mut self, f: impl Fn(char) -> bool) -> usize {
+        let mut count = 0;
+
+        while self.peek().is_some_and(&f) {
+            self.bump();
+            count += 1;
+        }
+
+        count
+    }
 
-    // Fallback parser for any non-special character.
-    let character = any().map(NorgToken::Regular);
+    /// Checks for a trailing `~`, which only joins its line with the next one when it is not at
+    /// the start of a line, after its indentation, and the next line is not empty.
+    fn at_continuation(&self, c: char) -> bool {
+        let mut rest = self.rest().chars().skip(1);
 
-    let parse_newline = filter(|c: &char| {
-        *c == '\n' || *c == '\r' || c.is_separator_line() || c.is_separator_paragraph()
-    });
+        c == '~'
+            && self.input[..self.position.offset]
+                .chars()
+                .rev()
+                .take_while(|c| !is_newline(*c))
+                .any(|c| !is_whitespace(c))
+            && rest.next().is_some_and(is_newline)
+            && rest
+                .find(|c| !is_whitespace(*c))
+                .is_some_and(|c| !is_newline(c))
+    }
+
+    /// Lexes a tag end like `@end`, which has to be followed by a newline or the end of input.
+    fn tag_end(&mut self, c: char) -> Option<NorgToken<'static>> {
+        let rest = self.rest()[c.len_utf8()..].strip_prefix("end")?;
+
+        if !is_special(c) || !(rest.is_empty() || rest.starts_with(['\n', '\r'])) {
+            return None;
+        }
 
-    let newline = parse_newline.to(NorgToken::SingleNewline);
+        (0..4).for_each(|_| {
+            self.bump();
+        });
 
-    let newlines = parse_newline
-        .repeated()
-        .at_least(2)
-        .map(|content| NorgToken::Newlines(content.len() as u16));
+        Some(NorgToken::End(c))
+    }
+}
 
-    let special = one_of(SPECIAL_CHARS).map(NorgToken::Special);
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::{prop_assert_eq, proptest};
 
-    let escape = just('\\').ignore_then(any()).map(NorgToken::Escape);
+    use crate::stage_1::{stage_1, NorgToken};
 
-    let tag_end = one_of(SPECIAL_CHARS)
-        .then_ignore(keyword("end"))
-        .then_ignore(choice((one_of("\n\r").rewind().map(|_| ()), end())))
-        .map(NorgToken::End);
+    #[test]
+    fn tokens() {
+        let examples: Vec<_> = [
+            "Hello, *world*!",
+            "  indented\n\n\ntext",
+            "\\*escaped\\",
+            "@code\nfoo\n@end",
+            "@endless",
+            "joined~\n  line, ~\nnot joined~\n\n~\nend~",
+        ]
+        .into_iter()
+        .map(|input| {
+            stage_1(input)
+                .into_iter()
+                .map(|(token, _)| token)
+                .collect::<Vec<_>>()
+        })
+        .collect();
 
-    choice((tag_end, escape, special, newlines, newline, ws, character))
-        .repeated()
-        .chain(end().to(NorgToken::Eof))
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn tokens_cover_input(input in r"[*\-~@\\ \n\ta-zä]*(end)?") {
+            let tokens = stage_1(&input);
+            let text: String = tokens
+                .iter()
+                .filter(|(token, _)| *token != NorgToken::Eof)
+                .map(|(_, span)| &input[span.range()])
+                .collect();
+
+            prop_assert_eq!(text, input);
+        }
+    }
 }
@@ -6,6 +6,7 @@ use chumsky::Parser;
 use itertools::Itertools;
 use serde::Serialize;
 
+use crate::span::{with_span, Span};
 use crate::stage_1::NorgToken;
 use chumsky::prelude::*;
 
@@ -34,91 +35,240 @@ impl From<ParagraphSegmentToken> for String {
     }
 }
 
-pub type ParagraphTokenList = Vec<ParagraphSegmentToken>;
+/// A list of paragraph tokens, each paired with its location in the input.
+pub type ParagraphTokenList = Vec<(ParagraphSegmentToken, Span)>;
 
-fn tokens_to_paragraph_segment(tokens: Vec<NorgToken>) -> ParagraphTokenList {
+/// A list of tokens which borrow from the input, each paired with its location.
+pub type TokenList<'a> = Vec<(NorgToken<'a>, Span)>;
+
+/// Converts the tokens of (part of) a paragraph into paragraph tokens, which own their text.
+/// Line continuations are dropped, which joins the lines around them.
+pub(crate) fn tokens_to_paragraph_segment(tokens: TokenList<'_>) -> ParagraphTokenList {
     tokens
         .into_iter()
-        .peekable()
-        .batching(|it| match it.next() {
-            Some(NorgToken::SingleNewline) | Some(NorgToken::Whitespace(_)) => {
-                Some(ParagraphSegmentToken::Whitespace)
-            }
-            Some(NorgToken::Special(c)) => Some(ParagraphSegmentToken::Special(c)),
-            Some(NorgToken::Escape(c)) => Some(ParagraphSegmentToken::Escape(c)),
-            Some(NorgToken::Regular(c)) => {
-                let mut result: String = it
-                    .peeking_take_while(|token| matches!(token, NorgToken::Regular(_)))
-                    .map_into::<String>()
-                    .collect();
-
-                result.insert(0, c);
+        .filter(|(token, _)| !matches!(token, NorgToken::Continuation(_)))
+        .map(|(token, span)| {
+            let token = match token {
+                NorgToken::SingleNewline | NorgToken::Whitespace(_) => {
+                    ParagraphSegmentToken::Whitespace
+                }
+                NorgToken::Special(c) => ParagraphSegmentToken::Special(c),
+                NorgToken::Escape(c) => ParagraphSegmentToken::Escape(c),
+                NorgToken::Text(text) => ParagraphSegmentToken::Text(text.to_string()),
+                // A tag end which does not close anything, e.g. `text @end`.
+                token @ NorgToken::End(_) => ParagraphSegmentToken::Text(token.to_string()),
+                NorgToken::Newlines(_) | NorgToken::Continuation(_) | NorgToken::Eof => {
+                    unreachable!()
+                }
+            };
 
-                Some(ParagraphSegmentToken::Text(result))
-            }
-            None => None,
-            _x => {
-                unreachable!();
-            }
+            (token, span)
         })
         .collect()
 }
 
 /// Represents various Norg blocks parsed from tokens.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
-pub enum NorgBlock {
+pub enum NorgBlock<'a> {
     /// A segment of a paragraph consisting of Norg tokens.
-    ParagraphSegment(ParagraphTokenList),
+    ParagraphSegment(TokenList<'a>),
     /// End of a paragraph segment.
-    ParagraphSegmentEnd(ParagraphTokenList),
+    ParagraphSegmentEnd(TokenList<'a>),
     /// A heading with a specified level, title, and optional extension section.
     Heading {
         level: u16,
-        title: ParagraphTokenList,
-        extension_section: ParagraphTokenList,
+        title: TokenList<'a>,
+        extension_section: TokenList<'a>,
     },
     /// A nestable detached modifier with a type, level, and optional extension section.
     NestableDetachedModifier {
         modifier_type: char,
         level: u16,
-        extension_section: ParagraphTokenList,
+        extension_section: TokenList<'a>,
     },
     /// A rangeable detached modifier with an indication if it is ranged, type, title, and optional extension section.
     RangeableDetachedModifier {
         ranged: bool,
         modifier_type: char,
-        title: ParagraphTokenList,
-        extension_section: ParagraphTokenList,
+        title: TokenList<'a>,
+        extension_section: TokenList<'a>,
     },
     /// Closing tag for a rangeable detached modifier.
     RangeableDetachedModifierClose(char),
     /// A ranged tag with a type, name, and optional parameters.
     RangedTag {
         tag_type: char,
-        name: ParagraphTokenList,
-        parameters: Option<Vec<ParagraphTokenList>>,
+        name: TokenList<'a>,
+        parameters: Option<Vec<TokenList<'a>>>,
     },
     /// End of a ranged tag.
     RangedTagEnd(char),
     /// A verbatim ranged tag with a name, optional parameters, and content.
     VerbatimRangedTag {
-        name: ParagraphTokenList,
-        parameters: Option<Vec<ParagraphTokenList>>,
-        content: Vec<NorgToken>,
+        name: TokenList<'a>,
+        parameters: Option<Vec<TokenList<'a>>>,
+        content: Vec<NorgToken<'a>>,
     },
     /// An infirm tag with a name and optional parameters.
     InfirmTag {
-        name: ParagraphTokenList,
-        parameters: Option<Vec<ParagraphTokenList>>,
+        name: TokenList<'a>,
+        parameters: Option<Vec<TokenList<'a>>>,
     },
     /// A carryover tag with a type, name, and optional parameters.
     CarryoverTag {
         tag_type: char,
-        name: ParagraphTokenList,
-        parameters: Option<Vec<ParagraphTokenList>>,
+        name: TokenList<'a>,
+        parameters: Option<Vec<TokenList<'a>>>,
     },
     /// A delimiting modifier, defined by a single char `-` (weak), `=` (string), or `_` (horizontal rule)
     DelimitingModifier(char),
+    /// A slide, i.e. a trailing `:` after a detached modifier, which makes the next element part
+    /// of it.
+    Slide,
+    /// An indent segment, i.e. a trailing `::` after a detached modifier, which makes everything
+    /// up to a closing `---` part of it.
+    IndentSegment,
+}
+
+/// Splits a trailing ` :` or ` ::` off the end of a line, returning the tokens before it and the
+/// block for the marker.
+fn split_trailing_modifier<'a>(
+    tokens: &[(NorgToken<'a>, Span)],
+) -> Option<(TokenList<'a>, (NorgBlock<'a>, Span))> {
+    let end = tokens.iter().rposition(|(token, _)| {
+        !matches!(token, NorgToken::SingleNewline | NorgToken::Whitespace(_))
+    })?;
+    let line = &tokens[..=end];
+
+    let colons = line
+        .iter()
+        .rev()
+        .take_while(|(token, _)| *token == NorgToken::Special(':'))
+        .count();
+    let (block, content_end) = match colons {
+        1 => (NorgBlock::Slide, line.len() - 1),
+        2 => (NorgBlock::IndentSegment, line.len() - 2),
+        _ => return None,
+    };
+
+    match line[..content_end] {
+        [ref content @ .., (NorgToken::Whitespace(_), _)] if !content.is_empty() => {
+            let span = line[content_end].1.union(line[end].1);
+            let content = content
+                .iter()
+                .map(|(token, span)| (*token, *span))
+                .collect();
+
+            Some((content, (block, span)))
+        }
+        _ => None,
+    }
+}
+
+/// Splits the title of a rangeable detached modifier at an intersecting modifier (` : `),
+/// returning the title and the content after it. Colons within links are left alone.
+fn split_intersecting_modifier<'a>(
+    tokens: &[(NorgToken<'a>, Span)],
+) -> Option<(TokenList<'a>, TokenList<'a>)> {
+    let mut depth = 0usize;
+
+    let colon = tokens.iter().enumerate().position(|(i, (token, _))| {
+        match token {
+            NorgToken::Special('{') => depth += 1,
+            NorgToken::Special('}') => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+
+        depth == 0
+            && *token == NorgToken::Special(':')
+            && i > 1
+            && matches!(tokens[i - 1].0, NorgToken::Whitespace(_))
+            && matches!(tokens.get(i + 1), Some((NorgToken::Whitespace(_), _)))
+    })?;
+
+    let title = tokens[..colon - 1].to_vec();
+    let content = tokens[colon + 2..].to_vec();
+
+    if content
+        .iter()
+        .all(|(token, _)| matches!(token, NorgToken::Whitespace(_)))
+    {
+        return None;
+    }
+
+    Some((title, content))
+}
+
+/// Finds slides and indent segments, which end the title of a rangeable detached modifier or the
+/// paragraph right after a nestable one, and turns them into blocks of their own. Content after
+/// an intersecting modifier in the title of a rangeable detached modifier becomes a paragraph.
+fn split_trailing_modifiers(blocks: Vec<(NorgBlock<'_>, Span)>) -> Vec<(NorgBlock<'_>, Span)> {
+    let mut result = Vec::with_capacity(blocks.len());
+    let mut blocks = blocks.into_iter().peekable();
+
+    while let Some((block, span)) = blocks.next() {
+        match block {
+            NorgBlock::NestableDetachedModifier { .. } => {
+                result.push((block, span));
+
+                while let Some((block, _)) = blocks.peek() {
+                    let (tokens, last) = match block {
+                        NorgBlock::ParagraphSegment(tokens) => (tokens, false),
+                        NorgBlock::ParagraphSegmentEnd(tokens) => (tokens, true),
+                        _ => break,
+                    };
+
+                    if let Some((content, marker)) = split_trailing_modifier(tokens) {
+                        let span = content_span(&content);
+                        result.extend([(NorgBlock::ParagraphSegmentEnd(content), span), marker]);
+                        blocks.next();
+                        break;
+                    }
+
+                    result.extend(blocks.next());
+                    if last {
+                        break;
+                    }
+                }
+            }
+            NorgBlock::RangeableDetachedModifier {
+                ranged: false,
+                modifier_type,
+                title,
+                extension_section,
+            } => {
+                let (title, after, span) =
+                    if let Some((title, marker)) = split_trailing_modifier(&title) {
+                        let span = Span::new(span.start, content_span(&title).end);
+                        (title, Some(marker), span)
+                    } else if let Some((title, content)) = split_intersecting_modifier(&title) {
+                        let span = Span::new(span.start, content_span(&title).end);
+                        let content_span = content_span(&content);
+                        let paragraph = (NorgBlock::ParagraphSegmentEnd(content), content_span);
+                        (title, Some(paragraph), span)
+                    } else {
+                        (title, None, span)
+                    };
+
+                let block = NorgBlock::RangeableDetachedModifier {
+                    ranged: false,
+                    modifier_type,
+                    title,
+                    extension_section,
+                };
+                result.push((block, span));
+                result.extend(after);
+            }
+            block => result.push((block, span)),
+        }
+    }
+
+    result
+}
+
+/// Returns the span covering all of the given tokens, which must not be empty.
+fn content_span(tokens: &[(NorgToken<'_>, Span)]) -> Span {
+    tokens[0].1.union(tokens[tokens.len() - 1].1)
 }
 
 /// Defines the parser for stage 2 of the Norg parsing process, which converts tokens into blocks.
@@ -126,9 +276,13 @@ pub enum NorgBlock {
 /// # Returns
 ///
 /// * A parser that processes `NorgToken`s into a vector of `NorgBlock`s, which properly define
-///   paragraph boundaries.
-pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::error::Simple<NorgToken>>
-{
+///   paragraph boundaries. Each block is paired with its span, which excludes any surrounding
+///   whitespace and newlines.
+pub fn stage_2<'a>() -> impl Parser<
+    NorgToken<'a>,
+    Vec<(NorgBlock<'a>, Span)>,
+    Error = chumsky::error::Simple<NorgToken<'a>, Span>,
+> {
     use NorgToken::*;
 
     let whitespace = select! { Whitespace(_) => () };
@@ -152,7 +306,11 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
         Eof => (),
     };
 
-    let paragraph_segment = newlines_or_eof.not().repeated().at_least(1);
+    let paragraph_segment = newlines_or_eof
+        .not()
+        .map_with_span(with_span)
+        .repeated()
+        .at_least(1);
 
     let extension_section = select! {
         SingleNewline => (),
@@ -161,12 +319,14 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
         Special(')') => (),
     }
     .not()
+    .map_with_span(with_span)
     .repeated()
     .at_least(1)
     .delimited_by(just(Special('(')), just(Special(')')));
 
     let parameters = newlines_whitespace_or_eof
         .not()
+        .map_with_span(with_span)
         .repeated()
         .at_least(1)
         .separated_by(whitespace.repeated().at_least(1));
@@ -179,15 +339,13 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
     .at_least(1)
     .map(|chars| chars.len() as u16)
     .then_ignore(whitespace.repeated().at_least(1))
-    .then(extension_section.clone().or_not())
+    .then(extension_section.or_not())
     .then(paragraph_segment)
-    .then_ignore(newlines_or_eof)
+    .then_ignore(newlines_or_eof.rewind())
     .map(|((level, extension_section), title)| NorgBlock::Heading {
         level,
-        title: tokens_to_paragraph_segment(title),
-        extension_section: extension_section
-            .map(tokens_to_paragraph_segment)
-            .unwrap_or_default(),
+        title,
+        extension_section: extension_section.unwrap_or_default(),
     })
     .labelled("heading");
 
@@ -217,12 +375,12 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
         }
     })
     .then_ignore(whitespace.repeated().at_least(1))
-    .then(extension_section.clone().or_not())
+    .then(extension_section.or_not())
     .map(
         |((modifier_type, level), extension_section)| NorgBlock::NestableDetachedModifier {
             modifier_type,
             level,
-            extension_section: extension_section.map(tokens_to_paragraph_segment).unwrap_or_default(),
+            extension_section: extension_section.unwrap_or_default(),
         },
     )
     .labelled("nestabled_detached_modifier");
@@ -234,17 +392,15 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
             .at_most(2)
             .map(|chars| (chars[0], chars.len() == 2))
             .then_ignore(whitespace.repeated().at_least(1))
-            .then(extension_section.clone().or_not())
+            .then(extension_section.or_not())
             .then(paragraph_segment)
-            .then_ignore(newlines_or_eof)
+            .then_ignore(newlines_or_eof.rewind())
             .map(|(((modifier_type, ranged), extension_section), title)| {
                 NorgBlock::RangeableDetachedModifier {
                     modifier_type,
                     ranged,
-                    title: tokens_to_paragraph_segment(title),
-                    extension_section: extension_section
-                        .map(tokens_to_paragraph_segment)
-                        .unwrap_or_default(),
+                    title,
+                    extension_section: extension_section.unwrap_or_default(),
                 }
             })
             .labelled("rangeable_detached_modifier")
@@ -255,7 +411,7 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
             .repeated()
             .exactly(2)
             .ignored()
-            .then_ignore(newlines_or_eof)
+            .then_ignore(newlines_or_eof.rewind())
             .map(move |_| NorgBlock::RangeableDetachedModifierClose(c))
             .labelled("rangeable_detached_modifier_closed")
     };
@@ -274,12 +430,19 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
             End(x) if x == c => ()
         }
         .not()
+        .map_with_span(with_span)
         .repeated()
         .at_least(1)
         .separated_by(whitespace.repeated().at_least(1));
 
         parse_char
-            .ignore_then(newlines_whitespace_or_eof.not().repeated().at_least(1))
+            .ignore_then(
+                newlines_whitespace_or_eof
+                    .not()
+                    .map_with_span(with_span)
+                    .repeated()
+                    .at_least(1),
+            )
             .then(
                 whitespace
                     .repeated()
@@ -293,13 +456,8 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
             .then_ignore(tag_end)
             .map(
                 |((name, parameters), content)| NorgBlock::VerbatimRangedTag {
-                    name: tokens_to_paragraph_segment(name),
-                    parameters: parameters.map(|tokens| {
-                        tokens
-                            .into_iter()
-                            .map(tokens_to_paragraph_segment)
-                            .collect()
-                    }),
+                    name,
+                    parameters,
                     content: content.unwrap_or(vec![]),
                 },
             )
@@ -309,7 +467,13 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
         let parse_char = select! { Special(x) if x == c => x };
 
         parse_char
-            .ignore_then(newlines_whitespace_or_eof.not().repeated().at_least(1))
+            .ignore_then(
+                newlines_whitespace_or_eof
+                    .not()
+                    .map_with_span(with_span)
+                    .repeated()
+                    .at_least(1),
+            )
             .then(
                 whitespace
                     .repeated()
@@ -317,25 +481,29 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
                     .ignore_then(parameters)
                     .or_not(),
             )
-            .then_ignore(select! {
-                SingleNewline => (),
-                Newlines(_) => (),
-            })
+            .then_ignore(
+                select! {
+                    SingleNewline => (),
+                    Newlines(_) => (),
+                }
+                .rewind(),
+            )
             .map(move |(name, parameters)| NorgBlock::RangedTag {
                 tag_type: c,
-                name: tokens_to_paragraph_segment(name),
-                parameters: parameters.map(|tokens| {
-                    tokens
-                        .into_iter()
-                        .map(tokens_to_paragraph_segment)
-                        .collect()
-                }),
+                name,
+                parameters,
             })
     };
 
     let infirm_tag = {
         select! { Special('.') => '.' }
-            .ignore_then(newlines_whitespace_or_eof.not().repeated().at_least(1))
+            .ignore_then(
+                newlines_whitespace_or_eof
+                    .not()
+                    .map_with_span(with_span)
+                    .repeated()
+                    .at_least(1),
+            )
             .then(
                 whitespace
                     .repeated()
@@ -343,19 +511,14 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
                     .ignore_then(parameters)
                     .or_not(),
             )
-            .then_ignore(select! {
-                SingleNewline => (),
-                Newlines(_) => (),
-            })
-            .map(|(name, parameters)| NorgBlock::InfirmTag {
-                name: tokens_to_paragraph_segment(name),
-                parameters: parameters.map(|tokens| {
-                    tokens
-                        .into_iter()
-                        .map(tokens_to_paragraph_segment)
-                        .collect()
-                }),
-            })
+            .then_ignore(
+                select! {
+                    SingleNewline => (),
+                    Newlines(_) => (),
+                }
+                .rewind(),
+            )
+            .map(|(name, parameters)| NorgBlock::InfirmTag { name, parameters })
     };
 
     let carryover_tags = {
@@ -363,7 +526,13 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
             Special('+') => '+',
             Special('#') => '#',
         }
-        .then(newlines_whitespace_or_eof.not().repeated().at_least(1))
+        .then(
+            newlines_whitespace_or_eof
+                .not()
+                .map_with_span(with_span)
+                .repeated()
+                .at_least(1),
+        )
         .then(
             whitespace
                 .repeated()
@@ -371,19 +540,17 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
                 .ignore_then(parameters)
                 .or_not(),
         )
-        .then_ignore(select! {
-        Newlines(_) => (),
-        SingleNewline => (),
-        })
+        .then_ignore(
+            select! {
+                Newlines(_) => (),
+                SingleNewline => (),
+            }
+            .rewind(),
+        )
         .map(|((tag_type, name), parameters)| NorgBlock::CarryoverTag {
             tag_type,
-            name: tokens_to_paragraph_segment(name),
-            parameters: parameters.map(|tokens| {
-                tokens
-                    .into_iter()
-                    .map(tokens_to_paragraph_segment)
-                    .collect()
-            }),
+            name,
+            parameters,
         })
     };
 
@@ -396,7 +563,7 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
     }
     .repeated()
     .at_least(2)
-    .then_ignore(newlines_or_eof)
+    .then_ignore(newlines_or_eof.rewind())
     .map(|chars| NorgBlock::DelimitingModifier(chars[0]));
 
     choice((
@@ -416,22 +583,30 @@ pub fn stage_2() -> impl Parser<NorgToken, Vec<NorgBlock>, Error = chumsky::erro
         carryover_tags,
         tag_end,
         paragraph_segment
-            .then(newlines_or_eof.repeated().at_least(1).rewind())
-            .map(|(content, trailing)| match trailing.last().unwrap() {
-                NorgToken::Eof => {
-                    NorgBlock::ParagraphSegmentEnd(tokens_to_paragraph_segment(content))
-                }
-                NorgToken::Newlines(_) => {
-                    NorgBlock::ParagraphSegmentEnd(tokens_to_paragraph_segment(content))
+            .then(
+                newlines_or_eof
+                    .map_with_span(with_span)
+                    .repeated()
+                    .at_least(1)
+                    .rewind(),
+            )
+            .map(|(content, trailing)| match trailing.last().unwrap().0 {
+                NorgToken::Eof => NorgBlock::ParagraphSegmentEnd(content),
+                NorgToken::Newlines(_) => NorgBlock::ParagraphSegmentEnd(content),
+                NorgToken::SingleNewline => {
+                    NorgBlock::ParagraphSegment(content.into_iter().chain(trailing).collect())
                 }
-                NorgToken::SingleNewline => NorgBlock::ParagraphSegment(
-                    tokens_to_paragraph_segment(content.into_iter().chain(trailing).collect()),
-                ),
                 _ => unreachable!(),
             })
             .labelled("paragraph_segment"),
     ))
+    .map_with_span(with_span)
+    // Blocks only look at the newlines which end them, so that these are left out of their
+    // spans and skipped here instead.
     .padded_by(newlines_whitespace.repeated())
     .repeated()
+    // Only reached for documents which consist solely of whitespace.
+    .then_ignore(newlines_whitespace.repeated())
     .then_ignore(just(Eof))
+    .map(split_trailing_modifiers)
 }
@@ -5,7 +5,12 @@ use itertools::Itertools;
 use serde::Serialize;
 use textwrap::dedent;
 
-use crate::stage_2::{NorgBlock, ParagraphSegmentToken, ParagraphTokenList};
+use crate::span::{spanned_stream, with_span, Span};
+use crate::stage_1::stage_1_between;
+use crate::stage_2::{
+    tokens_to_paragraph_segment, NorgBlock, ParagraphSegmentToken, ParagraphTokenList, TokenList,
+};
+use crate::timestamp::NorgTimestamp;
 
 #[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
 pub enum NestableDetachedModifier {
@@ -41,6 +46,15 @@ impl std::fmt::Display for RangeableDetachedModifier {
     }
 }
 
+/// Marks a detached modifier whose content continues past its own paragraph.
+#[derive(Clone, Copy, Hash, Debug, PartialEq, Eq, Serialize)]
+pub enum ContentSegment {
+    /// A trailing ` :`, making the next element part of the modifier.
+    Slide,
+    /// A trailing ` ::`, making everything up to a closing `---` part of the modifier.
+    IndentSegment,
+}
+
 #[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
 pub enum TodoStatus {
     /// ` `
@@ -54,7 +68,7 @@ pub enum TodoStatus {
     /// `!`
     Urgent,
     /// `+` or `+ 4th may`
-    Recurring(Option<String>),
+    Recurring(Option<NorgTimestamp>),
     /// `-`
     Pending,
     /// `_`
@@ -81,15 +95,15 @@ pub enum DetachedModifierExtension {
 
     /// Time stamp extension:
     /// `- (@ <some time>) list item text`
-    Timestamp(String),
+    Timestamp(NorgTimestamp),
 
     /// Time stamp for the due date/deadline for this item
     /// `- (< 1 Jan 2025) Do something`
-    DueDate(String),
+    DueDate(NorgTimestamp),
 
     /// Time stamp for the start time of the item:
     /// `- (> 2 Jan 2025)` Start something
-    StartDate(String),
+    StartDate(NorgTimestamp),
 }
 
 #[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
@@ -104,24 +118,196 @@ pub enum RangedTag {
     Standard,
 }
 
-fn paragraph_parser_opener_candidates_and_links() -> impl Parser<
+/// The attached modifiers whose content may contain further markup: bold, italic, underline,
+/// strikethrough, spoiler, superscript, subscript and null. The verbatim ones (`` ` ``, `$` and
+/// `&`) are parsed separately.
+const ATTACHED_MODIFIERS: &str = "*/_-!^,%";
+
+/// Turns the content of a verbatim segment into a single text token holding the content exactly
+/// as it is written in the source, backslashes and all.
+fn raw_text(source: &str, content: &[(ParagraphSegmentToken, Span)]) -> (String, Span) {
+    let span = content[0].1.union(content[content.len() - 1].1);
+
+    (source[span.range()].to_string(), span)
+}
+
+/// Parses a verbatim attached modifier like inline math (`$`) or a variable (`&`). The content
+/// is kept as written, without looking for any nested markup.
+fn verbatim_attached_modifier(
+    source: &str,
+    modifier_type: char,
+) -> impl Parser<
     ParagraphSegmentToken,
-    Vec<ParagraphSegment>,
-    Error = chumsky::error::Simple<ParagraphSegmentToken>,
-> {
-    let token = any().map(ParagraphSegment::Token);
+    ParagraphSegment,
+    Error = chumsky::error::Simple<ParagraphSegmentToken, Span>,
+> + Clone
+       + '_ {
+    let delimiter = just(ParagraphSegmentToken::Special(modifier_type));
+    let whitespace = just(ParagraphSegmentToken::Whitespace);
+
+    // Like any other closing modifier, the closing delimiter has to be followed by whitespace,
+    // punctuation or the end of the paragraph.
+    let closer = delimiter.clone().then(choice((
+        select! {
+            ParagraphSegmentToken::Whitespace => (),
+            ParagraphSegmentToken::Special(_) => (),
+        }
+        .rewind(),
+        end(),
+    )));
+
+    let word = whitespace.clone().not().map_with_span(with_span).chain(
+        choice((whitespace.clone().ignored(), closer.clone().ignored()))
+            .not()
+            .map_with_span(with_span)
+            .repeated(),
+    );
+
+    let content = word
+        .clone()
+        .chain::<(ParagraphSegmentToken, Span), Vec<_>, _>(
+            whitespace
+                .map_with_span(with_span)
+                .repeated()
+                .at_least(1)
+                .chain::<(ParagraphSegmentToken, Span), Vec<_>, _>(word)
+                .repeated()
+                .flatten(),
+        );
+
+    // Link modifiers on either side, as in `e:$=mc^2$:`, become part of the modifier.
+    let link_modifier = just(ParagraphSegmentToken::Special(':')).or_not();
+
+    link_modifier
+        .clone()
+        .ignore_then(delimiter)
+        .ignore_then(content)
+        .then_ignore(closer)
+        .then_ignore(link_modifier)
+        .map_with_span(move |content, span| {
+            let (text, text_span) = raw_text(source, &content);
+
+            ParagraphSegment::AttachedModifier {
+                modifier_type,
+                content: vec![ParagraphSegment::Token {
+                    token: ParagraphSegmentToken::Text(text),
+                    span: text_span,
+                }],
+                span,
+            }
+        })
+}
+
+/// Parses a free-form attached modifier like `*| text |*`, whose content is kept verbatim,
+/// including any whitespace at its start and end.
+fn free_form_attached_modifier(
+    source: &str,
+) -> impl Parser<
+    ParagraphSegmentToken,
+    ParagraphSegment,
+    Error = chumsky::error::Simple<ParagraphSegmentToken, Span>,
+> + Clone
+       + '_ {
+    let pipe = just(ParagraphSegmentToken::Special('|'));
+    let link_modifier = just(ParagraphSegmentToken::Special(':')).or_not();
     let modifier = select! {
-        ParagraphSegmentToken::Special(c @ ('*' | '/' | '_' | '-')) => c,
+        ParagraphSegmentToken::Special(c) if ATTACHED_MODIFIERS.contains(c) || "`$&".contains(c) => c,
     };
 
+    link_modifier
+        .clone()
+        .ignore_then(modifier)
+        .then_ignore(pipe.clone())
+        .then_with(move |modifier_type| {
+            let closer = pipe
+                .clone()
+                .then(just(ParagraphSegmentToken::Special(modifier_type)));
+
+            closer
+                .clone()
+                .not()
+                .map_with_span(with_span)
+                .repeated()
+                .at_least(1)
+                .then_ignore(closer)
+                .map(move |content| (modifier_type, content))
+        })
+        .then_ignore(link_modifier)
+        .map_with_span(move |(modifier_type, content), span| {
+            let (text, text_span) = raw_text(source, &content);
+
+            ParagraphSegment::FreeFormAttachedModifier {
+                modifier_type,
+                content: vec![ParagraphSegment::Token {
+                    token: ParagraphSegmentToken::Text(text),
+                    span: text_span,
+                }],
+                span,
+            }
+        })
+}
+
+fn paragraph_parser_opener_candidates_and_links(
+    source: &str,
+) -> impl Parser<
+    ParagraphSegmentToken,
+    Vec<ParagraphSegment>,
+    Error = chumsky::error::Simple<ParagraphSegmentToken, Span>,
+> + '_ {
+    let token = any().map_with_span(|token, span| ParagraphSegment::Token { token, span });
+    let modifier = select! {
+        ParagraphSegmentToken::Special(c) if ATTACHED_MODIFIERS.contains(c) => c,
+    }
+    .map_with_span(with_span);
+
+    let free_form_modifier = free_form_attached_modifier(source);
+
+    let verbatim_modifier = choice((
+        verbatim_attached_modifier(source, '$'),
+        verbatim_attached_modifier(source, '&'),
+    ));
+
+    // A verbatim modifier cannot start in the middle of a word, so the delimiter is consumed
+    // together with the word in front of it.
+    let word_and_verbatim_delimiter = select! {
+        t @ ParagraphSegmentToken::Text(_) => t,
+        t @ ParagraphSegmentToken::Escape(_) => t,
+    }
+    .map_with_span(|token, span| ParagraphSegment::Token { token, span })
+    .chain(
+        select! {
+            t @ ParagraphSegmentToken::Special('$' | '&') => t,
+        }
+        .map_with_span(|token, span| ParagraphSegment::Token { token, span }),
+    );
+
     let whitespace_or_special = select! {
         w @ ParagraphSegmentToken::Whitespace => w,
         s @ ParagraphSegmentToken::Special(_) => s,
-    };
+    }
+    .map_with_span(with_span);
+
+    // Tokens which start an inline object (anchor, link, linkable or verbatim) are left in the
+    // stream so that the object can still be parsed inside of the attached modifier.
+    let opening_modifier_right = choice((
+        select! {
+            ParagraphSegmentToken::Special('[' | '{' | '<' | '`' | '$' | '&') => (),
+        }
+        .rewind()
+        .to(None),
+        // `*|` starts a free-form modifier instead.
+        choice((
+            just(ParagraphSegmentToken::Whitespace),
+            just(ParagraphSegmentToken::Special('|')),
+        ))
+        .not()
+        .map_with_span(with_span)
+        .map(Some),
+    ));
 
     let opening_modifier_candidate = whitespace_or_special
         .then(modifier.repeated().at_least(1))
-        .then(just(ParagraphSegmentToken::Whitespace).not())
+        .then(opening_modifier_right.clone())
         .map(|((left, modifiers), right)| {
             ParagraphSegment::AttachedModifierOpener((Some(left), modifiers, right))
         });
@@ -129,7 +315,7 @@ fn paragraph_parser_opener_candidates_and_links() -> impl Parser<
     let left_empty_opening_modifier = modifier
         .repeated()
         .at_least(1)
-        .then(just(ParagraphSegmentToken::Whitespace).not())
+        .then(opening_modifier_right)
         .map(|(modifiers, right)| {
             ParagraphSegment::AttachedModifierOpener((None, modifiers, right))
         });
@@ -138,21 +324,67 @@ fn paragraph_parser_opener_candidates_and_links() -> impl Parser<
         .ignore_then(
             just(ParagraphSegmentToken::Special('`'))
                 .not()
+                .map_with_span(with_span)
                 .repeated()
                 .at_least(1),
         )
         .then_ignore(just(ParagraphSegmentToken::Special('`')))
-        .map(ParagraphSegment::InlineVerbatim);
+        .map_with_span(|content, span| ParagraphSegment::InlineVerbatim {
+            content: vec![ParagraphSegmentToken::Text(raw_text(source, &content).0)],
+            span,
+        });
 
     let anchor = just(ParagraphSegmentToken::Special('['))
         .ignore_then(
             just(ParagraphSegmentToken::Special(']'))
                 .not()
+                .map_with_span(with_span)
                 .repeated()
                 .at_least(1),
         )
         .then_ignore(just(ParagraphSegmentToken::Special(']')));
 
+    let link_modifier = choice((
+        just(ParagraphSegmentToken::Special('*'))
+            .repeated()
+            .at_least(1)
+            .map(|tokens| "*".repeat(tokens.len())),
+        just(ParagraphSegmentToken::Special('$')).to("$".to_string()),
+        just(ParagraphSegmentToken::Special('^')).to("^".to_string()),
+        just(ParagraphSegmentToken::Special('/')).to("/".to_string()),
+        just(ParagraphSegmentToken::Special('=')).to("=".to_string()),
+        just(ParagraphSegmentToken::Special('?')).to("?".to_string()),
+        just(ParagraphSegmentToken::Special('@')).to("@".to_string()),
+        just(ParagraphSegmentToken::Special('#')).to("#".to_string()),
+    ))
+    .then_ignore(
+        just(ParagraphSegmentToken::Whitespace)
+            .repeated()
+            .at_least(1),
+    );
+
+    // Scoped links chain several targets with ` : `, e.g. `{* Heading : ** Subheading}`. Every
+    // target after the first one needs a modifier, so that a ` : ` in a URL is left alone.
+    let scope_separator = just(ParagraphSegmentToken::Whitespace)
+        .then(just(ParagraphSegmentToken::Special(':')))
+        .then(just(ParagraphSegmentToken::Whitespace))
+        .then(link_modifier.clone().rewind());
+
+    let target_content = choice((
+        just(ParagraphSegmentToken::Special('}')).ignored(),
+        scope_separator.clone().ignored(),
+    ))
+    .not()
+    .map_with_span(with_span)
+    .repeated()
+    .at_least(1);
+
+    let scoped_target = scope_separator
+        .ignore_then(link_modifier.clone().then(target_content.clone()))
+        .map_with_span(|(modifiers, content), span| {
+            link_target(source, Some(modifiers), content, span)
+        });
+
     let link = just(ParagraphSegmentToken::Special('{'))
         .ignore_then(
             just(ParagraphSegmentToken::Special(':'))
@@ -166,73 +398,28 @@ fn paragraph_parser_opener_candidates_and_links() -> impl Parser<
                 .or_not(),
         )
         .then(
-            choice((
-                just(ParagraphSegmentToken::Special('*'))
-                    .repeated()
-                    .at_least(1)
-                    .map(|tokens| "*".repeat(tokens.len())),
-                just(ParagraphSegmentToken::Special('$')).to("$".to_string()),
-                just(ParagraphSegmentToken::Special('^')).to("^".to_string()),
-                just(ParagraphSegmentToken::Special('/')).to("/".to_string()),
-                just(ParagraphSegmentToken::Special('=')).to("=".to_string()),
-                just(ParagraphSegmentToken::Special('?')).to("?".to_string()),
-                just(ParagraphSegmentToken::Special('@')).to("@".to_string()),
-                just(ParagraphSegmentToken::Special('#')).to("#".to_string()),
-            ))
-            .then_ignore(
-                just(ParagraphSegmentToken::Whitespace)
-                    .repeated()
-                    .at_least(1),
-            )
-            .or_not(),
-        )
-        .then(
-            just(ParagraphSegmentToken::Special('}'))
-                .not()
-                .repeated()
-                .at_least(1)
-                .or_not(),
+            link_modifier
+                .or_not()
+                .then(target_content.or_not())
+                .map_with_span(with_span),
         )
+        .then(scoped_target.repeated())
         .then_ignore(just(ParagraphSegmentToken::Special('}')))
         .then(anchor.clone().or_not())
-        .map(
-            |(((filepath, modifiers), content), description)| ParagraphSegment::Link {
-                filepath: filepath
-                    .map(|content| content.into_iter().map_into::<String>().collect()),
-                description: description.map(|content| parse_paragraph(content).unwrap()),
-                targets: if let Some(content) = content {
-                    vec![if let Some(modifiers) = modifiers {
-                        match modifiers.as_str() {
-                            "$" => LinkTarget::Definition(parse_paragraph(content).unwrap()),
-                            "^" => LinkTarget::Footnote(parse_paragraph(content).unwrap()),
-                            "?" => LinkTarget::Wiki(parse_paragraph(content).unwrap()),
-                            "=" => LinkTarget::Extendable(parse_paragraph(content).unwrap()),
-                            "#" => LinkTarget::Generic(parse_paragraph(content).unwrap()),
-                            "/" => {
-                                LinkTarget::Path(content.into_iter().map_into::<String>().collect())
-                            }
-                            "@" => LinkTarget::Timestamp(
-                                content.into_iter().map_into::<String>().collect(),
-                            ),
-
-                            // Only other possibility is a heading.
-                            str => LinkTarget::Heading {
-                                level: str.len() as u16,
-                                title: parse_paragraph(content).unwrap(),
-                            },
-                        }
-                    } else {
-                        let content_str: String =
-                            content.into_iter().map_into::<String>().collect();
-                        if let Ok(line_number) = content_str.trim().parse::<usize>() {
-                            LinkTarget::LineNumber(line_number)
-                        } else {
-                            LinkTarget::Url(content_str)
-                        }
-                    }]
-                } else {
-                    vec![]
-                },
+        .map_with_span(
+            |(((filepath, ((modifiers, content), target_span)), scopes), description), span| {
+                ParagraphSegment::Link {
+                    filepath: filepath
+                        .map(|content| content.into_iter().map_into::<String>().collect()),
+                    description: description
+                        .map(|content| parse_paragraph(source, content).unwrap()),
+                    targets: content
+                        .map(|content| link_target(source, modifiers, content, target_span))
+                        .into_iter()
+                        .chain(scopes)
+                        .collect(),
+                    span,
+                }
             },
         );
 
@@ -240,37 +427,119 @@ fn paragraph_parser_opener_candidates_and_links() -> impl Parser<
         .ignore_then(
             just(ParagraphSegmentToken::Special('>'))
                 .not()
+                .map_with_span(with_span)
                 .repeated()
                 .at_least(1),
         )
         .then_ignore(just(ParagraphSegmentToken::Special('>')))
-        .map(|content| ParagraphSegment::InlineLinkTarget(parse_paragraph(content).unwrap()));
+        .map_with_span(|content, span| ParagraphSegment::InlineLinkTarget {
+            content: parse_paragraph(source, content).unwrap(),
+            span,
+        });
 
-    left_empty_opening_modifier.or_not().chain(
-        choice((
-            link.clone(),
-            anchor
-                .clone()
-                .then(link)
-                .map(|(content, link)| ParagraphSegment::AnchorDefinition {
-                    content: parse_paragraph(content).unwrap(),
-                    target: Box::new(link),
-                }),
-            inline_verbatim,
-            anchor
-                .clone()
-                .then(anchor.clone().or_not())
-                .map(|(content, description)| ParagraphSegment::Anchor {
-                    content: parse_paragraph(content).unwrap(),
-                    description: description.map(|content| parse_paragraph(content).unwrap()),
-                }),
-            inline_linkable,
-            opening_modifier_candidate,
-            token,
-        ))
-        .repeated()
-        .at_least(1),
-    )
+    choice((free_form_modifier.clone(), left_empty_opening_modifier))
+        .or_not()
+        .chain(
+            choice((
+                word_and_verbatim_delimiter,
+                choice((
+                    link.clone(),
+                    anchor
+                        .clone()
+                        .then(link)
+                        .map_with_span(|(content, link), span| {
+                            ParagraphSegment::AnchorDefinition {
+                                content: parse_paragraph(source, content).unwrap(),
+                                target: Box::new(link),
+                                span,
+                            }
+                        }),
+                    free_form_modifier,
+                    inline_verbatim,
+                    anchor.clone().then(anchor.clone().or_not()).map_with_span(
+                        |(content, description), span| ParagraphSegment::Anchor {
+                            content: parse_paragraph(source, content).unwrap(),
+                            description: description
+                                .map(|content| parse_paragraph(source, content).unwrap()),
+                            span,
+                        },
+                    ),
+                    inline_linkable,
+                    verbatim_modifier,
+                    opening_modifier_candidate,
+                    token,
+                ))
+                .map(|segment| vec![segment]),
+            ))
+            // The opener may already have consumed the entire input, e.g. `*a`.
+            .repeated()
+            .flatten(),
+        )
+}
+
+/// Builds a link target out of the modifier (`*`, `$`, `/`, ...) and the content of a link.
+fn link_target(
+    source: &str,
+    modifiers: Option<String>,
+    content: ParagraphTokenList,
+    span: Span,
+) -> LinkTarget {
+    let stringify = |content: ParagraphTokenList| -> String {
+        content
+            .into_iter()
+            .map(|(token, _)| token.to_string())
+            .collect()
+    };
+
+    if let Some(modifiers) = modifiers {
+        match modifiers.as_str() {
+            "$" => LinkTarget::Definition {
+                title: parse_paragraph(source, content).unwrap(),
+                span,
+            },
+            "^" => LinkTarget::Footnote {
+                title: parse_paragraph(source, content).unwrap(),
+                span,
+            },
+            "?" => LinkTarget::Wiki {
+                title: parse_paragraph(source, content).unwrap(),
+                span,
+            },
+            "=" => LinkTarget::Extendable {
+                title: parse_paragraph(source, content).unwrap(),
+                span,
+            },
+            "#" => LinkTarget::Generic {
+                title: parse_paragraph(source, content).unwrap(),
+                span,
+            },
+            "/" => LinkTarget::Path {
+                path: stringify(content),
+                span,
+            },
+            "@" => LinkTarget::Timestamp {
+                timestamp: NorgTimestamp::parse(stringify(content)),
+                span,
+            },
+
+            // Only other possibility is a heading.
+            str => LinkTarget::Heading {
+                level: str.len() as u16,
+                title: parse_paragraph(source, content).unwrap(),
+                span,
+            },
+        }
+    } else {
+        let content_str = stringify(content);
+        if let Ok(line) = content_str.trim().parse::<usize>() {
+            LinkTarget::LineNumber { line, span }
+        } else {
+            LinkTarget::Url {
+                url: content_str,
+                span,
+            }
+        }
+    }
 }
 
 fn dedup_opener_candidates(input: Vec<ParagraphSegment>) -> Vec<ParagraphSegment> {
@@ -294,15 +563,19 @@ fn paragraph_parser_closer_candidates(
 
     let token = any();
     let modifier = select! {
-        Token(ParagraphSegmentToken::Special(c @ ('*' | '/' | '_' | '-'))) => c,
+        Token { token: ParagraphSegmentToken::Special(c), span } if ATTACHED_MODIFIERS.contains(c) => (c, span),
     };
 
     let whitespace_or_special = select! {
-        w @ Token(ParagraphSegmentToken::Whitespace) => w,
-        s @ Token(ParagraphSegmentToken::Special(_)) => s,
+        w @ Token { token: ParagraphSegmentToken::Whitespace, .. } => w,
+        s @ Token { token: ParagraphSegmentToken::Special(_), .. } => s,
     };
 
-    let closing_modifier_candidate = just(Token(ParagraphSegmentToken::Whitespace))
+    let whitespace = select! {
+        Token { token: ParagraphSegmentToken::Whitespace, .. } => (),
+    };
+
+    let closing_modifier_candidate = whitespace
         .not()
         .then(modifier.repeated().at_least(1))
         .then(whitespace_or_special)
@@ -316,7 +589,7 @@ fn paragraph_parser_closer_candidates(
 
     // TODO(vhyrro): This is not optimal, as it causes a second parse of a potentially long string
     // of nodes. Ideally, the `end()` check should be done directly in a single parse.
-    let closing_modifier_candidate_with_eof = just(Token(ParagraphSegmentToken::Whitespace))
+    let closing_modifier_candidate_with_eof = whitespace
         .not()
         .then(modifier.repeated().at_least(1))
         .then_ignore(end())
@@ -340,19 +613,22 @@ fn unravel_candidates(input: Vec<ParagraphSegment>) -> Vec<ParagraphSegment> {
         .into_iter()
         .fold(Vec::new(), |mut acc: Vec<ParagraphSegment>, segment| {
             match segment {
-                t @ Token(_) => acc.push(t),
+                t @ Token { .. } => acc.push(t),
                 AttachedModifierOpener((left, modifiers, right)) => {
-                    if let Some(left) = left {
-                        acc.push(Token(left));
+                    if let Some((token, span)) = left {
+                        acc.push(Token { token, span });
                     }
-                    acc.extend(modifiers.into_iter().map(|modifier_type| {
+                    acc.extend(modifiers.into_iter().map(|(modifier_type, span)| {
                         AttachedModifierCandidate {
                             modifier_type,
                             content: Vec::default(),
                             closer: None,
+                            span,
                         }
                     }));
-                    acc.push(Token(right));
+                    if let Some((token, span)) = right {
+                        acc.push(Token { token, span });
+                    }
                 }
                 AttachedModifierCloserCandidate((left, modifiers, right)) => {
                     acc.push(*left);
@@ -361,17 +637,21 @@ fn unravel_candidates(input: Vec<ParagraphSegment>) -> Vec<ParagraphSegment> {
                         acc.push(*right);
                     }
                 }
-                AttachedModifierCloser(c) => acc.push(Token(ParagraphSegmentToken::Special(c))),
+                AttachedModifierCloser((c, span)) => acc.push(Token {
+                    token: ParagraphSegmentToken::Special(c),
+                    span,
+                }),
                 AttachedModifierOpenerFail((left, modifiers, right)) => {
-                    if let Some(left) = left {
-                        acc.push(Token(left));
+                    if let Some((token, span)) = left {
+                        acc.push(Token { token, span });
+                    }
+                    acc.extend(modifiers.into_iter().map(|(c, span)| Token {
+                        token: ParagraphSegmentToken::Special(c),
+                        span,
+                    }));
+                    if let Some((token, span)) = right {
+                        acc.push(Token { token, span });
                     }
-                    acc.extend(
-                        modifiers
-                            .into_iter()
-                            .map(|c| Token(ParagraphSegmentToken::Special(c))),
-                    );
-                    acc.push(Token(right));
                 }
                 others => acc.push(others),
             };
@@ -383,27 +663,52 @@ fn unravel_candidates(input: Vec<ParagraphSegment>) -> Vec<ParagraphSegment> {
 fn paragraph_rollup_candidates(
 ) -> impl Parser<ParagraphSegment, Vec<ParagraphSegment>, Error = chumsky::error::Simple<ParagraphSegment>>
 {
-    let candidate = select! { ParagraphSegment::AttachedModifierCloser(c) => c, };
+    // A link modifier is a `:` which lets an attached modifier start or end within a word, as in
+    // `bold:*ness*:`. The candidate passes accept it like any other punctuation, and it becomes
+    // part of the modifier once the modifier is closed.
+    let link_modifier = select! {
+        ParagraphSegment::Token { token: ParagraphSegmentToken::Special(':'), span } => span,
+    };
 
     let attached_modifier = recursive(|attached_modifier| {
-        select! {
-            ParagraphSegment::AttachedModifierCandidate { modifier_type, .. } => modifier_type,
-        }
-        .then(attached_modifier.or(candidate.not()).repeated().at_least(1))
-        .then(candidate)
-        .try_map(|((modifier_type, content), closer), span| {
-            if modifier_type == closer {
-                Ok(ParagraphSegment::AttachedModifier {
-                    modifier_type,
-                    content,
+        // Closers of other modifiers are just part of the content, e.g. the comma in `*a, b*`.
+        choice(
+            ATTACHED_MODIFIERS
+                .chars()
+                .map(|modifier_type| {
+                    let opener = select! {
+                        ParagraphSegment::AttachedModifierCandidate { modifier_type: c, span, .. } if c == modifier_type => span,
+                    };
+                    let closer = select! {
+                        ParagraphSegment::AttachedModifierCloser((c, span)) if c == modifier_type => span,
+                    };
+
+                    link_modifier
+                        .or_not()
+                        .then(opener)
+                        .map(|(link, span)| link.map_or(span, |link| link.union(span)))
+                        .then(
+                            attached_modifier
+                                .clone()
+                                .or(closer.not())
+                                .repeated()
+                                .at_least(1),
+                        )
+                        .then(
+                            closer
+                                .then(link_modifier.or_not())
+                                .map(|(span, link)| link.map_or(span, |link| span.union(link))),
+                        )
+                        .map(move |((opener_span, content), closer_span)| {
+                            ParagraphSegment::AttachedModifier {
+                                modifier_type,
+                                content: eliminate_invalid_candidates(unravel_candidates(content)),
+                                span: opener_span.union(closer_span),
+                            }
+                        })
                 })
-            } else {
-                Err(Simple::custom(
-                    span,
-                    "differing opening and closing modifiers found",
-                ))
-            }
-        })
+                .collect::<Vec<_>>(),
+        )
     });
 
     choice((attached_modifier, any())).repeated().at_least(1)
@@ -418,10 +723,12 @@ fn eliminate_invalid_candidates(input: Vec<ParagraphSegment>) -> Vec<ParagraphSe
                     modifier_type,
                     content,
                     closer,
+                    span,
                 } => {
-                    acc.push(ParagraphSegment::Token(ParagraphSegmentToken::Special(
-                        modifier_type,
-                    )));
+                    acc.push(ParagraphSegment::Token {
+                        token: ParagraphSegmentToken::Special(modifier_type),
+                        span,
+                    });
                     acc.extend(content);
 
                     if let Some(closer) = closer {
@@ -440,78 +747,286 @@ pub enum LinkTarget {
     Heading {
         level: u16,
         title: Vec<ParagraphSegment>,
+        span: Span,
+    },
+    Footnote {
+        title: Vec<ParagraphSegment>,
+        span: Span,
+    },
+    Definition {
+        title: Vec<ParagraphSegment>,
+        span: Span,
+    },
+    Generic {
+        title: Vec<ParagraphSegment>,
+        span: Span,
     },
-    Footnote(Vec<ParagraphSegment>),
-    Definition(Vec<ParagraphSegment>),
-    Generic(Vec<ParagraphSegment>),
-    Wiki(Vec<ParagraphSegment>),
-    Extendable(Vec<ParagraphSegment>),
-    LineNumber(usize),
-    Path(String),
-    Url(String),
-    Timestamp(String),
+    Wiki {
+        title: Vec<ParagraphSegment>,
+        span: Span,
+    },
+    Extendable {
+        title: Vec<ParagraphSegment>,
+        span: Span,
+    },
+    LineNumber {
+        line: usize,
+        span: Span,
+    },
+    Path {
+        path: String,
+        span: Span,
+    },
+    Url {
+        url: String,
+        span: Span,
+    },
+    Timestamp {
+        timestamp: NorgTimestamp,
+        span: Span,
+    },
+}
+
+impl LinkTarget {
+    /// Returns the location of the target (the part of the link after any file path).
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Heading { span, .. }
+            | Self::Footnote { span, .. }
+            | Self::Definition { span, .. }
+            | Self::Generic { span, .. }
+            | Self::Wiki { span, .. }
+            | Self::Extendable { span, .. }
+            | Self::LineNumber { span, .. }
+            | Self::Path { span, .. }
+            | Self::Url { span, .. }
+            | Self::Timestamp { span, .. } => *span,
+        }
+    }
+
+    /// Calls `f` on every span stored in this target, including the ones of its title.
+    pub(crate) fn spans_mut(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Self::Heading { title, span, .. }
+            | Self::Footnote { title, span }
+            | Self::Definition { title, span }
+            | Self::Generic { title, span }
+            | Self::Wiki { title, span }
+            | Self::Extendable { title, span } => {
+                segments_spans_mut(title, f);
+                f(span);
+            }
+            Self::LineNumber { span, .. }
+            | Self::Path { span, .. }
+            | Self::Url { span, .. }
+            | Self::Timestamp { span, .. } => f(span),
+        }
+    }
 }
 
+/// A paragraph token paired with its location, as stored in the intermediate candidate segments.
+pub type SpannedToken = (ParagraphSegmentToken, Span);
+
+/// A run of attached modifier characters, each paired with its location.
+pub type SpannedModifiers = Vec<(char, Span)>;
+
 #[derive(Debug, Clone, PartialEq, Serialize, Hash, Eq)]
 pub enum ParagraphSegment {
-    Token(ParagraphSegmentToken),
-    AttachedModifierOpener(
-        (
-            Option<ParagraphSegmentToken>,
-            Vec<char>,
-            ParagraphSegmentToken,
-        ),
-    ),
-    AttachedModifierOpenerFail(
-        (
-            Option<ParagraphSegmentToken>,
-            Vec<char>,
-            ParagraphSegmentToken,
-        ),
-    ),
+    Token {
+        token: ParagraphSegmentToken,
+        span: Span,
+    },
+    AttachedModifierOpener((Option<SpannedToken>, SpannedModifiers, Option<SpannedToken>)),
+    AttachedModifierOpenerFail((Option<SpannedToken>, SpannedModifiers, Option<SpannedToken>)),
     AttachedModifierCloserCandidate(
         (
             Box<ParagraphSegment>,
-            Vec<char>,
+            SpannedModifiers,
             Option<Box<ParagraphSegment>>,
         ),
     ),
-    AttachedModifierCloser(char),
+    AttachedModifierCloser((char, Span)),
     AttachedModifierCandidate {
         modifier_type: char,
         content: Vec<Self>,
         closer: Option<Box<Self>>,
+        span: Span,
     },
+    /// An attached modifier like `*bold*`. The content of inline math (`$`) and variables (`&`)
+    /// is a single text token holding the text exactly as written.
     AttachedModifier {
         modifier_type: char,
         content: Vec<Self>,
+        span: Span,
+    },
+    /// A free-form attached modifier like `*| text |*`. The content is a single text token
+    /// holding the text exactly as written, including the whitespace at its start and end.
+    FreeFormAttachedModifier {
+        modifier_type: char,
+        content: Vec<Self>,
+        span: Span,
     },
     Link {
         filepath: Option<String>,
         targets: Vec<LinkTarget>,
         description: Option<Vec<ParagraphSegment>>,
+        span: Span,
     },
     AnchorDefinition {
         content: Vec<ParagraphSegment>,
         target: Box<Self>,
+        span: Span,
     },
     Anchor {
         content: Vec<ParagraphSegment>,
         description: Option<Vec<ParagraphSegment>>,
+        span: Span,
     },
-    InlineLinkTarget(Vec<ParagraphSegment>),
-    InlineVerbatim(Vec<ParagraphSegmentToken>),
+    InlineLinkTarget {
+        content: Vec<ParagraphSegment>,
+        span: Span,
+    },
+    /// Inline verbatim like `` `code` ``, whose content is a single text token holding the text
+    /// exactly as written.
+    InlineVerbatim {
+        content: Vec<ParagraphSegmentToken>,
+        span: Span,
+    },
+}
+
+impl ParagraphSegment {
+    /// Returns the location of this segment in the input.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Token { span, .. }
+            | Self::AttachedModifierCloser((_, span))
+            | Self::AttachedModifierCandidate { span, .. }
+            | Self::AttachedModifier { span, .. }
+            | Self::FreeFormAttachedModifier { span, .. }
+            | Self::Link { span, .. }
+            | Self::AnchorDefinition { span, .. }
+            | Self::Anchor { span, .. }
+            | Self::InlineLinkTarget { span, .. }
+            | Self::InlineVerbatim { span, .. } => *span,
+            Self::AttachedModifierOpener((left, modifiers, right))
+            | Self::AttachedModifierOpenerFail((left, modifiers, right)) => left
+                .iter()
+                .map(|(_, span)| *span)
+                .chain(modifiers.iter().map(|(_, span)| *span))
+                .chain(right.iter().map(|(_, span)| *span))
+                .reduce(Span::union)
+                .unwrap_or_default(),
+            Self::AttachedModifierCloserCandidate((left, modifiers, right)) => modifiers
+                .iter()
+                .map(|(_, span)| *span)
+                .chain(right.iter().map(|right| right.span()))
+                .fold(left.span(), Span::union),
+        }
+    }
+
+    /// Calls `f` on every span stored in this segment and the segments nested within it.
+    pub(crate) fn spans_mut(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Self::Token { span, .. }
+            | Self::AttachedModifierCloser((_, span))
+            | Self::InlineVerbatim { span, .. } => f(span),
+            Self::AttachedModifierOpener((left, modifiers, right))
+            | Self::AttachedModifierOpenerFail((left, modifiers, right)) => {
+                left.iter_mut()
+                    .map(|(_, span)| span)
+                    .chain(modifiers.iter_mut().map(|(_, span)| span))
+                    .chain(right.iter_mut().map(|(_, span)| span))
+                    .for_each(f);
+            }
+            Self::AttachedModifierCloserCandidate((left, modifiers, right)) => {
+                left.spans_mut(f);
+                modifiers.iter_mut().for_each(|(_, span)| f(span));
+                if let Some(right) = right {
+                    right.spans_mut(f);
+                }
+            }
+            Self::AttachedModifierCandidate {
+                content,
+                closer,
+                span,
+                ..
+            } => {
+                segments_spans_mut(content, f);
+                if let Some(closer) = closer {
+                    closer.spans_mut(f);
+                }
+                f(span);
+            }
+            Self::AttachedModifier { content, span, .. }
+            | Self::FreeFormAttachedModifier { content, span, .. }
+            | Self::InlineLinkTarget { content, span } => {
+                segments_spans_mut(content, f);
+                f(span);
+            }
+            Self::Link {
+                targets,
+                description,
+                span,
+                ..
+            } => {
+                targets.iter_mut().for_each(|target| target.spans_mut(f));
+                if let Some(description) = description {
+                    segments_spans_mut(description, f);
+                }
+                f(span);
+            }
+            Self::AnchorDefinition {
+                content,
+                target,
+                span,
+            } => {
+                segments_spans_mut(content, f);
+                target.spans_mut(f);
+                f(span);
+            }
+            Self::Anchor {
+                content,
+                description,
+                span,
+            } => {
+                segments_spans_mut(content, f);
+                if let Some(description) = description {
+                    segments_spans_mut(description, f);
+                }
+                f(span);
+            }
+        }
+    }
+}
+
+fn segments_spans_mut(segments: &mut [ParagraphSegment], f: &mut dyn FnMut(&mut Span)) {
+    segments.iter_mut().for_each(|segment| segment.spans_mut(f));
+}
+
+/// Parses the inline markup within `span`, e.g. the text of a table cell. The span must not
+/// contain any newlines.
+pub(crate) fn parse_inline(input: &str, span: Span) -> Vec<ParagraphSegment> {
+    let mut tokens = stage_1_between(input, span.start, span.end);
+    tokens.pop();
+
+    if tokens.is_empty() {
+        return vec![];
+    }
+
+    parse_paragraph(input, tokens_to_paragraph_segment(tokens)).unwrap_or_default()
 }
 
 fn parse_paragraph(
-    input: Vec<ParagraphSegmentToken>,
-) -> Result<Vec<ParagraphSegment>, Vec<chumsky::error::Simple<ParagraphSegmentToken>>> {
+    source: &str,
+    input: ParagraphTokenList,
+) -> Result<Vec<ParagraphSegment>, Vec<chumsky::error::Simple<ParagraphSegmentToken, Span>>> {
     Ok(eliminate_invalid_candidates(unravel_candidates(
         paragraph_rollup_candidates()
             .parse(unravel_candidates(
                 paragraph_parser_closer_candidates()
                     .parse(unravel_candidates(dedup_opener_candidates(
-                        paragraph_parser_opener_candidates_and_links().parse(input)?,
+                        paragraph_parser_opener_candidates_and_links(source)
+                            .parse(spanned_stream(input))?,
                     )))
                     .unwrap(),
             ))
@@ -521,45 +1036,122 @@ fn parse_paragraph(
 
 #[derive(Clone, Debug, PartialEq, Hash, Eq, Serialize)]
 pub enum NorgASTFlat {
-    Paragraph(Vec<ParagraphSegment>),
+    Paragraph {
+        content: Vec<ParagraphSegment>,
+        span: Span,
+    },
     NestableDetachedModifier {
         modifier_type: NestableDetachedModifier,
         level: u16,
         extensions: Vec<DetachedModifierExtension>,
+        segment: Option<ContentSegment>,
         content: Box<Self>,
+        span: Span,
     },
     RangeableDetachedModifier {
         modifier_type: RangeableDetachedModifier,
         title: Vec<ParagraphSegment>,
         extensions: Vec<DetachedModifierExtension>,
+        segment: Option<ContentSegment>,
         content: Vec<Self>,
+        span: Span,
     },
     Heading {
         level: u16,
         title: Vec<ParagraphSegment>,
         extensions: Vec<DetachedModifierExtension>,
+        span: Span,
     },
     CarryoverTag {
         tag_type: CarryoverTag,
         name: Vec<String>,
         parameters: Vec<String>,
         next_object: Box<Self>,
+        span: Span,
     },
     VerbatimRangedTag {
         name: Vec<String>,
         parameters: Vec<String>,
         content: String,
+        span: Span,
     },
     RangedTag {
         name: Vec<String>,
         parameters: Vec<String>,
         content: Vec<Self>,
+        span: Span,
     },
     InfirmTag {
         name: Vec<String>,
         parameters: Vec<String>,
+        span: Span,
     },
-    DelimitingModifier(DelimitingModifier),
+    DelimitingModifier {
+        modifier_type: DelimitingModifier,
+        span: Span,
+    },
+    /// A region of the input which could not be parsed, see [`crate::parse_recovering`].
+    Error { content: String, span: Span },
+}
+
+impl NorgASTFlat {
+    /// Returns the location of this node in the input.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Paragraph { span, .. }
+            | Self::NestableDetachedModifier { span, .. }
+            | Self::RangeableDetachedModifier { span, .. }
+            | Self::Heading { span, .. }
+            | Self::CarryoverTag { span, .. }
+            | Self::VerbatimRangedTag { span, .. }
+            | Self::RangedTag { span, .. }
+            | Self::InfirmTag { span, .. }
+            | Self::DelimitingModifier { span, .. }
+            | Self::Error { span, .. } => *span,
+        }
+    }
+
+    /// Calls `f` on every span stored in this node and everything nested within it.
+    pub(crate) fn spans_mut(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Self::Paragraph { content, span }
+            | Self::Heading {
+                title: content,
+                span,
+                ..
+            } => {
+                segments_spans_mut(content, f);
+                f(span);
+            }
+            Self::NestableDetachedModifier { content, span, .. }
+            | Self::CarryoverTag {
+                next_object: content,
+                span,
+                ..
+            } => {
+                content.spans_mut(f);
+                f(span);
+            }
+            Self::RangeableDetachedModifier {
+                title,
+                content,
+                span,
+                ..
+            } => {
+                segments_spans_mut(title, f);
+                content.iter_mut().for_each(|node| node.spans_mut(f));
+                f(span);
+            }
+            Self::RangedTag { content, span, .. } => {
+                content.iter_mut().for_each(|node| node.spans_mut(f));
+                f(span);
+            }
+            Self::VerbatimRangedTag { span, .. }
+            | Self::InfirmTag { span, .. }
+            | Self::DelimitingModifier { span, .. }
+            | Self::Error { span, .. } => f(span),
+        }
+    }
 }
 
 #[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
@@ -572,7 +1164,7 @@ pub enum DelimitingModifier {
 fn detached_modifier_extensions() -> impl Parser<
     ParagraphSegmentToken,
     Vec<DetachedModifierExtension>,
-    Error = chumsky::error::Simple<ParagraphSegmentToken>,
+    Error = chumsky::error::Simple<ParagraphSegmentToken, Span>,
 > {
     use ParagraphSegmentToken::*;
 
@@ -596,15 +1188,15 @@ fn detached_modifier_extensions() -> impl Parser<
                 }),
         )
         .map(|(spec, metadata)| match spec {
-            Special('@') => DetachedModifierExtension::Timestamp(metadata),
+            Special('@') => DetachedModifierExtension::Timestamp(NorgTimestamp::parse(metadata)),
             Special('#') => DetachedModifierExtension::Priority(metadata),
-            Special('<') => DetachedModifierExtension::DueDate(metadata),
-            Special('>') => DetachedModifierExtension::StartDate(metadata),
+            Special('<') => DetachedModifierExtension::DueDate(NorgTimestamp::parse(metadata)),
+            Special('>') => DetachedModifierExtension::StartDate(NorgTimestamp::parse(metadata)),
             Special('+') => {
                 DetachedModifierExtension::Todo(TodoStatus::Recurring(if metadata.is_empty() {
                     None
                 } else {
-                    Some(metadata)
+                    Some(NorgTimestamp::parse(metadata))
                 }))
             }
             Special('=') => DetachedModifierExtension::Todo(TodoStatus::Paused),
@@ -624,8 +1216,80 @@ fn detached_modifier_extensions() -> impl Parser<
         .at_least(1)
 }
 
-pub fn stage_3(
-) -> impl Parser<NorgBlock, Vec<NorgASTFlat>, Error = chumsky::error::Simple<NorgBlock>> {
+/// Parses the blocks of `input` into nodes.
+pub fn stage_3<'a>(
+    input: &'a str,
+) -> impl Parser<NorgBlock<'a>, Vec<NorgASTFlat>, Error = chumsky::error::Simple<NorgBlock<'a>, Span>> + 'a
+{
+    block(input).repeated().at_least(1)
+}
+
+/// Runs stage 3 over the blocks, skipping over the ones which cannot be parsed.
+///
+/// Whenever parsing fails, blocks are skipped until a node can be parsed again, and the skipped
+/// blocks are turned into a single [`NorgASTFlat::Error`] node. Every node is returned together
+/// with the errors that caused it.
+pub(crate) fn stage_3_recovering<'a>(
+    input: &'a str,
+    blocks: Vec<(NorgBlock<'a>, Span)>,
+) -> Vec<(NorgASTFlat, Vec<Simple<NorgBlock<'a>, Span>>)> {
+    let spans: Vec<Span> = blocks.iter().map(|(_, span)| *span).collect();
+    let (parsed, errors) = block(input)
+        .recover_with(skip_then_retry_until([]))
+        .repeated()
+        .parse_recovery(spanned_stream(blocks.clone()));
+
+    // Every recovery skips at least one block before the node it ends on and reports a single
+    // error, so the errors line up with the gaps between the parsed nodes.
+    let mut errors = errors.into_iter();
+    let mut nodes = Vec::new();
+    let mut next = 0;
+    let error_node = |blocks: &[Span]| {
+        let span = blocks[0].union(blocks[blocks.len() - 1]);
+        NorgASTFlat::Error {
+            content: input[span.range()].to_string(),
+            span,
+        }
+    };
+
+    for node in parsed.unwrap_or_default() {
+        let span = node.span();
+        let start = next + spans[next..].partition_point(|block| block.start < span.start);
+        if start > next {
+            nodes.push((
+                error_node(&spans[next..start]),
+                errors.next().into_iter().collect(),
+            ));
+        }
+        next = start + spans[start..].partition_point(|block| block.start < span.end);
+        nodes.push((node, vec![]));
+    }
+
+    // Nothing could be parsed after the last node, so its blocks are only parsed once more to
+    // report why.
+    if next < spans.len() {
+        let errors = block(input)
+            .parse(spanned_stream(blocks[next..].to_vec()))
+            .err()
+            .unwrap_or_default();
+        nodes.push((error_node(&spans[next..]), errors));
+    }
+
+    nodes
+}
+
+/// Parses a single top-level node, which may span several blocks.
+fn block<'a>(
+    source: &'a str,
+) -> impl Parser<NorgBlock<'a>, NorgASTFlat, Error = Simple<NorgBlock<'a>, Span>> + Clone + 'a {
+    let parse_extensions = |extension_section: TokenList| {
+        detached_modifier_extensions()
+            .parse(spanned_stream(tokens_to_paragraph_segment(
+                extension_section,
+            )))
+            .unwrap_or_default()
+    };
+
     recursive(|stage_3| {
         let paragraph_segment = select! {
             NorgBlock::ParagraphSegment(content) => content,
@@ -643,36 +1307,52 @@ pub fn stage_3(
                 .chain(paragraph_segment_end.or_not()),
             paragraph_segment_end,
         ))
-            .map(|mut tokens| {
-                // Trim trailing whitespace (both user-induced but also induced by us when
-                // converting single newlines to whitespace).
-                if let Some(ParagraphSegmentToken::Whitespace) = tokens.last() {
-                    tokens.pop();
-                }
+        .map(tokens_to_paragraph_segment)
+        .map_with_span(|mut tokens, span| {
+            // Trim trailing whitespace (both user-induced but also induced by us when
+            // converting single newlines to whitespace).
+            if let Some((ParagraphSegmentToken::Whitespace, _)) = tokens.last() {
+                tokens.pop();
+            }
 
-                NorgASTFlat::Paragraph(parse_paragraph(tokens).unwrap())
-            });
+            NorgASTFlat::Paragraph {
+                content: parse_paragraph(source, tokens).unwrap(),
+                span,
+            }
+        });
+
+        let segment = select! {
+            NorgBlock::Slide => ContentSegment::Slide,
+            NorgBlock::IndentSegment => ContentSegment::IndentSegment,
+        };
 
         let nestable_detached_modifier = select! {
             NorgBlock::NestableDetachedModifier { modifier_type: '-', level, extension_section } => (NestableDetachedModifier::UnorderedList, level, extension_section),
             NorgBlock::NestableDetachedModifier { modifier_type: '~', level, extension_section } => (NestableDetachedModifier::OrderedList, level, extension_section),
             NorgBlock::NestableDetachedModifier { modifier_type: '>', level, extension_section } => (NestableDetachedModifier::Quote, level, extension_section),
-        }.then(paragraph).map(|((modifier_type, level, extension_section), paragraph)| NorgASTFlat::NestableDetachedModifier {
+        }.then(paragraph).then(segment.or_not()).map_with_span(move |(((modifier_type, level, extension_section), paragraph), segment), span| NorgASTFlat::NestableDetachedModifier {
                 modifier_type,
                 level,
-                extensions: detached_modifier_extensions().parse(extension_section).unwrap_or_default(),
+                extensions: parse_extensions(extension_section),
+                segment,
                 content: Box::new(paragraph),
+                span,
             });
 
         let nonranged_detached_modifier = select! {
             NorgBlock::RangeableDetachedModifier { modifier_type: '$', ranged: false, title, extension_section } => (RangeableDetachedModifier::Definition, title, extension_section),
             NorgBlock::RangeableDetachedModifier { modifier_type: '^', ranged: false, title, extension_section} => (RangeableDetachedModifier::Footnote, title, extension_section),
             NorgBlock::RangeableDetachedModifier { modifier_type: ':', ranged: false, title, extension_section } => (RangeableDetachedModifier::Table, title, extension_section),
-        }.then(paragraph).map(|((modifier_type, title, extension_section), paragraph)| NorgASTFlat::RangeableDetachedModifier {
+        }.then(choice((
+            segment.map(|segment| (Some(segment), vec![])),
+            paragraph.map(|paragraph| (None, vec![paragraph])),
+        ))).map_with_span(move |((modifier_type, title, extension_section), (segment, content)), span| NorgASTFlat::RangeableDetachedModifier {
                 modifier_type,
-                title: parse_paragraph(title).unwrap(),
-                extensions: detached_modifier_extensions().parse(extension_section).unwrap_or_default(),
-                content: vec![paragraph],
+                title: parse_paragraph(source, tokens_to_paragraph_segment(title)).unwrap(),
+                extensions: parse_extensions(extension_section),
+                segment,
+                content,
+                span,
             });
 
         let ranged_detached_modifier = select! {
@@ -682,13 +1362,15 @@ pub fn stage_3(
         }
             .then(stage_3.clone().repeated())
             .then(select! { NorgBlock::RangeableDetachedModifierClose(c) => c })
-            .try_map(|(((opening_ch, modifier_type, title, extension_section), content), closing_ch), span|
+            .try_map(move |(((opening_ch, modifier_type, title, extension_section), content), closing_ch), span|
                 if opening_ch == closing_ch {
                     Ok(NorgASTFlat::RangeableDetachedModifier {
                         modifier_type,
-                        title: parse_paragraph(title).unwrap(),
-                        extensions: detached_modifier_extensions().parse(extension_section).unwrap_or_default(),
+                        title: parse_paragraph(source, tokens_to_paragraph_segment(title)).unwrap(),
+                        extensions: parse_extensions(extension_section),
+                        segment: None,
                         content,
+                        span,
                     })
                 } else {
                     Err(Simple::custom(span, format!("Expected '{0}{0}' to close modifier, found '{1}{1}' instead.", opening_ch, closing_ch)))
@@ -697,58 +1379,76 @@ pub fn stage_3(
         let heading = select! {
             NorgBlock::Heading { level, title, extension_section } => (level, title, extension_section),
         }
-        .try_map(move |(level, title, extension_section), _span| Ok(NorgASTFlat::Heading {
+        .try_map(move |(level, title, extension_section), span| Ok(NorgASTFlat::Heading {
             level,
-            title: parse_paragraph(title).unwrap(),
-            extensions: detached_modifier_extensions().parse(extension_section).unwrap_or_default(),
+            title: parse_paragraph(source, tokens_to_paragraph_segment(title)).unwrap(),
+            extensions: parse_extensions(extension_section),
+            span,
         }));
 
-        let stringify_tokens_and_split = move |tokens: ParagraphTokenList| -> Vec<String> {
-            tokens.into_iter().map_into::<String>().collect::<String>().split('.').map_into().collect()
+        let stringify_tokens = |tokens: TokenList| -> String {
+            tokens_to_paragraph_segment(tokens)
+                .into_iter()
+                .map(|(token, _)| token.to_string())
+                .collect()
+        };
+
+        let stringify_tokens_and_split = move |tokens: TokenList| -> Vec<String> {
+            stringify_tokens(tokens).split('.').map_into().collect()
+        };
+
+        let stringify_parameters = move |parameters: Option<Vec<TokenList>>| -> Vec<String> {
+            parameters
+                .unwrap_or_default()
+                .into_iter()
+                .map(stringify_tokens)
+                .collect()
         };
 
         let carryover_tag = select! {
             NorgBlock::CarryoverTag { tag_type: '+', name, parameters } => (CarryoverTag::Attribute, name, parameters),
             NorgBlock::CarryoverTag { tag_type: '#', name, parameters } => (CarryoverTag::Macro, name, parameters),
-        }.then(stage_3.clone()).map(move |((tag_type, name, parameters), next_object)| {
+        }.then(stage_3.clone()).map_with_span(move |((tag_type, name, parameters), next_object), span| {
                 NorgASTFlat::CarryoverTag {
                     tag_type,
                     name: stringify_tokens_and_split(name),
-                    parameters: parameters.unwrap_or_default().into_iter().map(|parameter| parameter.into_iter().map_into::<String>().collect()).collect(),
+                    parameters: stringify_parameters(parameters),
                     next_object: Box::new(next_object),
+                    span,
                 }
             });
 
         let verbatim_ranged_tag = select! {
-            NorgBlock::VerbatimRangedTag { name, parameters, content } => {
+            |span| NorgBlock::VerbatimRangedTag { name, parameters, content } => {
                 NorgASTFlat::VerbatimRangedTag {
                     name: stringify_tokens_and_split(name),
-                    parameters: parameters.unwrap_or_default().into_iter().map(|parameter| parameter.into_iter().map_into::<String>().collect()).collect(),
+                    parameters: stringify_parameters(parameters),
                     content: dedent(content.into_iter().map_into::<String>().collect::<String>().as_str()),
+                    span,
                 }
             },
         };
 
         let ranged_tag = select! {
-            NorgBlock::RangedTag { tag_type: '=', name, parameters } => (RangedTag::Macro, stringify_tokens_and_split(name), parameters.unwrap_or_default().into_iter().map(|parameter| parameter.into_iter().map_into::<String>().collect()).collect()),
-            NorgBlock::RangedTag { tag_type: '|', name, parameters } => (RangedTag::Standard, stringify_tokens_and_split(name), parameters.unwrap_or_default().into_iter().map(|parameter| parameter.into_iter().map_into::<String>().collect()).collect())
+            NorgBlock::RangedTag { tag_type: '=', name, parameters } => (RangedTag::Macro, stringify_tokens_and_split(name), stringify_parameters(parameters)),
+            NorgBlock::RangedTag { tag_type: '|', name, parameters } => (RangedTag::Standard, stringify_tokens_and_split(name), stringify_parameters(parameters))
         }.then(stage_3.repeated()).then(select! {
             NorgBlock::RangedTagEnd('=') => RangedTag::Macro,
             NorgBlock::RangedTagEnd('|') => RangedTag::Standard,
         }).try_map(|(((tag_type, name, parameters), content), closing_tag_type), span| if tag_type == closing_tag_type {
-            Ok(NorgASTFlat::RangedTag { name, parameters, content })
+            Ok(NorgASTFlat::RangedTag { name, parameters, content, span })
         } else {
             Err(Simple::custom(span, "Invalid closing modifier for ranged tag.")) // TODO: Improve errors
         });
 
         let infirm_tag = select! {
-            NorgBlock::InfirmTag { name, parameters, } => NorgASTFlat::InfirmTag { name: stringify_tokens_and_split(name), parameters: parameters.unwrap_or_default().into_iter().map(|parameter| parameter.into_iter().map_into::<String>().collect()).collect() },
+            |span| NorgBlock::InfirmTag { name, parameters, } => NorgASTFlat::InfirmTag { name: stringify_tokens_and_split(name), parameters: stringify_parameters(parameters), span },
         };
 
         let delimiting_mod = select! {
-            NorgBlock::DelimitingModifier('-') => NorgASTFlat::DelimitingModifier(DelimitingModifier::Weak),
-            NorgBlock::DelimitingModifier('=') => NorgASTFlat::DelimitingModifier(DelimitingModifier::Strong),
-            NorgBlock::DelimitingModifier('_') => NorgASTFlat::DelimitingModifier(DelimitingModifier::HorizontalRule),
+            |span| NorgBlock::DelimitingModifier('-') => NorgASTFlat::DelimitingModifier { modifier_type: DelimitingModifier::Weak, span },
+            NorgBlock::DelimitingModifier('=') => NorgASTFlat::DelimitingModifier { modifier_type: DelimitingModifier::Strong, span },
+            NorgBlock::DelimitingModifier('_') => NorgASTFlat::DelimitingModifier { modifier_type: DelimitingModifier::HorizontalRule, span },
         };
 
         choice((
@@ -763,5 +1463,5 @@ pub fn stage_3(
             ranged_detached_modifier,
             paragraph,
         ))
-    }).repeated().at_least(1)
+    })
 }
@@ -1,130 +1,344 @@
 use serde::Serialize;
 
 use crate::{
-    stage_3::{DelimitingModifier, NorgASTFlat, ParagraphSegment},
+    span::Span,
+    stage_3::{ContentSegment, DelimitingModifier, NorgASTFlat, ParagraphSegment},
     CarryoverTag, DetachedModifierExtension, NestableDetachedModifier, RangeableDetachedModifier,
 };
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize)]
 pub enum NorgAST {
-    Paragraph(Vec<ParagraphSegment>),
+    Paragraph {
+        content: Vec<ParagraphSegment>,
+        span: Span,
+    },
     NestableDetachedModifier {
         modifier_type: NestableDetachedModifier,
         level: u16,
         extensions: Vec<DetachedModifierExtension>,
-        text: Box<NorgASTFlat>,
+        segment: Option<ContentSegment>,
+        text: Box<Self>,
         content: Vec<Self>,
+        span: Span,
     },
     RangeableDetachedModifier {
         modifier_type: RangeableDetachedModifier,
         title: Vec<ParagraphSegment>,
         extensions: Vec<DetachedModifierExtension>,
-        content: Vec<NorgASTFlat>,
+        segment: Option<ContentSegment>,
+        content: Vec<Self>,
+        span: Span,
     },
     Heading {
         level: u16,
         title: Vec<ParagraphSegment>,
         extensions: Vec<DetachedModifierExtension>,
         content: Vec<Self>,
+        span: Span,
     },
     CarryoverTag {
         tag_type: CarryoverTag,
         name: Vec<String>,
         parameters: Vec<String>,
         next_object: Box<NorgAST>,
+        span: Span,
     },
     VerbatimRangedTag {
         name: Vec<String>,
         parameters: Vec<String>,
         content: String,
+        span: Span,
     },
     RangedTag {
         name: Vec<String>,
         parameters: Vec<String>,
-        content: Vec<NorgASTFlat>,
+        content: Vec<Self>,
+        span: Span,
     },
     InfirmTag {
         name: Vec<String>,
         parameters: Vec<String>,
+        span: Span,
+    },
+    DelimitingModifier {
+        modifier_type: DelimitingModifier,
+        span: Span,
+    },
+    Error {
+        content: String,
+        span: Span,
     },
-    DelimitingModifier(DelimitingModifier),
+}
+
+impl NorgAST {
+    /// Returns the location of this node in the input. For headings and nestable detached
+    /// modifiers the span also covers all of the nested content.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Paragraph { span, .. }
+            | Self::NestableDetachedModifier { span, .. }
+            | Self::RangeableDetachedModifier { span, .. }
+            | Self::Heading { span, .. }
+            | Self::CarryoverTag { span, .. }
+            | Self::VerbatimRangedTag { span, .. }
+            | Self::RangedTag { span, .. }
+            | Self::InfirmTag { span, .. }
+            | Self::DelimitingModifier { span, .. }
+            | Self::Error { span, .. } => *span,
+        }
+    }
+
+    /// Calls `f` on every span stored in this node and everything nested within it.
+    #[cfg(test)]
+    pub(crate) fn spans_mut(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Self::Paragraph { content, span } => {
+                content.iter_mut().for_each(|segment| segment.spans_mut(f));
+                f(span);
+            }
+            Self::NestableDetachedModifier {
+                text,
+                content,
+                span,
+                ..
+            } => {
+                text.spans_mut(f);
+                content.iter_mut().for_each(|node| node.spans_mut(f));
+                f(span);
+            }
+            Self::RangeableDetachedModifier {
+                title,
+                content,
+                span,
+                ..
+            }
+            | Self::Heading {
+                title,
+                content,
+                span,
+                ..
+            } => {
+                title.iter_mut().for_each(|segment| segment.spans_mut(f));
+                content.iter_mut().for_each(|node| node.spans_mut(f));
+                f(span);
+            }
+            Self::CarryoverTag {
+                next_object, span, ..
+            } => {
+                next_object.spans_mut(f);
+                f(span);
+            }
+            Self::RangedTag { content, span, .. } => {
+                content.iter_mut().for_each(|node| node.spans_mut(f));
+                f(span);
+            }
+            Self::VerbatimRangedTag { span, .. }
+            | Self::InfirmTag { span, .. }
+            | Self::DelimitingModifier { span, .. }
+            | Self::Error { span, .. } => f(span),
+        }
+    }
+}
+
+/// Extends the span of a node so that it also covers all of its nested content.
+fn span_with_content(span: Span, content: &[NorgAST]) -> Span {
+    content
+        .last()
+        .map(|last| span.union(last.span()))
+        .unwrap_or(span)
 }
 
 fn convert(flat: NorgASTFlat) -> NorgAST {
     match flat {
-        NorgASTFlat::Paragraph(tokens) => NorgAST::Paragraph(tokens),
+        NorgASTFlat::Paragraph { content, span } => NorgAST::Paragraph { content, span },
         NorgASTFlat::RangeableDetachedModifier {
             modifier_type,
             title,
             extensions,
+            segment,
             content,
+            span,
         } => NorgAST::RangeableDetachedModifier {
             modifier_type,
             title,
             extensions,
-            content,
+            segment,
+            content: stage_4(content),
+            span,
         },
         NorgASTFlat::VerbatimRangedTag {
             name,
             parameters,
             content,
+            span,
         } => NorgAST::VerbatimRangedTag {
             name,
             parameters,
             content,
+            span,
         },
         NorgASTFlat::RangedTag {
             name,
             parameters,
             content,
+            span,
         } => NorgAST::RangedTag {
             name,
             parameters,
-            content,
+            content: stage_4(content),
+            span,
         },
-        NorgASTFlat::InfirmTag { name, parameters } => NorgAST::InfirmTag { name, parameters },
-        NorgASTFlat::DelimitingModifier(t) => NorgAST::DelimitingModifier(t),
+        NorgASTFlat::InfirmTag {
+            name,
+            parameters,
+            span,
+        } => NorgAST::InfirmTag {
+            name,
+            parameters,
+            span,
+        },
+        NorgASTFlat::DelimitingModifier {
+            modifier_type,
+            span,
+        } => NorgAST::DelimitingModifier {
+            modifier_type,
+            span,
+        },
+        NorgASTFlat::Error { content, span } => NorgAST::Error { content, span },
         NorgASTFlat::NestableDetachedModifier {
             modifier_type,
             level,
             extensions,
+            segment,
             content,
+            span,
         } => NorgAST::NestableDetachedModifier {
             modifier_type,
             level,
             extensions,
-            text: content,
+            segment,
+            text: Box::new(convert(*content)),
             content: vec![],
+            span,
         },
         NorgASTFlat::Heading {
             level,
             title,
             extensions,
+            span,
         } => NorgAST::Heading {
             level,
             title,
             extensions,
             content: vec![],
+            span,
         },
         NorgASTFlat::CarryoverTag {
             tag_type,
             name,
             parameters,
             next_object,
+            span,
         } => NorgAST::CarryoverTag {
             tag_type,
             name,
             parameters,
-            next_object: Box::new(convert(*next_object.clone())),
+            next_object: Box::new(convert(*next_object)),
+            span,
         },
     }
 }
 
+/// Looks through carryover tags to the node they apply to.
+fn tagged(node: &NorgASTFlat) -> &NorgASTFlat {
+    match node {
+        NorgASTFlat::CarryoverTag { next_object, .. } => tagged(next_object),
+        node => node,
+    }
+}
+
+/// Checks whether `node` ends the indent segment of `owner`: a nestable detached modifier ends at
+/// the next one of the same or a lower level, a rangeable one at the next one of the same type.
+fn closes_indent_segment(owner: &NorgASTFlat, node: &NorgASTFlat) -> bool {
+    match (owner, node) {
+        (
+            NorgASTFlat::NestableDetachedModifier { level, .. },
+            NorgASTFlat::NestableDetachedModifier { level: other, .. },
+        ) => other <= level,
+        (
+            NorgASTFlat::RangeableDetachedModifier { modifier_type, .. },
+            NorgASTFlat::RangeableDetachedModifier {
+                modifier_type: other,
+                ..
+            },
+        ) => other == modifier_type,
+        _ => false,
+    }
+}
+
+/// Returns the index of the last node which belongs to `flat[i]` because of a slide or an indent
+/// segment, or `i` if it has neither.
+fn segment_end(flat: &[NorgASTFlat], i: usize) -> usize {
+    let owner = tagged(&flat[i]);
+    let segment = match owner {
+        NorgASTFlat::NestableDetachedModifier { segment, .. }
+        | NorgASTFlat::RangeableDetachedModifier { segment, .. } => *segment,
+        _ => None,
+    };
+
+    match segment {
+        Some(ContentSegment::Slide) => (i + 1).min(flat.len() - 1),
+        Some(ContentSegment::IndentSegment) => {
+            let mut j = i + 1;
+            while j < flat.len() {
+                match tagged(&flat[j]) {
+                    // The `---` closing the segment belongs to it as well.
+                    NorgASTFlat::DelimitingModifier {
+                        modifier_type: DelimitingModifier::Weak,
+                        ..
+                    } => return j,
+                    NorgASTFlat::DelimitingModifier {
+                        modifier_type: DelimitingModifier::Strong,
+                        ..
+                    }
+                    | NorgASTFlat::Heading { .. } => break,
+                    node if closes_indent_segment(owner, node) => break,
+                    _ => j = segment_end(flat, j) + 1,
+                }
+            }
+
+            j - 1
+        }
+        None => i,
+    }
+}
+
+/// Collects the nodes which a slide or an indent segment makes part of `flat[*i]`.
+///
+/// **Mutates** i to be the index of the last consumed node.
+fn consume_segment_content(flat: &[NorgASTFlat], i: &mut usize) -> Vec<NorgASTFlat> {
+    let end = segment_end(flat, *i);
+    let mut content = flat[(*i + 1)..=end].to_vec();
+    *i = end;
+
+    // The `---` only marks the end of the segment.
+    if let Some(NorgASTFlat::DelimitingModifier {
+        modifier_type: DelimitingModifier::Weak,
+        ..
+    }) = content.last()
+    {
+        content.pop();
+    }
+
+    content
+}
+
 fn consume_heading_content(start_level: &u16, flat: &[NorgASTFlat], i: &mut usize) -> Vec<NorgAST> {
     let mut heading_level = *start_level as i16;
     let mut content = vec![];
     let mut seen = false;
-    for j in (*i + 1)..flat.len() {
+    let mut j = *i + 1;
+    while j < flat.len() {
         match &flat[j] {
             NorgASTFlat::Heading { level, .. } => {
                 if level <= start_level {
@@ -137,7 +351,10 @@ fn consume_heading_content(start_level: &u16, flat: &[NorgASTFlat], i: &mut usiz
                     heading_level = *level as i16;
                 }
             }
-            NorgASTFlat::DelimitingModifier(DelimitingModifier::Weak) => {
+            NorgASTFlat::DelimitingModifier {
+                modifier_type: DelimitingModifier::Weak,
+                ..
+            } => {
                 heading_level -= 1;
                 if heading_level < *start_level as i16 {
                     content = stage_4(flat[(*i + 1)..j].to_vec());
@@ -146,7 +363,10 @@ fn consume_heading_content(start_level: &u16, flat: &[NorgASTFlat], i: &mut usiz
                     break;
                 }
             }
-            NorgASTFlat::DelimitingModifier(DelimitingModifier::Strong) => {
+            NorgASTFlat::DelimitingModifier {
+                modifier_type: DelimitingModifier::Strong,
+                ..
+            } => {
                 content = stage_4(flat[(*i + 1)..j].to_vec());
                 *i = j;
                 seen = true;
@@ -169,8 +389,10 @@ fn consume_heading_content(start_level: &u16, flat: &[NorgASTFlat], i: &mut usiz
                     unreachable!()
                 }
             }
-            _ => {}
+            // Skip over slides and indent segments, whose `---` does not close the heading.
+            _ => j = segment_end(flat, j),
         }
+        j += 1;
     }
     if !seen {
         content = stage_4(flat[*i + 1..].to_vec());
@@ -191,135 +413,117 @@ fn consume_nestable_detached_mod_content(
     flat: &[NorgASTFlat],
     i: &mut usize,
 ) -> Vec<NorgAST> {
-    let mut content = vec![];
-    for j in (*i + 1)..flat.len() {
-        match &flat[j] {
-            NorgASTFlat::NestableDetachedModifier { level, .. } => {
-                if level <= start_level {
-                    content = stage_4(flat[(*i + 1)..j].to_vec());
-                    *i = j - 1;
-                    break;
-                } else if j == flat.len() - 1 {
-                    content = stage_4(flat[(*i + 1)..].to_vec());
-                    *i = j + 1;
-                    break;
-                }
+    let mut j = *i + 1;
+    while let Some(NorgASTFlat::NestableDetachedModifier { level, .. }) = flat.get(j).map(tagged) {
+        if level <= start_level {
+            break;
+        }
+
+        // Deeper modifiers bring the content of their slides and indent segments along.
+        j = segment_end(flat, j) + 1;
+    }
+
+    let content = stage_4(flat[(*i + 1)..j].to_vec());
+    *i = j - 1;
+
+    content
+}
+
+/// Converts `node`, which is `flat[*i]` or the node a carryover tag at `flat[*i]` applies to,
+/// together with all of the nodes after it which make up its content.
+///
+/// **Mutates** i to be the index of the last consumed node.
+fn convert_with_content(node: &NorgASTFlat, flat: &[NorgASTFlat], i: &mut usize) -> NorgAST {
+    match node {
+        NorgASTFlat::Heading {
+            level,
+            title,
+            extensions,
+            span,
+        } => {
+            let content = consume_heading_content(level, flat, i);
+
+            NorgAST::Heading {
+                level: *level,
+                title: title.to_vec(),
+                extensions: extensions.to_vec(),
+                span: span_with_content(*span, &content),
+                content,
             }
-            NorgASTFlat::CarryoverTag { next_object, .. }
-                if matches!(**next_object, NorgASTFlat::NestableDetachedModifier { .. }) =>
-            {
-                if let NorgASTFlat::NestableDetachedModifier { level, .. } = **next_object {
-                    if level <= *start_level {
-                        content = stage_4(flat[(*i + 1)..j].to_vec());
-                        *i = j - 1;
-                        break;
-                    } else if j == flat.len() - 1 {
-                        content = stage_4(flat[(*i + 1)..].to_vec());
-                        *i = j + 1;
-                        break;
-                    }
-                } else {
-                    unreachable!()
-                }
+        }
+        NorgASTFlat::NestableDetachedModifier {
+            modifier_type,
+            level,
+            extensions,
+            segment,
+            content: text,
+            span,
+        } => {
+            let mut content = stage_4(consume_segment_content(flat, i));
+            content.extend(consume_nestable_detached_mod_content(level, flat, i));
+
+            NorgAST::NestableDetachedModifier {
+                modifier_type: modifier_type.clone(),
+                level: *level,
+                extensions: extensions.to_vec(),
+                segment: *segment,
+                text: Box::new(convert(*text.clone())),
+                span: span_with_content(*span, &content),
+                content,
             }
-            _ => {
-                content = stage_4(flat[(*i + 1)..j].to_vec());
-                *i = j - 1;
-                // stop immediately if we see something that's not a NestableDetachedModifier
-                // of lesser level
-                break;
+        }
+        NorgASTFlat::RangeableDetachedModifier {
+            modifier_type,
+            title,
+            extensions,
+            segment: segment @ Some(_),
+            content,
+            span,
+        } => {
+            let content = stage_4(
+                content
+                    .iter()
+                    .cloned()
+                    .chain(consume_segment_content(flat, i))
+                    .collect(),
+            );
+
+            NorgAST::RangeableDetachedModifier {
+                modifier_type: modifier_type.clone(),
+                title: title.to_vec(),
+                extensions: extensions.to_vec(),
+                segment: *segment,
+                span: content.last().map_or(*span, |last| span.union(last.span())),
+                content,
             }
         }
+        node => convert(node.clone()),
     }
-
-    content
 }
 
 pub fn stage_4(flat: Vec<NorgASTFlat>) -> Vec<NorgAST> {
     let mut ast = vec![];
     let mut i = 0;
     while i < flat.len() {
-        let item = &flat[i];
-        match item {
-            NorgASTFlat::Heading {
-                level: start_level,
-                title,
-                extensions,
-            } => {
-                let content = consume_heading_content(start_level, &flat, &mut i);
-
-                ast.push(NorgAST::Heading {
-                    level: *start_level,
-                    title: title.to_vec(),
-                    extensions: extensions.to_vec(),
-                    content,
-                })
-            }
+        match &flat[i] {
             NorgASTFlat::CarryoverTag {
                 tag_type,
                 name,
                 parameters,
                 next_object,
-            } => match *next_object.clone() {
-                NorgASTFlat::Heading {
-                    level,
-                    title,
-                    extensions,
-                } => {
-                    let content = consume_heading_content(&level, &flat, &mut i);
-                    ast.push(NorgAST::CarryoverTag {
-                        tag_type: tag_type.clone(),
-                        name: name.to_vec(),
-                        parameters: parameters.to_vec(),
-                        next_object: Box::new(NorgAST::Heading {
-                            level,
-                            title,
-                            extensions,
-                            content,
-                        }),
-                    })
-                }
-                NorgASTFlat::NestableDetachedModifier {
-                    modifier_type,
-                    level,
-                    extensions,
-                    content,
-                } => {
-                    let new_content = consume_nestable_detached_mod_content(&level, &flat, &mut i);
-                    ast.push(NorgAST::CarryoverTag {
-                        tag_type: tag_type.clone(),
-                        name: name.to_vec(),
-                        parameters: parameters.to_vec(),
-                        next_object: Box::new(NorgAST::NestableDetachedModifier {
-                            modifier_type,
-                            level,
-                            extensions,
-                            text: content,
-                            content: new_content,
-                        }),
-                    })
-                }
-                _ => ast.push(convert(item.clone())),
-            },
-            NorgASTFlat::NestableDetachedModifier {
-                level: start_level,
-                modifier_type,
-                extensions,
-                content: text,
+                span,
             } => {
-                let content = consume_nestable_detached_mod_content(start_level, &flat, &mut i);
+                let next_object = convert_with_content(next_object, &flat, &mut i);
 
-                ast.push(NorgAST::NestableDetachedModifier {
-                    modifier_type: modifier_type.clone(),
-                    level: *start_level,
-                    extensions: extensions.to_vec(),
-                    text: text.clone(),
-                    content,
-                });
-            }
-            _ => {
-                ast.push(convert(item.clone()));
+                ast.push(NorgAST::CarryoverTag {
+                    tag_type: tag_type.clone(),
+                    name: name.to_vec(),
+                    parameters: parameters.to_vec(),
+                    span: span.union(next_object.span()),
+                    next_object: Box::new(next_object),
+                })
             }
+            item => ast.push(convert_with_content(item, &flat, &mut i)),
         }
 
         i += 1;
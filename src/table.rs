@@ -0,0 +1,390 @@
+//! Tables, gathered from the parsed tree.
+//!
+//! Norg tables are written as table cells (`: A1` or `:: A1 ... ::`) addressed like in a
+//! spreadsheet. All of the cells directly within the same scope (e.g. the document, a heading or a
+//! list item) form one table. `@table` verbatim ranged tags written as
+//! `| a | b |` rows are turned into the same model.
+
+use std::{
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::Serialize;
+
+use crate::{
+    span::{source_end, Position, Span},
+    stage_3::parse_inline,
+    stage_4::NorgAST,
+    text::plain_text,
+    RangeableDetachedModifier,
+};
+
+/// The number of rows or columns up to which [`NorgTable::grid`] keeps empty ones.
+pub const GRID_LIMIT: usize = 256;
+
+/// A table made up of the cells which were written, so that far away addresses like `ZZ9999`
+/// don't take up any more room than `A1`.
+#[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
+pub struct NorgTable {
+    /// The cells of the table, ordered by row and then by column. A cell written several times
+    /// appears only once.
+    pub cells: Vec<TableCell>,
+    pub span: Span,
+}
+
+/// A single cell of a [`NorgTable`].
+#[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
+pub struct TableCell {
+    pub address: CellAddress,
+    /// The content of the cell. When a cell is written several times, the contents are joined.
+    pub content: Vec<NorgAST>,
+    pub span: Span,
+}
+
+/// The zero-based location of a cell, written as `A1` for the top left one.
+#[derive(Clone, Copy, Hash, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct CellAddress {
+    pub column: usize,
+    pub row: usize,
+}
+
+impl CellAddress {
+    /// Parses an address made up of column letters (`A` to `Z`, then `AA` and so on) followed
+    /// by a row number starting from 1, e.g. `B2` or `AA10`.
+    pub fn parse(address: &str) -> Option<Self> {
+        let split = address
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(address.len());
+        let (letters, digits) = address.split_at(split);
+
+        if letters.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let column = letters.bytes().try_fold(0usize, |column, letter| {
+            let value = (letter.to_ascii_uppercase() - b'A') as usize + 1;
+            column.checked_mul(26)?.checked_add(value)
+        })?;
+        let row = digits.parse::<usize>().ok()?.checked_sub(1)?;
+
+        Some(Self {
+            column: column - 1,
+            row,
+        })
+    }
+}
+
+impl fmt::Display for CellAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut letters = Vec::new();
+        let mut column = self.column + 1;
+
+        while column > 0 {
+            letters.push((b'A' + ((column - 1) % 26) as u8) as char);
+            column = (column - 1) / 26;
+        }
+
+        letters.iter().rev().try_for_each(|c| write!(f, "{}", c))?;
+        write!(f, "{}", self.row + 1)
+    }
+}
+
+impl NorgTable {
+    fn from_cells(cells: Vec<TableCell>) -> Option<Self> {
+        let span = cells.iter().map(|cell| cell.span).reduce(Span::union)?;
+
+        let mut merged = BTreeMap::new();
+        for cell in cells {
+            let CellAddress { column, row } = cell.address;
+
+            match merged.entry((row, column)) {
+                Entry::Occupied(mut entry) => {
+                    let TableCell { content, span, .. } = entry.get_mut();
+                    content.extend(cell.content);
+                    *span = span.union(cell.span);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(cell);
+                }
+            }
+        }
+
+        Some(Self {
+            cells: merged.into_values().collect(),
+            span,
+        })
+    }
+
+    /// Builds a table out of sibling table cell nodes, reading the addresses from their titles.
+    /// Cells without a valid address are left out.
+    pub(crate) fn from_nodes<'a>(nodes: impl IntoIterator<Item = &'a NorgAST>) -> Option<Self> {
+        let cells = nodes
+            .into_iter()
+            .filter_map(|node| match node {
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Table,
+                    title,
+                    content,
+                    span,
+                    ..
+                } => Some(TableCell {
+                    address: CellAddress::parse(plain_text(title).trim())?,
+                    content: content.clone(),
+                    span: *span,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Self::from_cells(cells)
+    }
+
+    /// Returns the cell at the given address, if it was written.
+    pub fn cell(&self, address: CellAddress) -> Option<&TableCell> {
+        self.cells
+            .binary_search_by_key(&(address.row, address.column), |cell| {
+                (cell.address.row, cell.address.column)
+            })
+            .ok()
+            .map(|index| &self.cells[index])
+    }
+
+    /// Lays the cells out as rows of the same length, with `None` for the cells which were never
+    /// written. The grid starts at `A1` and reaches up to the last written row and column, so
+    /// empty rows and columns in between are kept. Along a side which would be longer than
+    /// [`GRID_LIMIT`], only the rows or columns holding a written cell are kept instead, so that
+    /// far away addresses don't blow up the size of the grid.
+    pub fn grid(&self) -> Vec<Vec<Option<&TableCell>>> {
+        let rows = Self::lines(self.cells.iter().map(|cell| cell.address.row));
+        let columns = Self::lines(self.cells.iter().map(|cell| cell.address.column));
+        let index = |values: &[usize], value| values.partition_point(|&other| other < value);
+
+        let mut grid = vec![vec![None; columns.len()]; rows.len()];
+        for cell in &self.cells {
+            grid[index(&rows, cell.address.row)][index(&columns, cell.address.column)] = Some(cell);
+        }
+
+        grid
+    }
+
+    /// The rows or columns making up one side of the grid, given the ones of the written cells.
+    fn lines(written: impl Iterator<Item = usize>) -> Vec<usize> {
+        let written: BTreeSet<_> = written.collect();
+
+        match written.last() {
+            Some(&last) if last < GRID_LIMIT => (0..=last).collect(),
+            _ => written.into_iter().collect(),
+        }
+    }
+}
+
+/// Finds every table in the tree, in the order in which they start.
+pub fn tables(tree: &[NorgAST]) -> Vec<NorgTable> {
+    let mut tables = Vec::new();
+    collect_tables(tree, &mut tables);
+    tables.sort_by_key(|table| table.span.start);
+
+    tables
+}
+
+fn collect_tables(scope: &[NorgAST], tables: &mut Vec<NorgTable>) {
+    let mut cells = Vec::new();
+
+    for node in scope {
+        match node {
+            NorgAST::RangeableDetachedModifier {
+                modifier_type: RangeableDetachedModifier::Table,
+                ..
+            } => cells.push(node),
+            NorgAST::Heading { content, .. }
+            | NorgAST::NestableDetachedModifier { content, .. }
+            | NorgAST::RangeableDetachedModifier { content, .. }
+            | NorgAST::RangedTag { content, .. } => collect_tables(content, tables),
+            NorgAST::CarryoverTag { next_object, .. } => {
+                collect_tables(std::slice::from_ref(next_object), tables)
+            }
+            NorgAST::VerbatimRangedTag {
+                name,
+                content,
+                span,
+                ..
+            } if name == &["table"] => tables.extend(pipe_table(content, *span)),
+            _ => {}
+        }
+    }
+
+    tables.extend(NorgTable::from_nodes(cells));
+}
+
+/// Parses the rows of an `@table` verbatim ranged tag. Lines made up of only `|`, `-`, `:`, `+`
+/// and whitespace separate the header from the body and are skipped.
+///
+/// The content of verbatim tags is kept dedented, so it can't be mapped back onto the input:
+/// the cells and everything within them carry the span of the whole tag.
+fn pipe_table(content: &str, span: Span) -> Option<NorgTable> {
+    let mut cells = Vec::new();
+    let mut row = 0;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() || trimmed.chars().all(|c| "|-:+ \t".contains(c)) {
+            continue;
+        }
+
+        let body = trimmed.strip_prefix('|').unwrap_or(trimmed);
+        let body = body.strip_suffix('|').unwrap_or(body);
+
+        for (column, text) in split_cells(body).enumerate() {
+            let text = text.trim();
+            let mut content = parse_inline(text, Span::new(Position::default(), source_end(text)));
+            content
+                .iter_mut()
+                .for_each(|segment| segment.spans_mut(&mut |inner| *inner = span));
+
+            cells.push(TableCell {
+                address: CellAddress { column, row },
+                content: if content.is_empty() {
+                    vec![]
+                } else {
+                    vec![NorgAST::Paragraph { content, span }]
+                },
+                span,
+            });
+        }
+
+        row += 1;
+    }
+
+    let mut table = NorgTable::from_cells(cells)?;
+    table.span = span;
+
+    Some(table)
+}
+
+/// Splits a row of a pipe table on the `|` which aren't escaped with a backslash.
+fn split_cells(row: &str) -> impl Iterator<Item = &str> {
+    let mut escaped = false;
+
+    row.split(move |c| {
+        let separator = c == '|' && !escaped;
+        escaped = c == '\\' && !escaped;
+        separator
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::proptest;
+
+    use crate::{
+        parse_tree,
+        table::{tables, CellAddress},
+    };
+
+    #[test]
+    fn cell_tables() {
+        let examples: Vec<_> = [
+            ": A1
+               Name
+             : B1
+               Age
+             : A2
+               Bob
+             : B2
+               42",
+            "* Heading
+               : B1
+                 Only cell
+               : B1
+                 Written twice
+             ---
+             : A1
+               Outside of the heading",
+            ":: A1
+                Long cell content.
+             ::
+             : Term
+               Not a cell",
+            ": A1
+               First
+             : ZZZZ999999999
+               Far away",
+        ]
+        .into_iter()
+        .map(|example| example.to_string() + "\n")
+        .map(|input| tables(&parse_tree(&input).unwrap()))
+        .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn pipe_tables() {
+        let examples: Vec<_> = [
+            "@table
+             | Name | *Age* |
+             |------|-------|
+             | Bob  | 42    |
+             | Alice |
+             @end",
+            "@table
+             a | b
+             @end",
+            "@table
+             | a \\| b | c |
+             @end",
+        ]
+        .into_iter()
+        .map(|example| example.to_string() + "\n")
+        .map(|input| tables(&parse_tree(&input).unwrap()))
+        .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn grids() {
+        let examples: Vec<_> = [
+            ": A1\n  a\n: C2\n  c\n",
+            ": B1\n  b\n: A3\n  a\n",
+            ": A1\n  a\n: ZZ9999\n  z\n: B2\n  b\n",
+        ]
+        .into_iter()
+        .flat_map(|input| tables(&parse_tree(input).unwrap()))
+        .map(|table| {
+            table
+                .grid()
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .map(|cell| cell.map(|cell| cell.address.to_string()))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    #[test]
+    fn cell_addresses() {
+        let examples: Vec<_> = ["A1", "b2", "Z10", "AA1", "AZ3", "A0", "1A", "A", "A1B"]
+            .into_iter()
+            .map(|address| (address, CellAddress::parse(address)))
+            .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn cell_addresses_round_trip(column in 0..100_000usize, row in 0..100_000usize) {
+            let address = CellAddress { column, row };
+            assert_eq!(CellAddress::parse(&address.to_string()), Some(address));
+        }
+    }
+}
@@ -0,0 +1,984 @@
+//! Rendering a tree as styled text for a terminal, using ANSI escape sequences.
+//!
+//! Attached modifiers become bold, italic, underlined, struck through or reversed text, inline
+//! code, math and variables are coloured, and headings are bold in a colour which depends on
+//! their level. Lists are indented with bullets or numbers, quotes get a bar in front of them
+//! and the todo status of items is shown as a glyph like `☐` or `☑` in place of the bullet.
+//! Other extensions are kept in their Norg form, dimmed. Verbatim ranged tags are drawn in a box
+//! labelled with their name and parameters, and tables made of cells in a grid. Footnotes are
+//! numbered, and `@document.meta` and `|comment` tags are left out.
+//!
+//! Paragraphs are wrapped at [`RenderOptions::width`]. Every line starts and ends its own
+//! styles, so that lines can be printed on their own. Links to URLs are written as OSC 8
+//! hyperlinks, which most terminals let you click, unless [`RenderOptions::hyperlinks`] is
+//! off. Control characters in the document are left out, so that it cannot send escape
+//! sequences of its own.
+
+use std::io::{self, Write};
+
+use textwrap::core::display_width;
+
+use crate::{
+    export::{groups, raw, slug, trim_start, verbatim, TargetKind, Targets},
+    format::extension_text,
+    table::NorgTable,
+    text::plain_text,
+    DelimitingModifier, DetachedModifierExtension, LinkTarget, NestableDetachedModifier, NorgAST,
+    ParagraphSegment, ParagraphSegmentToken, RangeableDetachedModifier, TodoStatus,
+};
+
+/// The colours of headings by level, as SGR codes. Deeper headings use the last one.
+const HEADING_COLORS: [u8; 6] = [35, 34, 36, 32, 33, 31];
+
+/// The bullets of unordered lists, repeating for deeper levels.
+const BULLETS: [&str; 3] = ["•", "◦", "▪"];
+
+const BLUE: u8 = 34;
+const CYAN: u8 = 36;
+const GREEN: u8 = 32;
+const MAGENTA: u8 = 35;
+const RED: u8 = 31;
+const YELLOW: u8 = 33;
+
+/// Options for [`render_with`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// The width paragraphs are wrapped at, including their indentation. Words longer than
+    /// that are never split, and verbatim blocks and tables are as wide as their content.
+    pub width: usize,
+    /// Whether links to URLs are written as OSC 8 hyperlinks.
+    pub hyperlinks: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            hyperlinks: true,
+        }
+    }
+}
+
+/// Renders a tree with the default options.
+pub fn render(tree: &[NorgAST], writer: impl Write) -> io::Result<()> {
+    render_with(tree, writer, &RenderOptions::default())
+}
+
+/// Renders a tree as returned by [`crate::parse_tree`] to `writer`.
+pub fn render_with(
+    tree: &[NorgAST],
+    mut writer: impl Write,
+    options: &RenderOptions,
+) -> io::Result<()> {
+    let mut renderer = Renderer {
+        hyperlinks: options.hyperlinks,
+        targets: Targets::new(tree, |_, text| slug(text)),
+    };
+
+    let lines = separated(renderer.blocks(tree, options.width.max(1)));
+    for line in lines {
+        writeln!(writer, "{line}")?;
+    }
+
+    writer.flush()
+}
+
+/// How a piece of text looks.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Style {
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+    reverse: bool,
+    strikethrough: bool,
+    color: Option<u8>,
+    link: Option<String>,
+}
+
+impl Style {
+    fn colored(color: u8) -> Self {
+        Self {
+            color: Some(color),
+            ..Self::default()
+        }
+    }
+
+    fn dimmed() -> Self {
+        Self {
+            dim: true,
+            ..Self::default()
+        }
+    }
+
+    /// The SGR sequence which switches to this style from any other.
+    fn sgr(&self) -> String {
+        let mut codes = vec!["0".to_string()];
+        let flags = [
+            (self.bold, 1),
+            (self.dim, 2),
+            (self.italic, 3),
+            (self.underline, 4),
+            (self.reverse, 7),
+            (self.strikethrough, 9),
+        ];
+        codes.extend(
+            flags
+                .into_iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, code)| code.to_string()),
+        );
+        codes.extend(self.color.map(|color| color.to_string()));
+
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+/// A piece of inline content, before it is wrapped.
+enum Run {
+    Text(String, Style),
+    /// A line break written with a trailing backslash.
+    Break,
+}
+
+struct Renderer {
+    hyperlinks: bool,
+    targets: Targets,
+}
+
+impl Renderer {
+    /// Renders the nodes of a scope as separate blocks of lines, grouping consecutive list
+    /// items, quotes and table cells.
+    fn blocks(&mut self, nodes: &[NorgAST], width: usize) -> Vec<Vec<String>> {
+        self.groups(nodes, width)
+            .into_iter()
+            .map(|(_, block)| block)
+            .collect()
+    }
+
+    /// Like [`Self::blocks`], also returning the first node of every block.
+    fn groups<'a>(
+        &mut self,
+        nodes: &'a [NorgAST],
+        width: usize,
+    ) -> Vec<(&'a NorgAST, Vec<String>)> {
+        let mut blocks = Vec::new();
+
+        for group in groups(nodes) {
+            let first = &group[0];
+            let block = match first {
+                NorgAST::NestableDetachedModifier { modifier_type, .. } => {
+                    self.list(modifier_type, group, width)
+                }
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Definition,
+                    ..
+                } => self.definitions(group, width),
+                NorgAST::RangeableDetachedModifier {
+                    modifier_type: RangeableDetachedModifier::Table,
+                    ..
+                } => self.table(group),
+                node => self.node(node, width),
+            };
+            if !block.is_empty() {
+                blocks.push((first, block));
+            }
+        }
+
+        blocks
+    }
+
+    fn node(&mut self, node: &NorgAST, width: usize) -> Vec<String> {
+        match node {
+            NorgAST::Paragraph { content, .. } => {
+                self.wrap(&self.inline(content, &Style::default()), width)
+            }
+            NorgAST::Heading {
+                level,
+                title,
+                extensions,
+                content,
+                ..
+            } => {
+                let color = HEADING_COLORS[(*level).clamp(1, 6) as usize - 1];
+                let style = Style {
+                    bold: true,
+                    underline: *level == 1,
+                    ..Style::colored(color)
+                };
+                let mut runs = status(extensions);
+                runs.extend(self.inline(trim_start(title), &style));
+
+                let heading = self.wrap(&runs, width);
+                separated(
+                    std::iter::once(heading)
+                        .chain(self.blocks(content, width))
+                        .collect(),
+                )
+            }
+            NorgAST::RangeableDetachedModifier {
+                modifier_type: RangeableDetachedModifier::Footnote,
+                title,
+                content,
+                ..
+            } => {
+                let number = self.footnote(title).unwrap_or_default();
+                let marker = format!("[{number}]");
+                let width = width.saturating_sub(marker.len() + 1);
+                let content = match self.blocks(content, width) {
+                    blocks if blocks.is_empty() => {
+                        self.wrap(&self.inline(trim_start(title), &Style::default()), width)
+                    }
+                    blocks => separated(blocks),
+                };
+
+                let first = styled(&marker, &Style::colored(CYAN), self.hyperlinks) + " ";
+                indented(content, &first, &" ".repeat(marker.len() + 1))
+            }
+            // Grouped by `groups`.
+            NorgAST::NestableDetachedModifier { .. }
+            | NorgAST::RangeableDetachedModifier { .. } => {
+                separated(self.blocks(std::slice::from_ref(node), width))
+            }
+            NorgAST::CarryoverTag { next_object, .. } => {
+                separated(self.blocks(std::slice::from_ref(next_object), width))
+            }
+            NorgAST::VerbatimRangedTag {
+                name,
+                parameters,
+                content,
+                ..
+            } => match name.join(".").as_str() {
+                "document.meta" => Vec::new(),
+                name => {
+                    let label = std::iter::once(name)
+                        .chain(parameters.iter().map(String::as_str))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    self.boxed(&label, content)
+                }
+            },
+            NorgAST::RangedTag { name, content, .. } => match name == &["comment"] {
+                true => Vec::new(),
+                false => separated(self.blocks(content, width)),
+            },
+            NorgAST::DelimitingModifier {
+                modifier_type: DelimitingModifier::HorizontalRule,
+                ..
+            } => vec![styled(
+                &"─".repeat(width),
+                &Style::dimmed(),
+                self.hyperlinks,
+            )],
+            NorgAST::DelimitingModifier { .. } | NorgAST::InfirmTag { .. } => Vec::new(),
+            NorgAST::Error { content, .. } => {
+                let runs = vec![Run::Text(clean(content.trim()), Style::default())];
+                self.wrap(&runs, width)
+            }
+        }
+    }
+
+    fn list(
+        &mut self,
+        modifier_type: &NestableDetachedModifier,
+        items: &[NorgAST],
+        width: usize,
+    ) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for (i, item) in items.iter().enumerate() {
+            let NorgAST::NestableDetachedModifier {
+                level,
+                extensions,
+                text,
+                content,
+                ..
+            } = item
+            else {
+                continue;
+            };
+            let mut runs = status(extensions);
+
+            // The glyph of a todo item takes the place of the bullet.
+            let (marker, style) = match modifier_type {
+                NestableDetachedModifier::Quote => ("│".to_string(), Style::dimmed()),
+                NestableDetachedModifier::UnorderedList if extensions.iter().any(is_todo) => {
+                    match runs.remove(0) {
+                        Run::Text(glyph, style) => (glyph, style),
+                        Run::Break => unreachable!("the status starts with its glyph"),
+                    }
+                }
+                NestableDetachedModifier::UnorderedList => {
+                    let bullet = BULLETS[(*level as usize).saturating_sub(1) % BULLETS.len()];
+                    (bullet.to_string(), Style::colored(CYAN))
+                }
+                NestableDetachedModifier::OrderedList => {
+                    (format!("{}.", i + 1), Style::colored(CYAN))
+                }
+            };
+            if let NorgAST::Paragraph { content, .. } = text.as_ref() {
+                runs.extend(self.inline(content, &Style::default()));
+            }
+            let marker_width = display_width(&marker);
+            let inner = width.saturating_sub(marker_width + 1);
+
+            let mut item = self.wrap(&runs, inner);
+            // Nested lists stay tight, anything else is separated by a blank line.
+            for (nested, block) in self.groups(content, inner) {
+                if !matches!(
+                    nested,
+                    NorgAST::NestableDetachedModifier {
+                        modifier_type: NestableDetachedModifier::UnorderedList
+                            | NestableDetachedModifier::OrderedList,
+                        ..
+                    }
+                ) {
+                    item.push(String::new());
+                }
+                item.extend(block);
+            }
+
+            let marker = styled(&marker, &style, self.hyperlinks);
+            let rest = match modifier_type {
+                NestableDetachedModifier::Quote => format!("{marker} "),
+                _ => " ".repeat(marker_width + 1),
+            };
+            lines.extend(indented(item, &format!("{marker} "), &rest));
+            if *modifier_type == NestableDetachedModifier::Quote && i + 1 < items.len() {
+                lines.push(marker);
+            }
+        }
+
+        lines
+    }
+
+    /// Draws table cells in a grid, with a rule after the first row. Cells without a valid
+    /// address are left out.
+    /// Renders every definition as its term in bold, with the definition indented below it.
+    fn definitions(&mut self, definitions: &[NorgAST], width: usize) -> Vec<String> {
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let mut blocks = Vec::new();
+
+        for definition in definitions {
+            if let NorgAST::RangeableDetachedModifier { title, content, .. } = definition {
+                let term = self.wrap(&self.inline(trim_start(title), &bold), width);
+                let content = separated(self.blocks(content, width.saturating_sub(4)));
+
+                blocks.push(
+                    term.into_iter()
+                        .chain(indented(content, "    ", "    "))
+                        .collect(),
+                );
+            }
+        }
+
+        separated(blocks)
+    }
+
+    fn table(&mut self, cells: &[NorgAST]) -> Vec<String> {
+        let Some(table) = NorgTable::from_nodes(cells) else {
+            return Vec::new();
+        };
+        let grid: Vec<Vec<String>> = table
+            .grid()
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|cell| match cell {
+                        Some(cell) => self.blocks(&cell.content, usize::MAX).concat().join(" "),
+                        None => String::new(),
+                    })
+                    .collect()
+            })
+            .collect();
+        let columns = grid.first().map_or(0, Vec::len);
+        let widths: Vec<_> = (0..columns)
+            .map(|column| {
+                grid.iter()
+                    .map(|row| display_width(&row[column]))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let border = |left: &str, middle: &str, right: &str| {
+            let segments: Vec<_> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+            styled(
+                &format!("{left}{}{right}", segments.join(middle)),
+                &Style::dimmed(),
+                self.hyperlinks,
+            )
+        };
+        let bar = styled("│", &Style::dimmed(), self.hyperlinks);
+
+        let mut lines = vec![border("┌", "┬", "┐")];
+        for (i, row) in grid.iter().enumerate() {
+            if i == 1 {
+                lines.push(border("├", "┼", "┤"));
+            }
+            let cells: Vec<_> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| {
+                    let padding = " ".repeat(width - display_width(cell));
+                    format!(" {cell}{padding} ")
+                })
+                .collect();
+            lines.push(format!("{bar}{}{bar}", cells.join(&bar)));
+        }
+        lines.push(border("└", "┴", "┘"));
+
+        lines
+    }
+
+    /// Draws verbatim content in a box, with the label in its top border. Tabs are expanded to
+    /// four spaces.
+    fn boxed(&self, label: &str, content: &str) -> Vec<String> {
+        let label = clean(label);
+        let content: Vec<_> = content
+            .lines()
+            .map(|line| clean(&line.replace('\t', "    ")))
+            .collect();
+        let inner = content
+            .iter()
+            .map(|line| display_width(line))
+            .chain(std::iter::once(display_width(&label) + 1))
+            .max()
+            .unwrap_or(0);
+
+        let border = Style::dimmed();
+        let top = format!(
+            "┌─ {label} {}┐",
+            "─".repeat(inner - display_width(&label) - 1)
+        );
+        let mut lines = vec![styled(&top, &border, self.hyperlinks)];
+        for line in content {
+            let padding = " ".repeat(inner - display_width(&line));
+            lines.push(format!(
+                "{}{line}{padding}{}",
+                styled("│ ", &border, self.hyperlinks),
+                styled(" │", &border, self.hyperlinks)
+            ));
+        }
+        let bottom = format!("└{}┘", "─".repeat(inner + 2));
+        lines.push(styled(&bottom, &border, self.hyperlinks));
+
+        lines
+    }
+
+    fn inline(&self, segments: &[ParagraphSegment], style: &Style) -> Vec<Run> {
+        let mut runs = Vec::new();
+
+        for segment in segments {
+            match segment {
+                ParagraphSegment::Token { token, .. } => match token {
+                    ParagraphSegmentToken::Escape('\n') => runs.push(Run::Break),
+                    ParagraphSegmentToken::Text(text) => {
+                        runs.push(Run::Text(clean(text), style.clone()))
+                    }
+                    ParagraphSegmentToken::Whitespace => {
+                        runs.push(Run::Text(" ".to_string(), style.clone()))
+                    }
+                    ParagraphSegmentToken::Special(c) | ParagraphSegmentToken::Escape(c) => {
+                        runs.push(Run::Text(clean(&c.to_string()), style.clone()))
+                    }
+                },
+                ParagraphSegment::AttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                }
+                | ParagraphSegment::FreeFormAttachedModifier {
+                    modifier_type,
+                    content,
+                    ..
+                } => {
+                    let mut inner = style.clone();
+                    match modifier_type {
+                        '*' => inner.bold = true,
+                        '/' => inner.italic = true,
+                        '_' => inner.underline = true,
+                        '-' => inner.strikethrough = true,
+                        '!' => inner.reverse = true,
+                        '^' | ',' => inner.dim = true,
+                        '`' => inner.color = Some(YELLOW),
+                        '$' => inner.color = Some(MAGENTA),
+                        '&' => inner.color = Some(GREEN),
+                        // The null modifier comments its content out.
+                        '%' => continue,
+                        _ => {}
+                    }
+
+                    match verbatim(segment) {
+                        Some(text) => runs.push(Run::Text(clean_verbatim(&text), inner)),
+                        None => runs.extend(self.inline(content, &inner)),
+                    }
+                }
+                ParagraphSegment::InlineVerbatim { content, .. } => {
+                    let code = Style {
+                        color: Some(YELLOW),
+                        ..style.clone()
+                    };
+                    runs.push(Run::Text(clean_verbatim(&raw(content)), code));
+                }
+                ParagraphSegment::Link {
+                    filepath,
+                    targets,
+                    description,
+                    ..
+                } => runs.extend(self.link(
+                    filepath.as_deref(),
+                    targets,
+                    description.as_deref(),
+                    style,
+                )),
+                ParagraphSegment::AnchorDefinition {
+                    content, target, ..
+                } => runs.extend(self.anchor(content, target, style)),
+                ParagraphSegment::Anchor {
+                    content,
+                    description,
+                    ..
+                } => {
+                    let text = description.as_deref().unwrap_or(content);
+                    match self.targets.anchor(&plain_text(content)) {
+                        Some(target) => runs.extend(self.anchor(text, target, style)),
+                        None => runs.extend(self.inline(text, style)),
+                    }
+                }
+                ParagraphSegment::InlineLinkTarget { content, .. } => {
+                    runs.extend(self.inline(content, style))
+                }
+                // Only used while parsing, these never end up in the tree.
+                ParagraphSegment::AttachedModifierOpener(_)
+                | ParagraphSegment::AttachedModifierOpenerFail(_)
+                | ParagraphSegment::AttachedModifierCloserCandidate(_)
+                | ParagraphSegment::AttachedModifierCloser(_)
+                | ParagraphSegment::AttachedModifierCandidate { .. } => {}
+            }
+        }
+
+        runs
+    }
+
+    fn link(
+        &self,
+        filepath: Option<&str>,
+        targets: &[LinkTarget],
+        description: Option<&[ParagraphSegment]>,
+        style: &Style,
+    ) -> Vec<Run> {
+        let link = link_style(style, filepath, targets);
+
+        // Footnote references are numbered, after the description if there is one.
+        if let (None, Some(LinkTarget::Footnote { title, .. })) = (filepath, targets.last()) {
+            if let Some(number) = self.footnote(title) {
+                let mut runs = description
+                    .map(|description| self.inline(description, style))
+                    .unwrap_or_default();
+                runs.push(Run::Text(format!("[{number}]"), Style::colored(CYAN)));
+                return runs;
+            }
+        }
+
+        match (description, targets.last()) {
+            (Some(description), _) => self.inline(description, &link),
+            (
+                None,
+                Some(LinkTarget::Url { url: text, .. } | LinkTarget::Path { path: text, .. }),
+            ) => vec![Run::Text(clean(text), link)],
+            (None, Some(LinkTarget::LineNumber { line, .. })) => {
+                vec![Run::Text(line.to_string(), link)]
+            }
+            (None, Some(LinkTarget::Timestamp { timestamp, .. })) => {
+                vec![Run::Text(clean(&timestamp.raw), link)]
+            }
+            (
+                None,
+                Some(
+                    LinkTarget::Heading { title, .. }
+                    | LinkTarget::Footnote { title, .. }
+                    | LinkTarget::Definition { title, .. }
+                    | LinkTarget::Generic { title, .. }
+                    | LinkTarget::Wiki { title, .. }
+                    | LinkTarget::Extendable { title, .. },
+                ),
+            ) => self.inline(title, &link),
+            (None, None) => vec![Run::Text(clean(filepath.unwrap_or_default()), link)],
+        }
+    }
+
+    /// Styles the text of an anchor as a link to the target of its definition.
+    fn anchor(
+        &self,
+        text: &[ParagraphSegment],
+        target: &ParagraphSegment,
+        style: &Style,
+    ) -> Vec<Run> {
+        match target {
+            ParagraphSegment::Link {
+                filepath, targets, ..
+            } => self.inline(text, &link_style(style, filepath.as_deref(), targets)),
+            _ => self.inline(text, style),
+        }
+    }
+
+    fn footnote(&self, title: &[ParagraphSegment]) -> Option<usize> {
+        let footnote = self.targets.find(Some(TargetKind::Footnote), title)?;
+        self.targets.footnote_number(&footnote.id)
+    }
+
+    /// Wraps runs into lines no wider than `width`, breaking only at spaces.
+    fn wrap(&self, runs: &[Run], width: usize) -> Vec<String> {
+        let mut wrapper = Wrapper {
+            width,
+            hyperlinks: self.hyperlinks,
+            lines: Vec::new(),
+            line: Vec::new(),
+            line_width: 0,
+            space: None,
+        };
+        let mut word: Vec<(String, Style)> = Vec::new();
+
+        for run in runs {
+            match run {
+                Run::Break => {
+                    wrapper.place(&mut word);
+                    wrapper.end_line();
+                }
+                Run::Text(text, style) => {
+                    for (i, part) in text.split(' ').enumerate() {
+                        if i > 0 {
+                            wrapper.place(&mut word);
+                            wrapper.space = Some(style.clone());
+                        }
+                        if !part.is_empty() {
+                            match word.last_mut() {
+                                Some((text, last)) if last == style => text.push_str(part),
+                                _ => word.push((part.to_string(), style.clone())),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        wrapper.place(&mut word);
+        if !wrapper.line.is_empty() {
+            wrapper.end_line();
+        }
+
+        wrapper.lines
+    }
+}
+
+/// Fills lines with words, one at a time.
+struct Wrapper {
+    width: usize,
+    hyperlinks: bool,
+    lines: Vec<String>,
+    line: Vec<(String, Style)>,
+    line_width: usize,
+    /// A space seen after the last word, which is only written if another word follows on the
+    /// same line.
+    space: Option<Style>,
+}
+
+impl Wrapper {
+    /// Adds a word to the current line, or to a new one if it does not fit.
+    fn place(&mut self, word: &mut Vec<(String, Style)>) {
+        if word.is_empty() {
+            return;
+        }
+        let word_width: usize = word.iter().map(|(text, _)| display_width(text)).sum();
+
+        if !self.line.is_empty() && self.line_width + 1 + word_width > self.width {
+            self.end_line();
+        }
+        if let Some(style) = self.space.take().filter(|_| !self.line.is_empty()) {
+            self.line.push((" ".to_string(), style));
+            self.line_width += 1;
+        }
+        self.line_width += word_width;
+        self.line.append(word);
+    }
+
+    fn end_line(&mut self) {
+        self.lines.push(line(&self.line, self.hyperlinks));
+        self.line.clear();
+        self.line_width = 0;
+        self.space = None;
+    }
+}
+
+/// Writes pieces of styled text as a line which starts and ends its own styles.
+fn line(pieces: &[(String, Style)], hyperlinks: bool) -> String {
+    let mut output = String::new();
+    let mut current = Style::default();
+
+    for (text, style) in pieces {
+        if style.sgr() != current.sgr() {
+            output += &style.sgr();
+        }
+        if hyperlinks && style.link != current.link {
+            output += &hyperlink(style.link.as_deref());
+        }
+        output += text;
+        current = style.clone();
+    }
+
+    if current.sgr() != Style::default().sgr() {
+        output += &Style::default().sgr();
+    }
+    if hyperlinks && current.link.is_some() {
+        output += &hyperlink(None);
+    }
+
+    output
+}
+
+/// Writes text in a single style as a line of its own.
+fn styled(text: &str, style: &Style, hyperlinks: bool) -> String {
+    line(&[(text.to_string(), style.clone())], hyperlinks)
+}
+
+/// The OSC 8 sequence which starts a hyperlink to `url`, or ends it if there is none.
+fn hyperlink(url: Option<&str>) -> String {
+    format!("\x1b]8;;{}\x1b\\", url.unwrap_or_default())
+}
+
+/// The style of a link: underlined and blue, and pointing to its URL if it has one.
+fn link_style(style: &Style, filepath: Option<&str>, targets: &[LinkTarget]) -> Style {
+    let url = match (filepath, targets.last()) {
+        (None, Some(LinkTarget::Url { url, .. })) => Some(clean(url)),
+        (None, Some(LinkTarget::Path { path, .. })) if path.starts_with('/') => {
+            Some(format!("file://{}", clean(path)))
+        }
+        _ => None,
+    };
+
+    Style {
+        underline: true,
+        color: Some(BLUE),
+        link: url,
+        ..style.clone()
+    }
+}
+
+/// The glyph of a todo status, followed by the other extensions in their Norg form.
+fn status(extensions: &[DetachedModifierExtension]) -> Vec<Run> {
+    let mut runs = Vec::new();
+
+    if let Some(DetachedModifierExtension::Todo(status)) = extensions.iter().find(|e| is_todo(e)) {
+        let (glyph, style) = match status {
+            TodoStatus::Undone => ("☐", Style::default()),
+            TodoStatus::Done => ("☑", Style::colored(GREEN)),
+            TodoStatus::NeedsClarification => ("?", Style::colored(MAGENTA)),
+            TodoStatus::Paused => ("‖", Style::colored(BLUE)),
+            TodoStatus::Urgent => (
+                "!",
+                Style {
+                    bold: true,
+                    ..Style::colored(RED)
+                },
+            ),
+            TodoStatus::Recurring(_) => ("↻", Style::colored(CYAN)),
+            TodoStatus::Pending => ("◐", Style::colored(YELLOW)),
+            TodoStatus::Canceled => ("☒", Style::dimmed()),
+        };
+        runs.push(Run::Text(glyph.to_string(), style));
+        runs.push(Run::Text(" ".to_string(), Style::default()));
+    }
+
+    // Recurring items keep their timestamp.
+    let rest: Vec<_> = extensions
+        .iter()
+        .filter(|extension| {
+            !matches!(
+                extension,
+                DetachedModifierExtension::Todo(status)
+                    if !matches!(status, TodoStatus::Recurring(Some(_)))
+            )
+        })
+        .cloned()
+        .collect();
+    if !rest.is_empty() {
+        runs.push(Run::Text(clean(&extension_text(&rest)), Style::dimmed()));
+        runs.push(Run::Text(" ".to_string(), Style::default()));
+    }
+
+    runs
+}
+
+fn is_todo(extension: &DetachedModifierExtension) -> bool {
+    matches!(extension, DetachedModifierExtension::Todo(_))
+}
+
+/// Joins blocks of lines with blank lines in between.
+fn separated(blocks: Vec<Vec<String>>) -> Vec<String> {
+    blocks.join(&String::new())
+}
+
+/// Puts `first` in front of the first line and `rest` in front of the others, leaving empty
+/// lines alone unless `rest` has something to show.
+fn indented(lines: Vec<String>, first: &str, rest: &str) -> Vec<String> {
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => format!("{first}{line}"),
+            _ if line.is_empty() => rest.trim_end().to_string(),
+            _ => format!("{rest}{line}"),
+        })
+        .collect()
+}
+
+/// Verbatim text on a single line, without control characters.
+fn clean_verbatim(text: &str) -> String {
+    clean(&text.replace('\n', " "))
+}
+
+/// Leaves out control characters, which could change the state of the terminal.
+fn clean(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::proptest;
+    use textwrap::core::display_width;
+
+    use crate::{
+        parse_tree,
+        terminal::{render_with, RenderOptions},
+    };
+
+    fn rendered(input: &str, options: &RenderOptions) -> String {
+        let mut output = Vec::new();
+        render_with(&parse_tree(input).unwrap(), &mut output, options).unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn rendering() {
+        let options = RenderOptions {
+            width: 40,
+            hyperlinks: true,
+        };
+
+        assert_yaml_snapshot!(
+            "headings",
+            rendered("* (x) Tasks\n** Second level\nText.\n___\n", &options)
+        );
+        assert_yaml_snapshot!(
+            "lists",
+            rendered(
+                "- ( ) Write *the* /renderer/
+                 -- (x) Strike -this- out
+                 -- (!|# A) Urgent
+                 - Plain item
+                 ~ First
+                 ~ (=) Second\n",
+                &options
+            )
+        );
+        assert_yaml_snapshot!("quotes", rendered("> Quoted text\n>> Nested\n", &options));
+        assert_yaml_snapshot!(
+            "markup",
+            rendered(
+                "*Bold*, /italic/, _underline_, `code`, $x^2$ and !a spoiler!.\n",
+                &options
+            )
+        );
+        assert_yaml_snapshot!(
+            "wrapped",
+            rendered(
+                "A paragraph with _long underlined text_ to wrap to the width of the terminal.\n",
+                &options
+            )
+        );
+        assert_yaml_snapshot!(
+            "links",
+            rendered(
+                "Links to {https://neorg.org}[Neorg] and {* Tasks}.\n* Tasks\n",
+                &options
+            )
+        );
+        assert_yaml_snapshot!(
+            "footnotes",
+            rendered("A note.{^ Note}\n^ Note\nA footnote.\n", &options)
+        );
+        assert_yaml_snapshot!(
+            "definitions",
+            rendered("$ Term\nThe definition.\n", &options)
+        );
+        assert_yaml_snapshot!(
+            "tables",
+            rendered(": A1\n  Name\n: B1\n  Age\n: A2\n  Bob\n", &options)
+        );
+        assert_yaml_snapshot!(
+            "sparse_tables",
+            rendered(": A1\n  a\n: C3\n  c\n", &options)
+        );
+        assert_yaml_snapshot!(
+            "code",
+            rendered(
+                "@code rust\nfn main() {\n    println!(\"hi\");\n}\n@end\n",
+                &options
+            )
+        );
+        assert_yaml_snapshot!(
+            "escapes",
+            rendered(
+                "Escaped \\*stars\\*, a backslash \\\\ and a pipe \\|.\n",
+                &options
+            )
+        );
+        assert_yaml_snapshot!(
+            "escaped_pipes",
+            rendered(": A1\n  a \\| b\n: B1\n  c\n", &options)
+        );
+    }
+
+    #[test]
+    fn without_hyperlinks() {
+        let options = RenderOptions {
+            width: 80,
+            hyperlinks: false,
+        };
+
+        assert_eq!(
+            rendered("{https://neorg.org}[Neorg]\n", &options),
+            "\x1b[0;4;34mNeorg\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn verbatim() {
+        let options = RenderOptions {
+            width: 80,
+            hyperlinks: false,
+        };
+
+        assert_eq!(
+            rendered("`a\\b`, $\\frac{1}{2}$ and *|a\\b|*\n", &options),
+            "\x1b[0;33ma\\b\x1b[0m, \x1b[0;35m\\frac{1}{2}\x1b[0m and \x1b[0;1ma\\b\x1b[0m\n"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn lines_fit_the_width(words in proptest::collection::vec("[a-z]{1,10}", 1..40), width in 10usize..60) {
+            let input = words.join(" ") + "\n";
+            let options = RenderOptions { width, hyperlinks: true };
+
+            for line in rendered(&input, &options).lines() {
+                assert!(display_width(line) <= width, "{line:?} is wider than {width}");
+            }
+        }
+    }
+}
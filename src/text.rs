@@ -0,0 +1,83 @@
+//! The text of the tree without its markup, shared by the selectors, tables and exporters.
+
+use crate::{
+    visit::{walk_segments, Visitor},
+    ParagraphSegment, TodoStatus,
+};
+
+/// The names of the todo statuses, in the order of [`TodoStatus`].
+pub(crate) const TODO_STATUSES: [&str; 8] = [
+    "undone",
+    "done",
+    "needs-clarification",
+    "paused",
+    "urgent",
+    "recurring",
+    "pending",
+    "canceled",
+];
+
+/// The name of a todo status, as used by the `todo` selector attribute and in the class
+/// names of exported todo items.
+pub(crate) fn todo_name(status: &TodoStatus) -> &'static str {
+    match status {
+        TodoStatus::Undone => TODO_STATUSES[0],
+        TodoStatus::Done => TODO_STATUSES[1],
+        TodoStatus::NeedsClarification => TODO_STATUSES[2],
+        TodoStatus::Paused => TODO_STATUSES[3],
+        TodoStatus::Urgent => TODO_STATUSES[4],
+        TodoStatus::Recurring(_) => TODO_STATUSES[5],
+        TodoStatus::Pending => TODO_STATUSES[6],
+        TodoStatus::Canceled => TODO_STATUSES[7],
+    }
+}
+
+/// The text of paragraph segments, leaving out markup.
+pub(crate) fn plain_text(segments: &[ParagraphSegment]) -> String {
+    let mut text = PlainText::default();
+    walk_segments(&mut text, segments);
+
+    text.0
+}
+
+/// Collects the text of paragraph segments, leaving out markup. Links are replaced by their
+/// description, if they have one.
+#[derive(Default)]
+pub(crate) struct PlainText(pub(crate) String);
+
+impl Visitor for PlainText {
+    fn visit_token(&mut self, segment: &ParagraphSegment) {
+        if let ParagraphSegment::Token { token, .. } = segment {
+            self.0 += &token.to_string();
+        }
+    }
+
+    fn visit_inline_verbatim(&mut self, segment: &ParagraphSegment) {
+        if let ParagraphSegment::InlineVerbatim { content, .. } = segment {
+            content
+                .iter()
+                .for_each(|token| self.0 += &token.to_string());
+        }
+    }
+
+    fn visit_link(&mut self, segment: &ParagraphSegment) {
+        if let ParagraphSegment::Link {
+            description: Some(description),
+            ..
+        } = segment
+        {
+            walk_segments(self, description);
+        }
+    }
+
+    fn visit_anchor(&mut self, segment: &ParagraphSegment) {
+        if let ParagraphSegment::Anchor {
+            content,
+            description,
+            ..
+        } = segment
+        {
+            walk_segments(self, description.as_ref().unwrap_or(content));
+        }
+    }
+}
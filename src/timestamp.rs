@@ -0,0 +1,376 @@
+//! Timestamps as used in `{@ ...}` links and the `(@ ...)`, `(< ...)`, `(> ...)` and `(+ ...)`
+//! detached modifier extensions.
+//!
+//! A timestamp is made up of `<weekday>, <day> <month> <year> <time> <timezone>`, where every
+//! part is optional and the day may also follow the month, e.g. `Sat, 29th Oct 1949 10:30 CET`
+//! or `Feb 1`. Two of them joined with `-` form a range.
+
+use chumsky::prelude::*;
+use serde::Serialize;
+
+/// A parsed timestamp, together with the text it was parsed from.
+#[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
+pub struct NorgTimestamp {
+    /// The timestamp as written in the document.
+    pub raw: String,
+    /// The start of the range, or the only point in time. `None` if `raw` does not follow the
+    /// timestamp grammar.
+    pub start: Option<NorgDateTime>,
+    /// The end of the range, if this is one.
+    pub end: Option<NorgDateTime>,
+}
+
+/// A single point in time. Only the parts which were written down are filled in.
+#[derive(Clone, Default, Hash, Debug, PartialEq, Eq, Serialize)]
+pub struct NorgDateTime {
+    pub weekday: Option<Weekday>,
+    pub day: Option<u8>,
+    pub month: Option<Month>,
+    pub year: Option<u32>,
+    pub time: Option<NorgTime>,
+    pub timezone: Option<String>,
+}
+
+/// A time of day, written as `10:30` or `10:30.15` with seconds.
+#[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
+pub struct NorgTime {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: Option<u8>,
+}
+
+#[derive(Clone, Copy, Hash, Debug, PartialEq, Eq, Serialize)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+#[derive(Clone, Copy, Hash, Debug, PartialEq, Eq, Serialize)]
+pub enum Month {
+    January,
+    February,
+    March,
+    April,
+    May,
+    June,
+    July,
+    August,
+    September,
+    October,
+    November,
+    December,
+}
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("monday", Weekday::Monday),
+    ("tuesday", Weekday::Tuesday),
+    ("wednesday", Weekday::Wednesday),
+    ("thursday", Weekday::Thursday),
+    ("friday", Weekday::Friday),
+    ("saturday", Weekday::Saturday),
+    ("sunday", Weekday::Sunday),
+];
+
+const MONTHS: [(&str, Month); 12] = [
+    ("january", Month::January),
+    ("february", Month::February),
+    ("march", Month::March),
+    ("april", Month::April),
+    ("may", Month::May),
+    ("june", Month::June),
+    ("july", Month::July),
+    ("august", Month::August),
+    ("september", Month::September),
+    ("october", Month::October),
+    ("november", Month::November),
+    ("december", Month::December),
+];
+
+/// Looks up a name which may be abbreviated down to its first three letters, e.g. `Wed` or
+/// `Sept`.
+fn lookup<T: Copy>(names: &[(&str, T)], word: &str) -> Option<T> {
+    let word = word.to_lowercase();
+
+    names
+        .iter()
+        .find(|(name, _)| word.len() >= 3 && name.starts_with(&word))
+        .map(|(_, value)| *value)
+}
+
+impl NorgTimestamp {
+    /// Parses a timestamp. This never fails: text which does not follow the grammar is kept in
+    /// [`NorgTimestamp::raw`] with nothing else filled in.
+    pub fn parse(raw: impl Into<String>) -> Self {
+        let raw = raw.into();
+
+        match timestamp().parse(raw.trim()) {
+            Ok((start, end)) => Self {
+                raw,
+                start: Some(start),
+                end,
+            },
+            Err(_) => Self {
+                raw,
+                start: None,
+                end: None,
+            },
+        }
+    }
+}
+
+fn timestamp() -> impl Parser<char, (NorgDateTime, Option<NorgDateTime>), Error = Simple<char>> {
+    date_time()
+        .then(just('-').padded().ignore_then(date_time()).or_not())
+        .then_ignore(end())
+}
+
+fn date_time() -> impl Parser<char, NorgDateTime, Error = Simple<char>> {
+    let digit = filter(|c: &char| c.is_ascii_digit());
+    let word = filter(|c: &char| c.is_alphabetic())
+        .repeated()
+        .at_least(1)
+        .collect::<String>();
+    let number = digit.repeated().at_least(1).collect::<String>();
+
+    let weekday = word
+        .try_map(|word, span| {
+            lookup(&WEEKDAYS, &word).ok_or_else(|| Simple::custom(span, "expected a weekday"))
+        })
+        .then_ignore(just(',').or_not());
+
+    let month = word.try_map(|word, span| {
+        lookup(&MONTHS, &word).ok_or_else(|| Simple::custom(span, "expected a month"))
+    });
+
+    // `1`, `01` or `1st`, but not the start of a year or a time.
+    let day = number
+        .try_map(|number, span| {
+            number
+                .parse()
+                .ok()
+                .filter(|day| number.len() <= 2 && (1..=31).contains(day))
+                .ok_or_else(|| Simple::custom(span, "expected a day"))
+        })
+        .then_ignore(choice((just("st"), just("nd"), just("rd"), just("th"))).or_not())
+        .then_ignore(
+            filter(|c: &char| c.is_alphanumeric() || *c == ':' || *c == '.')
+                .not()
+                .rewind()
+                .ignored()
+                .or(end()),
+        );
+
+    let year = number.try_map(|number, span| {
+        number
+            .parse()
+            .ok()
+            .filter(|_| number.len() >= 3)
+            .ok_or_else(|| Simple::custom(span, "expected a year"))
+    });
+
+    let two_digits = digit
+        .repeated()
+        .at_least(1)
+        .at_most(2)
+        .collect::<String>()
+        .from_str::<u8>()
+        .unwrapped();
+
+    let time = two_digits
+        .then_ignore(just(':'))
+        .then(two_digits)
+        .then(just('.').ignore_then(two_digits).or_not())
+        .try_map(|((hour, minute), second), span| {
+            if hour < 24 && minute < 60 && second.map_or(true, |second| second < 60) {
+                Ok(NorgTime {
+                    hour,
+                    minute,
+                    second,
+                })
+            } else {
+                Err(Simple::custom(span, "invalid time"))
+            }
+        });
+
+    // `CET`, `UTC` or `UTC+2`. The offset has to follow right away, to tell it apart from a
+    // range.
+    let offset = one_of("+-")
+        .chain(digit.repeated().at_least(1))
+        .chain::<char, _, _>(
+            just(':')
+                .chain(digit.repeated().at_least(1))
+                .or_not()
+                .map(Option::unwrap_or_default),
+        );
+    let timezone = filter(|c: &char| c.is_ascii_uppercase())
+        .repeated()
+        .at_least(2)
+        .chain::<char, _, _>(offset.or_not().map(Option::unwrap_or_default))
+        .collect::<String>();
+
+    let day_and_month = choice((
+        day.clone()
+            .then(month.padded().or_not())
+            .map(|(day, month)| (Some(day), month)),
+        month
+            .then(day.padded().or_not())
+            .map(|(month, day)| (day, Some(month))),
+    ));
+
+    weekday
+        .padded()
+        .or_not()
+        .then(day_and_month.padded().or_not())
+        .then(year.padded().or_not())
+        .then(time.padded().or_not())
+        .then(timezone.padded().or_not())
+        .try_map(
+            |((((weekday, day_and_month), year), time), timezone), span| {
+                let (day, month) = day_and_month.unwrap_or_default();
+                let date_time = NorgDateTime {
+                    weekday,
+                    day,
+                    month,
+                    year,
+                    time,
+                    timezone,
+                };
+
+                if date_time == NorgDateTime::default() {
+                    Err(Simple::custom(span, "expected a timestamp"))
+                } else {
+                    Ok(date_time)
+                }
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use proptest::{
+        option,
+        prelude::{any, Strategy},
+        prop_assert_eq, prop_compose, proptest,
+        sample::select,
+    };
+
+    use crate::timestamp::{NorgDateTime, NorgTime, NorgTimestamp, MONTHS, WEEKDAYS};
+
+    /// Writes a weekday or month name, cut down to `length` letters but no fewer than three.
+    fn name<T: Copy>(names: &[(&str, T)], index: usize, length: usize) -> (T, String) {
+        let (name, value) = names[index];
+        let name = &name[..length.clamp(3, name.len())];
+
+        (value, name[..1].to_uppercase() + &name[1..])
+    }
+
+    prop_compose! {
+        /// A point in time with some of its parts filled in, together with one of the ways it
+        /// can be written.
+        fn any_date_time()(
+            weekday in option::of((0..7usize, 3..10usize, any::<bool>())),
+            day in option::of((1..=31u8, any::<bool>(), select(vec!["", "st", "nd", "rd", "th"]))),
+            month in option::of((0..12usize, 3..10usize)),
+            day_first in any::<bool>(),
+            year in option::of(100..=9999u32),
+            time in option::of((0..24u8, 0..60u8, option::of(0..60u8))),
+            timezone in option::of((
+                select(vec!["CET", "UTC", "GMT", "AEST"]),
+                select(vec!["", "+2", "-5", "+05:30"]),
+            )),
+        ) -> (NorgDateTime, String) {
+            let mut parts = Vec::new();
+
+            let weekday = weekday.map(|(index, length, comma)| {
+                let (weekday, written) = name(&WEEKDAYS, index, length);
+                parts.push(written + if comma { "," } else { "" });
+                weekday
+            });
+
+            let month = month.map(|(index, length)| name(&MONTHS, index, length));
+            let mut day_and_month: Vec<_> = day
+                .map(|(day, padded, suffix)| match padded {
+                    true => format!("{day:02}{suffix}"),
+                    false => format!("{day}{suffix}"),
+                })
+                .into_iter()
+                .chain(month.as_ref().map(|(_, written)| written.clone()))
+                .collect();
+            if !day_first {
+                day_and_month.reverse();
+            }
+            parts.extend(day_and_month);
+
+            parts.extend(year.map(|year| year.to_string()));
+            parts.extend(time.map(|(hour, minute, second)| match second {
+                Some(second) => format!("{hour}:{minute:02}.{second:02}"),
+                None => format!("{hour}:{minute:02}"),
+            }));
+            let timezone = timezone.map(|(name, offset)| name.to_string() + offset);
+            parts.extend(timezone.clone());
+
+            let date_time = NorgDateTime {
+                weekday,
+                day: day.map(|(day, ..)| day),
+                month: month.map(|(month, _)| month),
+                year,
+                time: time.map(|(hour, minute, second)| NorgTime { hour, minute, second }),
+                timezone,
+            };
+
+            (date_time, parts.join(" "))
+        }
+    }
+
+    /// A point in time as generated by [`any_date_time`], with at least one of its parts filled in.
+    fn date_time() -> impl Strategy<Value = (NorgDateTime, String)> {
+        any_date_time().prop_filter("a timestamp needs at least one part", |(date_time, _)| {
+            date_time != &NorgDateTime::default()
+        })
+    }
+
+    #[test]
+    fn timestamps() {
+        let examples: Vec<_> = [
+            "Feb 1",
+            "5th",
+            "Friday",
+            "Jan 1 2025",
+            "Sat, 29th Oct 1949 10:30.15 CET",
+            "10:30 UTC+2",
+            "5th May - 10th May",
+            "Mon 09:00 - 17:30",
+            "tomorrow",
+        ]
+        .into_iter()
+        .map(NorgTimestamp::parse)
+        .collect();
+
+        assert_yaml_snapshot!(examples);
+    }
+
+    proptest! {
+        #[test]
+        fn timestamps_keep_raw_text(input in r"[A-Za-z0-9,:.+\- ]*") {
+            assert_eq!(NorgTimestamp::parse(input.clone()).raw, input);
+        }
+
+        #[test]
+        fn timestamps_parse_their_parts(start in date_time(), end in option::of(date_time())) {
+            let raw = match &end {
+                Some((_, end)) => format!("{} - {}", start.1, end),
+                None => start.1,
+            };
+            let timestamp = NorgTimestamp::parse(raw);
+
+            prop_assert_eq!(timestamp.start, Some(start.0));
+            prop_assert_eq!(timestamp.end, end.map(|(end, _)| end));
+        }
+    }
+}
@@ -0,0 +1,677 @@
+//! Traversal of the tree returned by [`crate::parse_tree`].
+//!
+//! [`Visitor`] and [`VisitorMut`] walk over [`NorgAST`] nodes, their [`ParagraphSegment`]s and
+//! [`LinkTarget`]s with a method per kind of node, so that tools only have to handle the nodes
+//! they are interested in. See [`crate::fold`] for rebuilding a tree instead.
+
+use crate::{DetachedModifierExtension, LinkTarget, NorgAST, ParagraphSegment};
+
+/// Walks over a tree by reference.
+///
+/// Every method has a default implementation which visits the children of the node through the
+/// matching `walk_*` function. Override the methods for the nodes you care about, and call the
+/// `walk_*` function from them to keep descending into the tree.
+pub trait Visitor {
+    /// Called for every node, dispatching to the method for its kind.
+    fn visit_node(&mut self, node: &NorgAST) {
+        walk_node(self, node)
+    }
+
+    fn visit_paragraph(&mut self, node: &NorgAST) {
+        walk_paragraph(self, node)
+    }
+
+    /// Called for list items and quotes.
+    fn visit_nestable_detached_modifier(&mut self, node: &NorgAST) {
+        walk_nestable_detached_modifier(self, node)
+    }
+
+    /// Called for definitions, footnotes and table cells.
+    fn visit_rangeable_detached_modifier(&mut self, node: &NorgAST) {
+        walk_rangeable_detached_modifier(self, node)
+    }
+
+    fn visit_heading(&mut self, node: &NorgAST) {
+        walk_heading(self, node)
+    }
+
+    fn visit_carryover_tag(&mut self, node: &NorgAST) {
+        walk_carryover_tag(self, node)
+    }
+
+    fn visit_verbatim_ranged_tag(&mut self, _node: &NorgAST) {}
+
+    fn visit_ranged_tag(&mut self, node: &NorgAST) {
+        walk_ranged_tag(self, node)
+    }
+
+    fn visit_infirm_tag(&mut self, _node: &NorgAST) {}
+
+    fn visit_delimiting_modifier(&mut self, _node: &NorgAST) {}
+
+    fn visit_error(&mut self, _node: &NorgAST) {}
+
+    /// Called for the extensions of headings and detached modifiers, e.g. their todo status.
+    fn visit_extension(&mut self, _extension: &DetachedModifierExtension) {}
+
+    /// Called for every paragraph segment, dispatching to the method for its kind.
+    fn visit_segment(&mut self, segment: &ParagraphSegment) {
+        walk_segment(self, segment)
+    }
+
+    fn visit_token(&mut self, _segment: &ParagraphSegment) {}
+
+    /// Called for both regular and free-form attached modifiers.
+    fn visit_attached_modifier(&mut self, segment: &ParagraphSegment) {
+        walk_attached_modifier(self, segment)
+    }
+
+    fn visit_link(&mut self, segment: &ParagraphSegment) {
+        walk_link(self, segment)
+    }
+
+    fn visit_link_target(&mut self, target: &LinkTarget) {
+        walk_link_target(self, target)
+    }
+
+    fn visit_anchor_definition(&mut self, segment: &ParagraphSegment) {
+        walk_anchor_definition(self, segment)
+    }
+
+    fn visit_anchor(&mut self, segment: &ParagraphSegment) {
+        walk_anchor(self, segment)
+    }
+
+    fn visit_inline_link_target(&mut self, segment: &ParagraphSegment) {
+        walk_inline_link_target(self, segment)
+    }
+
+    fn visit_inline_verbatim(&mut self, _segment: &ParagraphSegment) {}
+}
+
+/// Visits all of the given nodes in order.
+pub fn walk_nodes<V: Visitor + ?Sized>(visitor: &mut V, nodes: &[NorgAST]) {
+    nodes.iter().for_each(|node| visitor.visit_node(node));
+}
+
+/// Visits all of the given segments in order.
+pub fn walk_segments<V: Visitor + ?Sized>(visitor: &mut V, segments: &[ParagraphSegment]) {
+    segments
+        .iter()
+        .for_each(|segment| visitor.visit_segment(segment));
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &NorgAST) {
+    match node {
+        NorgAST::Paragraph { .. } => visitor.visit_paragraph(node),
+        NorgAST::NestableDetachedModifier { .. } => visitor.visit_nestable_detached_modifier(node),
+        NorgAST::RangeableDetachedModifier { .. } => {
+            visitor.visit_rangeable_detached_modifier(node)
+        }
+        NorgAST::Heading { .. } => visitor.visit_heading(node),
+        NorgAST::CarryoverTag { .. } => visitor.visit_carryover_tag(node),
+        NorgAST::VerbatimRangedTag { .. } => visitor.visit_verbatim_ranged_tag(node),
+        NorgAST::RangedTag { .. } => visitor.visit_ranged_tag(node),
+        NorgAST::InfirmTag { .. } => visitor.visit_infirm_tag(node),
+        NorgAST::DelimitingModifier { .. } => visitor.visit_delimiting_modifier(node),
+        NorgAST::Error { .. } => visitor.visit_error(node),
+    }
+}
+
+pub fn walk_paragraph<V: Visitor + ?Sized>(visitor: &mut V, node: &NorgAST) {
+    if let NorgAST::Paragraph { content, .. } = node {
+        walk_segments(visitor, content);
+    }
+}
+
+pub fn walk_nestable_detached_modifier<V: Visitor + ?Sized>(visitor: &mut V, node: &NorgAST) {
+    if let NorgAST::NestableDetachedModifier {
+        extensions,
+        text,
+        content,
+        ..
+    } = node
+    {
+        extensions
+            .iter()
+            .for_each(|extension| visitor.visit_extension(extension));
+        visitor.visit_node(text);
+        walk_nodes(visitor, content);
+    }
+}
+
+pub fn walk_rangeable_detached_modifier<V: Visitor + ?Sized>(visitor: &mut V, node: &NorgAST) {
+    if let NorgAST::RangeableDetachedModifier {
+        title,
+        extensions,
+        content,
+        ..
+    } = node
+    {
+        walk_segments(visitor, title);
+        extensions
+            .iter()
+            .for_each(|extension| visitor.visit_extension(extension));
+        walk_nodes(visitor, content);
+    }
+}
+
+pub fn walk_heading<V: Visitor + ?Sized>(visitor: &mut V, node: &NorgAST) {
+    if let NorgAST::Heading {
+        title,
+        extensions,
+        content,
+        ..
+    } = node
+    {
+        walk_segments(visitor, title);
+        extensions
+            .iter()
+            .for_each(|extension| visitor.visit_extension(extension));
+        walk_nodes(visitor, content);
+    }
+}
+
+pub fn walk_carryover_tag<V: Visitor + ?Sized>(visitor: &mut V, node: &NorgAST) {
+    if let NorgAST::CarryoverTag { next_object, .. } = node {
+        visitor.visit_node(next_object);
+    }
+}
+
+pub fn walk_ranged_tag<V: Visitor + ?Sized>(visitor: &mut V, node: &NorgAST) {
+    if let NorgAST::RangedTag { content, .. } = node {
+        walk_nodes(visitor, content);
+    }
+}
+
+pub fn walk_segment<V: Visitor + ?Sized>(visitor: &mut V, segment: &ParagraphSegment) {
+    match segment {
+        ParagraphSegment::Token { .. } => visitor.visit_token(segment),
+        ParagraphSegment::AttachedModifier { .. }
+        | ParagraphSegment::FreeFormAttachedModifier { .. } => {
+            visitor.visit_attached_modifier(segment)
+        }
+        ParagraphSegment::Link { .. } => visitor.visit_link(segment),
+        ParagraphSegment::AnchorDefinition { .. } => visitor.visit_anchor_definition(segment),
+        ParagraphSegment::Anchor { .. } => visitor.visit_anchor(segment),
+        ParagraphSegment::InlineLinkTarget { .. } => visitor.visit_inline_link_target(segment),
+        ParagraphSegment::InlineVerbatim { .. } => visitor.visit_inline_verbatim(segment),
+        // Only used while parsing, these never end up in the tree.
+        ParagraphSegment::AttachedModifierOpener(_)
+        | ParagraphSegment::AttachedModifierOpenerFail(_)
+        | ParagraphSegment::AttachedModifierCloserCandidate(_)
+        | ParagraphSegment::AttachedModifierCloser(_)
+        | ParagraphSegment::AttachedModifierCandidate { .. } => {}
+    }
+}
+
+pub fn walk_attached_modifier<V: Visitor + ?Sized>(visitor: &mut V, segment: &ParagraphSegment) {
+    if let ParagraphSegment::AttachedModifier { content, .. }
+    | ParagraphSegment::FreeFormAttachedModifier { content, .. } = segment
+    {
+        walk_segments(visitor, content);
+    }
+}
+
+pub fn walk_link<V: Visitor + ?Sized>(visitor: &mut V, segment: &ParagraphSegment) {
+    if let ParagraphSegment::Link {
+        targets,
+        description,
+        ..
+    } = segment
+    {
+        targets
+            .iter()
+            .for_each(|target| visitor.visit_link_target(target));
+        if let Some(description) = description {
+            walk_segments(visitor, description);
+        }
+    }
+}
+
+pub fn walk_link_target<V: Visitor + ?Sized>(visitor: &mut V, target: &LinkTarget) {
+    match target {
+        LinkTarget::Heading { title, .. }
+        | LinkTarget::Footnote { title, .. }
+        | LinkTarget::Definition { title, .. }
+        | LinkTarget::Generic { title, .. }
+        | LinkTarget::Wiki { title, .. }
+        | LinkTarget::Extendable { title, .. } => walk_segments(visitor, title),
+        LinkTarget::LineNumber { .. }
+        | LinkTarget::Path { .. }
+        | LinkTarget::Url { .. }
+        | LinkTarget::Timestamp { .. } => {}
+    }
+}
+
+pub fn walk_anchor_definition<V: Visitor + ?Sized>(visitor: &mut V, segment: &ParagraphSegment) {
+    if let ParagraphSegment::AnchorDefinition {
+        content, target, ..
+    } = segment
+    {
+        walk_segments(visitor, content);
+        visitor.visit_segment(target);
+    }
+}
+
+pub fn walk_anchor<V: Visitor + ?Sized>(visitor: &mut V, segment: &ParagraphSegment) {
+    if let ParagraphSegment::Anchor {
+        content,
+        description,
+        ..
+    } = segment
+    {
+        walk_segments(visitor, content);
+        if let Some(description) = description {
+            walk_segments(visitor, description);
+        }
+    }
+}
+
+pub fn walk_inline_link_target<V: Visitor + ?Sized>(visitor: &mut V, segment: &ParagraphSegment) {
+    if let ParagraphSegment::InlineLinkTarget { content, .. } = segment {
+        walk_segments(visitor, content);
+    }
+}
+
+/// Walks over a tree by mutable reference, e.g. to edit it in place.
+///
+/// Every method has a default implementation which visits the children of the node through the
+/// matching `walk_*_mut` function. Override the methods for the nodes you care about, and call the
+/// `walk_*_mut` function from them to keep descending into the tree.
+pub trait VisitorMut {
+    /// Called for every node, dispatching to the method for its kind.
+    fn visit_node_mut(&mut self, node: &mut NorgAST) {
+        walk_node_mut(self, node)
+    }
+
+    fn visit_paragraph_mut(&mut self, node: &mut NorgAST) {
+        walk_paragraph_mut(self, node)
+    }
+
+    /// Called for list items and quotes.
+    fn visit_nestable_detached_modifier_mut(&mut self, node: &mut NorgAST) {
+        walk_nestable_detached_modifier_mut(self, node)
+    }
+
+    /// Called for definitions, footnotes and table cells.
+    fn visit_rangeable_detached_modifier_mut(&mut self, node: &mut NorgAST) {
+        walk_rangeable_detached_modifier_mut(self, node)
+    }
+
+    fn visit_heading_mut(&mut self, node: &mut NorgAST) {
+        walk_heading_mut(self, node)
+    }
+
+    fn visit_carryover_tag_mut(&mut self, node: &mut NorgAST) {
+        walk_carryover_tag_mut(self, node)
+    }
+
+    fn visit_verbatim_ranged_tag_mut(&mut self, _node: &mut NorgAST) {}
+
+    fn visit_ranged_tag_mut(&mut self, node: &mut NorgAST) {
+        walk_ranged_tag_mut(self, node)
+    }
+
+    fn visit_infirm_tag_mut(&mut self, _node: &mut NorgAST) {}
+
+    fn visit_delimiting_modifier_mut(&mut self, _node: &mut NorgAST) {}
+
+    fn visit_error_mut(&mut self, _node: &mut NorgAST) {}
+
+    /// Called for the extensions of headings and detached modifiers, e.g. their todo status.
+    fn visit_extension_mut(&mut self, _extension: &mut DetachedModifierExtension) {}
+
+    /// Called for every paragraph segment, dispatching to the method for its kind.
+    fn visit_segment_mut(&mut self, segment: &mut ParagraphSegment) {
+        walk_segment_mut(self, segment)
+    }
+
+    fn visit_token_mut(&mut self, _segment: &mut ParagraphSegment) {}
+
+    /// Called for both regular and free-form attached modifiers.
+    fn visit_attached_modifier_mut(&mut self, segment: &mut ParagraphSegment) {
+        walk_attached_modifier_mut(self, segment)
+    }
+
+    fn visit_link_mut(&mut self, segment: &mut ParagraphSegment) {
+        walk_link_mut(self, segment)
+    }
+
+    fn visit_link_target_mut(&mut self, target: &mut LinkTarget) {
+        walk_link_target_mut(self, target)
+    }
+
+    fn visit_anchor_definition_mut(&mut self, segment: &mut ParagraphSegment) {
+        walk_anchor_definition_mut(self, segment)
+    }
+
+    fn visit_anchor_mut(&mut self, segment: &mut ParagraphSegment) {
+        walk_anchor_mut(self, segment)
+    }
+
+    fn visit_inline_link_target_mut(&mut self, segment: &mut ParagraphSegment) {
+        walk_inline_link_target_mut(self, segment)
+    }
+
+    fn visit_inline_verbatim_mut(&mut self, _segment: &mut ParagraphSegment) {}
+}
+
+/// Visits all of the given nodes in order.
+pub fn walk_nodes_mut<V: VisitorMut + ?Sized>(visitor: &mut V, nodes: &mut [NorgAST]) {
+    nodes
+        .iter_mut()
+        .for_each(|node| visitor.visit_node_mut(node));
+}
+
+/// Visits all of the given segments in order.
+pub fn walk_segments_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    segments: &mut [ParagraphSegment],
+) {
+    segments
+        .iter_mut()
+        .for_each(|segment| visitor.visit_segment_mut(segment));
+}
+
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut NorgAST) {
+    match node {
+        NorgAST::Paragraph { .. } => visitor.visit_paragraph_mut(node),
+        NorgAST::NestableDetachedModifier { .. } => {
+            visitor.visit_nestable_detached_modifier_mut(node)
+        }
+        NorgAST::RangeableDetachedModifier { .. } => {
+            visitor.visit_rangeable_detached_modifier_mut(node)
+        }
+        NorgAST::Heading { .. } => visitor.visit_heading_mut(node),
+        NorgAST::CarryoverTag { .. } => visitor.visit_carryover_tag_mut(node),
+        NorgAST::VerbatimRangedTag { .. } => visitor.visit_verbatim_ranged_tag_mut(node),
+        NorgAST::RangedTag { .. } => visitor.visit_ranged_tag_mut(node),
+        NorgAST::InfirmTag { .. } => visitor.visit_infirm_tag_mut(node),
+        NorgAST::DelimitingModifier { .. } => visitor.visit_delimiting_modifier_mut(node),
+        NorgAST::Error { .. } => visitor.visit_error_mut(node),
+    }
+}
+
+pub fn walk_paragraph_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut NorgAST) {
+    if let NorgAST::Paragraph { content, .. } = node {
+        walk_segments_mut(visitor, content);
+    }
+}
+
+pub fn walk_nestable_detached_modifier_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    node: &mut NorgAST,
+) {
+    if let NorgAST::NestableDetachedModifier {
+        extensions,
+        text,
+        content,
+        ..
+    } = node
+    {
+        extensions
+            .iter_mut()
+            .for_each(|extension| visitor.visit_extension_mut(extension));
+        visitor.visit_node_mut(text);
+        walk_nodes_mut(visitor, content);
+    }
+}
+
+pub fn walk_rangeable_detached_modifier_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    node: &mut NorgAST,
+) {
+    if let NorgAST::RangeableDetachedModifier {
+        title,
+        extensions,
+        content,
+        ..
+    } = node
+    {
+        walk_segments_mut(visitor, title);
+        extensions
+            .iter_mut()
+            .for_each(|extension| visitor.visit_extension_mut(extension));
+        walk_nodes_mut(visitor, content);
+    }
+}
+
+pub fn walk_heading_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut NorgAST) {
+    if let NorgAST::Heading {
+        title,
+        extensions,
+        content,
+        ..
+    } = node
+    {
+        walk_segments_mut(visitor, title);
+        extensions
+            .iter_mut()
+            .for_each(|extension| visitor.visit_extension_mut(extension));
+        walk_nodes_mut(visitor, content);
+    }
+}
+
+pub fn walk_carryover_tag_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut NorgAST) {
+    if let NorgAST::CarryoverTag { next_object, .. } = node {
+        visitor.visit_node_mut(next_object);
+    }
+}
+
+pub fn walk_ranged_tag_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut NorgAST) {
+    if let NorgAST::RangedTag { content, .. } = node {
+        walk_nodes_mut(visitor, content);
+    }
+}
+
+pub fn walk_segment_mut<V: VisitorMut + ?Sized>(visitor: &mut V, segment: &mut ParagraphSegment) {
+    match segment {
+        ParagraphSegment::Token { .. } => visitor.visit_token_mut(segment),
+        ParagraphSegment::AttachedModifier { .. }
+        | ParagraphSegment::FreeFormAttachedModifier { .. } => {
+            visitor.visit_attached_modifier_mut(segment)
+        }
+        ParagraphSegment::Link { .. } => visitor.visit_link_mut(segment),
+        ParagraphSegment::AnchorDefinition { .. } => visitor.visit_anchor_definition_mut(segment),
+        ParagraphSegment::Anchor { .. } => visitor.visit_anchor_mut(segment),
+        ParagraphSegment::InlineLinkTarget { .. } => visitor.visit_inline_link_target_mut(segment),
+        ParagraphSegment::InlineVerbatim { .. } => visitor.visit_inline_verbatim_mut(segment),
+        // Only used while parsing, these never end up in the tree.
+        ParagraphSegment::AttachedModifierOpener(_)
+        | ParagraphSegment::AttachedModifierOpenerFail(_)
+        | ParagraphSegment::AttachedModifierCloserCandidate(_)
+        | ParagraphSegment::AttachedModifierCloser(_)
+        | ParagraphSegment::AttachedModifierCandidate { .. } => {}
+    }
+}
+
+pub fn walk_attached_modifier_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    segment: &mut ParagraphSegment,
+) {
+    if let ParagraphSegment::AttachedModifier { content, .. }
+    | ParagraphSegment::FreeFormAttachedModifier { content, .. } = segment
+    {
+        walk_segments_mut(visitor, content);
+    }
+}
+
+pub fn walk_link_mut<V: VisitorMut + ?Sized>(visitor: &mut V, segment: &mut ParagraphSegment) {
+    if let ParagraphSegment::Link {
+        targets,
+        description,
+        ..
+    } = segment
+    {
+        targets
+            .iter_mut()
+            .for_each(|target| visitor.visit_link_target_mut(target));
+        if let Some(description) = description {
+            walk_segments_mut(visitor, description);
+        }
+    }
+}
+
+pub fn walk_link_target_mut<V: VisitorMut + ?Sized>(visitor: &mut V, target: &mut LinkTarget) {
+    match target {
+        LinkTarget::Heading { title, .. }
+        | LinkTarget::Footnote { title, .. }
+        | LinkTarget::Definition { title, .. }
+        | LinkTarget::Generic { title, .. }
+        | LinkTarget::Wiki { title, .. }
+        | LinkTarget::Extendable { title, .. } => walk_segments_mut(visitor, title),
+        LinkTarget::LineNumber { .. }
+        | LinkTarget::Path { .. }
+        | LinkTarget::Url { .. }
+        | LinkTarget::Timestamp { .. } => {}
+    }
+}
+
+pub fn walk_anchor_definition_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    segment: &mut ParagraphSegment,
+) {
+    if let ParagraphSegment::AnchorDefinition {
+        content, target, ..
+    } = segment
+    {
+        walk_segments_mut(visitor, content);
+        visitor.visit_segment_mut(target);
+    }
+}
+
+pub fn walk_anchor_mut<V: VisitorMut + ?Sized>(visitor: &mut V, segment: &mut ParagraphSegment) {
+    if let ParagraphSegment::Anchor {
+        content,
+        description,
+        ..
+    } = segment
+    {
+        walk_segments_mut(visitor, content);
+        if let Some(description) = description {
+            walk_segments_mut(visitor, description);
+        }
+    }
+}
+
+pub fn walk_inline_link_target_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    segment: &mut ParagraphSegment,
+) {
+    if let ParagraphSegment::InlineLinkTarget { content, .. } = segment {
+        walk_segments_mut(visitor, content);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+
+    use crate::{
+        parse_tree,
+        visit::{
+            walk_attached_modifier, walk_heading, walk_link_target,
+            walk_nestable_detached_modifier, walk_nodes, walk_nodes_mut, Visitor, VisitorMut,
+        },
+        DetachedModifierExtension, LinkTarget, NorgAST, ParagraphSegment, ParagraphSegmentToken,
+    };
+
+    const DOCUMENT: &str = "* Heading with */bold italic/* text
+  - (x) a list item linking to {* Heading}[the *heading*]
+  -- a nested item with an {https://example.com}
+  |details
+  ** Nested heading
+  |end
+";
+
+    /// Records the nodes it comes across, in order.
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl Visitor for Recorder {
+        fn visit_heading(&mut self, node: &NorgAST) {
+            if let NorgAST::Heading { level, .. } = node {
+                self.events.push(format!("heading {}", level));
+            }
+            walk_heading(self, node);
+        }
+
+        fn visit_nestable_detached_modifier(&mut self, node: &NorgAST) {
+            if let NorgAST::NestableDetachedModifier { level, .. } = node {
+                self.events.push(format!("list item {}", level));
+            }
+            walk_nestable_detached_modifier(self, node);
+        }
+
+        fn visit_extension(&mut self, extension: &DetachedModifierExtension) {
+            self.events.push(format!("extension {:?}", extension));
+        }
+
+        fn visit_attached_modifier(&mut self, segment: &ParagraphSegment) {
+            if let ParagraphSegment::AttachedModifier { modifier_type, .. } = segment {
+                self.events
+                    .push(format!("attached modifier {}", modifier_type));
+            }
+            walk_attached_modifier(self, segment);
+        }
+
+        fn visit_link_target(&mut self, target: &LinkTarget) {
+            let kind = match target {
+                LinkTarget::Heading { .. } => "heading",
+                LinkTarget::Url { .. } => "url",
+                _ => "other",
+            };
+            self.events.push(format!("link target {}", kind));
+            walk_link_target(self, target);
+        }
+
+        fn visit_token(&mut self, segment: &ParagraphSegment) {
+            if let ParagraphSegment::Token {
+                token: ParagraphSegmentToken::Text(text),
+                ..
+            } = segment
+            {
+                self.events.push(text.clone());
+            }
+        }
+    }
+
+    /// Turns all text upper case.
+    struct Shout;
+
+    impl VisitorMut for Shout {
+        fn visit_token_mut(&mut self, segment: &mut ParagraphSegment) {
+            if let ParagraphSegment::Token {
+                token: ParagraphSegmentToken::Text(text),
+                ..
+            } = segment
+            {
+                *text = text.to_uppercase();
+            }
+        }
+    }
+
+    #[test]
+    fn visitor() {
+        let tree = parse_tree(DOCUMENT).unwrap();
+
+        let mut recorder = Recorder::default();
+        walk_nodes(&mut recorder, &tree);
+
+        assert_yaml_snapshot!(recorder.events);
+    }
+
+    #[test]
+    fn visitor_mut() {
+        let mut tree = parse_tree(DOCUMENT).unwrap();
+        walk_nodes_mut(&mut Shout, &mut tree);
+
+        let mut recorder = Recorder::default();
+        walk_nodes(&mut recorder, &tree);
+
+        assert_yaml_snapshot!(recorder.events);
+    }
+}